@@ -0,0 +1,91 @@
+// Run with `cargo bench`. Reports the time to serialize a large generated
+// dependency graph, as produced by tools that write BUILD files for every
+// crate in a Cargo lockfile.
+
+// The benchmark is not held to the library's minimum supported Rust version.
+#![allow(clippy::incompatible_msrv)]
+
+use serde_derive::Serialize;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+#[derive(Serialize)]
+#[serde(rename = "rust_library")]
+struct RustLibrary {
+    name: String,
+    srcs: Vec<String>,
+    crate_features: Vec<String>,
+    edition: &'static str,
+    rustc_flags: Vec<String>,
+    deps: Vec<String>,
+    version: String,
+    build_number: u32,
+    sizes: Vec<u64>,
+}
+
+fn graph() -> Vec<RustLibrary> {
+    (0..2000)
+        .map(|i| RustLibrary {
+            name: format!("crate_{i}"),
+            srcs: (0..10)
+                .map(|j| format!("src/module_{}/file_{}.rs", i % 7, j))
+                .collect(),
+            crate_features: vec!["default".to_owned(), "std".to_owned()],
+            edition: "2021",
+            rustc_flags: vec![
+                "--cap-lints=allow".to_owned(),
+                format!("--cfg=feature=\"level_{}\"", i % 3),
+            ],
+            deps: (0..25)
+                .map(|j| format!("//third-party/rust:dependency_{}", (i * 31 + j) % 2000))
+                .collect(),
+            version: format!("{}.{}.{}", i % 4, i % 17, i % 101),
+            build_number: i * 7919,
+            sizes: (0..8).map(|j| u64::from(i) * 1031 + j * 977).collect(),
+        })
+        .collect()
+}
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    f();
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(2) {
+        f();
+        iterations += 1;
+    }
+    let per_iter = start.elapsed() / iterations;
+    println!("{name:<12} {per_iter:>10.2?}/iter");
+}
+
+fn main() {
+    let graph = graph();
+    let strings: Vec<&str> = graph
+        .iter()
+        .flat_map(|library| library.deps.iter().map(String::as_str))
+        .collect();
+    let ints: Vec<u32> = (0..100_000u32)
+        .map(|i| i.wrapping_mul(2_654_435_761) % 2_000_000_000)
+        .collect();
+
+    bench("graph", || {
+        black_box(serde_starlark::to_string(black_box(&graph)).unwrap());
+    });
+    bench("strings", || {
+        black_box(serde_starlark::to_string(black_box(&strings)).unwrap());
+    });
+    bench("ints", || {
+        black_box(serde_starlark::to_string(black_box(&ints)).unwrap());
+    });
+
+    // The same few strings containing quotes, repeated many times over.
+    let flags: Vec<&str> = graph
+        .iter()
+        .flat_map(|library| library.rustc_flags.iter().map(String::as_str))
+        .cycle()
+        .take(50_000)
+        .collect();
+    bench("escapes", || {
+        black_box(serde_starlark::to_string(black_box(&flags)).unwrap());
+    });
+}
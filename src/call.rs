@@ -1,4 +1,5 @@
-use crate::FunctionCall;
+use crate::ser::bare_string;
+use crate::{Args, Arguments, FunctionCall, Kwargs, NamedArg, Positional};
 use serde::ser::{
     Error, Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple,
     SerializeTupleStruct, Serializer,
@@ -13,19 +14,24 @@ where
         S: Serializer,
     {
         self.args.serialize(FunctionCallSerializer {
-            function: self.function,
+            function: &self.function,
+            dynamic: self.dynamic,
             delegate: serializer,
         })
     }
 }
 
 struct FunctionCallSerializer<'name, S> {
+    // The name of the function, or if dynamic, of the field holding it.
     function: &'name str,
+    dynamic: bool,
     delegate: S,
 }
 
 impl<'name, S> FunctionCallSerializer<'name, S> {
     const UNSUPPORTED: &'static str = "unsupported function call argument type";
+    const POSITIONAL: &'static str =
+        "function call whose name is given by a field must have named arguments";
 }
 
 impl<'a, S> Serializer for FunctionCallSerializer<'a, S>
@@ -38,8 +44,8 @@ where
     type SerializeTuple = FunctionCallArgs<S::SerializeTupleStruct>;
     type SerializeTupleStruct = S::SerializeTupleStruct;
     type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
-    type SerializeMap = FunctionCallArgs<S::SerializeStruct>;
-    type SerializeStruct = S::SerializeStruct;
+    type SerializeMap = NamedArgs<'a, S::SerializeStruct>;
+    type SerializeStruct = NamedArgs<'a, S::SerializeStruct>;
     type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
 
     fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
@@ -159,6 +165,9 @@ where
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        if self.dynamic {
+            return Err(Error::custom(Self::POSITIONAL));
+        }
         let len = len.unwrap_or(2);
         let mut delegate = self.delegate.serialize_tuple_struct("(", len)?;
         delegate.serialize_field(self.function)?;
@@ -166,6 +175,9 @@ where
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        if self.dynamic {
+            return Err(Error::custom(Self::POSITIONAL));
+        }
         let mut delegate = self.delegate.serialize_tuple_struct("(", len)?;
         delegate.serialize_field(self.function)?;
         Ok(FunctionCallArgs { delegate })
@@ -176,6 +188,9 @@ where
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        if self.dynamic {
+            return Err(Error::custom(Self::POSITIONAL));
+        }
         let mut delegate = self.delegate.serialize_tuple_struct("(", len)?;
         delegate.serialize_field(self.function)?;
         Ok(delegate)
@@ -192,10 +207,7 @@ where
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        let len = len.unwrap_or(2);
-        let mut delegate = self.delegate.serialize_struct("(", len)?;
-        delegate.serialize_field("", self.function)?;
-        Ok(FunctionCallArgs { delegate })
+        self.serialize_struct("", len.unwrap_or(2))
     }
 
     fn serialize_struct(
@@ -203,9 +215,26 @@ where
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        let mut delegate = self.delegate.serialize_struct("(", len)?;
-        delegate.serialize_field("", self.function)?;
-        Ok(delegate)
+        if !self.dynamic {
+            let mut delegate = self.delegate.serialize_struct("(", len)?;
+            delegate.serialize_field("", self.function)?;
+            return Ok(NamedArgs {
+                delegate,
+                name_field: None,
+                name_value: false,
+            });
+        }
+        // One of the fields is the function name rather than an argument.
+        let len = match len {
+            crate::MULTILINE => len,
+            _ => len.saturating_sub(1),
+        };
+        let delegate = self.delegate.serialize_struct("(", len)?;
+        Ok(NamedArgs {
+            delegate,
+            name_field: Some(self.function),
+            name_value: false,
+        })
     }
 
     fn serialize_struct_variant(
@@ -265,7 +294,33 @@ where
     }
 }
 
-impl<S> SerializeMap for FunctionCallArgs<S>
+// Named arguments of a function call. If the function name comes from a
+// field, that field must be the first one.
+struct NamedArgs<'name, S> {
+    delegate: S,
+    name_field: Option<&'name str>,
+    // Whether the map value about to be serialized is the function name.
+    name_value: bool,
+}
+
+impl<'name, S> NamedArgs<'name, S>
+where
+    S: SerializeStruct,
+{
+    // Check that the first field is the one holding the function name.
+    fn is_name(&mut self, key: &str) -> Result<bool, S::Error> {
+        match self.name_field.take() {
+            Some(field) if key == field => Ok(true),
+            Some(field) => Err(Error::custom(format_args!(
+                "expected the function name in field `{}`, which must come first",
+                field,
+            ))),
+            None => Ok(false),
+        }
+    }
+}
+
+impl<'name, S> SerializeMap for NamedArgs<'name, S>
 where
     S: SerializeStruct,
 {
@@ -276,6 +331,11 @@ where
     where
         T: Serialize + ?Sized,
     {
+        if self.name_field.is_some() {
+            let key = bare_string(key).map_err(Error::custom)?;
+            self.name_value = self.is_name(&key)?;
+            return Ok(());
+        }
         self.delegate.serialize_field("*key", key)
     }
 
@@ -283,10 +343,125 @@ where
     where
         T: Serialize + ?Sized,
     {
+        if self.name_value {
+            self.name_value = false;
+            return self.delegate.serialize_field("", value);
+        }
         self.delegate.serialize_field("*value", value)
     }
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.is_name("")?;
         self.delegate.end()
     }
 }
+
+impl<'name, S> SerializeStruct for NamedArgs<'name, S>
+where
+    S: SerializeStruct,
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        if self.is_name(key)? {
+            return self.delegate.serialize_field("", value);
+        }
+        self.delegate.serialize_field(key, value)
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.is_name("")?;
+        self.delegate.end()
+    }
+}
+
+impl<'a, T> Serialize for Arguments<'a, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // An empty key is a positional argument in a FunctionCall.
+        let len = self.positional.len()
+            + self.named.len()
+            + usize::from(self.args.is_some())
+            + usize::from(self.kwargs.is_some());
+        let mut map = serializer.serialize_map(Some(len))?;
+        for value in &self.positional {
+            map.serialize_entry("", value)?;
+        }
+        for (name, value) in &self.named {
+            map.serialize_entry(name, value)?;
+        }
+        if let Some(value) = &self.args {
+            map.serialize_entry("", &Args(value))?;
+        }
+        if let Some(value) = &self.kwargs {
+            map.serialize_entry("", &Kwargs(value))?;
+        }
+        map.end()
+    }
+}
+
+impl<'name, T> Serialize for NamedArg<'name, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut arg = serializer.serialize_tuple_struct("*named", 2)?;
+        arg.serialize_field(self.name)?;
+        arg.serialize_field(&self.value)?;
+        arg.end()
+    }
+}
+
+impl<T> Serialize for Args<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("*", &self.0)
+    }
+}
+
+impl<T> Serialize for Kwargs<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("**", &self.0)
+    }
+}
+
+impl<A, T> Serialize for Positional<A>
+where
+    for<'a> &'a A: IntoIterator<Item = &'a T>,
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let len = (&self.0).into_iter().count();
+        let mut args = serializer.serialize_tuple_struct("*positional", len)?;
+        for arg in &self.0 {
+            args.serialize_field(arg)?;
+        }
+        args.end()
+    }
+}
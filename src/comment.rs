@@ -1,4 +1,4 @@
-use crate::LineComment;
+use crate::{BlockComment, Commented, KeepSorted, LineComment, Suppress};
 use serde::ser::{Serialize, SerializeTupleStruct, Serializer};
 
 impl<'a, T> Serialize for LineComment<'a, T>
@@ -15,3 +15,56 @@ where
         comment.end()
     }
 }
+
+impl<'a, T> Serialize for BlockComment<'a, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut comment = serializer.serialize_tuple_struct("*block", 2)?;
+        comment.serialize_field(&self.comment)?;
+        comment.serialize_field(&self.value)?;
+        comment.end()
+    }
+}
+
+impl<T> Serialize for Commented<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        LineComment::new(&self.value, &self.comment).serialize(serializer)
+    }
+}
+
+impl<'a, T> Serialize for Suppress<'a, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let comment = format!("buildifier: disable={}", self.lint);
+        BlockComment::new(&self.value, &comment).serialize(serializer)
+    }
+}
+
+impl<T> Serialize for KeepSorted<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let name = if self.sort { "*sort" } else { "*keep_sorted" };
+        serializer.serialize_newtype_struct(name, &self.value)
+    }
+}
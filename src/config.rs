@@ -0,0 +1,525 @@
+use crate::de;
+use crate::display::Starlark;
+use crate::error;
+use crate::ser::{WriteInto, WriteStarlark};
+use crate::{
+    ArgumentOrder, ArgumentPolicy, Config, Error, IntegerOverflow, Layout, QuoteStyle,
+    StarlarkFormat,
+};
+use serde::de::Deserialize;
+use serde::ser::Serialize;
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::sync::Arc;
+
+// The hook installed by Config::format.
+#[derive(Clone)]
+pub(crate) struct Format(pub Arc<dyn StarlarkFormat>);
+
+impl Debug for Format {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("StarlarkFormat")
+    }
+}
+
+// The configuration of the serializers and functions that do not take one.
+pub(crate) static DEFAULT: Config = Config::new();
+
+impl Config {
+    /// The default configuration, matching [`to_string`][crate::to_string].
+    #[must_use]
+    pub const fn new() -> Self {
+        Config {
+            quote_style: QuoteStyle::Double,
+            hex_escapes: false,
+            ascii_only: false,
+            integer_overflow: IntegerOverflow::Error,
+            floats: false,
+            non_finite_floats: true,
+            unit_as_none: false,
+            skip_none: false,
+            skip_empty: false,
+            max_width: None,
+            argument_order: ArgumentOrder::Declaration,
+            sort_map_keys: false,
+            deny_duplicate_keys: false,
+            uppercase_assignments: false,
+            escape_keywords: false,
+            argument_renames: Vec::new(),
+            argument_policies: Vec::new(),
+            format: None,
+            lenient: false,
+        }
+    }
+
+    /// Select the quote character used for string literals.
+    ///
+    /// The default is [`QuoteStyle::Double`].
+    pub fn quote_style(&mut self, style: QuoteStyle) -> &mut Self {
+        self.quote_style = style;
+        self
+    }
+
+    /// Escape control characters using only hexadecimal escape sequences.
+    ///
+    /// By default, ASCII control characters for which Starlark has no named
+    /// escape like `\n` are written using the shortest octal escape: `\0`
+    /// through `\177`. This relies on the character following the escape not
+    /// being an octal digit, which is easy for a human reader to misjudge. With
+    /// this option enabled, these characters are written as `\x00` through
+    /// `\x7F` instead, consistent with how other control characters are
+    /// written as `\uXXXX` or `\UXXXXXXXX`.
+    pub fn hex_escapes(&mut self, enable: bool) -> &mut Self {
+        self.hex_escapes = enable;
+        self
+    }
+
+    /// Escape every non-ASCII character in string literals, even printable ones.
+    ///
+    /// Characters up to U+FFFF are written as `\uXXXX` and the rest as
+    /// `\UXXXXXXXX`, so that the generated file consists purely of ASCII. This
+    /// applies to string literals only; the text of comments and the names of
+    /// functions and arguments are always written as given.
+    pub fn ascii_only(&mut self, enable: bool) -> &mut Self {
+        self.ascii_only = enable;
+        self
+    }
+
+    /// Select how to handle integers outside the range of a 32-bit `int`.
+    ///
+    /// The default is [`IntegerOverflow::Error`].
+    pub fn integer_overflow(&mut self, policy: IntegerOverflow) -> &mut Self {
+        self.integer_overflow = policy;
+        self
+    }
+
+    /// Serialize `f32` and `f64` as Starlark float literals.
+    ///
+    /// Floats are part of the Starlark spec but were a late addition to
+    /// Bazel, so by default serializing one is an error. When enabled, finite
+    /// values are written in their shortest round-tripping form with a decimal
+    /// point or exponent always present, like `1.0` or `1e-7`, so that they
+    /// are not mistaken for an `int`.
+    pub fn floats(&mut self, enable: bool) -> &mut Self {
+        self.floats = enable;
+        self
+    }
+
+    /// Allow NaN and infinite floats, which have no literal syntax in Starlark.
+    ///
+    /// These are written as calls to the builtin `float` function:
+    /// `float("nan")`, `float("inf")`, `-float("inf")`. This is enabled by
+    /// default and takes effect only together with [`floats`][Config::floats].
+    /// If disabled, serializing a non-finite float is an error.
+    pub fn non_finite_floats(&mut self, enable: bool) -> &mut Self {
+        self.non_finite_floats = enable;
+        self
+    }
+
+    /// Serialize `()` and `PhantomData` as `None`.
+    ///
+    /// By default serializing `()` is an error, as it is more likely a mistake
+    /// than intended to become `None`. Enabling this option allows types that
+    /// use `()` or `PhantomData` as a placeholder to be serialized using their
+    /// derived `Serialize` impl.
+    pub fn unit_as_none(&mut self, enable: bool) -> &mut Self {
+        self.unit_as_none = enable;
+        self
+    }
+
+    /// Omit named function call arguments whose value is `None`.
+    ///
+    /// This has the same effect as putting `#[serde(skip_serializing_if =
+    /// "Option::is_none")]` on every optional field of every struct. Elements
+    /// of lists and tuples, dict entries, and positional arguments are never
+    /// skipped.
+    pub fn skip_none(&mut self, enable: bool) -> &mut Self {
+        self.skip_none = enable;
+        self
+    }
+
+    /// Omit named function call arguments whose value is an empty list or
+    /// dict.
+    ///
+    /// Hand-written BUILD files typically leave out arguments like
+    /// `rustc_env = {}` rather than spelling out the default. As with
+    /// [`skip_none`][Config::skip_none], only named arguments are affected.
+    pub fn skip_empty(&mut self, enable: bool) -> &mut Self {
+        self.skip_empty = enable;
+        self
+    }
+
+    /// Choose between one-line and multi-line format based on line length.
+    ///
+    /// By default the format of each function call, array, tuple, and map is
+    /// decided by the number of elements, as described in the documentation of
+    /// [`ONELINE`][crate::ONELINE]. With a maximum width, every one of them is
+    /// instead written on one line if it fits within that many columns, the
+    /// way buildifier formats BUILD files, and otherwise with one element per
+    /// line. Containers holding a comment or a multi-line string are always
+    /// multi-line. An explicit `ONELINE` or `MULTILINE` length hint, as well as
+    /// [`Compact`][crate::Compact] and [`Expanded`][crate::Expanded], take
+    /// precedence over this option.
+    ///
+    /// The value of an [`Assignment`][crate::Assignment] that is a sum, such as
+    /// a list plus a [`Select`][crate::Select], and does not fit is wrapped in
+    /// parentheses with one operand per line.
+    pub fn max_width(&mut self, width: Option<usize>) -> &mut Self {
+        self.max_width = width;
+        self
+    }
+
+    /// Select the order of named arguments in function calls.
+    ///
+    /// The default is [`ArgumentOrder::Declaration`]. This applies equally to
+    /// structs and to maps serialized by way of [`FunctionCall`], such as
+    /// structs containing `#[serde(flatten)]`. Positional arguments always
+    /// stay in front of named arguments in their original order.
+    ///
+    /// [`FunctionCall`]: crate::FunctionCall
+    pub fn argument_order(&mut self, order: ArgumentOrder) -> &mut Self {
+        self.argument_order = order;
+        self
+    }
+
+    /// Write the entries of every map in order of their serialized key.
+    ///
+    /// Maps whose iteration order is unspecified, like `HashMap`, otherwise
+    /// produce different output from one run to the next. Keys are compared by
+    /// their Starlark text, so string keys sort the same as the strings do,
+    /// apart from characters that need to be escaped.
+    pub fn sort_map_keys(&mut self, enable: bool) -> &mut Self {
+        self.sort_map_keys = enable;
+        self
+    }
+
+    /// Fail serialization of a map in which two keys have the same Starlark
+    /// text.
+    ///
+    /// Bazel rejects a dict literal with duplicate keys, for example a
+    /// `select` in which two Cargo features map to the same condition. This
+    /// option catches that at the time the file is generated instead. Keys are
+    /// compared by their serialized text, so `1` and `"1"` are distinct.
+    pub fn deny_duplicate_keys(&mut self, enable: bool) -> &mut Self {
+        self.deny_duplicate_keys = enable;
+        self
+    }
+
+    /// Require the identifier of every [`Assignment`] to be written in
+    /// ALL_CAPS.
+    ///
+    /// Buildifier's naming lint expects top-level constants in a BUILD or .bzl
+    /// file to be uppercase, like `VERSION = "1.0.0"`. With this option, an
+    /// assignment to any other name is an error.
+    ///
+    /// [`Assignment`]: crate::Assignment
+    pub fn uppercase_assignments(&mut self, enable: bool) -> &mut Self {
+        self.uppercase_assignments = enable;
+        self
+    }
+
+    /// Append an underscore to named arguments that are Starlark keywords.
+    ///
+    /// A struct field like `r#if` is otherwise serialized as the argument
+    /// `if`, which is a syntax error, unless every such field is given a
+    /// `#[serde(rename)]`. With this option it becomes `if_` instead. The
+    /// keywords include the Python keywords that Starlark reserves, such as
+    /// `class` and `import`.
+    pub fn escape_keywords(&mut self, enable: bool) -> &mut Self {
+        self.escape_keywords = enable;
+        self
+    }
+
+    /// Write every named argument called `from` as `to` instead.
+    ///
+    /// This applies to the fields of all structs and to the keys of maps
+    /// serialized by way of [`FunctionCall`], for example to turn a field named
+    /// `type` into the `kind` argument expected by a rule. Renaming takes place
+    /// before [`escape_keywords`][Config::escape_keywords]. Renaming the same
+    /// argument again replaces the earlier rename.
+    ///
+    /// [`FunctionCall`]: crate::FunctionCall
+    pub fn rename_argument(&mut self, from: &str, to: &str) -> &mut Self {
+        self.argument_renames
+            .retain(|(existing, _)| existing != from);
+        self.argument_renames.push((from.to_owned(), to.to_owned()));
+        self
+    }
+
+    /// Format the value of every named argument called `name` according to
+    /// `policy`, in whichever struct or [`FunctionCall`] the argument appears.
+    ///
+    /// The name is the one written in the Starlark output, after
+    /// [`rename_argument`][Config::rename_argument]. The policy applies to a
+    /// list or dict that is the whole value of the argument, or the first
+    /// operand of a sum, and does not override an explicit [`ONELINE`] or
+    /// [`MULTILINE`] length hint given by the value itself. Setting a policy
+    /// for the same argument again replaces the earlier one.
+    ///
+    /// ```
+    /// use serde_derive::Serialize;
+    /// use serde_starlark::{ArgumentPolicy, Config, Layout};
+    ///
+    /// #[derive(Serialize)]
+    /// #[serde(rename = "rust_library")]
+    /// pub struct RustLibrary {
+    ///     pub name: &'static str,
+    ///     pub srcs: Vec<&'static str>,
+    ///     pub deps: Vec<&'static str>,
+    /// }
+    ///
+    /// let mut config = Config::new();
+    /// let policy = ArgumentPolicy {
+    ///     layout: Layout::Multiline,
+    ///     sort: true,
+    /// };
+    /// for name in ["deps", "srcs", "data"] {
+    ///     config.argument_policy(name, policy);
+    /// }
+    ///
+    /// let rust_library = RustLibrary {
+    ///     name: "syn",
+    ///     srcs: vec!["src/lib.rs"],
+    ///     deps: vec![":quote", ":proc-macro2"],
+    /// };
+    /// let expected = r#"rust_library(
+    ///     name = "syn",
+    ///     srcs = [
+    ///         "src/lib.rs",
+    ///     ],
+    ///     deps = [
+    ///         ":proc-macro2",
+    ///         ":quote",
+    ///     ],
+    /// )
+    /// "#;
+    /// assert_eq!(config.to_string(&rust_library).unwrap(), expected);
+    /// ```
+    ///
+    /// [`FunctionCall`]: crate::FunctionCall
+    /// [`ONELINE`]: crate::ONELINE
+    /// [`MULTILINE`]: crate::MULTILINE
+    pub fn argument_policy(&mut self, name: &str, policy: ArgumentPolicy) -> &mut Self {
+        self.argument_policies
+            .retain(|(existing, _)| existing != name);
+        self.argument_policies.push((name.to_owned(), policy));
+        self
+    }
+
+    /// Decide the layout of containers throughout the output by way of a
+    /// [`StarlarkFormat`] hook, which replaces any earlier one.
+    pub fn format(&mut self, format: impl StarlarkFormat + 'static) -> &mut Self {
+        self.format = Some(Format(Arc::new(format)));
+        self
+    }
+
+    /// When deserializing with [`Config::from_str`], turn expressions that
+    /// cannot be deserialized into [`Raw`] values instead of failing.
+    ///
+    /// This applies to comprehensions, conditional expressions, operators,
+    /// identifiers, and calls of functions other than `select`, wherever the
+    /// type being deserialized accepts a `Raw`, such as a `Raw` field or an
+    /// untagged enum with a `Raw` variant. A BUILD file deserialized as a
+    /// sequence also produces a `Raw` for each statement that is not an
+    /// expression, such as an assignment or a `def`.
+    ///
+    /// ```
+    /// use serde_derive::Deserialize;
+    /// use serde_starlark::{Config, Raw};
+    ///
+    /// #[derive(Deserialize, Debug, PartialEq)]
+    /// #[serde(untagged)]
+    /// enum Srcs {
+    ///     List(Vec<String>),
+    ///     Other(Raw),
+    /// }
+    ///
+    /// let mut config = Config::new();
+    /// config.lenient(true);
+    ///
+    /// let srcs: Vec<Srcs> = config.from_str(r#"[["lib.rs"], glob(["*.rs"]) + EXTRA]"#).unwrap();
+    /// assert_eq!(
+    ///     srcs,
+    ///     [
+    ///         Srcs::List(vec!["lib.rs".to_owned()]),
+    ///         Srcs::Other(Raw(r#"glob(["*.rs"]) + EXTRA"#.to_owned())),
+    ///     ],
+    /// );
+    /// ```
+    ///
+    /// [`Raw`]: crate::Raw
+    pub fn lenient(&mut self, enable: bool) -> &mut Self {
+        self.lenient = enable;
+        self
+    }
+
+    /// Serialize the given value to Starlark using this configuration.
+    pub fn to_string<T>(&self, value: &T) -> Result<String, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(WriteStarlark::new(self))
+    }
+
+    /// Serialize the given value to Starlark using this configuration,
+    /// appending to the end of an existing string.
+    ///
+    /// Generators that write out thousands of files can clear and reuse one
+    /// `String` for all of them, created with enough capacity up front for a
+    /// typical file, instead of allocating a new one for each. If
+    /// serialization fails, `output` is left as it was.
+    ///
+    /// ```
+    /// use serde_starlark::{Config, FunctionCall};
+    ///
+    /// let config = Config::new();
+    /// let mut output = String::with_capacity(4096);
+    /// for file in [["LICENSE-APACHE"], ["LICENSE-MIT"]] {
+    ///     output.clear();
+    ///     let exports = FunctionCall::new("exports_files", [&file[..]]);
+    ///     config.to_string_into(&mut output, &exports).unwrap();
+    ///     # assert_eq!(output, format!("exports_files([\"{}\"])\n", file[0]));
+    ///     // write output to a BUILD file
+    /// }
+    /// ```
+    pub fn to_string_into<T>(&self, output: &mut String, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        WriteStarlark::append(self, output, value)
+    }
+
+    /// Serialize the given value as Starlark into an IO stream using this
+    /// configuration.
+    ///
+    /// Streaming happens only at the granularity of the statements of a
+    /// top-level [`Document`]: each statement is written to the stream as
+    /// soon as it is complete, so peak memory is bounded by the largest
+    /// single statement rather than by the whole file. A statement itself,
+    /// and any value that is not a top-level `Document`, is still built up
+    /// in memory and written in one piece. Consider wrapping an unbuffered
+    /// writer such as `File` in a `BufWriter`.
+    ///
+    /// [`Document`]: crate::Document
+    pub fn to_writer<W, T>(&self, mut writer: W, value: &T) -> Result<(), Error>
+    where
+        W: io::Write,
+        T: ?Sized + Serialize,
+    {
+        let output = value.serialize(WriteStarlark::with_sink(self, Some(&mut writer)))?;
+        writer.write_all(output.as_bytes()).map_err(error::io)
+    }
+
+    /// Adapter whose `Display` impl serializes the given value as Starlark
+    /// using this configuration.
+    ///
+    /// The value is serialized each time it is formatted, directly into the
+    /// formatter, which saves building an intermediate `String` when the
+    /// Starlark is only going to be written into a larger `format!` or
+    /// `write!`. The statements of a top-level [`Document`] are handed to the
+    /// formatter one at a time, as with [`to_writer`][Config::to_writer].
+    ///
+    /// `Display` has no way to report what went wrong, so a value that fails
+    /// to serialize makes formatting fail with `fmt::Error`, and makes
+    /// `format!` panic. Use [`to_string`][Config::to_string] for values that
+    /// might not be representable in Starlark.
+    ///
+    /// ```
+    /// use serde_starlark::{Config, FunctionCall};
+    /// use std::fmt::Write;
+    ///
+    /// let config = Config::new();
+    /// let exports = FunctionCall::new("exports_files", [&["LICENSE"][..]]);
+    /// let mut build = String::from("# Generated.\n");
+    /// write!(build, "{}", config.display(&exports)).unwrap();
+    /// assert_eq!(build, "# Generated.\nexports_files([\"LICENSE\"])\n");
+    /// ```
+    ///
+    /// [`Document`]: crate::Document
+    #[must_use]
+    pub fn display<'a, T>(&'a self, value: &'a T) -> impl Display + 'a
+    where
+        T: ?Sized + Serialize,
+    {
+        Starlark {
+            config: self,
+            value,
+        }
+    }
+
+    /// Deserialize an instance of type `T` from Starlark, like
+    /// [`from_str`][crate::from_str], using this configuration.
+    ///
+    /// With [`IntegerOverflow::Emit`], integer literals outside the range of
+    /// a 32-bit `int` deserialize into `i64`, `u64`, `i128` or `u128` fields,
+    /// for the dialects that implement arbitrary precision integers as in
+    /// the Starlark spec. Otherwise they are an error, as when serializing.
+    ///
+    /// ```
+    /// use serde_starlark::{Config, IntegerOverflow};
+    ///
+    /// let mut config = Config::new();
+    /// config.integer_overflow(IntegerOverflow::Emit);
+    ///
+    /// let size: u64 = config.from_str("4294967296").unwrap();
+    /// assert_eq!(size, 1 << 32);
+    /// assert!(serde_starlark::from_str::<u64>("4294967296").is_err());
+    /// ```
+    pub fn from_str<'de, T>(&self, starlark: &'de str) -> Result<T, Error>
+    where
+        T: Deserialize<'de>,
+    {
+        de::from_str(self, starlark)
+    }
+
+    /// Serializer whose output is Starlark written using this configuration.
+    ///
+    /// `value.serialize(config.serializer())` is equivalent to
+    /// `config.to_string(&value)`.
+    #[must_use]
+    pub fn serializer(&self) -> impl serde::Serializer<Ok = String, Error = Error> + '_ {
+        WriteStarlark::new(self)
+    }
+
+    /// Serializer that appends Starlark written using this configuration to
+    /// the end of `output`, and whose `Ok` type is `()`.
+    ///
+    /// This is the one to use with Serializer adapters such as erased-serde,
+    /// which discard the `Ok` value of the serializer they wrap and so would
+    /// lose the String produced by [`serializer`][Config::serializer]. If
+    /// serialization fails, `output` is left as it was.
+    ///
+    /// ```
+    /// use serde_starlark::{Config, FunctionCall};
+    ///
+    /// let config = Config::new();
+    /// let mut output = String::new();
+    /// let serializer = config.serializer_into(&mut output);
+    /// let mut erased: Box<dyn erased_serde::Serializer> =
+    ///     Box::new(<dyn erased_serde::Serializer>::erase(serializer));
+    /// let exports: Box<dyn erased_serde::Serialize> =
+    ///     Box::new(FunctionCall::new("exports_files", [&["LICENSE"][..]]));
+    /// exports.erased_serialize(&mut erased).unwrap();
+    /// drop(erased);
+    /// assert_eq!(output, "exports_files([\"LICENSE\"])\n");
+    /// ```
+    #[must_use]
+    pub fn serializer_into<'a>(
+        &'a self,
+        output: &'a mut String,
+    ) -> impl serde::Serializer<Ok = (), Error = Error> + 'a {
+        WriteInto::new(self, output)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Auto
+    }
+}
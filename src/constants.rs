@@ -0,0 +1,51 @@
+use crate::{error, ident};
+use crate::{Assignment, Constants, Error};
+use serde::ser::{Serialize, SerializeTupleStruct, Serializer};
+
+impl<T> Constants<T> {
+    /// Build the block from an ordered map, such as a `BTreeMap` or a `Vec`
+    /// of pairs, failing on the first invalid or repeated identifier.
+    pub fn from_map<I, K>(assignments: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (K, T)>,
+        K: Into<String>,
+    {
+        let mut constants = Constants::new();
+        for (identifier, value) in assignments {
+            constants.insert(identifier, value)?;
+        }
+        Ok(constants)
+    }
+
+    /// Append an assignment to the end of the block.
+    ///
+    /// Fails if `identifier` is not a Starlark identifier or if the block
+    /// already assigns to it.
+    pub fn insert(&mut self, identifier: impl Into<String>, value: T) -> Result<&mut Self, Error> {
+        let identifier = identifier.into();
+        if !ident::is_identifier(&identifier) {
+            return Err(error::invalid_name(&identifier));
+        }
+        if self.assignments.iter().any(|(name, _)| *name == identifier) {
+            return Err(error::duplicate_assignment(&identifier));
+        }
+        self.assignments.push((identifier, value));
+        Ok(self)
+    }
+}
+
+impl<T> Serialize for Constants<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut constants = serializer.serialize_tuple_struct("*constants", 0)?;
+        for (identifier, value) in &self.assignments {
+            constants.serialize_field(&Assignment::new(identifier, value))?;
+        }
+        constants.end()
+    }
+}
@@ -0,0 +1,125 @@
+use crate::lex;
+use crate::parse::{self, Argument, Expr, ExprKind, Group, Item, Statement, StatementKind};
+use crate::Error;
+use std::ops::Range;
+
+// Concrete syntax tree of a Starlark file: the syntax tree from parse.rs
+// together with the source it was parsed from. Every node knows its span, so
+// whatever the tree does not model, such as whitespace, commas, and the exact
+// spelling of literals, is still there between and inside the spans. Printing
+// with no edits gives back the source byte for byte, and printing with edits
+// rewrites only the spans they replace.
+pub(crate) struct Cst<'a> {
+    pub source: &'a str,
+    pub statements: Vec<Statement<'a>>,
+}
+
+// A top-level call statement, like `rust_library(name = "syn", ...)`.
+pub(crate) struct Target<'t, 'a> {
+    pub statement: &'t Statement<'a>,
+    pub call: &'t Expr<'a>,
+    pub function: &'a str,
+    pub args: &'t Group<'a, Argument<'a>>,
+}
+
+impl<'a> Cst<'a> {
+    pub fn parse(source: &'a str) -> Result<Self, Error> {
+        let statements = parse::parse(source)?;
+        Ok(Cst { source, statements })
+    }
+
+    // Every top-level call statement of a function given by its name.
+    pub fn targets(&self) -> impl Iterator<Item = Target<'_, 'a>> {
+        self.statements.iter().filter_map(Target::of)
+    }
+
+    // The top-level call whose `name` argument is the string `name`.
+    pub fn target(&self, name: &str) -> Option<Target<'_, 'a>> {
+        self.targets()
+            .find(|target| target.name().map_or(false, |value| value == name))
+    }
+
+    // The source with each range replaced by its text. The ranges must not
+    // overlap.
+    pub fn print(&self, mut edits: Vec<(Range<usize>, String)>) -> String {
+        edits.sort_by_key(|(range, _)| range.start);
+        let mut output = String::with_capacity(self.source.len());
+        let mut offset = 0;
+        for (range, replacement) in edits {
+            debug_assert!(offset <= range.start);
+            output.push_str(&self.source[offset..range.start]);
+            output.push_str(&replacement);
+            offset = range.end;
+        }
+        output.push_str(&self.source[offset..]);
+        output
+    }
+}
+
+impl<'t, 'a> Target<'t, 'a> {
+    pub fn of(statement: &'t Statement<'a>) -> Option<Self> {
+        let call = match &statement.kind {
+            StatementKind::Expression(call) => call,
+            _ => return None,
+        };
+        let (function, args) = match &call.kind {
+            ExprKind::Call(function, args) => match function.kind {
+                ExprKind::Identifier(function) => (function, args),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(Target {
+            statement,
+            call,
+            function,
+            args,
+        })
+    }
+
+    // The value of the `name` argument, if it is a string literal.
+    pub fn name(&self) -> Option<String> {
+        match self.arg("name").map(|arg| &arg.value) {
+            Some(Argument::Named(_, value)) => match value.kind {
+                ExprKind::String(literal) => Some(lex::unquote(literal)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn arg(&self, name: &str) -> Option<&'t Item<'a, Argument<'a>>> {
+        self.args
+            .items
+            .iter()
+            .find(|arg| arg_name(arg) == Some(name))
+    }
+}
+
+pub(crate) fn arg_name<'a>(arg: &Item<'a, Argument<'a>>) -> Option<&'a str> {
+    match arg.value {
+        Argument::Named(name, _) => Some(name),
+        Argument::Positional(_) | Argument::Args(_) | Argument::Kwargs(_) => None,
+    }
+}
+
+// The value of an argument, after `=` if it is named.
+pub(crate) fn arg_value<'t, 'a>(arg: &'t Item<'a, Argument<'a>>) -> &'t Expr<'a> {
+    match &arg.value {
+        Argument::Positional(value)
+        | Argument::Named(_, value)
+        | Argument::Args(value)
+        | Argument::Kwargs(value) => value,
+    }
+}
+
+pub(crate) fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+// Just past the newline that ends the line containing `offset`.
+pub(crate) fn line_end(source: &str, offset: usize) -> usize {
+    source[offset..]
+        .find('\n')
+        .map_or(source.len(), |i| offset + i + 1)
+}
@@ -0,0 +1,1144 @@
+use crate::config::DEFAULT;
+use crate::error;
+use crate::lex;
+use crate::parse::{
+    self, Argument, Comments, Expr, ExprKind, Group, Item, Statement, StatementKind,
+};
+use crate::raw::RAW;
+use crate::{Comment, CommentPlacement, Config, Error, IntegerOverflow};
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer as _, Visitor};
+use serde::forward_to_deserialize_any;
+use std::{slice, vec};
+
+pub(crate) fn from_str<'de, T>(config: &Config, source: &'de str) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    let statements = parse::parse(source)?;
+    File::new(config, source, &statements).deserialize()
+}
+
+pub(crate) fn from_str_with_comments<'de, T>(
+    source: &'de str,
+) -> Result<(T, Vec<Comment<'de>>), Error>
+where
+    T: Deserialize<'de>,
+{
+    let statements = parse::parse(source)?;
+    let value = File::new(&DEFAULT, source, &statements).deserialize()?;
+    let mut collector = Collector {
+        source,
+        comments: Vec::new(),
+        statement: None,
+    };
+    for statement in &statements {
+        collector.statement(statement);
+    }
+    Ok((value, collector.comments))
+}
+
+// Deserialize one top-level statement, which must be an expression.
+pub(crate) fn statement<'de, T>(source: &'de str, statement: &Statement<'de>) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    match &statement.kind {
+        StatementKind::Expression(expr) => T::deserialize(Value {
+            source,
+            config: &DEFAULT,
+            expr,
+        })
+        .map_err(|error| error.at(source, expr.span.start)),
+        _ => Err(error::expected_expression().at(source, statement_start(source, statement))),
+    }
+}
+
+// Start of the code of a statement, after any comment lines above it.
+fn statement_start(source: &str, statement: &Statement) -> usize {
+    match &statement.kind {
+        StatementKind::Expression(expr) | StatementKind::Assignment { target: expr, .. } => {
+            expr.span.start
+        }
+        StatementKind::Comment | StatementKind::Other => match statement.comments.leading.last() {
+            Some(comment) => {
+                let end = comment.as_ptr() as usize - source.as_ptr() as usize + comment.len();
+                let rest = &source[end..];
+                end + rest.len() - rest.trim_start().len()
+            }
+            None => statement.span.start,
+        },
+    }
+}
+
+// Deserializer for a whole file. A file of one expression deserializes as
+// that expression. Asked for a sequence, a file whose statements are not a
+// single list deserializes as the sequence of its statements, so that a BUILD
+// file can be read into a Vec of rule structs.
+struct File<'de, 'a> {
+    source: &'de str,
+    config: &'a Config,
+    statements: Vec<&'a Statement<'de>>,
+}
+
+impl<'de, 'a> File<'de, 'a> {
+    fn new(config: &'a Config, source: &'de str, statements: &'a [Statement<'de>]) -> Self {
+        let statements = statements
+            .iter()
+            .filter(|statement| !matches!(statement.kind, StatementKind::Comment))
+            .collect();
+        File {
+            source,
+            config,
+            statements,
+        }
+    }
+
+    // Errors without a more precise location are reported at the first
+    // statement.
+    fn deserialize<T>(self) -> Result<T, Error>
+    where
+        T: Deserialize<'de>,
+    {
+        let source = self.source;
+        let start = self
+            .statements
+            .first()
+            .map(|statement| statement_start(source, statement));
+        T::deserialize(self).map_err(|error| match start {
+            Some(start) => error.at(source, start),
+            None => error,
+        })
+    }
+
+    fn expression(&self) -> Result<Value<'de, 'a>, Error> {
+        match self.statements.as_slice() {
+            [statement] => match &statement.kind {
+                StatementKind::Expression(expr) => Ok(Value {
+                    source: self.source,
+                    config: self.config,
+                    expr,
+                }),
+                _ => Err(self.expected_expression(statement)),
+            },
+            [_, extra, ..] => Err(self.expected_expression(extra)),
+            [] => Err(error::expected_expression()),
+        }
+    }
+
+    fn expected_expression(&self, statement: &Statement) -> Error {
+        error::expected_expression().at(self.source, statement_start(self.source, statement))
+    }
+
+    // The statements as a sequence, or as a tuple of `len` elements, unless
+    // the file is a single list or tuple.
+    fn statements<V>(self, len: Option<usize>, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if let [Statement {
+            kind: StatementKind::Expression(expr),
+            ..
+        }] = self.statements.as_slice()
+        {
+            if let ExprKind::List(_) | ExprKind::Tuple(_) = expr.kind {
+                let value = Value {
+                    source: self.source,
+                    config: self.config,
+                    expr,
+                };
+                return match len {
+                    Some(len) => value.deserialize_tuple(len, visitor),
+                    None => value.deserialize_any(visitor),
+                };
+            }
+        }
+        if !self.config.lenient {
+            for statement in &self.statements {
+                if !matches!(statement.kind, StatementKind::Expression(_)) {
+                    return Err(self.expected_expression(statement));
+                }
+            }
+        }
+        if len.map_or(false, |len| len != self.statements.len()) {
+            return Err(de::Error::invalid_length(self.statements.len(), &visitor));
+        }
+        visitor.visit_seq(Statements {
+            source: self.source,
+            config: self.config,
+            remaining: self.statements.into_iter(),
+        })
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for File<'de, 'a> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.expression()?.deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.expression()?.deserialize_option(visitor)
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.expression()?.deserialize_unit_struct(name, visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.expression()?.deserialize_newtype_struct(name, visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.statements(None, visitor)
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.statements(Some(len), visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.expression()?
+            .deserialize_tuple_struct(name, len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.expression()?.deserialize_struct(name, fields, visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.expression()?.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit map identifier ignored_any
+    }
+}
+
+// Deserializer for one expression of the syntax tree.
+struct Value<'de, 'a> {
+    source: &'de str,
+    config: &'a Config,
+    expr: &'a Expr<'de>,
+}
+
+impl<'de, 'a> Value<'de, 'a> {
+    fn nested(&self, expr: &'a Expr<'de>) -> Self {
+        Value {
+            source: self.source,
+            config: self.config,
+            expr,
+        }
+    }
+
+    fn unsupported(&self) -> Error {
+        error::unsupported_expression(&self.source[self.expr.span.clone()])
+            .at(self.source, self.expr.span.start)
+    }
+
+    // An expression with no counterpart in the data model, which in lenient
+    // mode is given to the visitor as its source text.
+    fn raw<V>(&self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.config.lenient {
+            let text = &self.source[self.expr.span.clone()];
+            visitor.visit_newtype_struct(BorrowedStrDeserializer::new(text))
+        } else {
+            Err(self.unsupported())
+        }
+    }
+
+    // The dict argument of `select({…})` or `selects.with_or({…})`, which are
+    // how Select and maps renamed to "select" are serialized.
+    fn select(&self) -> Option<&'a Expr<'de>> {
+        let (function, args) = match &self.expr.kind {
+            ExprKind::Call(function, args) => (function, args),
+            _ => return None,
+        };
+        let is_select = match &function.kind {
+            ExprKind::Identifier(name) => *name == "select",
+            ExprKind::Dot(object, "with_or") => {
+                matches!(object.kind, ExprKind::Identifier("selects"))
+            }
+            _ => false,
+        };
+        match args.items.as_slice() {
+            [Item {
+                value: Argument::Positional(dict),
+                ..
+            }] if is_select && matches!(dict.kind, ExprKind::Dict(_)) => Some(dict),
+            _ => None,
+        }
+    }
+
+    // The arguments of a call to the function `name`, which is how a struct
+    // renamed to `name` is serialized. Calls to other functions are an error.
+    fn call(&self, name: &'static str) -> Result<Option<Call<'de, 'a>>, Error> {
+        match &self.expr.kind {
+            ExprKind::Call(function, args) => {
+                let function = &self.source[function.span.clone()];
+                if function == name {
+                    Ok(Some(Call {
+                        source: self.source,
+                        config: self.config,
+                        args: &args.items,
+                    }))
+                } else {
+                    Err(error::unexpected_function(name, function))
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Integers are limited to the range of i32 like in Bazel, unless the
+    // configuration allows integers of any size as in the Starlark spec, in
+    // which case larger ones are given to the visitor as the smallest of i64,
+    // u64, i128, or u128 that can represent them.
+    fn int<V>(&self, text: &str, negative: bool, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let (radix, digits) = match text.get(..2) {
+            Some("0x" | "0X") => (16, &text[2..]),
+            Some("0o" | "0O") => (8, &text[2..]),
+            Some("0b" | "0B") => (2, &text[2..]),
+            _ => (10, text),
+        };
+        let magnitude = u128::from_str_radix(digits, radix).map_err(|_| self.unsupported())?;
+        let int = match i128::try_from(magnitude) {
+            Ok(int) if negative => -int,
+            Ok(int) => int,
+            Err(_) if negative => return Err(self.unsupported()),
+            Err(_) if self.config.integer_overflow == IntegerOverflow::Emit => {
+                return visitor.visit_u128(magnitude);
+            }
+            Err(_) => return Err(error::unsupported_u128(magnitude)),
+        };
+        if let Ok(int) = i32::try_from(int) {
+            visitor.visit_i32(int)
+        } else if self.config.integer_overflow != IntegerOverflow::Emit {
+            Err(error::unsupported_i128(int))
+        } else if let Ok(int) = i64::try_from(int) {
+            visitor.visit_i64(int)
+        } else if let Ok(int) = u64::try_from(int) {
+            visitor.visit_u64(int)
+        } else {
+            visitor.visit_i128(int)
+        }
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Value<'de, 'a> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.expr.kind {
+            ExprKind::Identifier("True") => visitor.visit_bool(true),
+            ExprKind::Identifier("False") => visitor.visit_bool(false),
+            ExprKind::Identifier("None") => visitor.visit_unit(),
+            ExprKind::Int(text) => self.int(text, false, visitor),
+            ExprKind::Unary(op @ ("-" | "+"), operand) => match operand.kind {
+                ExprKind::Int(text) => self.int(text, *op == "-", visitor),
+                _ => self.raw(visitor),
+            },
+            ExprKind::Float(text) => match text.parse() {
+                Ok(float) => visitor.visit_f64(float),
+                Err(_) => Err(self.unsupported()),
+            },
+            ExprKind::String(literal) => {
+                let quote = literal.find(['"', '\'']).unwrap();
+                if literal[..quote].contains(['b', 'B']) {
+                    return self.raw(visitor);
+                }
+                let body = &literal[1..literal.len() - 1];
+                if quote == 0 && !body.starts_with(['"', '\'']) && !body.contains('\\') {
+                    visitor.visit_borrowed_str(body)
+                } else {
+                    visitor.visit_string(lex::unquote(literal))
+                }
+            }
+            ExprKind::List(items) | ExprKind::Tuple(items) => visitor.visit_seq(Seq {
+                source: self.source,
+                config: self.config,
+                items: items.items.iter().map(|item| &item.value),
+            }),
+            ExprKind::Dict(items) => visitor.visit_map(Map {
+                source: self.source,
+                config: self.config,
+                items: items.items.iter(),
+                value: None,
+            }),
+            _ => match self.select() {
+                Some(dict) => self.nested(dict).deserialize_any(visitor),
+                None => self.raw(visitor),
+            },
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.expr.kind {
+            ExprKind::Identifier("None") => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.expr.kind {
+            ExprKind::Identifier(identifier) if identifier == name => visitor.visit_unit(),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if name == RAW {
+            let text = &self.source[self.expr.span.clone()];
+            return visitor.visit_newtype_struct(BorrowedStrDeserializer::new(text));
+        }
+        match self.call(name)? {
+            Some(call) => visitor.visit_newtype_struct(call),
+            None => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    // A list or tuple must have exactly `len` elements. This includes the
+    // single-element form `(x,)`, which is a tuple and not a parenthesized
+    // expression.
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.expr.kind {
+            ExprKind::List(items) | ExprKind::Tuple(items) if items.items.len() != len => {
+                Err(de::Error::invalid_length(items.items.len(), &visitor))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.call(name)? {
+            Some(call) => call.deserialize_tuple(len, visitor),
+            None => self.deserialize_tuple(len, visitor),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.call(name)? {
+            Some(call) => call.named(fields, visitor),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.expr.kind {
+            ExprKind::String(_) => visitor.visit_enum(VariantName(self)),
+            ExprKind::Call(function, args) => visitor.visit_enum(CallVariant {
+                function: &self.source[function.span.clone()],
+                call: Call {
+                    source: self.source,
+                    config: self.config,
+                    args: &args.items,
+                },
+            }),
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit seq map identifier ignored_any
+    }
+}
+
+// The arguments of a function call, deserialized as the fields of a struct,
+// the elements of a tuple struct, or the value of a newtype struct.
+struct Call<'de, 'a> {
+    source: &'de str,
+    config: &'a Config,
+    args: &'a [Item<'de, Argument<'de>>],
+}
+
+impl<'de, 'a> Call<'de, 'a> {
+    fn positional<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let mut exprs = Vec::new();
+        for arg in self.args {
+            match &arg.value {
+                Argument::Positional(expr) => exprs.push(expr),
+                Argument::Named(name, _) => {
+                    let error = error::unexpected_named(name);
+                    return Err(error.at(self.source, arg.span.start));
+                }
+                Argument::Args(expr) | Argument::Kwargs(expr) => {
+                    return Err(self.unsupported(expr));
+                }
+            }
+        }
+        visitor.visit_seq(Seq {
+            source: self.source,
+            config: self.config,
+            items: exprs.into_iter(),
+        })
+    }
+
+    fn named<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(Arguments {
+            source: self.source,
+            config: self.config,
+            fields,
+            items: self.args.iter(),
+            positional: 0,
+            value: None,
+        })
+    }
+
+    fn unsupported(&self, expr: &Expr) -> Error {
+        error::unsupported_expression(&self.source[expr.span.clone()])
+            .at(self.source, expr.span.start)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Call<'de, 'a> {
+    type Error = Error;
+
+    // A single positional argument is the value itself, as written by
+    // FunctionCall, and otherwise the arguments are a sequence if all are
+    // positional or a map if all are named.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.args {
+            [Item {
+                value: Argument::Positional(expr),
+                ..
+            }] => Value {
+                source: self.source,
+                config: self.config,
+                expr,
+            }
+            .deserialize_any(visitor),
+            [Item {
+                value: Argument::Named(..),
+                ..
+            }, ..] => self.named(&[], visitor),
+            _ => self.positional(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.args {
+            [Item {
+                value: Argument::Positional(expr),
+                ..
+            }] => Value {
+                source: self.source,
+                config: self.config,
+                expr,
+            }
+            .deserialize_seq(visitor),
+            _ => self.positional(visitor),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        if self.args.len() != len {
+            return Err(de::Error::invalid_length(self.args.len(), &visitor));
+        }
+        self.positional(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.named(fields, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct map enum identifier ignored_any
+    }
+}
+
+struct Seq<'de, 'a, I> {
+    source: &'de str,
+    config: &'a Config,
+    items: I,
+}
+
+impl<'de, 'a, I> de::SeqAccess<'de> for Seq<'de, 'a, I>
+where
+    I: ExactSizeIterator<Item = &'a Expr<'de>>,
+    'de: 'a,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.items.next() {
+            Some(expr) => seed
+                .deserialize(Value {
+                    source: self.source,
+                    config: self.config,
+                    expr,
+                })
+                .map(Some)
+                .map_err(|error| error.at(self.source, expr.span.start)),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+// The statements of a file, which in lenient mode need not all be
+// expressions. Any other statement is given to the visitor as its source text.
+struct Statements<'de, 'a> {
+    source: &'de str,
+    config: &'a Config,
+    remaining: vec::IntoIter<&'a Statement<'de>>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for Statements<'de, 'a> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let statement = match self.remaining.next() {
+            Some(statement) => statement,
+            None => return Ok(None),
+        };
+        let start = statement_start(self.source, statement);
+        let value = if let StatementKind::Expression(expr) = &statement.kind {
+            seed.deserialize(Value {
+                source: self.source,
+                config: self.config,
+                expr,
+            })
+        } else {
+            let end = match statement.comments.suffix {
+                Some(comment) => comment.as_ptr() as usize - self.source.as_ptr() as usize,
+                None => statement.span.end,
+            };
+            let text = self.source[start..end].trim_end();
+            seed.deserialize(RawText(text))
+        };
+        value
+            .map(Some)
+            .map_err(|error| error.at(self.source, start))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining.len())
+    }
+}
+
+// Source text handed to a `Raw`.
+struct RawText<'de>(&'de str);
+
+impl<'de> de::Deserializer<'de> for RawText<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(BorrowedStrDeserializer::new(self.0))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Map<'de, 'a> {
+    source: &'de str,
+    config: &'a Config,
+    items: slice::Iter<'a, Item<'de, (Expr<'de>, Expr<'de>)>>,
+    value: Option<&'a Expr<'de>>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Map<'de, 'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.items.next() {
+            Some(item) => {
+                let (key, value) = &item.value;
+                self.value = Some(value);
+                seed.deserialize(Value {
+                    source: self.source,
+                    config: self.config,
+                    expr: key,
+                })
+                .map(Some)
+                .map_err(|error| error.at(self.source, key.span.start))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let expr = self.value.take().unwrap();
+        seed.deserialize(Value {
+            source: self.source,
+            config: self.config,
+            expr,
+        })
+        .map_err(|error| error.at(self.source, expr.span.start))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+// The arguments of a function call as a map from parameter name to value.
+// Positional arguments are given the names of the struct's fields in order.
+struct Arguments<'de, 'a> {
+    source: &'de str,
+    config: &'a Config,
+    fields: &'static [&'static str],
+    items: slice::Iter<'a, Item<'de, Argument<'de>>>,
+    positional: usize,
+    value: Option<&'a Expr<'de>>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Arguments<'de, 'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let item = match self.items.next() {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let (key, value) = match &item.value {
+            Argument::Positional(value) => {
+                let key = if let Some(field) = self.fields.get(self.positional) {
+                    *field
+                } else {
+                    let error = error::unexpected_positional(self.positional);
+                    return Err(error.at(self.source, item.span.start));
+                };
+                self.positional += 1;
+                (key, value)
+            }
+            Argument::Named(key, value) => (*key, value),
+            Argument::Args(value) | Argument::Kwargs(value) => {
+                let span = value.span.clone();
+                let error = error::unsupported_expression(&self.source[span.clone()]);
+                return Err(error.at(self.source, span.start));
+            }
+        };
+        self.value = Some(value);
+        seed.deserialize(BorrowedStrDeserializer::new(key))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let expr = self.value.take().unwrap();
+        seed.deserialize(Value {
+            source: self.source,
+            config: self.config,
+            expr,
+        })
+        .map_err(|error| error.at(self.source, expr.span.start))
+    }
+}
+
+// An enum variant serialized as a call to the function of the same name, with
+// the variant's content as arguments.
+struct CallVariant<'de, 'a> {
+    function: &'de str,
+    call: Call<'de, 'a>,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for CallVariant<'de, 'a> {
+    type Error = Error;
+    type Variant = Call<'de, 'a>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Call<'de, 'a>), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.function))?;
+        Ok((variant, self.call))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for Call<'de, 'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.args.first() {
+            None => Ok(()),
+            Some(arg) => match &arg.value {
+                Argument::Positional(expr)
+                | Argument::Named(_, expr)
+                | Argument::Args(expr)
+                | Argument::Kwargs(expr) => Err(self.unsupported(expr)),
+            },
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.positional(visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.named(fields, visitor)
+    }
+}
+
+// A unit enum variant, which is serialized as a string of its name.
+struct VariantName<'de, 'a>(Value<'de, 'a>);
+
+impl<'de, 'a> de::EnumAccess<'de> for VariantName<'de, 'a> {
+    type Error = Error;
+    type Variant = UnitVariant;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, UnitVariant), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.0)?;
+        Ok((variant, UnitVariant))
+    }
+}
+
+struct UnitVariant;
+
+impl<'de> de::VariantAccess<'de> for UnitVariant {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, _seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"newtype variant",
+        ))
+    }
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"tuple variant",
+        ))
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"struct variant",
+        ))
+    }
+}
+
+// Gathers the comments of the syntax tree, with the paths of the values they
+// are attached to.
+struct Collector<'de> {
+    source: &'de str,
+    comments: Vec<Comment<'de>>,
+    statement: Option<usize>,
+}
+
+impl<'de> Collector<'de> {
+    fn statement(&mut self, statement: &Statement<'de>) {
+        let expr = match &statement.kind {
+            StatementKind::Comment => {
+                for text in &statement.comments.leading {
+                    self.push(text, String::new(), CommentPlacement::Detached);
+                }
+                return;
+            }
+            StatementKind::Expression(expr) => Some(expr),
+            StatementKind::Assignment { value, .. } => Some(value),
+            StatementKind::Other => None,
+        };
+        self.statement = Some(self.statement.map_or(0, |index| index + 1));
+        let (leading, suffix) = (&statement.comments.leading, statement.comments.suffix);
+        for text in leading {
+            self.push(text, String::new(), CommentPlacement::Above);
+        }
+        if let Some(expr) = expr {
+            self.expr(expr, "");
+        }
+        if let Some(text) = suffix {
+            self.push(text, String::new(), CommentPlacement::Suffix);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr<'de>, path: &str) {
+        match &expr.kind {
+            ExprKind::List(items) | ExprKind::Tuple(items) => {
+                self.items(
+                    items,
+                    path,
+                    |i, _| format!("{}[{}]", path, i),
+                    |collector, path, value| {
+                        collector.expr(value, path);
+                    },
+                );
+            }
+            ExprKind::Dict(items) => {
+                let source = self.source;
+                self.items(
+                    items,
+                    path,
+                    |_, (key, _)| format!("{}[{}]", path, &source[key.span.clone()]),
+                    |collector, path, (_, value)| collector.expr(value, path),
+                );
+            }
+            ExprKind::Call(_, args) => {
+                self.items(
+                    args,
+                    path,
+                    |i, arg| match arg {
+                        Argument::Named(name, _) if path.is_empty() => (*name).to_owned(),
+                        Argument::Named(name, _) => format!("{}.{}", path, name),
+                        _ => format!("{}[{}]", path, i),
+                    },
+                    |collector, path, arg| match arg {
+                        Argument::Positional(value)
+                        | Argument::Named(_, value)
+                        | Argument::Args(value)
+                        | Argument::Kwargs(value) => collector.expr(value, path),
+                    },
+                );
+            }
+            ExprKind::Unary(_, operand) => self.expr(operand, path),
+            ExprKind::Binary(left, _, right) => {
+                self.expr(left, path);
+                self.expr(right, path);
+            }
+            _ => {}
+        }
+    }
+
+    fn items<T>(
+        &mut self,
+        items: &Group<'de, T>,
+        path: &str,
+        item_path: impl Fn(usize, &T) -> String,
+        mut visit: impl FnMut(&mut Self, &str, &T),
+    ) {
+        if let Some(text) = items.open_comment {
+            self.push(text, path.to_owned(), CommentPlacement::Suffix);
+        }
+        for (i, item) in items.items.iter().enumerate() {
+            let item_path = item_path(i, &item.value);
+            self.comments(&item.comments, &item_path, |collector| {
+                visit(collector, &item_path, &item.value);
+            });
+        }
+        for text in &items.trailing {
+            self.push(text, path.to_owned(), CommentPlacement::Detached);
+        }
+    }
+
+    fn comments(&mut self, comments: &Comments<'de>, path: &str, inner: impl FnOnce(&mut Self)) {
+        for text in &comments.leading {
+            self.push(text, path.to_owned(), CommentPlacement::Above);
+        }
+        inner(self);
+        if let Some(text) = comments.suffix {
+            self.push(text, path.to_owned(), CommentPlacement::Suffix);
+        }
+    }
+
+    fn push(&mut self, text: &'de str, path: String, placement: CommentPlacement) {
+        // Every comment in the syntax tree is a slice of the source.
+        let offset = text.as_ptr() as usize - self.source.as_ptr() as usize;
+        self.comments.push(Comment {
+            text,
+            line: lex::position(self.source, offset).0,
+            statement: self.statement,
+            path,
+            placement,
+        });
+    }
+}
@@ -0,0 +1,44 @@
+use crate::ser::WriteStarlark;
+use crate::Config;
+use serde::ser::Serialize;
+use std::fmt::{self, Display};
+use std::io;
+use std::str;
+
+pub(crate) struct Starlark<'a, T: ?Sized> {
+    pub config: &'a Config,
+    pub value: &'a T,
+}
+
+impl<'a, T> Display for Starlark<'a, T>
+where
+    T: ?Sized + Serialize,
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        // The statements of a Document go straight to the formatter as each
+        // one is finished. Anything else arrives in one piece at the end.
+        let mut sink = FormatterSink(&mut *formatter);
+        let serializer = WriteStarlark::with_sink(self.config, Some(&mut sink));
+        let output = self.value.serialize(serializer).map_err(|_| fmt::Error)?;
+        formatter.write_str(&output)
+    }
+}
+
+// Adapter from the io::Write that the serializer flushes to onto the
+// formatter. The serializer only ever writes whole strings.
+struct FormatterSink<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl<'a, 'b> io::Write for FormatterSink<'a, 'b> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let string = str::from_utf8(buf)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        self.0
+            .write_str(string)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use crate::load::{self, Load};
+#[cfg(feature = "rayon")]
+use crate::{error::Segment, Config, Error};
+use crate::{order, Compact, Document, Section};
+#[cfg(feature = "rayon")]
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::ser::{Serialize, SerializeTupleStruct, Serializer};
+use std::iter;
+
+// Column limit for the banner comment, including the leading `# `.
+const BANNER_WIDTH: usize = 80;
+
+// How the statements of a Document are separated. The serializer receives this
+// as the length of the "*document" tuple struct.
+#[derive(Copy, Clone)]
+pub(crate) struct Separators {
+    pub blank_lines: usize,
+    pub group_loads: bool,
+}
+
+impl Separators {
+    pub(crate) const DEFAULT: Self = Separators {
+        blank_lines: 1,
+        group_loads: false,
+    };
+
+    fn encode(self) -> usize {
+        self.blank_lines << 1 | usize::from(self.group_loads)
+    }
+
+    pub(crate) fn decode(len: usize) -> Self {
+        Separators {
+            blank_lines: len >> 1,
+            group_loads: len & 1 != 0,
+        }
+    }
+
+    // Append one rendered statement to the statements before it.
+    pub(crate) fn push(self, output: &mut String, previous: Option<&str>, statement: &str) {
+        if let Some(previous) = previous {
+            if !(self.group_loads && is_load(previous) && is_load(statement)) {
+                for _ in 0..self.blank_lines {
+                    output.push('\n');
+                }
+            }
+        }
+        output.push_str(statement);
+    }
+}
+
+pub(crate) fn is_load(statement: &str) -> bool {
+    statement.starts_with("load(")
+}
+
+// One statement of a Document as it is written, after the `load` statements
+// of each file have been merged into the first of them and the statements have
+// been put in order.
+pub(crate) enum Statement<'a, S> {
+    Verbatim(&'a S),
+    Load {
+        first: &'a S,
+        load: Load,
+        merged: bool,
+    },
+    // The comment that begins a section.
+    Header(String),
+}
+
+impl<'a, S> Serialize for Statement<'a, S>
+where
+    S: Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: Serializer,
+    {
+        match self {
+            Statement::Verbatim(statement)
+            | Statement::Load {
+                first: statement,
+                merged: false,
+                ..
+            } => statement.serialize(serializer),
+            Statement::Load { load, .. } => Compact(load).serialize(serializer),
+            Statement::Header(label) => {
+                let header = format!("==== {} ====", label);
+                Banner(&header).serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'a, T> Document<'a, T> {
+    // The statements in the order they are written.
+    pub(crate) fn rearranged<S>(&self) -> Vec<Statement<'_, S>>
+    where
+        for<'b> &'b T: IntoIterator<Item = &'b S>,
+        S: Serialize,
+    {
+        let mut statements = load::merge_loads(&self.statements);
+        order::rearrange(&mut statements, self.order, &self.sections);
+        statements
+    }
+}
+
+impl<'a, T, S> Serialize for Document<'a, T>
+where
+    for<'b> &'b T: IntoIterator<Item = &'b S>,
+    S: Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: Serializer,
+    {
+        let len = self.separators.encode();
+        let mut document = serializer.serialize_tuple_struct("*document", len)?;
+        if let Some((tool, regenerate)) = self.banner {
+            let banner = banner(tool, regenerate);
+            document.serialize_field(&Banner(&banner))?;
+        }
+        for statement in self.rearranged() {
+            document.serialize_field(&statement)?;
+        }
+        document.end()
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+impl Config {
+    /// Serialize a [`Document`] using this configuration, rendering its
+    /// statements on multiple threads.
+    ///
+    /// The statements are independent of one another, so each one is
+    /// serialized on its own and the results are concatenated in their
+    /// original order. The output is identical to that of
+    /// [`to_string`][Config::to_string], and so is the error if more than one
+    /// statement fails: the one for the earliest statement is returned.
+    pub fn to_string_parallel<T, S>(&self, document: &Document<T>) -> Result<String, Error>
+    where
+        for<'b> &'b T: IntoIterator<Item = &'b S>,
+        S: Serialize + Sync,
+    {
+        let mut output = String::new();
+        let mut previous = None;
+        let mut index = 0;
+        if let Some((tool, regenerate)) = document.banner {
+            let banner = banner(tool, regenerate);
+            previous = Some(self.to_string(&Banner(&banner))?);
+            index += 1;
+        }
+        let statements = document.rearranged();
+        let rendered: Vec<Result<String, Error>> = statements
+            .par_iter()
+            .enumerate()
+            .map(|(i, statement)| {
+                self.to_string(statement)
+                    .map_err(|error| error.within(Segment::Index(index + i)))
+            })
+            .collect();
+        if let Some(banner) = &previous {
+            output.push_str(banner);
+        }
+        for statement in rendered {
+            let statement = statement?;
+            document
+                .separators
+                .push(&mut output, previous.as_deref(), &statement);
+            previous = Some(statement);
+        }
+        Ok(output)
+    }
+}
+
+impl<'a, T> Serialize for Section<'a, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut section = serializer.serialize_tuple_struct("*section", 2)?;
+        section.serialize_field(self.label)?;
+        section.serialize_field(&self.statement)?;
+        section.end()
+    }
+}
+
+pub(crate) struct Banner<'a>(pub(crate) &'a str);
+
+impl<'a> Serialize for Banner<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("*banner", self.0)
+    }
+}
+
+// Word-wrap the banner text. The regenerate command is never split across
+// lines, so that it can be copied out of the comment intact.
+pub(crate) fn banner(tool: &str, regenerate: &str) -> String {
+    let intro = format!("DO NOT EDIT -- generated by {}; run", tool);
+    let words = intro
+        .split_whitespace()
+        .chain(iter::once(regenerate))
+        .chain(["to", "regenerate."]);
+    let mut banner = String::new();
+    let mut column = 0;
+    for word in words {
+        let len = word.chars().count();
+        if column > 0 && column + 1 + len > BANNER_WIDTH - 2 {
+            banner.push('\n');
+            column = 0;
+        } else if column > 0 {
+            banner.push(' ');
+            column += 1;
+        }
+        banner.push_str(word);
+        column += len;
+    }
+    banner
+}
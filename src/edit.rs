@@ -0,0 +1,387 @@
+//! Buildozer-style edits of existing Starlark.
+//!
+//! Each operation finds a target, meaning a top-level function call like
+//! `rust_library(name = "syn", ...)`, by the value of its `name` argument, and
+//! returns the source with that one change applied. Everything outside of the
+//! edited argument is preserved byte for byte, including comments and
+//! formatting that this crate would not itself produce. Values are serialized
+//! the same way as by [`to_string`][crate::to_string], at the indentation of
+//! the place they are inserted.
+//!
+//! ```
+//! use serde_starlark::edit;
+//!
+//! let build = r#"rust_library(
+//!     name = "syn",
+//!     deps = [":proc-macro2"],  # direct
+//! )
+//! "#;
+//!
+//! let build = edit::add_attr(build, "syn", "deps", ":quote").unwrap();
+//! let build = edit::set_attr(build.as_str(), "syn", "edition", "2021").unwrap();
+//! let expected = r#"rust_library(
+//!     name = "syn",
+//!     deps = [":proc-macro2", ":quote"],  # direct
+//!     edition = "2021",
+//! )
+//! "#;
+//! assert_eq!(build, expected);
+//! ```
+
+use crate::config::DEFAULT;
+use crate::cst::{arg_name, arg_value, line_end, line_start, Cst, Target};
+use crate::error;
+use crate::ident;
+use crate::lex::{Lexer, Token, TokenKind};
+use crate::parse::{ExprKind, Group};
+use crate::ser::WriteStarlark;
+use crate::{Config, Error};
+use serde::ser::Serialize;
+use std::ops::Range;
+use std::ptr;
+use std::slice;
+
+/// Set the value of an argument of the target named `target`, adding the
+/// argument if the target does not have it yet.
+///
+/// A comment that comes with the value, from a [`LineComment`], replaces the
+/// comment at the end of the argument's line if there is one.
+///
+/// [`LineComment`]: crate::LineComment
+pub fn set_attr<T>(source: &str, target: &str, attr: &str, value: &T) -> Result<String, Error>
+where
+    T: ?Sized + Serialize,
+{
+    Editor::new(&DEFAULT).set_attr(source, target, attr, value)
+}
+
+/// Append an element to a list argument of the target named `target`.
+///
+/// If the argument does not exist yet, it is added as a list holding just
+/// this element. If an identical element is already present, the source is
+/// returned unchanged. An argument whose value is anything other than a list
+/// literal, such as a `select` or a concatenation, is an error.
+pub fn add_attr<T>(source: &str, target: &str, attr: &str, value: &T) -> Result<String, Error>
+where
+    T: ?Sized + Serialize,
+{
+    Editor::new(&DEFAULT).add_attr(source, target, attr, value)
+}
+
+/// Remove an argument from the target named `target`, along with any comment
+/// lines directly above it. Removing an argument the target does not have is
+/// not an error.
+pub fn remove_attr(source: &str, target: &str, attr: &str) -> Result<String, Error> {
+    Editor::new(&DEFAULT).remove_attr(source, target, attr)
+}
+
+/// Remove the target named `target`, along with the comment lines directly
+/// above it and the blank line that separated it from the next statement.
+pub fn remove_target(source: &str, target: &str) -> Result<String, Error> {
+    Editor::new(&DEFAULT).remove_target(source, target)
+}
+
+/// The operations of this module, serializing values using a particular
+/// [`Config`].
+///
+/// ```
+/// use serde_starlark::edit::Editor;
+/// use serde_starlark::{Config, QuoteStyle};
+///
+/// let mut config = Config::new();
+/// config.quote_style(QuoteStyle::Single);
+/// let editor = Editor::new(&config);
+///
+/// let build = "alias(name = 'syn', actual = ':syn-1')\n";
+/// let build = editor.set_attr(build, "syn", "actual", ":syn-2").unwrap();
+/// assert_eq!(build, "alias(name = 'syn', actual = ':syn-2')\n");
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Editor<'a> {
+    config: &'a Config,
+}
+
+impl<'a> Editor<'a> {
+    #[must_use]
+    pub fn new(config: &'a Config) -> Self {
+        Editor { config }
+    }
+
+    /// Like [`edit::set_attr`][set_attr], with this editor's configuration.
+    pub fn set_attr<T>(
+        &self,
+        source: &str,
+        target: &str,
+        attr: &str,
+        value: &T,
+    ) -> Result<String, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let cst = Cst::parse(source)?;
+        let call = find_target(&cst, target)?;
+        let edits = match call.arg(attr) {
+            Some(arg) => {
+                let indent = line_indent(source, arg.span.start);
+                let (text, comment) = WriteStarlark::fragment(self.config, value, indent)?;
+                let mut edits = vec![(arg_value(arg).span.clone(), text)];
+                if let Some(comment) = comment {
+                    edits.push(line_comment(source, arg.end(), &comment));
+                }
+                edits
+            }
+            None => self.insert_arg(source, &call, attr, value)?,
+        };
+        Ok(cst.print(edits))
+    }
+
+    /// Like [`edit::add_attr`][add_attr], with this editor's configuration.
+    pub fn add_attr<T>(
+        &self,
+        source: &str,
+        target: &str,
+        attr: &str,
+        value: &T,
+    ) -> Result<String, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let cst = Cst::parse(source)?;
+        let call = find_target(&cst, target)?;
+        let arg = if let Some(arg) = call.arg(attr) {
+            arg
+        } else {
+            let edits = self.insert_arg(source, &call, attr, slice::from_ref(&value))?;
+            return Ok(cst.print(edits));
+        };
+        let list = match &arg_value(arg).kind {
+            ExprKind::List(list) => list,
+            _ => return Err(error::not_a_list(attr)),
+        };
+        let multiline = first_on_line(source, close_bracket(list));
+        let indent = match list.items.first() {
+            Some(item) if multiline => line_indent(source, item.span.start),
+            _ if multiline => line_indent(source, list.span.start) + 4,
+            _ => line_indent(source, list.span.start),
+        };
+        let (text, comment) = WriteStarlark::fragment(self.config, value, indent)?;
+        if list
+            .items
+            .iter()
+            .any(|item| same_value(&source[item.span.clone()], &text))
+        {
+            return Ok(source.to_owned());
+        }
+        let edits = insert_item(source, list, multiline, indent, &text, comment);
+        Ok(cst.print(edits))
+    }
+
+    /// Like [`edit::remove_attr`][remove_attr], with this editor's
+    /// configuration.
+    pub fn remove_attr(&self, source: &str, target: &str, attr: &str) -> Result<String, Error> {
+        let cst = Cst::parse(source)?;
+        let call = find_target(&cst, target)?;
+        let args = &call.args.items;
+        let index = match args.iter().position(|arg| arg_name(arg) == Some(attr)) {
+            Some(index) => index,
+            None => return Ok(source.to_owned()),
+        };
+        let arg = &args[index];
+        let end = arg.end();
+        let next = args.get(index + 1);
+        let multiline = first_on_line(source, close_bracket(call.args));
+        // Whether the next argument begins a line of its own, or there is no
+        // next argument and the closing bracket does.
+        let last_on_line = next.map_or(multiline, |next| first_on_line(source, next.span.start));
+        let range = if multiline && first_on_line(source, arg.span.start) && last_on_line {
+            let mut start = line_start(source, arg.span.start);
+            while let Some(above) = comment_line_above(source, start) {
+                start = above;
+            }
+            start..line_end(source, end)
+        } else if let Some(next) = next.filter(|_| !last_on_line) {
+            arg.span.start..next.span.start
+        } else if index > 0 && multiline {
+            // Keep the comma of the argument before, which ends its line.
+            args[index - 1].end()..end
+        } else if index > 0 {
+            args[index - 1].span.end..end
+        } else {
+            arg.span.start..end
+        };
+        Ok(cst.print(vec![(range, String::new())]))
+    }
+
+    /// Like [`edit::remove_target`][remove_target], with this editor's
+    /// configuration.
+    pub fn remove_target(&self, source: &str, target: &str) -> Result<String, Error> {
+        let cst = Cst::parse(source)?;
+        let call = find_target(&cst, target)?;
+        let statements = &cst.statements;
+        let index = statements
+            .iter()
+            .position(|statement| ptr::eq(statement, call.statement))
+            .unwrap();
+        let line = line_start(source, call.call.span.start);
+        // Another statement on the same line, separated by `;`, stays.
+        if let Some(next) = statements
+            .get(index + 1)
+            .filter(|next| line_start(source, next.span.start) == line)
+        {
+            let range = call.call.span.start..next.span.start;
+            return Ok(cst.print(vec![(range, String::new())]));
+        }
+        if index > 0 && !source[line..call.call.span.start].trim().is_empty() {
+            let range = statements[index - 1].span.end..call.call.span.end;
+            return Ok(cst.print(vec![(range, String::new())]));
+        }
+        let mut start = line;
+        while let Some(above) = comment_line_above(source, start) {
+            start = above;
+        }
+        let mut end = line_end(source, call.statement.span.end);
+        if source[end..].starts_with('\n') {
+            end += 1;
+        } else if source[..start].ends_with("\n\n") {
+            start -= 1;
+        }
+        Ok(cst.print(vec![(start..end, String::new())]))
+    }
+
+    fn insert_arg<T>(
+        self,
+        source: &str,
+        call: &Target,
+        attr: &str,
+        value: &T,
+    ) -> Result<Vec<(Range<usize>, String)>, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if !ident::is_identifier(attr) {
+            return Err(error::invalid_argument_name(attr));
+        }
+        let args = call.args;
+        let multiline = first_on_line(source, close_bracket(args));
+        let indent = match args.items.first() {
+            Some(arg) if multiline => line_indent(source, arg.span.start),
+            _ if multiline => line_indent(source, call.call.span.start) + 4,
+            _ => line_indent(source, call.call.span.start),
+        };
+        let (text, comment) = WriteStarlark::fragment(self.config, value, indent)?;
+        let text = format!("{} = {}", attr, text);
+        Ok(insert_item(source, args, multiline, indent, &text, comment))
+    }
+}
+
+// Insertion of a new last item into the arguments of a call or the elements
+// of a list, written on its own line if the brackets span multiple lines.
+fn insert_item<T>(
+    source: &str,
+    group: &Group<T>,
+    multiline: bool,
+    indent: usize,
+    text: &str,
+    comment: Option<String>,
+) -> Vec<(Range<usize>, String)> {
+    let comment = match comment {
+        Some(comment) => format!("  # {}", comment),
+        None => String::new(),
+    };
+    let mut edits = Vec::new();
+    let last = group.items.last();
+    let close = close_bracket(group);
+    if multiline {
+        if let Some(last) = last.filter(|last| last.comma.is_none()) {
+            let end = last.span.end;
+            edits.push((end..end, ",".to_owned()));
+        }
+        let at = line_start(source, close);
+        let line = format!("{:indent$}{},{}\n", "", text, comment, indent = indent);
+        edits.push((at..at, line));
+    } else {
+        let separator = match last {
+            None => "",
+            Some(last) if last.comma.is_some() => " ",
+            Some(_) => ", ",
+        };
+        edits.push((close..close, format!("{}{}", separator, text)));
+        if !comment.is_empty() {
+            let end = line_end(source, group.span.end);
+            let end = if source[..end].ends_with('\n') {
+                end - 1
+            } else {
+                end
+            };
+            edits.push((end..end, comment));
+        }
+    }
+    edits
+}
+
+// Where to write the comment that goes with a value ending at `end`: at the end
+// of its line, in place of the comment already there if there is one.
+fn line_comment(source: &str, end: usize, comment: &str) -> (Range<usize>, String) {
+    let line_end = line_end(source, end);
+    let existing = Lexer::new(source).map_while(Result::ok).find(|token| {
+        token.kind == TokenKind::Comment && (end..line_end).contains(&token.span.start)
+    });
+    let range = if let Some(existing) = existing {
+        source[..existing.span.start].trim_end_matches(' ').len()..existing.span.end
+    } else {
+        let end = source[..line_end].trim_end_matches('\n').len();
+        end..end
+    };
+    (range, format!("  # {}", comment))
+}
+
+// Whether two values are written the same apart from the quotes and escapes
+// of their string literals and the spacing between tokens.
+fn same_value(a: &str, b: &str) -> bool {
+    fn tokens(text: &str) -> Vec<Token<'_>> {
+        Lexer::new(text)
+            .map_while(Result::ok)
+            .filter(|token| !matches!(token.kind, TokenKind::Comment | TokenKind::Newline))
+            .collect()
+    }
+    let (a, b) = (tokens(a), tokens(b));
+    a.len() == b.len()
+        && a.iter()
+            .zip(&b)
+            .all(|(a, b)| match (a.string_value(), b.string_value()) {
+                (Some(a), Some(b)) => a == b,
+                _ => a.kind == b.kind && a.text == b.text,
+            })
+}
+
+fn find_target<'t, 'a>(cst: &'t Cst<'a>, target: &str) -> Result<Target<'t, 'a>, Error> {
+    cst.target(target)
+        .ok_or_else(|| error::target_not_found(target))
+}
+
+fn close_bracket<T>(group: &Group<T>) -> usize {
+    group.span.end - 1
+}
+
+fn line_indent(source: &str, offset: usize) -> usize {
+    let line = &source[line_start(source, offset)..];
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn first_on_line(source: &str, offset: usize) -> bool {
+    source[line_start(source, offset)..offset].trim().is_empty()
+}
+
+// Start of the line above the one beginning at `start`, if that line holds
+// only a comment.
+fn comment_line_above(source: &str, start: usize) -> Option<usize> {
+    if start == 0 {
+        return None;
+    }
+    let above = line_start(source, start - 1);
+    if source[above..start].trim_start().starts_with('#') {
+        Some(above)
+    } else {
+        None
+    }
+}
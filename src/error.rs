@@ -1,9 +1,22 @@
+use crate::lex;
 use crate::Error;
 use std::fmt::{self, Debug, Display};
+use std::io;
 
 #[derive(Debug)]
 pub(crate) enum ErrorKind {
     Message(String),
+    Io(io::Error),
+    #[cfg(feature = "yaml")]
+    Yaml(serde_norway::Error),
+    #[cfg(feature = "yaml")]
+    YamlTopLevel,
+    #[cfg(feature = "toml")]
+    Toml(Box<toml::de::Error>),
+    #[cfg(feature = "stardoc")]
+    StardocJson(serde_json::Error),
+    #[cfg(feature = "stardoc")]
+    StardocExpected(String),
     UnsupportedI64(i64),
     UnsupportedI128(i128),
     UnsupportedU32(u32),
@@ -11,47 +24,343 @@ pub(crate) enum ErrorKind {
     UnsupportedU128(u128),
     UnsupportedF32(f32),
     UnsupportedF64(f64),
+    NonFiniteF32(f32),
+    NonFiniteF64(f64),
     UnsupportedChar(char),
     UnsupportedBytes,
     UnsupportedUnit,
-    UnsupportedEnum(&'static str, &'static str),
     UnsupportedCall,
+    ExpectedString,
+    InvalidName(String),
+    InvalidArgumentName(String),
+    LowercaseAssignment(String),
+    InvalidFunctionName(String),
+    DuplicateKey(String),
+    UnhashableKey(&'static str),
+    UnsupportedSpacer,
+    UnsupportedArgument(&'static str),
+    UnsupportedPositional,
+    PositionalAfterNamed,
+    ArgumentAfterArgs,
+    ArgumentAfterKwargs,
+    CommentPrefix(String),
+    PrivateName(String),
+    TargetNotFound(String),
+    NotAList(String),
+    Syntax(&'static str),
+    MissingPlaceholder(String),
+    TemplateValues,
+    SelectConflict(String),
+    NestedSelect,
+    DuplicateAssignment(String),
+    ExpectedExpression,
+    UnsupportedExpression(String),
+    UnexpectedFunction {
+        expected: &'static str,
+        found: String,
+    },
+    UnexpectedPositional(usize),
+    UnexpectedNamed(String),
+    InvalidTargetName(String, &'static str),
+    MissingAttribute {
+        rule: String,
+        target: Option<String>,
+        attribute: String,
+    },
+}
+
+// One step of the path from the top-level value to the place where an error
+// occurred. Segments are pushed onto Error::path as the error propagates
+// outward, so the path is stored innermost first.
+#[derive(Debug)]
+pub(crate) enum Segment {
+    Field(String),
+    Index(usize),
+    Key(String),
+}
+
+// Where in the Starlark source an error occurred: the offending token of a
+// syntax error, or the expression that failed to deserialize.
+#[derive(Debug)]
+pub(crate) struct Location {
+    offset: usize,
+    line: usize,
+    column: usize,
+    // The whole line of source containing the error, without its newline.
+    snippet: String,
+}
+
+impl Error {
+    /// Byte offset in the Starlark input of the syntax error or of the
+    /// expression that could not be deserialized.
+    ///
+    /// This is `None` for errors that do not come from reading Starlark, such
+    /// as serialization errors.
+    ///
+    /// ```
+    /// let source = "[\n    1,\n    \"two\",\n]\n";
+    /// let error = serde_starlark::from_str::<Vec<i32>>(source).unwrap_err();
+    /// assert_eq!(error.offset(), Some(13));
+    /// assert_eq!(error.line(), Some(3));
+    /// assert_eq!(error.column(), Some(5));
+    /// assert_eq!(error.snippet(), Some("    \"two\","));
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "invalid type: string \"two\", expected i32 at line 3 column 5",
+    /// );
+    /// ```
+    #[must_use]
+    pub fn offset(&self) -> Option<usize> {
+        self.location.as_ref().map(|location| location.offset)
+    }
+
+    /// One-based line number of the error in the Starlark input.
+    #[must_use]
+    pub fn line(&self) -> Option<usize> {
+        self.location.as_ref().map(|location| location.line)
+    }
+
+    /// One-based column of the error in the Starlark input, counted in
+    /// characters.
+    #[must_use]
+    pub fn column(&self) -> Option<usize> {
+        self.location.as_ref().map(|location| location.column)
+    }
+
+    /// The line of the Starlark input on which the error occurred.
+    #[must_use]
+    pub fn snippet(&self) -> Option<&str> {
+        self.location
+            .as_ref()
+            .map(|location| location.snippet.as_str())
+    }
+
+    // Attach the position of `offset` in `source`, unless the error already
+    // has a more precise one from deeper inside.
+    pub(crate) fn at(mut self, source: &str, offset: usize) -> Self {
+        if self.location.is_none() {
+            let (line, column) = lex::position(source, offset);
+            let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+            let end = source[offset..]
+                .find('\n')
+                .map_or(source.len(), |i| offset + i);
+            self.location = Some(Box::new(Location {
+                offset,
+                line,
+                column,
+                snippet: source[start..end].trim_end_matches('\r').to_owned(),
+            }));
+        }
+        self
+    }
+
+    // Adjust the position of an error in a piece of a larger input that
+    // begins after the given number of lines and bytes.
+    pub(crate) fn after(mut self, lines: usize, bytes: usize) -> Self {
+        if let Some(location) = &mut self.location {
+            location.line += lines;
+            location.offset += bytes;
+        }
+        self
+    }
+
+    pub(crate) fn within(mut self, segment: Segment) -> Self {
+        self.path.push(segment);
+        self
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_kind(formatter)?;
+        if let Some(location) = &self.location {
+            write!(
+                formatter,
+                " at line {} column {}",
+                location.line, location.column,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    fn fmt_kind(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         use self::ErrorKind::*;
+        if !self.path.is_empty() {
+            for (i, segment) in self.path.iter().rev().enumerate() {
+                match segment {
+                    Segment::Field(name) if i == 0 => formatter.write_str(name)?,
+                    Segment::Field(name) => write!(formatter, ".{}", name)?,
+                    Segment::Index(index) => write!(formatter, "[{}]", index)?,
+                    Segment::Key(key) => write!(formatter, "[{}]", key)?,
+                }
+            }
+            formatter.write_str(": ")?;
+        }
         match &self.kind {
             Message(msg) => formatter.write_str(msg),
-            UnsupportedI64(v) => write_unsupported_int(v, formatter),
-            UnsupportedI128(v) => write_unsupported_int(v, formatter),
-            UnsupportedU32(v) => write_unsupported_int(v, formatter),
-            UnsupportedU64(v) => write_unsupported_int(v, formatter),
-            UnsupportedU128(v) => write_unsupported_int(v, formatter),
-            UnsupportedF32(v) => write_unsupported_float(v, formatter),
-            UnsupportedF64(v) => write_unsupported_float(v, formatter),
-            UnsupportedChar(v) => write!(
+            Io(error) => Display::fmt(error, formatter),
+            #[cfg(feature = "yaml")]
+            Yaml(error) => write!(formatter, "invalid YAML: {}", error),
+            #[cfg(feature = "yaml")]
+            YamlTopLevel => formatter.write_str("expected a mapping at the top level of the YAML"),
+            #[cfg(feature = "toml")]
+            Toml(error) => write!(formatter, "invalid TOML: {}", error),
+            #[cfg(feature = "stardoc")]
+            StardocJson(error) => write!(formatter, "invalid Stardoc JSON: {}", error),
+            #[cfg(feature = "stardoc")]
+            StardocExpected(expected) => {
+                write!(formatter, "invalid Stardoc JSON: expected {}", expected)
+            }
+            PrivateName(name) => write!(
                 formatter,
-                "serialization of char is not supported: '{}'",
-                v.escape_debug(),
+                "{:?} is private to the shard that assigns it and cannot be loaded into another",
+                name,
             ),
-            UnsupportedBytes => formatter
-                .write_str("serialization of Starlark byte string literals is not supported yet"),
-            UnsupportedUnit => formatter.write_str(
-                "serialization of () is not supported; use serialize_none to produce `None`",
+            TargetNotFound(name) => write!(formatter, "no target named {:?}", name),
+            NotAList(attr) => write!(
+                formatter,
+                "cannot add to {}: its value is not a list literal",
+                attr,
             ),
-            UnsupportedEnum(name, variant) => {
+            Syntax(msg) => formatter.write_str(msg),
+            MissingPlaceholder(name) => {
+                write!(formatter, "no value for template placeholder {{{}}}", name)
+            }
+            TemplateValues => formatter.write_str("template values must be a map or struct"),
+            SelectConflict(condition) => write!(
+                formatter,
+                "conflicting values in select for condition {}",
+                condition,
+            ),
+            NestedSelect => formatter.write_str("select cannot be used within another select"),
+            ExpectedExpression => {
+                formatter.write_str("expected the input to consist of one Starlark expression")
+            }
+            UnsupportedExpression(text) => {
                 write!(
                     formatter,
-                    "serialization of enum variant is not supported: {}::{}",
-                    name, variant,
+                    "unsupported expression for deserialization: {}",
+                    text
                 )
             }
-            UnsupportedCall => formatter.write_str("unsupported function call argument type"),
+            UnexpectedFunction { expected, found } => {
+                write!(
+                    formatter,
+                    "expected a call to {}, found {}",
+                    expected, found
+                )
+            }
+            UnexpectedNamed(name) => write!(
+                formatter,
+                "unexpected named argument {} in call with positional arguments",
+                name,
+            ),
+            UnexpectedPositional(index) => write!(
+                formatter,
+                "unexpected positional argument at index {}",
+                index,
+            ),
+            InvalidTargetName(name, reason) => {
+                write!(formatter, "invalid target name {:?}: {}", name, reason)
+            }
+            MissingAttribute {
+                rule,
+                target,
+                attribute,
+            } => {
+                write!(formatter, "{} target ", rule)?;
+                if let Some(target) = target {
+                    write!(formatter, "{:?} ", target)?;
+                }
+                write!(formatter, "is missing mandatory attribute `{}`", attribute)
+            }
+            kind => write_serialize_error(kind, formatter),
         }
     }
 }
 
+// The errors that come from the value given to the serializer.
+fn write_serialize_error(kind: &ErrorKind, formatter: &mut fmt::Formatter) -> fmt::Result {
+    use self::ErrorKind::*;
+    match kind {
+        UnsupportedI64(v) => write_unsupported_int(v, formatter),
+        UnsupportedI128(v) => write_unsupported_int(v, formatter),
+        UnsupportedU32(v) => write_unsupported_int(v, formatter),
+        UnsupportedU64(v) => write_unsupported_int(v, formatter),
+        UnsupportedU128(v) => write_unsupported_int(v, formatter),
+        UnsupportedF32(v) => write_unsupported_float(v, formatter),
+        UnsupportedF64(v) => write_unsupported_float(v, formatter),
+        NonFiniteF32(v) => write_non_finite_float(v, formatter),
+        NonFiniteF64(v) => write_non_finite_float(v, formatter),
+        UnsupportedChar(v) => write!(
+            formatter,
+            "serialization of char is not supported: '{}'",
+            v.escape_debug(),
+        ),
+        UnsupportedBytes => formatter
+            .write_str("serialization of Starlark byte string literals is not supported yet"),
+        UnsupportedUnit => formatter.write_str(
+            "serialization of () is not supported; use serialize_none to produce `None`",
+        ),
+        UnsupportedCall => formatter.write_str("unsupported function call argument type"),
+        ExpectedString => formatter.write_str("expected a string or unit enum variant"),
+        InvalidName(name) => write!(
+            formatter,
+            "invalid identifier: {:?} is not a Starlark identifier",
+            name,
+        ),
+        InvalidArgumentName(name) => write!(
+            formatter,
+            "invalid named argument: {:?} is not a valid Starlark parameter name",
+            name,
+        ),
+        LowercaseAssignment(name) => write!(
+            formatter,
+            "assignment to {:?} must be in ALL_CAPS like a constant",
+            name,
+        ),
+        InvalidFunctionName(name) => write!(
+            formatter,
+            "invalid function name: {:?} is not a Starlark identifier",
+            name,
+        ),
+        DuplicateKey(key) => write!(formatter, "duplicate key in dict: {}", key),
+        DuplicateAssignment(name) => write!(formatter, "duplicate assignment to {}", name),
+        UnhashableKey(kind) => write!(
+            formatter,
+            "unsupported dict key: a {} is not hashable in Starlark",
+            kind,
+        ),
+        UnsupportedSpacer => {
+            formatter.write_str("Spacer is only supported as an element of a list")
+        }
+        UnsupportedArgument(kind) => write!(
+            formatter,
+            "{} is only supported directly among the arguments of a function call",
+            kind,
+        ),
+        UnsupportedPositional => formatter.write_str(
+            "Positional is only supported as the value of a named argument of a function call",
+        ),
+        PositionalAfterNamed => formatter
+            .write_str("no positional argument may follow a named argument in a function call"),
+        ArgumentAfterArgs => {
+            formatter.write_str("only **kwargs may follow *args in a function call")
+        }
+        ArgumentAfterKwargs => {
+            formatter.write_str("no argument may follow **kwargs in a function call")
+        }
+        CommentPrefix(comment) => write!(
+            formatter,
+            "comment must not begin with '#', it is inserted automatically: {:?}",
+            comment,
+        ),
+        _ => unreachable!("formatted by Error::fmt_kind"),
+    }
+}
+
 fn write_unsupported_int(int: &dyn Display, formatter: &mut fmt::Formatter) -> fmt::Result {
     write!(
         formatter,
@@ -68,9 +377,25 @@ fn write_unsupported_float(float: &dyn Display, formatter: &mut fmt::Formatter)
     )
 }
 
+fn write_non_finite_float(float: &dyn Display, formatter: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+        formatter,
+        "serialization of non-finite floating point is disabled: {}",
+        float,
+    )
+}
+
 impl Debug for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        Debug::fmt(&self.kind, formatter)
+        Debug::fmt(&self.kind, formatter)?;
+        if let Some(location) = &self.location {
+            write!(
+                formatter,
+                " at line {} column {}",
+                location.line, location.column,
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -78,6 +403,18 @@ impl serde::ser::Error for Error {
     fn custom<T: Display>(message: T) -> Self {
         Error {
             kind: ErrorKind::Message(message.to_string()),
+            path: Vec::new(),
+            location: None,
+        }
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(message: T) -> Self {
+        Error {
+            kind: ErrorKind::Message(message.to_string()),
+            path: Vec::new(),
+            location: None,
         }
     }
 }
@@ -86,10 +423,43 @@ impl serde::ser::StdError for Error {}
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Self {
-        Error { kind }
+        Error {
+            kind,
+            path: Vec::new(),
+            location: None,
+        }
     }
 }
 
+pub(crate) fn io(error: io::Error) -> Error {
+    ErrorKind::Io(error).into()
+}
+
+#[cfg(feature = "yaml")]
+pub(crate) fn yaml(error: serde_norway::Error) -> Error {
+    ErrorKind::Yaml(error).into()
+}
+
+#[cfg(feature = "yaml")]
+pub(crate) fn yaml_top_level() -> Error {
+    ErrorKind::YamlTopLevel.into()
+}
+
+#[cfg(feature = "toml")]
+pub(crate) fn toml(error: toml::de::Error) -> Error {
+    ErrorKind::Toml(Box::new(error)).into()
+}
+
+#[cfg(feature = "stardoc")]
+pub(crate) fn stardoc_json(error: serde_json::Error) -> Error {
+    ErrorKind::StardocJson(error).into()
+}
+
+#[cfg(feature = "stardoc")]
+pub(crate) fn stardoc_expected(expected: String) -> Error {
+    ErrorKind::StardocExpected(expected).into()
+}
+
 pub(crate) fn unsupported_i64(v: i64) -> Error {
     ErrorKind::UnsupportedI64(v).into()
 }
@@ -118,6 +488,14 @@ pub(crate) fn unsupported_f64(v: f64) -> Error {
     ErrorKind::UnsupportedF64(v).into()
 }
 
+pub(crate) fn non_finite_f32(v: f32) -> Error {
+    ErrorKind::NonFiniteF32(v).into()
+}
+
+pub(crate) fn non_finite_f64(v: f64) -> Error {
+    ErrorKind::NonFiniteF64(v).into()
+}
+
 pub(crate) fn unsupported_char(v: char) -> Error {
     ErrorKind::UnsupportedChar(v).into()
 }
@@ -130,10 +508,135 @@ pub(crate) fn unsupported_unit() -> Error {
     ErrorKind::UnsupportedUnit.into()
 }
 
-pub(crate) fn unsupported_enum(name: &'static str, variant: &'static str) -> Error {
-    ErrorKind::UnsupportedEnum(name, variant).into()
-}
-
 pub(crate) fn unsupported_call() -> Error {
     ErrorKind::UnsupportedCall.into()
 }
+
+pub(crate) fn expected_string() -> Error {
+    ErrorKind::ExpectedString.into()
+}
+
+pub(crate) fn invalid_name(name: &str) -> Error {
+    ErrorKind::InvalidName(name.to_owned()).into()
+}
+
+pub(crate) fn invalid_argument_name(name: &str) -> Error {
+    ErrorKind::InvalidArgumentName(name.to_owned()).into()
+}
+
+pub(crate) fn lowercase_assignment(name: &str) -> Error {
+    ErrorKind::LowercaseAssignment(name.to_owned()).into()
+}
+
+pub(crate) fn invalid_function_name(name: &str) -> Error {
+    ErrorKind::InvalidFunctionName(name.to_owned()).into()
+}
+
+pub(crate) fn duplicate_key(key: &str) -> Error {
+    ErrorKind::DuplicateKey(key.to_owned()).into()
+}
+
+pub(crate) fn duplicate_assignment(name: &str) -> Error {
+    ErrorKind::DuplicateAssignment(name.to_owned()).into()
+}
+
+pub(crate) fn unhashable_key(kind: &'static str) -> Error {
+    ErrorKind::UnhashableKey(kind).into()
+}
+
+pub(crate) fn unsupported_spacer() -> Error {
+    ErrorKind::UnsupportedSpacer.into()
+}
+
+pub(crate) fn unsupported_argument(kind: &'static str) -> Error {
+    ErrorKind::UnsupportedArgument(kind).into()
+}
+
+pub(crate) fn unsupported_positional() -> Error {
+    ErrorKind::UnsupportedPositional.into()
+}
+
+pub(crate) fn positional_after_named() -> Error {
+    ErrorKind::PositionalAfterNamed.into()
+}
+
+pub(crate) fn argument_after_args() -> Error {
+    ErrorKind::ArgumentAfterArgs.into()
+}
+
+pub(crate) fn argument_after_kwargs() -> Error {
+    ErrorKind::ArgumentAfterKwargs.into()
+}
+
+pub(crate) fn comment_prefix(comment: &str) -> Error {
+    ErrorKind::CommentPrefix(comment.to_owned()).into()
+}
+
+pub(crate) fn private_name(name: &str) -> Error {
+    ErrorKind::PrivateName(name.to_owned()).into()
+}
+
+pub(crate) fn target_not_found(name: &str) -> Error {
+    ErrorKind::TargetNotFound(name.to_owned()).into()
+}
+
+pub(crate) fn not_a_list(attr: &str) -> Error {
+    ErrorKind::NotAList(attr.to_owned()).into()
+}
+
+pub(crate) fn syntax(msg: &'static str, source: &str, offset: usize) -> Error {
+    Error::from(ErrorKind::Syntax(msg)).at(source, offset)
+}
+
+pub(crate) fn missing_placeholder(name: &str) -> Error {
+    ErrorKind::MissingPlaceholder(name.to_owned()).into()
+}
+
+pub(crate) fn template_values() -> Error {
+    ErrorKind::TemplateValues.into()
+}
+
+pub(crate) fn select_conflict(conditions: &[String]) -> Error {
+    ErrorKind::SelectConflict(conditions.join(" or ")).into()
+}
+
+pub(crate) fn nested_select() -> Error {
+    ErrorKind::NestedSelect.into()
+}
+
+pub(crate) fn expected_expression() -> Error {
+    ErrorKind::ExpectedExpression.into()
+}
+
+pub(crate) fn unsupported_expression(text: &str) -> Error {
+    ErrorKind::UnsupportedExpression(text.to_owned()).into()
+}
+
+pub(crate) fn unexpected_function(expected: &'static str, found: &str) -> Error {
+    ErrorKind::UnexpectedFunction {
+        expected,
+        found: found.to_owned(),
+    }
+    .into()
+}
+
+pub(crate) fn unexpected_positional(index: usize) -> Error {
+    ErrorKind::UnexpectedPositional(index).into()
+}
+
+pub(crate) fn unexpected_named(name: &str) -> Error {
+    ErrorKind::UnexpectedNamed(name.to_owned()).into()
+}
+
+pub(crate) fn invalid_target_name(name: &str, reason: &'static str) -> Error {
+    ErrorKind::InvalidTargetName(name.to_owned(), reason).into()
+}
+
+pub(crate) fn missing_attribute(rule: &str, target: Option<&str>, attribute: &str) -> Error {
+    ErrorKind::MissingAttribute {
+        rule: rule.to_owned(),
+        target: target.map(str::to_owned),
+        attribute: attribute.to_owned(),
+    }
+    .into()
+}
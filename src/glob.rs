@@ -0,0 +1,56 @@
+use crate::Glob;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+const FIELDS: &[&str] = &["include", "exclude"];
+
+impl Serialize for Glob {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.exclude.is_empty() {
+            serializer.serialize_newtype_struct("glob", &self.include)
+        } else {
+            let mut glob = serializer.serialize_struct("glob", 2)?;
+            glob.serialize_field("include", &self.include)?;
+            glob.serialize_field("exclude", &self.exclude)?;
+            glob.end()
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Glob {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct GlobVisitor;
+
+        impl<'de> Visitor<'de> for GlobVisitor {
+            type Value = Glob;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a call to glob")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut glob = Glob::default();
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "include" => glob.include = map.next_value()?,
+                        "exclude" => glob.exclude = map.next_value()?,
+                        _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+                    }
+                }
+                Ok(glob)
+            }
+        }
+
+        deserializer.deserialize_struct("glob", FIELDS, GlobVisitor)
+    }
+}
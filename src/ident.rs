@@ -0,0 +1,51 @@
+use crate::Identifier;
+use serde::ser::{Serialize, Serializer};
+
+impl<T> Serialize for Identifier<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("*ident", &self.0)
+    }
+}
+
+// Words that cannot be used as an identifier in Starlark. This includes the
+// Python keywords which Starlark reserves for possible future use.
+const KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+    "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+    "load", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+pub(crate) fn is_keyword(name: &str) -> bool {
+    KEYWORDS.contains(&name)
+}
+
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first == '_' || first.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|ch| ch == '_' || ch.is_ascii_alphanumeric()) && !KEYWORDS.contains(&name)
+}
+
+// A name such as `ctx.attr.srcs`, made of identifiers separated by dots.
+pub(crate) fn is_dotted_identifier(name: &str) -> bool {
+    name.split('.').all(is_identifier)
+}
+
+// A name such as `VERSION` or `RUST_EDITIONS`, with no lowercase letters.
+pub(crate) fn is_uppercase(name: &str) -> bool {
+    !name.bytes().any(|b| b.is_ascii_lowercase())
+}
+
+// The callee of a function call. The load statement is written with the same
+// syntax as a call even though `load` is reserved.
+pub(crate) fn is_function_name(name: &str) -> bool {
+    name == "load" || is_dotted_identifier(name)
+}
@@ -0,0 +1,72 @@
+use crate::{Evaluated, FunctionCall};
+use serde::ser::{Error, Serialize, SerializeTuple, Serializer};
+use starlark::values::dict::DictRef;
+use starlark::values::float::StarlarkFloat;
+use starlark::values::list::ListRef;
+use starlark::values::structs::StructRef;
+use starlark::values::tuple::TupleRef;
+use starlark::values::UnpackValue;
+
+impl<'v> Serialize for Evaluated<'v> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let value = self.0;
+        if value.is_none() {
+            serializer.serialize_none()
+        } else if let Some(b) = value.unpack_bool() {
+            serializer.serialize_bool(b)
+        } else if let Some(int) = value.unpack_i32() {
+            serializer.serialize_i32(int)
+        } else if let Some(string) = value.unpack_str() {
+            serializer.serialize_str(string)
+        } else if let Some(float) = StarlarkFloat::unpack_value_opt(value) {
+            serializer.serialize_f64(float.0)
+        } else if let Some(list) = ListRef::from_value(value) {
+            serializer.collect_seq(list.iter().map(Evaluated))
+        } else if let Some(tuple) = TupleRef::from_value(value) {
+            let mut seq = serializer.serialize_tuple(tuple.len())?;
+            for element in tuple.iter() {
+                seq.serialize_element(&Evaluated(element))?;
+            }
+            seq.end()
+        } else if let Some(dict) = DictRef::from_value(value) {
+            serializer.collect_map(dict.iter().map(|(k, v)| (Evaluated(k), Evaluated(v))))
+        } else if let Some(fields) = StructRef::from_value(value) {
+            let args: Vec<(&str, Evaluated)> = fields
+                .iter()
+                .map(|(name, value)| (name.as_str(), Evaluated(value)))
+                .collect();
+            FunctionCall::new("struct", Args(args)).serialize(serializer)
+        } else if value.get_type() == "int" {
+            // Beyond the range of i32, but the integer_overflow option of the
+            // configuration may still allow it.
+            match value.to_repr().parse::<i128>() {
+                Ok(int) => serializer.serialize_i128(int),
+                Err(_) => Err(S::Error::custom(format_args!(
+                    "integer too large: {}",
+                    value
+                ))),
+            }
+        } else {
+            Err(S::Error::custom(format_args!(
+                "unsupported starlark-rust value of type {}: {}",
+                value.get_type(),
+                value,
+            )))
+        }
+    }
+}
+
+// Named arguments of a `struct(...)` call, in the order of the struct's fields.
+struct Args<'a, 'v>(Vec<(&'a str, Evaluated<'v>)>);
+
+impl<'a, 'v> Serialize for Args<'a, 'v> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
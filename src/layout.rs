@@ -0,0 +1,43 @@
+use crate::{Compact, Expanded, MultilineArray};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+impl<T> Serialize for Compact<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("*compact", &self.0)
+    }
+}
+
+impl<T> Serialize for Expanded<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("*expanded", &self.0)
+    }
+}
+
+impl<A, T> Serialize for MultilineArray<A>
+where
+    for<'a> &'a A: IntoIterator<Item = &'a T>,
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut array = serializer.serialize_seq(Some(crate::MULTILINE))?;
+        for element in &self.0 {
+            array.serialize_element(element)?;
+        }
+        array.end()
+    }
+}
@@ -0,0 +1,496 @@
+//! Tokenizer for Starlark source code.
+//!
+//! A [`Lexer`] splits source code into [`Token`]s, each carrying the byte range
+//! it occupies, following the lexical rules of the [Starlark spec]. That is
+//! enough for lightweight analyses of BUILD files, such as listing the symbols
+//! a file loads or the names of its targets, without depending on an
+//! interpreter.
+//!
+//! [Starlark spec]: https://github.com/bazelbuild/starlark/blob/master/spec.md#lexical-elements
+//!
+//! ```
+//! use serde_starlark::lex::{Lexer, TokenKind};
+//!
+//! let build = r#"
+//! load("@rules_rust//rust:defs.bzl", "rust_library", "rust_test")
+//!
+//! rust_library(name = "syn")
+//! "#;
+//!
+//! let tokens = Lexer::new(build).collect::<Result<Vec<_>, _>>().unwrap();
+//! let mut loaded = Vec::new();
+//! for (i, token) in tokens.iter().enumerate() {
+//!     if token.kind == TokenKind::Identifier && token.text == "load" {
+//!         let args = tokens[i + 1..].iter().take_while(|token| !token.is(")"));
+//!         let strings = args.filter(|token| token.kind == TokenKind::String);
+//!         // The first argument is the file being loaded from.
+//!         for symbol in strings.skip(1) {
+//!             loaded.push(symbol.string_value().unwrap());
+//!         }
+//!     }
+//! }
+//! assert_eq!(loaded, ["rust_library", "rust_test"]);
+//! ```
+
+use crate::error;
+use crate::Error;
+use std::ops::Range;
+
+// Operators and delimiters, longest first so that the first match is the
+// longest.
+const PUNCTUATION: &[&str] = &[
+    "**=", "//=", ">>=", "<<=", "==", "!=", "<=", ">=", "**", "//", "<<", ">>", "->", "+=", "-=",
+    "*=", "/=", "%=", "&=", "|=", "^=", "(", ")", "[", "]", "{", "}", ",", ";", ":", ".", "=", "+",
+    "-", "*", "/", "%", "<", ">", "&", "|", "^", "~",
+];
+
+/// The kind of a [`Token`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A name such as `rust_library` or `True`, including keywords like `def`
+    /// and `load`.
+    Identifier,
+    /// An integer literal, in decimal or with a `0x`, `0o` or `0b` prefix.
+    Int,
+    /// A floating point literal such as `1.5` or `1e-7`.
+    Float,
+    /// A string or bytes literal, possibly raw or triple-quoted. Its text
+    /// includes the prefix and quotes.
+    String,
+    /// An operator or delimiter such as `(`, `,`, `=` or `+=`.
+    Punctuation,
+    /// A comment, from `#` to the end of the line.
+    Comment,
+    /// The end of a logical line. Lines that are blank or hold only a comment,
+    /// and line breaks inside of brackets, do not produce one. Its text is
+    /// empty at the end of the input.
+    Newline,
+    /// The start of a more indented block, such as the body of a `def`. Its
+    /// text is the indentation.
+    Indent,
+    /// The end of an indented block. Its text is empty.
+    Dedent,
+}
+
+/// A token of Starlark source code.
+#[derive(Clone, Debug)]
+pub struct Token<'a> {
+    /// What kind of token this is.
+    pub kind: TokenKind,
+    /// The source text of the token.
+    pub text: &'a str,
+    /// Byte offsets of the token in the source.
+    pub span: Range<usize>,
+}
+
+impl<'a> Token<'a> {
+    /// Whether this is the given operator or delimiter.
+    #[must_use]
+    pub fn is(&self, punctuation: &str) -> bool {
+        self.kind == TokenKind::Punctuation && self.text == punctuation
+    }
+
+    /// The value of a string literal, with its escape sequences decoded.
+    ///
+    /// Returns `None` if this token is not a string literal. Escape sequences
+    /// that Starlark does not define are kept as written, backslash included.
+    ///
+    /// ```
+    /// use serde_starlark::lex::Lexer;
+    ///
+    /// let token = Lexer::new(r"'tab\tseparated'").next().unwrap().unwrap();
+    /// assert_eq!(token.string_value().unwrap(), "tab\tseparated");
+    /// ```
+    #[must_use]
+    pub fn string_value(&self) -> Option<String> {
+        if self.kind == TokenKind::String {
+            Some(unquote(self.text))
+        } else {
+            None
+        }
+    }
+}
+
+/// Iterator over the tokens of Starlark source code.
+///
+/// As in Python, a [`Newline`][TokenKind::Newline] token ends each logical
+/// line, and changes in indentation at the start of a logical line produce
+/// [`Indent`][TokenKind::Indent] and [`Dedent`][TokenKind::Dedent] tokens. At
+/// the end of the input every indented block is closed.
+///
+/// The iterator ends after the first error, which is a [syntax error] such
+/// as an unterminated string literal or an unclosed bracket.
+///
+/// [syntax error]: crate::Error
+pub struct Lexer<'a> {
+    source: &'a str,
+    offset: usize,
+    depth: usize,
+    indents: Vec<usize>,
+    pending_dedents: usize,
+    line_start: bool,
+    line_has_tokens: bool,
+    done: bool,
+}
+
+impl<'a> Lexer<'a> {
+    /// Tokenize the given source code.
+    #[must_use]
+    pub fn new(source: &'a str) -> Self {
+        Lexer {
+            source,
+            offset: 0,
+            depth: 0,
+            indents: vec![0],
+            pending_dedents: 0,
+            line_start: true,
+            line_has_tokens: false,
+            done: false,
+        }
+    }
+
+    fn token(&mut self, kind: TokenKind, start: usize) -> Token<'a> {
+        match kind {
+            TokenKind::Comment | TokenKind::Newline | TokenKind::Indent | TokenKind::Dedent => {}
+            _ => self.line_has_tokens = true,
+        }
+        Token {
+            kind,
+            text: &self.source[start..self.offset],
+            span: start..self.offset,
+        }
+    }
+
+    fn error(&self, msg: &'static str, offset: usize) -> Error {
+        error::syntax(msg, self.source, offset)
+    }
+
+    // Indentation of the line beginning at the current offset, if the line has
+    // anything other than whitespace and comments on it.
+    fn measure_indent(&mut self) -> Option<usize> {
+        let rest = &self.source[self.offset..];
+        let width = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        match rest[width..].chars().next() {
+            None | Some('\n' | '#') => None,
+            Some('\r') if rest[width..].starts_with("\r\n") => None,
+            Some(_) => Some(width),
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, Error> {
+        if self.pending_dedents > 0 {
+            self.pending_dedents -= 1;
+            return Ok(Some(self.token(TokenKind::Dedent, self.offset)));
+        }
+
+        loop {
+            if self.line_start && self.depth == 0 {
+                self.line_start = false;
+                if let Some(token) = self.indentation()? {
+                    return Ok(Some(token));
+                }
+            }
+            let rest = &self.source[self.offset..];
+            let ch = match rest.chars().next() {
+                Some(ch) => ch,
+                None if self.depth > 0 => {
+                    return Err(self.error("unclosed bracket", self.offset));
+                }
+                None => return Ok(self.finish()),
+            };
+            let start = self.offset;
+            match ch {
+                ' ' | '\t' | '\r' => self.offset += 1,
+                '\\' if rest[1..].starts_with('\n') => self.offset += 2,
+                '\\' if rest[1..].starts_with("\r\n") => self.offset += 3,
+                '\n' => {
+                    self.offset += 1;
+                    if self.depth > 0 {
+                        continue;
+                    }
+                    self.line_start = true;
+                    if self.line_has_tokens {
+                        self.line_has_tokens = false;
+                        return Ok(Some(self.token(TokenKind::Newline, start)));
+                    }
+                }
+                '#' => {
+                    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+                    self.offset += line.trim_end_matches('\r').len();
+                    return Ok(Some(self.token(TokenKind::Comment, start)));
+                }
+                '"' | '\'' => {
+                    self.offset = self.skip_string(start, start)?;
+                    return Ok(Some(self.token(TokenKind::String, start)));
+                }
+                '0'..='9' => return self.number(start).map(Some),
+                '.' if rest[1..].starts_with(|ch: char| ch.is_ascii_digit()) => {
+                    return self.number(start).map(Some);
+                }
+                _ if ch == '_' || ch.is_alphabetic() => {
+                    let len = rest
+                        .find(|ch: char| !(ch == '_' || ch.is_alphanumeric()))
+                        .unwrap_or(rest.len());
+                    self.offset += len;
+                    let prefix = matches!(&rest[..len], "r" | "b" | "rb" | "br" | "R" | "B");
+                    if prefix && self.source[self.offset..].starts_with(['"', '\'']) {
+                        self.offset = self.skip_string(start, self.offset)?;
+                        return Ok(Some(self.token(TokenKind::String, start)));
+                    }
+                    return Ok(Some(self.token(TokenKind::Identifier, start)));
+                }
+                _ => return self.punctuation(start).map(Some),
+            }
+        }
+    }
+
+    // At the start of a line outside of brackets: compare the line's
+    // indentation against the enclosing blocks.
+    fn indentation(&mut self) -> Result<Option<Token<'a>>, Error> {
+        let width = match self.measure_indent() {
+            Some(width) => width,
+            None => return Ok(None),
+        };
+        let start = self.offset;
+        self.offset += width;
+        if width > *self.indents.last().unwrap() {
+            self.indents.push(width);
+            return Ok(Some(self.token(TokenKind::Indent, start)));
+        }
+        while width < *self.indents.last().unwrap() {
+            self.indents.pop();
+            self.pending_dedents += 1;
+        }
+        if width != *self.indents.last().unwrap() {
+            return Err(self.error("unindent does not match any outer indentation level", start));
+        }
+        if self.pending_dedents > 0 {
+            self.pending_dedents -= 1;
+            return Ok(Some(self.token(TokenKind::Dedent, self.offset)));
+        }
+        Ok(None)
+    }
+
+    fn punctuation(&mut self, start: usize) -> Result<Token<'a>, Error> {
+        let rest = &self.source[start..];
+        let punctuation = match PUNCTUATION.iter().find(|p| rest.starts_with(**p)) {
+            Some(punctuation) => *punctuation,
+            None => return Err(self.error("unexpected character", start)),
+        };
+        match punctuation {
+            "(" | "[" | "{" => self.depth += 1,
+            ")" | "]" | "}" => {
+                if self.depth == 0 {
+                    return Err(self.error("unmatched closing bracket", start));
+                }
+                self.depth -= 1;
+            }
+            _ => {}
+        }
+        self.offset += punctuation.len();
+        Ok(self.token(TokenKind::Punctuation, start))
+    }
+
+    // End of input: close the last logical line and every indented block.
+    fn finish(&mut self) -> Option<Token<'a>> {
+        if self.line_has_tokens {
+            self.line_has_tokens = false;
+            return Some(self.token(TokenKind::Newline, self.offset));
+        }
+        if self.indents.len() > 1 {
+            self.indents.pop();
+            return Some(self.token(TokenKind::Dedent, self.offset));
+        }
+        None
+    }
+
+    // Offset just past the string literal whose opening quote is at `quote`.
+    fn skip_string(&self, start: usize, quote: usize) -> Result<usize, Error> {
+        let bytes = self.source.as_bytes();
+        let delimiter = bytes[quote];
+        let triple = bytes[quote..].starts_with(&[delimiter; 3]);
+        let mut i = quote + if triple { 3 } else { 1 };
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'\n' if !triple => break,
+                ch if ch == delimiter => {
+                    if !triple {
+                        return Ok(i + 1);
+                    }
+                    if bytes[i..].starts_with(&[delimiter; 3]) {
+                        return Ok(i + 3);
+                    }
+                    i += 1;
+                }
+                _ => i += 1,
+            }
+        }
+        Err(self.error("unterminated string literal", start))
+    }
+
+    fn number(&mut self, start: usize) -> Result<Token<'a>, Error> {
+        let rest = &self.source[start..];
+        let radix = rest.len() > 2
+            && rest.starts_with('0')
+            && matches!(rest.as_bytes()[1], b'x' | b'X' | b'o' | b'O' | b'b' | b'B');
+        if radix {
+            let len = 2 + rest[2..]
+                .find(|ch: char| !ch.is_ascii_alphanumeric())
+                .unwrap_or(rest.len() - 2);
+            self.offset = start + len;
+            return Ok(self.token(TokenKind::Int, start));
+        }
+        let bytes = rest.as_bytes();
+        let mut i = 0;
+        let mut float = false;
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        if i < bytes.len() && bytes[i] == b'.' {
+            float = true;
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+        if i < bytes.len() && matches!(bytes[i], b'e' | b'E') {
+            let mut j = i + 1;
+            if j < bytes.len() && matches!(bytes[j], b'+' | b'-') {
+                j += 1;
+            }
+            if j < bytes.len() && bytes[j].is_ascii_digit() {
+                float = true;
+                i = j;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+        }
+        if rest[i..].starts_with(|ch: char| ch == '_' || ch.is_alphanumeric()) {
+            return Err(self.error("invalid number literal", start));
+        }
+        self.offset = start + i;
+        let kind = if float {
+            TokenKind::Float
+        } else {
+            TokenKind::Int
+        };
+        Ok(self.token(kind, start))
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_token().transpose();
+        if matches!(next, None | Some(Err(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+/// The line and column of a byte offset into `source`, for reporting the
+/// location of a token. Both are 1-based, and columns count characters.
+///
+/// ```
+/// use serde_starlark::lex::{self, Lexer};
+///
+/// let source = "x = 1\ny = \"é\" + z\n";
+/// let z = Lexer::new(source).find(|token| token.as_ref().unwrap().text == "z");
+/// let z = z.unwrap().unwrap();
+/// assert_eq!(lex::position(source, z.span.start), (2, 11));
+/// ```
+#[must_use]
+pub fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+// The value of a string literal token. Escape sequences that Starlark does
+// not define are kept as written, backslash included.
+pub(crate) fn unquote(literal: &str) -> String {
+    let prefix = literal.find(['"', '\'']).unwrap_or(0);
+    let raw = literal[..prefix].contains(['r', 'R']);
+    let body = &literal[prefix..];
+    let quotes = if body.len() >= 6 && (body.starts_with("\"\"\"") || body.starts_with("'''")) {
+        3
+    } else {
+        1
+    };
+    let body = &body[quotes..body.len() - quotes];
+    if raw {
+        return body.to_owned();
+    }
+    let mut value = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+        let escape = if let Some(escape) = chars.next() {
+            escape
+        } else {
+            value.push('\\');
+            break;
+        };
+        match escape {
+            '\n' => {}
+            'a' => value.push('\x07'),
+            'b' => value.push('\x08'),
+            'f' => value.push('\x0C'),
+            'n' => value.push('\n'),
+            'r' => value.push('\r'),
+            't' => value.push('\t'),
+            'v' => value.push('\x0B'),
+            '\\' | '\'' | '"' => value.push(escape),
+            '0'..='7' => {
+                let mut code = escape.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|ch| ch.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                value.extend(char::from_u32(code));
+            }
+            'x' | 'u' | 'U' => {
+                let digits = match escape {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let hex: String = chars.clone().take(digits).collect();
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(ch) if hex.len() == digits => {
+                        value.push(ch);
+                        for _ in 0..digits {
+                            chars.next();
+                        }
+                    }
+                    _ => {
+                        value.push('\\');
+                        value.push(escape);
+                    }
+                }
+            }
+            _ => {
+                value.push('\\');
+                value.push(escape);
+            }
+        }
+    }
+    value
+}
@@ -142,8 +142,10 @@
 //! omit fields with value `None` from the serialized output.
 
 #![doc(html_root_url = "https://docs.rs/serde_starlark/0.1.16")]
+#![cfg_attr(docsrs, feature(doc_cfg))]
 #![allow(
     clippy::doc_markdown,
+    clippy::elidable_lifetime_names,
     clippy::enum_glob_use,
     clippy::missing_errors_doc,
     clippy::missing_panics_doc,
@@ -157,18 +159,63 @@
 mod assignment;
 mod call;
 mod comment;
+mod config;
+mod constants;
+mod cst;
+mod de;
+mod dict;
+mod display;
+mod document;
+pub mod edit;
 mod error;
+mod glob;
+mod ident;
+#[cfg(feature = "starlark-interop")]
+mod interop;
+mod key;
+mod layout;
+pub mod lex;
+mod load;
+mod order;
+mod parse;
+mod patch;
+mod path;
+mod radix;
+mod raw;
+mod reformat;
+#[cfg(feature = "bazel-rules")]
+#[cfg_attr(docsrs, doc(cfg(feature = "bazel-rules")))]
+pub mod rules;
+pub mod schema;
+mod select;
 mod ser;
+pub mod ser_helpers;
+mod shard;
+mod spacer;
+mod stream;
+mod template;
+#[cfg(feature = "toml")]
+mod toml;
+mod triple;
+mod wrapper;
+#[cfg(feature = "yaml")]
+mod yaml;
 
+use crate::config::DEFAULT;
+use crate::document::Separators;
 use crate::ser::{WriteMap, WriteSeq, WriteStarlark, WriteStruct, WriteTuple, WriteTupleStruct};
-use serde::ser::{Impossible, Serialize};
-
-/// For "deserialization", consider using <https://github.com/facebookexperimental/starlark-rust>.
-#[cfg(doc)]
-pub mod de {}
+use serde::de::{Deserialize, DeserializeOwned};
+use serde::ser::Serialize;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io;
 
 pub struct Error {
     kind: crate::error::ErrorKind,
+    path: Vec<crate::error::Segment>,
+    location: Option<Box<crate::error::Location>>,
 }
 
 pub fn to_string<T>(value: &T) -> Result<String, Error>
@@ -178,6 +225,252 @@ where
     value.serialize(Serializer)
 }
 
+/// Deserialize an instance of type `T` from a Starlark expression, such as
+/// the output of [`to_string`].
+///
+/// Comment lines around the expression are ignored. Lists and tuples
+/// deserialize as sequences, dicts as maps, and a `select({…})` or
+/// `selects.with_or({…})` as the map of its branches, so that a map field
+/// round-trips through [`Select`] or `#[serde(rename = "select")]` newtype
+/// struct. Integers are limited to 32 bits, as when serializing, unless
+/// deserializing with [`Config::from_str`] and [`IntegerOverflow::Emit`].
+///
+/// Function calls deserialize the way structs are serialized: a call of
+/// `rust_library` into a struct renamed to `rust_library`, with positional
+/// arguments into a tuple struct, and into an enum whose variant is named
+/// after the function. Deserializing a sequence from a file of several
+/// statements, such as a BUILD file, produces one element per statement.
+///
+/// ```
+/// use serde_derive::Deserialize;
+/// use std::collections::BTreeMap;
+///
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// #[serde(rename = "select")]
+/// struct Select(BTreeMap<String, Vec<String>>);
+///
+/// let starlark = r#"select({
+///     "@platforms//os:linux": [":libc"],
+///     "//conditions:default": [],
+/// })
+/// "#;
+/// let select: Select = serde_starlark::from_str(starlark).unwrap();
+/// let expected = BTreeMap::from([
+///     ("@platforms//os:linux".to_owned(), vec![":libc".to_owned()]),
+///     ("//conditions:default".to_owned(), vec![]),
+/// ]);
+/// assert_eq!(select, Select(expected.clone()));
+///
+/// let branches: BTreeMap<String, Vec<String>> = serde_starlark::from_str(starlark).unwrap();
+/// assert_eq!(branches, expected);
+/// ```
+pub fn from_str<'de, T>(starlark: &'de str) -> Result<T, Error>
+where
+    T: Deserialize<'de>,
+{
+    de::from_str(&DEFAULT, starlark)
+}
+
+/// Deserialize like [`from_str`], and also return the comments of the source
+/// along with the places they are attached to.
+///
+/// A tool that reads a hand-edited file, modifies the data, and serializes it
+/// again can use the [`Comment`]s to put the comments back, for example with
+/// [`LineComment`] and [`BlockComment`].
+///
+/// ```
+/// use serde_derive::Deserialize;
+/// use serde_starlark::{Comment, CommentPlacement};
+///
+/// #[derive(Deserialize)]
+/// #[serde(rename = "rust_library")]
+/// struct RustLibrary {
+///     name: String,
+///     deps: Vec<String>,
+/// }
+///
+/// let starlark = r#"rust_library(
+///     name = "syn",
+///     deps = [
+///         ## Only on unix.
+///         ":libc",
+///     ],
+/// )  # vendored
+/// "#;
+/// let (_rust_library, comments) =
+///     serde_starlark::from_str_with_comments::<RustLibrary>(starlark).unwrap();
+/// assert_eq!(
+///     comments,
+///     [
+///         Comment {
+///             text: "# Only on unix.",
+///             line: 4,
+///             statement: Some(0),
+///             path: "deps[0]".to_owned(),
+///             placement: CommentPlacement::Above,
+///         },
+///         Comment {
+///             text: "# vendored",
+///             line: 7,
+///             statement: Some(0),
+///             path: String::new(),
+///             placement: CommentPlacement::Suffix,
+///         },
+///     ],
+/// );
+/// ```
+pub fn from_str_with_comments<'de, T>(starlark: &'de str) -> Result<(T, Vec<Comment<'de>>), Error>
+where
+    T: Deserialize<'de>,
+{
+    de::from_str_with_comments(starlark)
+}
+
+/// Deserialize the top-level statements of Starlark source one at a time as
+/// they are read from an IO stream.
+///
+/// Each statement deserializes into a `T`, as described for [`from_str`]. Only
+/// as much of the input as is needed for the next statement is read and held
+/// in memory, so a very large generated file can be processed statement by
+/// statement. After an error from the reader or a syntax error, the iterator
+/// ends.
+///
+/// ```
+/// use serde_derive::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// #[serde(rename = "alias")]
+/// struct Alias {
+///     name: String,
+///     actual: String,
+/// }
+///
+/// let build = br#"alias(
+///     name = "syn",
+///     actual = "//third-party:syn-2.0.48",
+/// )
+///
+/// alias(name = "quote", actual = "//third-party:quote-1.0.35")
+/// "#;
+/// let mut names = Vec::new();
+/// for alias in serde_starlark::from_reader::<_, Alias>(&build[..]) {
+///     names.push(alias.unwrap().name);
+/// }
+/// assert_eq!(names, ["syn", "quote"]);
+/// ```
+pub fn from_reader<R, T>(reader: R) -> StreamDeserializer<R, T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    StreamDeserializer::new(reader)
+}
+
+/// Serialize a [`Document`], rendering its statements in parallel.
+///
+/// Equivalent to [`Config::to_string_parallel`] with the default
+/// configuration.
+#[cfg(feature = "rayon")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+pub fn to_string_parallel<T, S>(document: &Document<T>) -> Result<String, Error>
+where
+    for<'b> &'b T: IntoIterator<Item = &'b S>,
+    S: Serialize + Sync,
+{
+    DEFAULT.to_string_parallel(document)
+}
+
+/// Update previously generated Starlark with new versions of some of its
+/// targets.
+///
+/// Equivalent to [`Config::patch`] with the default configuration.
+pub fn patch<I>(previous: &str, statements: I) -> Result<String, Error>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    DEFAULT.patch(previous, statements)
+}
+
+/// Reformat Starlark source code in the style of this crate's output.
+///
+/// Equivalent to [`Config::fmt`] with the default configuration.
+pub fn fmt(source: &str) -> Result<String, Error> {
+    DEFAULT.fmt(source)
+}
+
+/// Fill in the placeholders of a template with serialized values.
+///
+/// Equivalent to [`Config::render_template`] with the default configuration.
+pub fn render_template<T>(template: &str, values: &T) -> Result<String, Error>
+where
+    T: ?Sized + Serialize,
+{
+    DEFAULT.render_template(template, values)
+}
+
+/// Split a [`Document`] into several files.
+///
+/// Equivalent to [`Config::to_shards`] with the default configuration.
+pub fn to_shards<T, S>(document: &Document<T>, sharding: &Sharding) -> Result<Vec<Shard>, Error>
+where
+    for<'b> &'b T: IntoIterator<Item = &'b S>,
+    S: Serialize,
+{
+    DEFAULT.to_shards(document, sharding)
+}
+
+/// Convert a YAML document into a file of Starlark constants.
+///
+/// Equivalent to [`Config::yaml_to_string`] with the default configuration.
+#[cfg(feature = "yaml")]
+#[cfg_attr(docsrs, doc(cfg(feature = "yaml")))]
+pub fn yaml_to_string(yaml: &str) -> Result<String, Error> {
+    DEFAULT.yaml_to_string(yaml)
+}
+
+/// Convert a TOML document into a file of Starlark constants.
+///
+/// Equivalent to [`Config::toml_to_string`] with the default configuration.
+#[cfg(feature = "toml")]
+#[cfg_attr(docsrs, doc(cfg(feature = "toml")))]
+pub fn toml_to_string(toml: &str) -> Result<String, Error> {
+    DEFAULT.toml_to_string(toml)
+}
+
+/// Adapter whose `Display` impl serializes the given value as Starlark.
+///
+/// Equivalent to [`Config::display`] with the default configuration.
+///
+/// ```
+/// use serde_starlark::FunctionCall;
+///
+/// let load = FunctionCall::new("load", ["@rules_rust//rust:defs.bzl", "rust_library"]);
+/// let build = format!("{}", serde_starlark::display(&load));
+/// assert_eq!(build, serde_starlark::to_string(&load).unwrap());
+/// ```
+#[must_use]
+pub fn display<T>(value: &T) -> impl Display + '_
+where
+    T: ?Sized + Serialize,
+{
+    DEFAULT.display(value)
+}
+
+/// Serialize the given value as Starlark into an IO stream.
+///
+/// Only the statements of a top-level [`Document`] are streamed one at a
+/// time; see [`Config::to_writer`].
+///
+/// Equivalent to [`Config::to_writer`] with the default configuration.
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<(), Error>
+where
+    W: io::Write,
+    T: ?Sized + Serialize,
+{
+    DEFAULT.to_writer(writer, value)
+}
+
 /// Format a function call, array, or map with all values on one line.
 ///
 /// # Defaults
@@ -335,6 +628,107 @@ pub const ONELINE: usize = usize::MIN;
 /// ```
 pub const MULTILINE: usize = usize::MAX;
 
+/// Format a value on one line, including every function call, array, tuple,
+/// and map nested inside of it.
+///
+/// Whereas [`ONELINE`] affects only the one container whose Serialize impl
+/// passes it, `Compact` applies recursively to everything within the wrapped
+/// value, regardless of the length hints passed by the Serialize impls of the
+/// nested values.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::Compact;
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "crate")]
+/// struct Crate {
+///     name: &'static str,
+///     features: Vec<&'static str>,
+/// }
+///
+/// fn main() {
+///     let krate = Compact(Crate {
+///         name: "serde",
+///         features: vec!["derive", "std"],
+///     });
+///
+///     print!("{}", serde_starlark::to_string(&krate).unwrap());
+/// #
+/// #   assert_eq!(
+/// #       serde_starlark::to_string(&krate).unwrap(),
+/// #       "crate(name = \"serde\", features = [\"derive\", \"std\"])\n",
+/// #   );
+/// }
+/// ```
+///
+/// ```bzl
+/// crate(name = "serde", features = ["derive", "std"])
+/// ```
+pub struct Compact<T>(pub T);
+
+/// Format a value with every element on its own line, including every function
+/// call, array, tuple, and map nested inside of it.
+///
+/// This is the recursive counterpart of [`MULTILINE`]. A common use is a
+/// `select` in which every branch should list one dependency per line, even
+/// the branches that contain only one.
+///
+/// ```
+/// use serde_starlark::{Expanded, FunctionCall};
+/// use std::collections::BTreeMap;
+///
+/// let deps = BTreeMap::from([
+///     ("@platforms//os:linux", vec![":libc"]),
+///     ("//conditions:default", vec![]),
+/// ]);
+///
+/// let select = FunctionCall::new("select", (Expanded(deps),));
+/// print!("{}", serde_starlark::to_string(&select).unwrap());
+/// #
+/// # assert_eq!(
+/// #     serde_starlark::to_string(&select).unwrap(),
+/// #     concat!(
+/// #         "select({\n",
+/// #         "    \"//conditions:default\": [],\n",
+/// #         "    \"@platforms//os:linux\": [\n",
+/// #         "        \":libc\",\n",
+/// #         "    ],\n",
+/// #         "})\n",
+/// #     ),
+/// # );
+/// ```
+///
+/// ```bzl
+/// select({
+///     "//conditions:default": [],
+///     "@platforms//os:linux": [
+///         ":libc",
+///     ],
+/// })
+/// ```
+pub struct Expanded<T>(pub T);
+
+/// Serialize a collection as a list with each element on its own line, even if
+/// there is just one.
+///
+/// This is the [`MULTILINE`] array from that constant's documentation, for any
+/// collection that can be iterated by reference, such as a `Vec` or
+/// `BTreeSet`. Unlike [`Expanded`], it does not affect the layout of the
+/// elements themselves.
+///
+/// ```
+/// use serde_starlark::MultilineArray;
+///
+/// let deps = MultilineArray(vec![("//third-party/rust:libc", 1)]);
+/// let expected = r#"[
+///     ("//third-party/rust:libc", 1),
+/// ]
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+/// ```
+pub struct MultilineArray<A>(pub A);
+
 /// Serialize a value as an assignment to an identifier.
 ///
 /// # Example
@@ -385,9 +779,101 @@ pub struct Assignment<'identifier, T> {
 }
 
 impl<'identifier, T> Assignment<'identifier, T> {
+    /// The identifier is validated when the assignment is serialized.
     pub fn new(identifier: &'identifier str, value: T) -> Self {
         Assignment { identifier, value }
     }
+
+    /// Like [`Assignment::new`], but rejects an identifier that is not a valid
+    /// Starlark identifier up front.
+    ///
+    /// ```
+    /// use serde_starlark::Assignment;
+    ///
+    /// assert!(Assignment::try_new("VERSION", "1.0.0").is_ok());
+    ///
+    /// let error = Assignment::try_new("my var", "1.0.0").err().unwrap();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "invalid identifier: \"my var\" is not a Starlark identifier",
+    /// );
+    /// ```
+    pub fn try_new(identifier: &'identifier str, value: T) -> Result<Self, Error> {
+        if !ident::is_identifier(identifier) {
+            return Err(error::invalid_name(identifier));
+        }
+        Ok(Assignment { identifier, value })
+    }
+}
+
+/// A block of top-level assignments, one per identifier, in insertion order.
+///
+/// This is the usual shape of generated metadata files such as `crates.bzl`.
+/// Assignments whose value spans more than one line are set apart from their
+/// neighbors by a blank line.
+///
+/// ```
+/// use serde_starlark::Constants;
+///
+/// # fn main() -> Result<(), serde_starlark::Error> {
+/// let mut constants = Constants::new();
+/// constants.insert("NAME", vec!["serde"])?;
+/// constants.insert("VERSION", vec!["1.0.188"])?;
+/// constants.insert("FEATURES", vec!["derive", "std"])?;
+/// constants.insert("EDITION", vec!["2018"])?;
+/// print!("{}", serde_starlark::to_string(&constants)?);
+/// #
+/// # assert_eq!(
+/// #     serde_starlark::to_string(&constants)?,
+/// #     concat!(
+/// #         "NAME = [\"serde\"]\n",
+/// #         "VERSION = [\"1.0.188\"]\n",
+/// #         "\n",
+/// #         "FEATURES = [\n",
+/// #         "    \"derive\",\n",
+/// #         "    \"std\",\n",
+/// #         "]\n",
+/// #         "\n",
+/// #         "EDITION = [\"2018\"]\n",
+/// #     ),
+/// # );
+/// #
+/// # let error = constants.insert("NAME", Vec::new()).err().unwrap();
+/// # assert_eq!(error.to_string(), "duplicate assignment to NAME");
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Produces:
+///
+/// ```bzl
+/// NAME = ["serde"]
+/// VERSION = ["1.0.188"]
+///
+/// FEATURES = [
+///     "derive",
+///     "std",
+/// ]
+///
+/// EDITION = ["2018"]
+/// ```
+pub struct Constants<T> {
+    assignments: Vec<(String, T)>,
+}
+
+impl<T> Constants<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Constants {
+            assignments: Vec::new(),
+        }
+    }
+}
+
+impl<T> Default for Constants<T> {
+    fn default() -> Self {
+        Constants::new()
+    }
 }
 
 /// Serialize a map as a function call.
@@ -464,19 +950,300 @@ impl<'identifier, T> Assignment<'identifier, T> {
 ///     }
 /// }
 /// ```
+///
+/// Equivalently, the enum can derive `Serialize`. Serde_starlark serializes a
+/// newtype variant in the same way as a `FunctionCall` whose function name is
+/// the name of the variant.
+///
+/// ```
+/// # use serde_derive::Serialize;
+/// #
+/// # #[derive(Serialize)]
+/// # pub struct RustLibrary {}
+/// # #[derive(Serialize)]
+/// # pub struct RustBinary {}
+/// #
+/// #[derive(Serialize)]
+/// #[serde(rename_all = "snake_case")]
+/// pub enum Rule {
+///     RustLibrary(RustLibrary),
+///     RustBinary(RustBinary),
+/// }
+/// ```
+///
+/// The function name may be borrowed or owned. A name computed at runtime can
+/// be passed as a `String`, giving a `FunctionCall<'static, A>` that is free
+/// to be returned from the function that built it.
+///
+/// ```
+/// use serde_starlark::FunctionCall;
+///
+/// fn rule<A>(language: &str, kind: &str, args: A) -> FunctionCall<'static, A> {
+///     FunctionCall::new(format!("{}_{}", language, kind), args)
+/// }
+/// #
+/// # let call = rule("rust", "library", ("syn",));
+/// # assert_eq!(serde_starlark::to_string(&call).unwrap(), "rust_library(\"syn\")\n");
+/// ```
 pub struct FunctionCall<'name, A> {
-    function: &'name str,
+    function: Cow<'name, str>,
+    dynamic: bool,
     args: A,
 }
 
 impl<'name, A> FunctionCall<'name, A> {
-    pub fn new(function: &'name str, args: A) -> Self {
-        FunctionCall { function, args }
+    pub fn new(function: impl Into<Cow<'name, str>>, args: A) -> Self {
+        FunctionCall {
+            function: function.into(),
+            dynamic: false,
+            args,
+        }
+    }
+
+    /// Call the function whose name is the value of a field of `args`, for
+    /// generators that carry the kind of rule as data rather than as a
+    /// distinct Rust type per rule.
+    ///
+    /// The field must be the first one of the struct or map, and is not
+    /// written as an argument.
+    ///
+    /// ```
+    /// use serde_derive::Serialize;
+    /// use serde_starlark::FunctionCall;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Target {
+    ///     kind: String,
+    ///     name: String,
+    /// }
+    ///
+    /// let target = Target {
+    ///     kind: "rust_library".to_owned(),
+    ///     name: "syn".to_owned(),
+    /// };
+    /// let call = FunctionCall::dynamic("kind", &target);
+    /// let expected = r#"rust_library(
+    ///     name = "syn",
+    /// )
+    /// "#;
+    /// assert_eq!(serde_starlark::to_string(&call).unwrap(), expected);
+    /// ```
+    pub fn dynamic(field: &'name str, args: A) -> Self {
+        FunctionCall {
+            function: Cow::Borrowed(field),
+            dynamic: true,
+            args,
+        }
+    }
+}
+
+/// Arguments of a [`FunctionCall`] mixing positional and named arguments.
+///
+/// Positional arguments are written first, in the order they were added,
+/// followed by the named arguments in the order they were added, regardless of
+/// how calls to `pos` and `named` were interleaved, then [`Args`] and
+/// [`Kwargs`]. The call is laid out like any other; wrap it in [`Compact`] to
+/// keep it on one line.
+///
+/// Arguments of different types can be collected as `&dyn
+/// erased_serde::Serialize`.
+///
+/// ```
+/// use serde_starlark::{Arguments, FunctionCall, Identifier};
+///
+/// let args = Arguments::new()
+///     .pos(Identifier("inputs"))
+///     .named("executable", Identifier("exe"))
+///     .pos(Identifier("outputs"));
+/// let call = FunctionCall::new("ctx.actions.run", args);
+/// let expected = r#"ctx.actions.run(
+///     inputs,
+///     outputs,
+///     executable = exe,
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&call).unwrap(), expected);
+/// ```
+pub struct Arguments<'a, T> {
+    positional: Vec<T>,
+    named: Vec<(&'a str, T)>,
+    args: Option<T>,
+    kwargs: Option<T>,
+}
+
+impl<'a, T> Arguments<'a, T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Arguments {
+            positional: Vec::new(),
+            named: Vec::new(),
+            args: None,
+            kwargs: None,
+        }
+    }
+
+    /// Add a positional argument.
+    #[must_use]
+    pub fn pos(mut self, value: T) -> Self {
+        self.positional.push(value);
+        self
+    }
+
+    /// Add a named argument, written as `name = value`.
+    #[must_use]
+    pub fn named(mut self, name: &'a str, value: T) -> Self {
+        self.named.push((name, value));
+        self
+    }
+
+    /// Unpack a list into the positional arguments, written as [`Args`] after
+    /// the named arguments.
+    #[must_use]
+    pub fn args(mut self, value: T) -> Self {
+        self.args = Some(value);
+        self
+    }
+
+    /// Unpack a dict into the named arguments, written as [`Kwargs`] after
+    /// all other arguments.
+    #[must_use]
+    pub fn kwargs(mut self, value: T) -> Self {
+        self.kwargs = Some(value);
+        self
+    }
+}
+
+impl<'a, T> Default for Arguments<'a, T> {
+    fn default() -> Self {
+        Arguments::new()
+    }
+}
+
+/// Serialize a named argument, `name = value`, among the positional arguments
+/// of a function call.
+///
+/// This makes it possible to pass a named argument to a call that is
+/// serialized as a tuple struct, or from a sequence of arguments. Like any
+/// named argument it must come after all positional arguments.
+///
+/// ```
+/// use serde::ser::{Serialize, SerializeTupleStruct, Serializer};
+/// use serde_starlark::NamedArg;
+///
+/// struct Glob(Vec<&'static str>);
+///
+/// impl Serialize for Glob {
+///     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+///     where
+///         S: Serializer,
+///     {
+///         let mut call = serializer.serialize_tuple_struct("glob", 2)?;
+///         call.serialize_field(&self.0)?;
+///         call.serialize_field(&NamedArg::new("allow_empty", true))?;
+///         call.end()
+///     }
+/// }
+///
+/// let glob = Glob(vec!["src/**/*.rs"]);
+/// let expected = r#"glob(
+///     ["src/**/*.rs"],
+///     allow_empty = True,
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&glob).unwrap(), expected);
+/// ```
+pub struct NamedArg<'name, T> {
+    name: &'name str,
+    value: T,
+}
+
+impl<'name, T> NamedArg<'name, T> {
+    pub fn new(name: &'name str, value: T) -> Self {
+        NamedArg { name, value }
     }
 }
 
+/// Serialize `*args`, unpacking a list into the positional arguments of a
+/// function call.
+///
+/// This is only supported as an argument of a function call. It must come
+/// after all positional and named arguments, and only a [`Kwargs`] may follow
+/// it.
+///
+/// ```
+/// use serde_starlark::{Args, Compact, FunctionCall, Identifier};
+///
+/// let call = Compact(FunctionCall::new("glob", ("*.rs", Args(Identifier("EXTRA_SRCS")))));
+/// let expected = r#"glob("*.rs", *EXTRA_SRCS)
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&call).unwrap(), expected);
+/// ```
+pub struct Args<T>(pub T);
+
+/// Serialize `**kwargs`, unpacking a dict into the named arguments of a
+/// function call.
+///
+/// This is only supported as an argument of a function call, and it must be
+/// the last one. Within a struct serialized as a call, the field holding it
+/// needs to be named with an empty string or be the value of a map entry whose
+/// key is empty, because named fields are written as `name = value`.
+///
+/// ```
+/// use serde_starlark::{FunctionCall, Identifier, Kwargs};
+///
+/// let call = FunctionCall::new("rust_library", (Kwargs(Identifier("CONFIG")),));
+/// let expected = r#"rust_library(**CONFIG)
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&call).unwrap(), expected);
+/// ```
+pub struct Kwargs<T>(pub T);
+
+/// Serialize a field of a struct as positional arguments of the function call
+/// that the struct is written as, one for each element of the collection.
+///
+/// This is only supported as the value of a named argument, and the field's own
+/// name is not written. Positional arguments must come before all named ones,
+/// so fields holding a `Positional` need to be declared first.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::Positional;
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "register_toolchains")]
+/// pub struct RegisterToolchains {
+///     toolchains: Positional<Vec<&'static str>>,
+///     dev: bool,
+/// }
+///
+/// let register = RegisterToolchains {
+///     toolchains: Positional(vec!["//toolchains:linux", "//toolchains:macos"]),
+///     dev: true,
+/// };
+/// let expected = r#"register_toolchains(
+///     "//toolchains:linux",
+///     "//toolchains:macos",
+///     dev = True,
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&register).unwrap(), expected);
+/// ```
+pub struct Positional<A>(pub A);
+
 /// Serialize a line comment on the end of the current line.
 ///
+/// A comment containing more than one line is written instead as a block of
+/// `#` lines above the value, like [`BlockComment`].
+///
+/// A `LineComment` may wrap a dict key as well as a value. The comment goes on
+/// the line holding the key. If both the key and the value of an entry carry
+/// a comment, they are joined by `; `.
+///
+/// Wrapped around an [`Assignment`], the comment goes at the end of the whole
+/// statement: `VERSION = "1.2.3"  # from Cargo.toml`. If the assigned value
+/// spans several lines, that is after its closing bracket rather than on its
+/// first line.
+///
 /// # Example
 ///
 /// This example demonstrates serializing a `select({…})` in which the keys are
@@ -509,66 +1276,31 @@ impl<'name, A> FunctionCall<'name, A> {
 /// })
 /// ```
 ///
+/// The owned [`Commented`] is the `LineComment` to use for the elements of a
+/// `BTreeSet`, which it orders by value and then by comment. The
+/// [`MultilineArray`] puts each dependency on its own line, even if there is
+/// just a single one which serde_starlark would ordinarily place on the same
+/// line as the array brackets.
+///
 /// ```
-/// use serde::ser::{Serialize, SerializeSeq, Serializer};
-/// use serde_starlark::{FunctionCall, LineComment};
+/// use serde_starlark::{Commented, FunctionCall, MultilineArray};
 /// use std::collections::{BTreeMap, BTreeSet};
 ///
-/// #[derive(Ord, PartialOrd, Eq, PartialEq)]
-/// pub struct WithCargoCfg<T> {
-///     value: T,
-///     cfg: String,
-/// }
-///
-/// impl<T> Serialize for WithCargoCfg<T>
-/// where
-///     T: Serialize,
-/// {
-///     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-///     where
-///         S: Serializer,
-///     {
-///         LineComment::new(&self.value, &self.cfg).serialize(serializer)
-///     }
-/// }
-///
-/// // Serialize an array with each element on its own line, even if there
-/// // is just a single element which serde_starlark would ordinarily place
-/// // on the same line as the array brackets.
-/// struct MultilineArray<A>(A);
-///
-/// impl<A, T> Serialize for MultilineArray<A>
-/// where
-///     for<'a> &'a A: IntoIterator<Item = &'a T>,
-///     T: Serialize,
-/// {
-///     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-///     where
-///         S: Serializer,
-///     {
-///         let mut array = serializer.serialize_seq(Some(serde_starlark::MULTILINE))?;
-///         for element in &self.0 {
-///             array.serialize_element(element)?;
-///         }
-///         array.end()
-///     }
-/// }
-///
 /// fn main() {
 ///     let deps = BTreeMap::from([
 ///         (
 ///             "@rules_rust//rust/platform:x86_64-pc-windows-msvc".to_owned(),
-///             MultilineArray(BTreeSet::from([WithCargoCfg {
-///                 value: "//third-party/rust:windows-sys",
-///                 cfg: "cfg(windows)".to_owned(),
-///             }])),
+///             MultilineArray(BTreeSet::from([Commented::new(
+///                 "//third-party/rust:windows-sys",
+///                 "cfg(windows)",
+///             )])),
 ///         ),
 ///         (
 ///             "@rules_rust//rust/platform:x86_64-unknown-linux-gnu".to_owned(),
-///             MultilineArray(BTreeSet::from([WithCargoCfg {
-///                 value: "//third-party/rust:libc",
-///                 cfg: "cfg(any(unix, target_os = \"wasi\"))".to_owned(),
-///             }])),
+///             MultilineArray(BTreeSet::from([Commented::new(
+///                 "//third-party/rust:libc",
+///                 "cfg(any(unix, target_os = \"wasi\"))",
+///             )])),
 ///         ),
 ///     ]);
 ///
@@ -582,11 +1314,1291 @@ pub struct LineComment<'comment, T> {
 }
 
 impl<'comment, T> LineComment<'comment, T> {
+    /// Any leading `#` characters in `comment`, along with whitespace after
+    /// them, are removed since the serializer inserts its own `# `.
     pub fn new(value: T, comment: &'comment str) -> Self {
-        assert!(!comment.starts_with('#'));
-        assert!(!comment.contains('\n'));
+        let comment = match comment.strip_prefix('#') {
+            Some(rest) => rest.trim_start_matches('#').trim_start(),
+            None => comment,
+        };
         LineComment { value, comment }
     }
+
+    /// Like [`LineComment::new`], but rejects a comment beginning with `#`
+    /// instead of removing it.
+    ///
+    /// ```
+    /// use serde_starlark::LineComment;
+    ///
+    /// assert!(LineComment::try_new("//util", "internal").is_ok());
+    ///
+    /// let error = LineComment::try_new("//util", "# internal").err().unwrap();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "comment must not begin with '#', it is inserted automatically: \"# internal\"",
+    /// );
+    /// ```
+    pub fn try_new(value: T, comment: &'comment str) -> Result<Self, Error> {
+        if comment.starts_with('#') {
+            return Err(error::comment_prefix(comment));
+        }
+        Ok(LineComment { value, comment })
+    }
+}
+
+/// Serialize one or more comment lines above a value.
+///
+/// The comment is placed on its own line(s) directly above the list element,
+/// dict entry, or function argument containing the value, at the same
+/// indentation. Each line of `comment` becomes one `#` line.
+///
+/// ```
+/// use serde_starlark::BlockComment;
+///
+/// let srcs = vec![
+///     BlockComment::new("lib.rs", "Crate root."),
+///     BlockComment::new("generated.rs", "Produced by build.rs.\nDo not edit."),
+/// ];
+/// let expected = r#"[
+///     ## Crate root.
+///     "lib.rs",
+///     ## Produced by build.rs.
+///     ## Do not edit.
+///     "generated.rs",
+/// ]
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&srcs).unwrap(), expected);
+/// ```
+///
+/// If the containing list or dict is formatted on one line, the comment lines
+/// are placed above that whole line.
+pub struct BlockComment<'comment, T> {
+    value: T,
+    comment: &'comment str,
+}
+
+impl<'comment, T> BlockComment<'comment, T> {
+    pub fn new(value: T, comment: &'comment str) -> Self {
+        BlockComment { value, comment }
+    }
+}
+
+/// Suppress a buildifier lint warning on a value.
+///
+/// This writes a `# buildifier: disable=<lint>` comment line above the value,
+/// which is where buildifier looks for it.
+///
+/// ```
+/// use serde_starlark::{FunctionCall, Suppress};
+///
+/// let call = FunctionCall::new("glob", [&["**/*.rs"][..]]);
+/// let srcs = Suppress::new(call, "native-glob");
+/// let expected = "# buildifier: disable=native-glob\nglob([\"**/*.rs\"])\n";
+/// assert_eq!(serde_starlark::to_string(&srcs).unwrap(), expected);
+/// ```
+pub struct Suppress<'lint, T> {
+    value: T,
+    lint: &'lint str,
+}
+
+impl<'lint, T> Suppress<'lint, T> {
+    pub fn new(value: T, lint: &'lint str) -> Self {
+        Suppress { value, lint }
+    }
+}
+
+/// A value with an owned line comment, for use as a field of a struct that is
+/// serialized as a function call.
+///
+/// This serializes the same as [`LineComment`], but owns its comment, which is
+/// convenient when the comment is computed at runtime, for example from Cargo
+/// metadata.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::Commented;
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "rust_library")]
+/// struct RustLibrary {
+///     name: &'static str,
+///     edition: Commented<&'static str>,
+/// }
+///
+/// let rust_library = RustLibrary {
+///     name: "syn",
+///     edition: Commented::new("2021", format!("from {}", "Cargo.toml")),
+/// };
+/// let expected = r#"rust_library(
+///     name = "syn",
+///     edition = "2021",  # from Cargo.toml
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&rust_library).unwrap(), expected);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Commented<T> {
+    pub value: T,
+    pub comment: String,
+}
+
+impl<T> Commented<T> {
+    pub fn new(value: T, comment: impl Into<String>) -> Self {
+        Commented {
+            value,
+            comment: comment.into(),
+        }
+    }
+}
+
+/// A comment of Starlark source read by [`from_str_with_comments`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment<'a> {
+    /// The comment, starting with `#`.
+    pub text: &'a str,
+    /// The line of the source that the comment is on, counting from 1.
+    pub line: usize,
+    /// The index of the top-level statement that the comment is in or
+    /// attached to. A comment between two statements, separated from the next
+    /// by a blank line, is detached and has the index of the statement before
+    /// it, or `None` at the top of the file.
+    pub statement: Option<usize>,
+    /// Where in the statement the comment is attached, in the notation of
+    /// this crate's error messages: `deps[0]` for the first element of the
+    /// `deps` argument. Empty for the statement itself.
+    pub path: String,
+    pub placement: CommentPlacement,
+}
+
+/// How a [`Comment`] is placed relative to the value it is attached to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommentPlacement {
+    /// On a line of its own directly above the value.
+    Above,
+    /// At the end of the value's line, or after the opening bracket of a
+    /// multi-line value.
+    Suffix,
+    /// On a line of its own, not directly above any value, such as after the
+    /// last element of a list.
+    Detached,
+}
+
+/// Serialize the definition of a macro that calls a rule, filling in default
+/// values for some of its attributes.
+///
+/// The macro takes the target's `name` and forwards every other argument to
+/// the rule as `**kwargs`. Each default is applied with `kwargs.setdefault`,
+/// so an attribute passed by the caller takes precedence. The defaults are a
+/// map or struct from attribute name to value, or `()` for none.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::{Compact, Document, FunctionCall, WrapperMacro};
+///
+/// #[derive(Serialize)]
+/// struct Defaults {
+///     edition: &'static str,
+///     rustc_flags: Vec<&'static str>,
+/// }
+///
+/// let load = Compact(FunctionCall::new(
+///     "load",
+///     ["@rules_rust//rust:defs.bzl", "rust_library"],
+/// ));
+/// let defaults = Defaults {
+///     edition: "2021",
+///     rustc_flags: vec!["-Dwarnings"],
+/// };
+/// let wrapper = WrapperMacro::new("strict_rust_library", "rust_library", defaults);
+///
+/// let statements: Vec<&dyn erased_serde::Serialize> = vec![&load, &wrapper];
+/// let expected = r#"load("@rules_rust//rust:defs.bzl", "rust_library")
+///
+/// def strict_rust_library(name, **kwargs):
+///     kwargs.setdefault("edition", "2021")
+///     kwargs.setdefault("rustc_flags", ["-Dwarnings"])
+///     rust_library(
+///         name = name,
+///         **kwargs,
+///     )
+/// "#;
+/// let bzl = serde_starlark::to_string(&Document::new(statements)).unwrap();
+/// assert_eq!(bzl, expected);
+/// ```
+pub struct WrapperMacro<'a, D> {
+    name: &'a str,
+    rule: &'a str,
+    defaults: D,
+}
+
+impl<'a, D> WrapperMacro<'a, D> {
+    pub fn new(name: &'a str, rule: &'a str, defaults: D) -> Self {
+        WrapperMacro {
+            name,
+            rule,
+            defaults,
+        }
+    }
+}
+
+/// A sequence of top-level statements, such as the contents of a BUILD file.
+///
+/// Statements are separated from one another by a blank line. Optionally the
+/// document begins with a banner comment identifying it as a generated file.
+///
+/// A `load` of a file that an earlier statement already loads from is merged
+/// into the earlier one, whose symbols are then sorted and deduplicated the way
+/// buildifier normalizes loads.
+///
+/// ```
+/// use serde_starlark::{Document, FunctionCall};
+///
+/// let statements = vec![
+///     FunctionCall::new("exports_files", [&["LICENSE"][..]]),
+///     FunctionCall::new("exports_files", [&["README.md"][..]]),
+/// ];
+/// let document = Document::new(statements).banner("cargo-bazel", "bazel run //:vendor");
+/// let expected = "\
+/// ## DO NOT EDIT -- generated by cargo-bazel; run bazel run //:vendor to
+/// ## regenerate.
+///
+/// exports_files([\"LICENSE\"])
+///
+/// exports_files([\"README.md\"])
+/// ";
+/// assert_eq!(serde_starlark::to_string(&document).unwrap(), expected);
+/// ```
+pub struct Document<'a, T> {
+    statements: T,
+    banner: Option<(&'a str, &'a str)>,
+    separators: Separators,
+    order: StatementOrder,
+    sections: Vec<&'a str>,
+}
+
+impl<'a, T> Document<'a, T> {
+    pub fn new(statements: T) -> Self {
+        Document {
+            statements,
+            banner: None,
+            separators: Separators::DEFAULT,
+            order: StatementOrder::Insertion,
+            sections: Vec::new(),
+        }
+    }
+
+    /// Rearrange the targets of the document, for example to match the output
+    /// of another generator.
+    ///
+    /// A target is a statement that calls a rule or macro with a `name`
+    /// argument. Any other statements, such as loads and assignments, stay in
+    /// front of the targets in their original order.
+    ///
+    /// ```
+    /// use serde_starlark::{Document, FunctionCall, StatementOrder};
+    /// use std::collections::BTreeMap;
+    ///
+    /// let statements = vec![
+    ///     FunctionCall::new("rust_test", BTreeMap::from([("name", "syn_test")])),
+    ///     FunctionCall::new("rust_library", BTreeMap::from([("name", "syn")])),
+    ///     FunctionCall::new("rust_library", BTreeMap::from([("name", "quote")])),
+    /// ];
+    /// let document = Document::new(statements).order(StatementOrder::KindAndName);
+    /// let expected = r#"rust_library(
+    ///     name = "quote",
+    /// )
+    ///
+    /// rust_library(
+    ///     name = "syn",
+    /// )
+    ///
+    /// rust_test(
+    ///     name = "syn_test",
+    /// )
+    /// "#;
+    /// assert_eq!(serde_starlark::to_string(&document).unwrap(), expected);
+    /// ```
+    #[must_use]
+    pub fn order(mut self, order: StatementOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Write the [`Section`]s with these labels first, in this order, followed
+    /// by any other sections in the order that they first appear.
+    ///
+    /// Listing a section that has no statements is not an error; no header is
+    /// written for it.
+    #[must_use]
+    pub fn section_order(mut self, labels: &[&'a str]) -> Self {
+        self.sections = labels.to_vec();
+        self
+    }
+
+    /// Put this many blank lines between top-level statements instead of one.
+    #[must_use]
+    pub fn blank_lines(mut self, lines: usize) -> Self {
+        self.separators.blank_lines = lines;
+        self
+    }
+
+    /// Write consecutive `load` statements on adjacent lines, with no blank
+    /// line between them.
+    ///
+    /// ```
+    /// use serde_starlark::{Compact, Document, FunctionCall};
+    ///
+    /// let rust = Compact(FunctionCall::new("load", ["@rules_rust//rust:defs.bzl", "rust_library"]));
+    /// let skylib = Compact(FunctionCall::new("load", ["@bazel_skylib//lib:selects.bzl", "selects"]));
+    /// let exports = FunctionCall::new("exports_files", [&["LICENSE"][..]]);
+    ///
+    /// let statements: Vec<&dyn erased_serde::Serialize> = vec![&rust, &skylib, &exports];
+    /// let document = Document::new(statements).group_loads(true).blank_lines(2);
+    /// let expected = r#"load("@rules_rust//rust:defs.bzl", "rust_library")
+    /// load("@bazel_skylib//lib:selects.bzl", "selects")
+    ///
+    ///
+    /// exports_files(["LICENSE"])
+    /// "#;
+    /// assert_eq!(serde_starlark::to_string(&document).unwrap(), expected);
+    /// ```
+    #[must_use]
+    pub fn group_loads(mut self, enable: bool) -> Self {
+        self.separators.group_loads = enable;
+        self
+    }
+
+    /// Begin the document with the conventional comment saying that it was
+    /// generated by `tool` and should not be edited by hand, and giving the
+    /// command to run to regenerate it. The comment is wrapped at 80 columns.
+    #[must_use]
+    pub fn banner(mut self, tool: &'a str, regenerate: &'a str) -> Self {
+        self.banner = Some((tool, regenerate));
+        self
+    }
+}
+
+/// Order in which the targets of a [`Document`] are written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StatementOrder {
+    /// The order of the statements given to [`Document::new`] &mdash; this is
+    /// the default.
+    Insertion,
+    /// Alphabetical by the value of the `name` argument.
+    Name,
+    /// Alphabetical by the name of the rule or macro that is called, then by
+    /// `name` among targets of the same rule.
+    KindAndName,
+}
+
+/// A statement of a [`Document`] that belongs to a labeled section.
+///
+/// The document writes the statements of each section together, after any
+/// statements that are not in a section, and introduces each section with a
+/// banner comment giving its label. Within a section, statements keep the
+/// [`StatementOrder`] of the document. Sections are written in the order that
+/// they first appear unless [`Document::section_order`] says otherwise.
+///
+/// ```
+/// use serde_starlark::{Document, FunctionCall, Section};
+/// use std::collections::BTreeMap;
+///
+/// let statements = vec![
+///     Section::new(FunctionCall::new("alias", BTreeMap::from([("name", "syn")])), "direct dependencies"),
+///     Section::new(FunctionCall::new("alias", BTreeMap::from([("name", "unicode-ident")])), "transitive dependencies"),
+///     Section::new(FunctionCall::new("alias", BTreeMap::from([("name", "quote")])), "direct dependencies"),
+/// ];
+/// let document = Document::new(statements);
+/// let expected = r#"# ==== direct dependencies ====
+///
+/// alias(
+///     name = "syn",
+/// )
+///
+/// alias(
+///     name = "quote",
+/// )
+///
+/// ## ==== transitive dependencies ====
+///
+/// alias(
+///     name = "unicode-ident",
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&document).unwrap(), expected);
+/// ```
+pub struct Section<'label, T> {
+    statement: T,
+    label: &'label str,
+}
+
+impl<'label, T> Section<'label, T> {
+    pub fn new(statement: T, label: &'label str) -> Self {
+        Section { statement, label }
+    }
+}
+
+/// Limits on the size of the files that [`Config::to_shards`] splits a
+/// [`Document`] into.
+///
+/// The files are named after the stem given to `new`, followed by the index
+/// of the shard: `defs_0.bzl`, `defs_1.bzl`, and so on. They are expected to
+/// be written side by side in the same Bazel package.
+///
+/// ```
+/// use serde_starlark::Sharding;
+///
+/// let sharding = Sharding::new("defs").max_statements(500).max_bytes(1 << 20);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Sharding<'a> {
+    file_stem: &'a str,
+    max_statements: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl<'a> Sharding<'a> {
+    /// No limits; until one is set, the whole document goes in one shard.
+    #[must_use]
+    pub fn new(file_stem: &'a str) -> Self {
+        Sharding {
+            file_stem,
+            max_statements: None,
+            max_bytes: None,
+        }
+    }
+
+    /// Put at most this many statements in each shard.
+    #[must_use]
+    pub fn max_statements(mut self, max: usize) -> Self {
+        self.max_statements = Some(max);
+        self
+    }
+
+    /// Start a new shard before the statements of the current one would
+    /// exceed this many bytes. A single statement larger than the limit gets
+    /// a shard to itself. The banner and `load` statements at the top of each
+    /// shard are not counted.
+    #[must_use]
+    pub fn max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+}
+
+/// One of the files produced by [`Config::to_shards`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shard {
+    /// The name of the file, like `defs_0.bzl`.
+    pub file_name: String,
+    /// The Starlark source of the file.
+    pub contents: String,
+}
+
+/// Mark a list with a `# keep sorted` comment on its opening line.
+///
+/// The comment is recognized by buildifier and keep-sorted tooling as a
+/// request to keep the elements of the list in sorted order. It is only
+/// written if the list is formatted on multiple lines.
+///
+/// `KeepSorted::new` writes the elements in the order given, which is
+/// appropriate if they are already sorted. `KeepSorted::sorted` additionally
+/// sorts the elements by their Starlark representation. Comments attached to
+/// an element move along with it, and groups of elements separated by a
+/// [`Spacer`] are sorted independently.
+///
+/// ```
+/// use serde_starlark::KeepSorted;
+///
+/// let deps = KeepSorted::sorted(vec!["//:syn", "//:quote", "//:proc-macro2"]);
+/// let expected = r#"[  # keep sorted
+///     "//:proc-macro2",
+///     "//:quote",
+///     "//:syn",
+/// ]
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+/// ```
+pub struct KeepSorted<T> {
+    value: T,
+    sort: bool,
+}
+
+impl<T> KeepSorted<T> {
+    #[must_use]
+    pub fn new(value: T) -> Self {
+        KeepSorted { value, sort: false }
+    }
+
+    #[must_use]
+    pub fn sorted(value: T) -> Self {
+        KeepSorted { value, sort: true }
+    }
+}
+
+/// Separate groups of elements inside a multi-line list by a blank line.
+///
+/// A `Spacer` is written in place of a list element. It produces no element in
+/// the Starlark list, only a blank line, optionally followed by a `#` comment
+/// line introducing the next group. In a list that is formatted on one line,
+/// a `Spacer` produces nothing at all. Serializing a `Spacer` anywhere other
+/// than as a list element is an error.
+///
+/// ```
+/// use serde::ser::{Serialize, SerializeSeq, Serializer};
+/// use serde_starlark::Spacer;
+///
+/// struct Deps {
+///     workspace: Vec<&'static str>,
+///     third_party: Vec<&'static str>,
+/// }
+///
+/// impl Serialize for Deps {
+///     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+///     where
+///         S: Serializer,
+///     {
+///         let mut seq = serializer.serialize_seq(None)?;
+///         for dep in &self.workspace {
+///             seq.serialize_element(dep)?;
+///         }
+///         seq.serialize_element(&Spacer::with_header("third-party"))?;
+///         for dep in &self.third_party {
+///             seq.serialize_element(dep)?;
+///         }
+///         seq.end()
+///     }
+/// }
+///
+/// let deps = Deps {
+///     workspace: vec!["//common", "//util"],
+///     third_party: vec!["//third-party/rust:serde"],
+/// };
+/// print!("{}", serde_starlark::to_string(&deps).unwrap());
+/// #
+/// # assert_eq!(
+/// #     serde_starlark::to_string(&deps).unwrap(),
+/// #     concat!(
+/// #         "[\n",
+/// #         "    \"//common\",\n",
+/// #         "    \"//util\",\n",
+/// #         "\n",
+/// #         "    # third-party\n",
+/// #         "    \"//third-party/rust:serde\",\n",
+/// #         "]\n",
+/// #     ),
+/// # );
+/// ```
+///
+/// ```bzl
+/// [
+///     "//common",
+///     "//util",
+///
+///     # third-party
+///     "//third-party/rust:serde",
+/// ]
+/// ```
+pub struct Spacer<'a> {
+    header: Option<&'a str>,
+}
+
+impl<'a> Spacer<'a> {
+    /// A blank line.
+    #[must_use]
+    pub fn new() -> Self {
+        Spacer { header: None }
+    }
+
+    /// A blank line followed by a comment line, `# header`.
+    #[must_use]
+    pub fn with_header(header: &'a str) -> Self {
+        Spacer {
+            header: Some(header),
+        }
+    }
+}
+
+impl<'a> Default for Spacer<'a> {
+    fn default() -> Self {
+        Spacer::new()
+    }
+}
+
+/// Serialize a string as a raw string literal, `r"…"`.
+///
+/// Strings full of backslashes, such as Windows paths and regular expressions,
+/// are much more readable as raw string literals than with every backslash
+/// escaped.
+///
+/// ```
+/// use serde_starlark::RawString;
+///
+/// let regex = RawString::new(r"^\d+\.\d+\.\d+$");
+/// print!("{}", serde_starlark::to_string(&regex).unwrap());
+/// #
+/// # assert_eq!(
+/// #     serde_starlark::to_string(&regex).unwrap(),
+/// #     "r\"^\\d+\\.\\d+\\.\\d+$\"\n",
+/// # );
+/// ```
+///
+/// ```bzl
+/// r"^\d+\.\d+\.\d+$"
+/// ```
+///
+/// Not every string can be written as a raw string literal. A string containing
+/// a double quote that is not preceded by a backslash, a string ending in an
+/// odd number of backslashes, or one containing control characters such as a
+/// newline, is serialized as an ordinary escaped string literal instead.
+pub struct RawString<'a> {
+    string: &'a str,
+}
+
+impl<'a> RawString<'a> {
+    /// Serialize `string` as a raw string literal where possible.
+    #[must_use]
+    pub fn new(string: &'a str) -> Self {
+        RawString { string }
+    }
+}
+
+/// Serialize a multi-line string as a triple-quoted string literal, `"""…"""`.
+///
+/// Long embedded scripts, such as the `cmd` of a genrule, are unreadable as a
+/// single-line string with every newline escaped as `\n`.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::TripleQuoted;
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "genrule")]
+/// struct Genrule<'a> {
+///     name: &'a str,
+///     outs: Vec<&'a str>,
+///     cmd: TripleQuoted<'a>,
+/// }
+///
+/// fn main() {
+///     let genrule = Genrule {
+///         name: "version",
+///         outs: vec!["version.txt"],
+///         cmd: TripleQuoted::new("set -eu\necho \"$$VERSION\" > $@\n"),
+///     };
+///
+///     print!("{}", serde_starlark::to_string(&genrule).unwrap());
+/// #
+/// #   assert_eq!(
+/// #       serde_starlark::to_string(&genrule).unwrap(),
+/// #       concat!(
+/// #           "genrule(\n",
+/// #           "    name = \"version\",\n",
+/// #           "    outs = [\"version.txt\"],\n",
+/// #           "    cmd = \"\"\"\\\n",
+/// #           "set -eu\n",
+/// #           "echo \"$$VERSION\" > $@\n",
+/// #           "\"\"\",\n",
+/// #           ")\n",
+/// #       ),
+/// #   );
+/// }
+/// ```
+///
+/// ```bzl
+/// genrule(
+///     name = "version",
+///     outs = ["version.txt"],
+///     cmd = """\
+/// set -eu
+/// echo "$$VERSION" > $@
+/// """,
+/// )
+/// ```
+///
+/// The content is never indented to match the surrounding code, because
+/// indentation inside the literal would become part of the string's value.
+/// The literal begins with an escaped newline so that every line of content
+/// starts in the first column. Strings that do not contain a newline are
+/// serialized as an ordinary string literal.
+pub struct TripleQuoted<'a> {
+    string: &'a str,
+}
+
+impl<'a> TripleQuoted<'a> {
+    /// Serialize `string` as a triple-quoted literal if it spans several lines.
+    #[must_use]
+    pub fn new(string: &'a str) -> Self {
+        TripleQuoted { string }
+    }
+}
+
+/// Serialize an integer as a hexadecimal literal, `0xFF`.
+///
+/// Useful for flag masks and similar values where decimal obscures the intent.
+/// See also [`Oct`] and [`Bin`].
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::{Hex, Oct};
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "pkg_files")]
+/// struct PkgFiles {
+///     name: &'static str,
+///     mode: Oct,
+///     flags: Hex,
+/// }
+///
+/// fn main() {
+///     let pkg_files = PkgFiles {
+///         name: "bin",
+///         mode: Oct(0o755),
+///         flags: Hex(0x8000_0000 >> 1),
+///     };
+///
+///     print!("{}", serde_starlark::to_string(&pkg_files).unwrap());
+/// #
+/// #   assert_eq!(
+/// #       serde_starlark::to_string(&pkg_files).unwrap(),
+/// #       "pkg_files(\n    name = \"bin\",\n    mode = 0o755,\n    flags = 0x40000000,\n)\n",
+/// #   );
+/// }
+/// ```
+///
+/// ```bzl
+/// pkg_files(
+///     name = "bin",
+///     mode = 0o755,
+///     flags = 0x40000000,
+/// )
+/// ```
+///
+/// Like any other integer, the value must be within the range of a 32-bit
+/// signed integer to be serialized.
+pub struct Hex(pub u32);
+
+/// Serialize an integer as an octal literal, `0o755`.
+///
+/// Useful for file modes. See also [`Hex`] and [`Bin`].
+pub struct Oct(pub u32);
+
+/// Serialize an integer as a binary literal, `0b1010`.
+///
+/// See also [`Hex`] and [`Oct`].
+pub struct Bin(pub u32);
+
+/// Serialize a filesystem path as a string with `/` as its separator.
+///
+/// Bazel and Buck only understand forward slashes in labels and file names,
+/// so a path built on Windows, such as the source files of a crate found by
+/// walking its directory, has each `\` replaced by `/`. A path that is not
+/// valid UTF-8 cannot be written in Starlark and fails to serialize, with an
+/// error showing the path.
+///
+/// ```
+/// use serde_starlark::SlashPath;
+/// use std::path::PathBuf;
+///
+/// let srcs: Vec<SlashPath<PathBuf>> = vec![
+///     SlashPath(PathBuf::from(r"src\lib.rs")),
+///     SlashPath(PathBuf::from("src/parse.rs")),
+/// ];
+/// let expected = r#"[
+///     "src/lib.rs",
+///     "src/parse.rs",
+/// ]
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&srcs).unwrap(), expected);
+/// ```
+pub struct SlashPath<P>(pub P);
+
+/// Serialize a string or unit enum variant as a bare identifier instead of a
+/// string literal.
+///
+/// This is for referring to a constant defined elsewhere in Starlark, such as
+/// one brought into scope by a `load` statement. A dotted name such as
+/// `config.VERSION` is allowed; anything else that is not an identifier is an
+/// error.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::Identifier;
+///
+/// #[derive(Serialize)]
+/// #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+/// enum LinkStyle {
+///     StaticLibrary,
+///     SharedLibrary,
+/// }
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "cc_library")]
+/// struct CcLibrary {
+///     name: &'static str,
+///     link_style: Identifier<LinkStyle>,
+/// }
+///
+/// fn main() {
+///     let cc_library = CcLibrary {
+///         name: "zlib",
+///         link_style: Identifier(LinkStyle::StaticLibrary),
+///     };
+///
+///     print!("{}", serde_starlark::to_string(&cc_library).unwrap());
+/// #
+/// #   assert_eq!(
+/// #       serde_starlark::to_string(&cc_library).unwrap(),
+/// #       "cc_library(\n    name = \"zlib\",\n    link_style = STATIC_LIBRARY,\n)\n",
+/// #   );
+/// }
+/// ```
+///
+/// ```bzl
+/// cc_library(
+///     name = "zlib",
+///     link_style = STATIC_LIBRARY,
+/// )
+/// ```
+pub struct Identifier<T>(pub T);
+
+/// Serialize a value computed by the [starlark-rust] interpreter.
+///
+/// This makes it possible to evaluate some Starlark, for example a `.bzl` file
+/// that computes a table of constants, and pretty-print the resulting values
+/// back out as Starlark. `None`, bools, ints, floats, strings, lists, tuples,
+/// dicts, and structs are supported. A struct is written as a call to
+/// `struct` with a named argument per field. Any other kind of value, such as
+/// a function, is an error.
+///
+/// A `FrozenValue`, as found in a frozen module, can be serialized by way of
+/// its `to_value()`.
+///
+/// [starlark-rust]: https://github.com/facebook/starlark-rust
+///
+/// ```
+/// use serde_starlark::Evaluated;
+/// use starlark::environment::{Globals, Module};
+/// use starlark::eval::Evaluator;
+/// use starlark::syntax::{AstModule, Dialect};
+///
+/// let source = "PLATFORMS = {os: ('x86_64', 'aarch64') for os in ['linux', 'macos']}";
+/// let ast = AstModule::parse("platforms.bzl", source.to_owned(), &Dialect::Standard).unwrap();
+/// let module = Module::new();
+/// let mut eval = Evaluator::new(&module);
+/// eval.eval_module(ast, &Globals::standard()).unwrap();
+///
+/// let platforms = module.get("PLATFORMS").unwrap();
+/// let expected = r#"{
+///     "linux": ("x86_64", "aarch64"),
+///     "macos": ("x86_64", "aarch64"),
+/// }
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&Evaluated(platforms)).unwrap(), expected);
+/// ```
+#[cfg(feature = "starlark-interop")]
+#[cfg_attr(docsrs, doc(cfg(feature = "starlark-interop")))]
+pub struct Evaluated<'v>(pub starlark::values::Value<'v>);
+
+/// Serialize a struct as a dict literal instead of a function call.
+///
+/// Ordinarily structs with named fields become function calls with named
+/// arguments. Within `Dict`, the outermost struct is instead serialized as a
+/// dict whose keys are the field names, in field order. Values nested inside of
+/// it are serialized as usual, so a field holding a struct still becomes a
+/// function call.
+///
+/// This is the way to serialize an internally tagged or adjacently tagged enum
+/// (`#[serde(tag = "…")]`) into a dict, with the tag as its first entry.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::Dict;
+///
+/// #[derive(Serialize)]
+/// #[serde(tag = "kind", rename_all = "snake_case")]
+/// enum Toolchain {
+///     Rustc { version: &'static str, edition: &'static str },
+///     Clang { version: &'static str },
+/// }
+///
+/// fn main() {
+///     let toolchain = Dict(Toolchain::Rustc {
+///         version: "1.70.0",
+///         edition: "2021",
+///     });
+///
+///     print!("{}", serde_starlark::to_string(&toolchain).unwrap());
+/// #
+/// #   assert_eq!(
+/// #       serde_starlark::to_string(&toolchain).unwrap(),
+/// #       "{\n    \"kind\": \"rustc\",\n    \"version\": \"1.70.0\",\n    \"edition\": \"2021\",\n}\n",
+/// #   );
+/// }
+/// ```
+///
+/// ```bzl
+/// {
+///     "kind": "rustc",
+///     "version": "1.70.0",
+///     "edition": "2021",
+/// }
+/// ```
+pub struct Dict<T>(pub T);
+
+/// Starlark source text of an expression, kept exactly as written.
+///
+/// Deserializing a `Raw` accepts any expression at all, including those that
+/// no Rust type can represent, like a list comprehension, a concatenation, or
+/// a call of a macro. Serializing one writes the text back out unchanged, so
+/// a tool can read a hand-written file, modify what it understands, and leave
+/// the rest alone. The text is not re-indented, which for a multi-line
+/// expression matters if it is written at a different depth than before.
+///
+/// Together with [`Config::lenient`], a `Raw` is also what any other
+/// expression that cannot be deserialized turns into, for example in an
+/// untagged enum with a `Raw` variant.
+///
+/// ```
+/// use serde_derive::{Deserialize, Serialize};
+/// use serde_starlark::Raw;
+///
+/// #[derive(Serialize, Deserialize)]
+/// #[serde(rename = "filegroup")]
+/// struct Filegroup {
+///     name: String,
+///     srcs: Raw,
+/// }
+///
+/// let starlark = r#"filegroup(
+///     name = "docs",
+///     srcs = [f + ".md" for f in DOCS],
+/// )
+/// "#;
+/// let mut filegroup: Filegroup = serde_starlark::from_str(starlark).unwrap();
+/// assert_eq!(filegroup.srcs, Raw(r#"[f + ".md" for f in DOCS]"#.to_owned()));
+///
+/// filegroup.name = "all_docs".to_owned();
+/// let expected = r#"filegroup(
+///     name = "all_docs",
+///     srcs = [f + ".md" for f in DOCS],
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&filegroup).unwrap(), expected);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Raw(pub String);
+
+/// Serialize a call to `glob`, which matches files of the package by pattern.
+///
+/// With no `exclude` patterns, the `include` patterns are written as the only
+/// argument. Either form deserializes back into a `Glob`, with the patterns in
+/// the order they are written.
+///
+/// ```
+/// use serde_starlark::Glob;
+///
+/// let srcs = Glob {
+///     include: vec!["**/*.rs".to_owned()],
+///     exclude: vec!["tests/**".to_owned()],
+/// };
+/// let expected = r#"glob(
+///     include = ["**/*.rs"],
+///     exclude = ["tests/**"],
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&srcs).unwrap(), expected);
+///
+/// let srcs: Glob = serde_starlark::from_str(r#"glob(["**/*.rs"], exclude = ["tests/**"])"#).unwrap();
+/// assert_eq!(srcs.include, ["**/*.rs"]);
+/// assert_eq!(srcs.exclude, ["tests/**"]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Glob {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Serialize a `select` whose value depends on the build configuration.
+///
+/// Each branch maps a condition, the label of a `config_setting` or
+/// `//conditions:default`, to the value the select takes when the condition
+/// matches. Branches are written in the [`BranchOrder`] chosen by
+/// [`branch_order`][Select::branch_order], by default in alphabetical order
+/// with `//conditions:default` last. Bazel does not
+/// allow a select inside the value of another select, and serializing one is
+/// an error.
+///
+/// A list-valued select can also have a base value, which applies under every
+/// condition and is written in front of the select: `[…] + select({…})`. See
+/// [`merge`][Select::merge] and [`union`][Select::union] for combining two
+/// selects into one.
+///
+/// A branch inserted with [`insert_or`][Select::insert_or] applies when any of
+/// several conditions matches. A select with such a branch is written using
+/// `selects.with_or` from bazel-skylib, with a tuple of conditions as the key
+/// of the branch. The `.bzl` file needs to load `selects` from
+/// `@bazel_skylib//lib:selects.bzl`.
+///
+/// ```
+/// use serde_starlark::Select;
+///
+/// let mut deps = Select::new();
+/// deps.insert_or(["@platforms//os:linux", "@platforms//os:macos"], vec![":unix"]);
+/// deps.insert("@platforms//os:windows", vec![":windows"]);
+/// deps.insert("//conditions:default", vec![]);
+///
+/// let expected = r#"selects.with_or({
+///     ("@platforms//os:linux", "@platforms//os:macos"): [":unix"],
+///     "@platforms//os:windows": [":windows"],
+///     "//conditions:default": [],
+/// })
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Select<T> {
+    base: Option<T>,
+    prefix: Option<String>,
+    order: BranchOrder,
+    branches: Vec<(Vec<String>, T)>,
+}
+
+impl<T> Select<T> {
+    /// A select with no branches.
+    #[must_use]
+    pub fn new() -> Self {
+        Select {
+            base: None,
+            prefix: None,
+            order: BranchOrder::Alphabetical,
+            branches: Vec::new(),
+        }
+    }
+}
+
+impl<T> Default for Select<T> {
+    fn default() -> Self {
+        Select::new()
+    }
+}
+
+/// Order in which the branches of a [`Select`] are written.
+#[derive(Copy, Clone, Debug)]
+pub enum BranchOrder {
+    /// In alphabetical order of condition, except that the
+    /// `//conditions:default` branch comes last.
+    Alphabetical,
+    /// In the order the branches were first inserted.
+    Insertion,
+    /// Sorted by a function that compares the conditions of two branches, as
+    /// they are written including any condition prefix.
+    By(fn(&[String], &[String]) -> Ordering),
+}
+
+/// Options for customizing the generated Starlark.
+///
+/// `Config::new()` matches the behavior of [`serde_starlark::to_string`]. Each
+/// option is changed through a method on `Config`, after which
+/// [`Config::to_string`] serializes a value with those options applied.
+///
+/// [`serde_starlark::to_string`]: to_string
+///
+/// ```
+/// use serde_starlark::{Config, QuoteStyle};
+///
+/// let mut config = Config::new();
+/// config.quote_style(QuoteStyle::Single);
+///
+/// let deps = ["//third-party/rust:serde", "//third-party/rust:syn"];
+/// print!("{}", config.to_string(&deps[..]).unwrap());
+/// #
+/// # assert_eq!(
+/// #     config.to_string(&deps[..]).unwrap(),
+/// #     concat!(
+/// #         "[\n",
+/// #         "    '//third-party/rust:serde',\n",
+/// #         "    '//third-party/rust:syn',\n",
+/// #         "]\n",
+/// #     ),
+/// # );
+/// ```
+///
+/// ```bzl
+/// [
+///     '//third-party/rust:serde',
+///     '//third-party/rust:syn',
+/// ]
+/// ```
+///
+/// For use with Serializer adapters such as serde-transcode,
+/// [`Config::serializer`] provides a Serializer that writes with the
+/// configuration. Adapters that discard the serializer's output value, such as
+/// erased-serde, need [`Config::serializer_into`] instead.
+#[derive(Clone, Debug)]
+pub struct Config {
+    quote_style: QuoteStyle,
+    hex_escapes: bool,
+    ascii_only: bool,
+    integer_overflow: IntegerOverflow,
+    floats: bool,
+    non_finite_floats: bool,
+    unit_as_none: bool,
+    skip_none: bool,
+    skip_empty: bool,
+    max_width: Option<usize>,
+    argument_order: ArgumentOrder,
+    sort_map_keys: bool,
+    deny_duplicate_keys: bool,
+    uppercase_assignments: bool,
+    escape_keywords: bool,
+    argument_renames: Vec<(String, String)>,
+    argument_policies: Vec<(String, ArgumentPolicy)>,
+    format: Option<crate::config::Format>,
+    lenient: bool,
+}
+
+/// Which quote character to use for string literals.
+///
+/// Whichever character is chosen, an occurrence of that same character inside
+/// the string is escaped with a backslash.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// `"…"` &mdash; this is the default.
+    Double,
+    /// `'…'` &mdash; the common convention in Buck codebases.
+    Single,
+    /// Whichever of the two requires fewer escapes in each particular string,
+    /// preferring double quotes in case of a tie.
+    FewestEscapes,
+}
+
+/// Order in which to write the named arguments of a function call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArgumentOrder {
+    /// The order in which the Serialize impl provides them, which for a derived
+    /// impl is the order of the struct's fields &mdash; this is the default.
+    Declaration,
+    /// `name` first, followed by all other named arguments in alphabetical
+    /// order, as buildifier arranges the arguments of rules.
+    NameFirst,
+}
+
+/// How to format the value of every named argument of a particular name,
+/// wherever it appears.
+///
+/// See [`Config::argument_policy`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArgumentPolicy {
+    /// Whether a list or dict value is written on one line or on multiple.
+    pub layout: Layout,
+    /// Sort the elements of a list by their Starlark representation, or the
+    /// entries of a dict by their key, as [`KeepSorted::sorted`] does but
+    /// without the `# keep sorted` comment.
+    pub sort: bool,
+}
+
+/// Whether to write a list or dict on one line or with each element on its
+/// own line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// The same as if there were no policy: multiple lines for more than one
+    /// element, or as decided by [`Config::max_width`] &mdash; this is the
+    /// default.
+    Auto,
+    /// Like the [`ONELINE`] length hint.
+    Oneline,
+    /// Like the [`MULTILINE`] length hint.
+    Multiline,
+}
+
+/// Decides the layout of lists, tuples, dicts, and function calls throughout
+/// the output, without wrapping each value in [`Compact`], [`Expanded`], or
+/// [`MultilineArray`].
+///
+/// Installed by [`Config::format`], it is consulted as each container is
+/// begun, given what kind of container it is and the length hint from its
+/// Serialize impl. For a derived impl, the length of a struct is its number of
+/// fields, and that of a `Vec` or map is its number of elements. Returning
+/// [`Layout::Auto`] leaves the decision as it would be without the hook.
+///
+/// Anything more specific takes precedence over the hook: a [`Compact`] or
+/// [`Expanded`] wrapper, an [`ONELINE`] or [`MULTILINE`] length hint, or an
+/// [`ArgumentPolicy`].
+///
+/// Closures taking a [`Container`] and the length hint implement this trait.
+///
+/// ```
+/// use serde_derive::Serialize;
+/// use serde_starlark::{Config, Container, Layout};
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "load")]
+/// struct Load(&'static str, &'static str);
+///
+/// let mut config = Config::new();
+/// config.format(|container: Container, _len: Option<usize>| match container {
+///     Container::Call("load") => Layout::Oneline,
+///     _ => Layout::Auto,
+/// });
+///
+/// let load = Load("@rules_rust//rust:defs.bzl", "rust_library");
+/// let expected = r#"load("@rules_rust//rust:defs.bzl", "rust_library")
+/// "#;
+/// assert_eq!(config.to_string(&load).unwrap(), expected);
+/// ```
+pub trait StarlarkFormat: Send + Sync {
+    fn layout(&self, container: Container, len: Option<usize>) -> Layout;
+}
+
+impl<F> StarlarkFormat for F
+where
+    F: Fn(Container, Option<usize>) -> Layout + Send + Sync,
+{
+    fn layout(&self, container: Container, len: Option<usize>) -> Layout {
+        self(container, len)
+    }
+}
+
+/// A container whose layout is being decided by a [`StarlarkFormat`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Container<'a> {
+    List,
+    Tuple,
+    Dict,
+    /// A function call, serialized from a struct, tuple struct, or
+    /// [`FunctionCall`], with the name of the function.
+    Call(&'a str),
+}
+
+/// What to do with an integer that does not fit in Starlark's `int`.
+///
+/// The Starlark spec allows integers of arbitrary size, but Bazel's
+/// implementation is limited to signed 32-bit values. By default, integers
+/// outside of that range are a serialization error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntegerOverflow {
+    /// Fail serialization &mdash; this is the default.
+    Error,
+    /// Write the full integer literal regardless of its size, for consumers
+    /// that implement arbitrary precision integers as per the spec.
+    Emit,
+    /// Write the integer as a string literal, `"4294967296"`, followed by a
+    /// line comment naming the Rust type it originally had.
+    Stringify,
+}
+
+/// Iterator over the statements of Starlark source read from an IO stream,
+/// returned by [`from_reader`].
+pub struct StreamDeserializer<R, T> {
+    reader: io::BufReader<R>,
+    // The first line of the next statement, already read.
+    lookahead: String,
+    // Number of lines and bytes before the statements being returned.
+    line: usize,
+    offset: usize,
+    pending: VecDeque<Result<T, Error>>,
+    done: bool,
 }
 
 /// Serializer whose output `Ok` type is Starlark.
@@ -594,101 +2606,102 @@ impl<'comment, T> LineComment<'comment, T> {
 /// `value.serialize(serde_starlark::Serializer)` is 100% equivalent to
 /// `serde_starlark::to_string(&value)`. However, having direct access to the
 /// Serializer is useful when dealing with libraries that act as Serializer
-/// adapters, such as the erased-serde crate or serde-transcode crate.
+/// adapters, such as the serde-transcode crate. For erased-serde, which does
+/// not give back the `Ok` value, use [`Config::serializer_into`].
 pub struct Serializer;
 
 impl serde::Serializer for Serializer {
     type Ok = String;
     type Error = Error;
-    type SerializeSeq = WriteSeq<WriteStarlark>;
-    type SerializeTuple = WriteTuple<WriteStarlark>;
-    type SerializeTupleStruct = WriteTupleStruct<WriteStarlark>;
-    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
-    type SerializeMap = WriteMap<WriteStarlark>;
-    type SerializeStruct = WriteStruct<WriteStarlark>;
-    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeSeq = WriteSeq<WriteStarlark<'static>>;
+    type SerializeTuple = WriteTuple<WriteStarlark<'static>>;
+    type SerializeTupleStruct = WriteTupleStruct<WriteStarlark<'static>>;
+    type SerializeTupleVariant = WriteTupleStruct<WriteStarlark<'static>>;
+    type SerializeMap = WriteMap<WriteStarlark<'static>>;
+    type SerializeStruct = WriteStruct<WriteStarlark<'static>>;
+    type SerializeStructVariant = WriteStruct<WriteStarlark<'static>>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_bool(v)
+        WriteStarlark::new(&DEFAULT).serialize_bool(v)
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_i8(v)
+        WriteStarlark::new(&DEFAULT).serialize_i8(v)
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_i16(v)
+        WriteStarlark::new(&DEFAULT).serialize_i16(v)
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_i32(v)
+        WriteStarlark::new(&DEFAULT).serialize_i32(v)
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_i64(v)
+        WriteStarlark::new(&DEFAULT).serialize_i64(v)
     }
 
     fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_i128(v)
+        WriteStarlark::new(&DEFAULT).serialize_i128(v)
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_u8(v)
+        WriteStarlark::new(&DEFAULT).serialize_u8(v)
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_u16(v)
+        WriteStarlark::new(&DEFAULT).serialize_u16(v)
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_u32(v)
+        WriteStarlark::new(&DEFAULT).serialize_u32(v)
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_u64(v)
+        WriteStarlark::new(&DEFAULT).serialize_u64(v)
     }
 
     fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_u128(v)
+        WriteStarlark::new(&DEFAULT).serialize_u128(v)
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_f32(v)
+        WriteStarlark::new(&DEFAULT).serialize_f32(v)
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_f64(v)
+        WriteStarlark::new(&DEFAULT).serialize_f64(v)
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_char(v)
+        WriteStarlark::new(&DEFAULT).serialize_char(v)
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_str(v)
+        WriteStarlark::new(&DEFAULT).serialize_str(v)
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_bytes(v)
+        WriteStarlark::new(&DEFAULT).serialize_bytes(v)
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_none()
+        WriteStarlark::new(&DEFAULT).serialize_none()
     }
 
     fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize + ?Sized,
     {
-        WriteStarlark::new().serialize_some(value)
+        WriteStarlark::new(&DEFAULT).serialize_some(value)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_unit()
+        WriteStarlark::new(&DEFAULT).serialize_unit()
     }
 
     fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_unit_struct(name)
+        WriteStarlark::new(&DEFAULT).serialize_unit_struct(name)
     }
 
     fn serialize_unit_variant(
@@ -697,7 +2710,7 @@ impl serde::Serializer for Serializer {
         variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        WriteStarlark::new().serialize_unit_variant(name, variant_index, variant)
+        WriteStarlark::new(&DEFAULT).serialize_unit_variant(name, variant_index, variant)
     }
 
     fn serialize_newtype_struct<T>(
@@ -708,7 +2721,7 @@ impl serde::Serializer for Serializer {
     where
         T: Serialize + ?Sized,
     {
-        WriteStarlark::new().serialize_newtype_struct(name, value)
+        WriteStarlark::new(&DEFAULT).serialize_newtype_struct(name, value)
     }
 
     fn serialize_newtype_variant<T>(
@@ -721,15 +2734,15 @@ impl serde::Serializer for Serializer {
     where
         T: Serialize + ?Sized,
     {
-        WriteStarlark::new().serialize_newtype_variant(name, variant_index, variant, value)
+        WriteStarlark::new(&DEFAULT).serialize_newtype_variant(name, variant_index, variant, value)
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        WriteStarlark::new().serialize_seq(len)
+        WriteStarlark::new(&DEFAULT).serialize_seq(len)
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        WriteStarlark::new().serialize_tuple(len)
+        WriteStarlark::new(&DEFAULT).serialize_tuple(len)
     }
 
     fn serialize_tuple_struct(
@@ -737,7 +2750,7 @@ impl serde::Serializer for Serializer {
         name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        WriteStarlark::new().serialize_tuple_struct(name, len)
+        WriteStarlark::new(&DEFAULT).serialize_tuple_struct(name, len)
     }
 
     fn serialize_tuple_variant(
@@ -747,11 +2760,11 @@ impl serde::Serializer for Serializer {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        WriteStarlark::new().serialize_tuple_variant(name, variant_index, variant, len)
+        WriteStarlark::new(&DEFAULT).serialize_tuple_variant(name, variant_index, variant, len)
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        WriteStarlark::new().serialize_map(len)
+        WriteStarlark::new(&DEFAULT).serialize_map(len)
     }
 
     fn serialize_struct(
@@ -759,7 +2772,7 @@ impl serde::Serializer for Serializer {
         name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        WriteStarlark::new().serialize_struct(name, len)
+        WriteStarlark::new(&DEFAULT).serialize_struct(name, len)
     }
 
     fn serialize_struct_variant(
@@ -769,6 +2782,6 @@ impl serde::Serializer for Serializer {
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        WriteStarlark::new().serialize_struct_variant(name, variant_index, variant, len)
+        WriteStarlark::new(&DEFAULT).serialize_struct_variant(name, variant_index, variant, len)
     }
 }
@@ -0,0 +1,351 @@
+use crate::document::Statement;
+use crate::error;
+use crate::ser::bare_string;
+use crate::Error;
+use serde::ser::{Impossible, Serialize, SerializeStruct, SerializeTupleStruct};
+use std::collections::BTreeMap;
+
+// The arguments of a `load`: the file, and the symbols loaded from it as pairs
+// of local name and exported name.
+pub(crate) struct Load {
+    module: String,
+    symbols: Vec<(String, String)>,
+}
+
+pub(crate) fn merge_loads<'a, I, S>(statements: I) -> Vec<Statement<'a, S>>
+where
+    I: IntoIterator<Item = &'a S>,
+    S: Serialize + 'a,
+{
+    let mut merged = Vec::new();
+    let mut modules = BTreeMap::new();
+    for statement in statements {
+        let load = if let Ok(load) = statement.serialize(LoadProbe) {
+            load
+        } else {
+            merged.push(Statement::Verbatim(statement));
+            continue;
+        };
+        if let Some(&index) = modules.get(&load.module) {
+            if let Statement::Load {
+                load: first,
+                merged,
+                ..
+            } = &mut merged[index]
+            {
+                first.symbols.extend(load.symbols);
+                *merged = true;
+            }
+        } else {
+            modules.insert(load.module.clone(), merged.len());
+            merged.push(Statement::Load {
+                first: statement,
+                load,
+                merged: false,
+            });
+        }
+    }
+    merged
+}
+
+// Written the way buildifier normalizes a load: symbols loaded under their own
+// name first, then aliases, each group sorted and without duplicates.
+impl Serialize for Load {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut symbols: Vec<(bool, &str, &str)> = self
+            .symbols
+            .iter()
+            .map(|(local, exported)| (local != exported, local.as_str(), exported.as_str()))
+            .collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+        let mut call = serializer.serialize_struct("load", 1 + symbols.len())?;
+        call.serialize_field("", &self.module)?;
+        for (alias, local, exported) in symbols {
+            if alias {
+                call.serialize_field("*key", local)?;
+                call.serialize_field("*value", exported)?;
+            } else {
+                call.serialize_field("", exported)?;
+            }
+        }
+        call.end()
+    }
+}
+
+// Recognizes a statement that is a call of `load` with only string arguments,
+// whether serialized as a tuple struct or struct named `load` or by way of
+// FunctionCall. Anything else is an error.
+struct LoadProbe;
+
+impl serde::Serializer for LoadProbe {
+    type Ok = Load;
+    type Error = Error;
+    type SerializeSeq = Impossible<Load, Error>;
+    type SerializeTuple = Impossible<Load, Error>;
+    type SerializeTupleStruct = LoadArgs;
+    type SerializeTupleVariant = Impossible<Load, Error>;
+    type SerializeMap = Impossible<Load, Error>;
+    type SerializeStruct = LoadArgs;
+    type SerializeStructVariant = Impossible<Load, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_none(self) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Load, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Load, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Load, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Load, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        LoadArgs::new(name)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        LoadArgs::new(name)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(error::unsupported_call())
+    }
+}
+
+struct LoadArgs {
+    // Whether the function name is still to come, as in FunctionCall.
+    rename: bool,
+    module: Option<String>,
+    symbols: Vec<(String, String)>,
+    key: Option<String>,
+}
+
+impl LoadArgs {
+    fn new(name: &str) -> Result<Self, Error> {
+        if name != "load" && name != "(" {
+            return Err(error::unsupported_call());
+        }
+        Ok(LoadArgs {
+            rename: name == "(",
+            module: None,
+            symbols: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn positional<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let string = bare_string(value)?;
+        if self.rename {
+            if string != "load" {
+                return Err(error::unsupported_call());
+            }
+            self.rename = false;
+        } else if self.module.is_none() {
+            self.module = Some(string);
+        } else {
+            self.symbols.push((string.clone(), string));
+        }
+        Ok(())
+    }
+
+    fn named<T>(&mut self, local: String, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        if self.module.is_none() {
+            return Err(error::unsupported_call());
+        }
+        let exported = bare_string(value)?;
+        self.symbols.push((local, exported));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Load, Error> {
+        match self.module {
+            Some(module) => Ok(Load {
+                module,
+                symbols: self.symbols,
+            }),
+            None => Err(error::unsupported_call()),
+        }
+    }
+}
+
+impl SerializeTupleStruct for LoadArgs {
+    type Ok = Load;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.positional(value)
+    }
+
+    fn end(self) -> Result<Load, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStruct for LoadArgs {
+    type Ok = Load;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        match key {
+            "" => self.positional(value),
+            "*key" => {
+                self.key = Some(bare_string(value)?);
+                Ok(())
+            }
+            "*value" => {
+                let local = self.key.take().unwrap_or_default();
+                self.named(local, value)
+            }
+            _ => self.named(key.to_owned(), value),
+        }
+    }
+
+    fn end(self) -> Result<Load, Error> {
+        self.finish()
+    }
+}
@@ -0,0 +1,403 @@
+use crate::document::Statement;
+use crate::error;
+use crate::ser::bare_string;
+use crate::{Error, StatementOrder};
+use serde::ser::{Impossible, Serialize, SerializeStruct, SerializeTupleStruct};
+
+// Put the statements of a Document in order. Statements that are not targets,
+// such as loads and assignments, keep their order in front of all targets.
+// Then statements are grouped by section, with the untagged statements first
+// and each section introduced by a header.
+pub(crate) fn rearrange<S>(
+    statements: &mut Vec<Statement<S>>,
+    order: StatementOrder,
+    section_order: &[&str],
+) where
+    S: Serialize,
+{
+    let mut keyed: Vec<(Option<Target>, Statement<S>)> = statements
+        .drain(..)
+        .map(|statement| {
+            let target = match &statement {
+                Statement::Verbatim(statement) => statement.serialize(TargetProbe).ok(),
+                Statement::Load { .. } | Statement::Header(_) => None,
+            };
+            (target, statement)
+        })
+        .collect();
+
+    if order != StatementOrder::Insertion {
+        keyed.sort_by(|(a, _), (b, _)| {
+            let a = a.as_ref().and_then(Target::key);
+            let b = b.as_ref().and_then(Target::key);
+            match (a, b) {
+                (Some(a), Some(b)) => match order {
+                    StatementOrder::Insertion | StatementOrder::Name => a.1.cmp(b.1),
+                    StatementOrder::KindAndName => a.cmp(&b),
+                },
+                _ => a.is_some().cmp(&b.is_some()),
+            }
+        });
+    }
+
+    let mut sections: Vec<String> = section_order
+        .iter()
+        .map(|&label| label.to_owned())
+        .collect();
+    for (target, _) in &keyed {
+        if let Some(label) = target.as_ref().and_then(|target| target.section.as_ref()) {
+            if !sections.contains(label) {
+                sections.push(label.clone());
+            }
+        }
+    }
+    if sections.is_empty() {
+        statements.extend(keyed.into_iter().map(|(_, statement)| statement));
+        return;
+    }
+    let position = |target: &Option<Target>| {
+        let label = target.as_ref().and_then(|target| target.section.as_ref());
+        label.map(|label| sections.iter().position(|section| section == label))
+    };
+    keyed.sort_by_key(|(target, _)| position(target));
+    let mut current = None;
+    for (target, statement) in keyed {
+        let section = position(&target);
+        if section != current {
+            if let Some(Some(index)) = section {
+                statements.push(Statement::Header(sections[index].clone()));
+            }
+            current = section;
+        }
+        statements.push(statement);
+    }
+}
+
+// The rule or macro called by a statement, the value of its `name` argument,
+// and the section that the statement is in.
+struct Target {
+    kind: String,
+    name: Option<String>,
+    section: Option<String>,
+}
+
+impl Target {
+    fn key(&self) -> Option<(&str, &str)> {
+        let name = self.name.as_ref()?;
+        Some((&self.kind, name))
+    }
+}
+
+// Finds the function called by a statement serialized as a struct, as a tuple
+// struct, or by way of FunctionCall, looking through comments and layout
+// wrappers. The values of arguments other than `name` are never serialized.
+struct TargetProbe;
+
+impl serde::Serializer for TargetProbe {
+    type Ok = Target;
+    type Error = Error;
+    type SerializeSeq = Impossible<Target, Error>;
+    type SerializeTuple = Impossible<Target, Error>;
+    type SerializeTupleStruct = TargetArgs;
+    type SerializeTupleVariant = Impossible<Target, Error>;
+    type SerializeMap = Impossible<Target, Error>;
+    type SerializeStruct = TargetArgs;
+    type SerializeStructVariant = Impossible<Target, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_none(self) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Target, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Target, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Target, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Target, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        TargetArgs::new(name)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(error::unsupported_call())
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        TargetArgs::new(name)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(error::unsupported_call())
+    }
+}
+
+enum TargetArgs {
+    // A LineComment or BlockComment, whose second field is the statement.
+    Comment {
+        len: usize,
+        target: Option<Target>,
+    },
+    // A Section, whose statement need not be a function call.
+    Section {
+        len: usize,
+        label: Option<String>,
+        target: Option<Target>,
+    },
+    // A function call whose name is its first field.
+    Rename,
+    Call {
+        target: Target,
+        name_key: bool,
+    },
+}
+
+impl TargetArgs {
+    fn new(name: &'static str) -> Result<Self, Error> {
+        match name {
+            "#" | "*block" => Ok(TargetArgs::Comment {
+                len: 0,
+                target: None,
+            }),
+            "*section" => Ok(TargetArgs::Section {
+                len: 0,
+                label: None,
+                target: None,
+            }),
+            "(" => Ok(TargetArgs::Rename),
+            _ if name.starts_with('*') || name == "=" || name == "+" => {
+                Err(error::unsupported_call())
+            }
+            _ => Ok(TargetArgs::Call {
+                target: Target {
+                    kind: name.to_owned(),
+                    name: None,
+                    section: None,
+                },
+                name_key: false,
+            }),
+        }
+    }
+
+    fn field<T>(&mut self, key: &str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        match self {
+            TargetArgs::Comment { len, target } => {
+                if *len == 1 {
+                    *target = Some(value.serialize(TargetProbe)?);
+                }
+                *len += 1;
+            }
+            TargetArgs::Section { len, label, target } => {
+                if *len == 0 {
+                    *label = Some(bare_string(value)?);
+                } else if *len == 1 {
+                    *target = value.serialize(TargetProbe).ok();
+                }
+                *len += 1;
+            }
+            TargetArgs::Rename => {
+                *self = TargetArgs::Call {
+                    target: Target {
+                        kind: bare_string(value)?,
+                        name: None,
+                        section: None,
+                    },
+                    name_key: false,
+                };
+            }
+            TargetArgs::Call { target, name_key } => match key {
+                "name" => target.name = Some(bare_string(value)?),
+                "*key" => *name_key = bare_string(value)? == "name",
+                "*value" if *name_key => target.name = Some(bare_string(value)?),
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Target, Error> {
+        match self {
+            TargetArgs::Section {
+                label: Some(label),
+                target,
+                ..
+            } => {
+                let mut target = target.unwrap_or(Target {
+                    kind: String::new(),
+                    name: None,
+                    section: None,
+                });
+                target.section = Some(label);
+                Ok(target)
+            }
+            TargetArgs::Comment {
+                target: Some(target),
+                ..
+            }
+            | TargetArgs::Call { target, .. } => Ok(target),
+            TargetArgs::Comment { target: None, .. }
+            | TargetArgs::Section { label: None, .. }
+            | TargetArgs::Rename => Err(error::unsupported_call()),
+        }
+    }
+}
+
+impl SerializeTupleStruct for TargetArgs {
+    type Ok = Target;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.field("", value)
+    }
+
+    fn end(self) -> Result<Target, Error> {
+        self.finish()
+    }
+}
+
+impl SerializeStruct for TargetArgs {
+    type Ok = Target;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Target, Error> {
+        self.finish()
+    }
+}
@@ -0,0 +1,868 @@
+use crate::error;
+use crate::ident;
+use crate::lex::{Lexer, Token, TokenKind};
+use crate::Error;
+use std::ops::Range;
+
+// Syntax tree of a Starlark file. The constructs that appear in BUILD files
+// are parsed in full, along with the comments next to them. Anything else,
+// such as a `def` or a list comprehension, is parsed only far enough to find
+// where it ends, and is represented by its span in the source.
+
+pub(crate) struct Statement<'a> {
+    pub kind: StatementKind<'a>,
+    pub comments: Comments<'a>,
+    // From the first comment line above the statement to the end of its last
+    // line, including any comment there.
+    pub span: Range<usize>,
+    // Whether there is a comment somewhere inside the statement that the
+    // syntax tree has no place for, like between a function and its argument
+    // list.
+    pub stray_comment: bool,
+}
+
+pub(crate) enum StatementKind<'a> {
+    // Comment lines separated by a blank line from the statement below them,
+    // or at the end of the file.
+    Comment,
+    Expression(Expr<'a>),
+    Assignment {
+        target: Expr<'a>,
+        op: &'a str,
+        value: Expr<'a>,
+    },
+    // `def`, `if`, `for`, or `return`.
+    Other,
+}
+
+#[derive(Default)]
+pub(crate) struct Comments<'a> {
+    pub blank_line_before: bool,
+    // Comment lines directly above, each including its `#`.
+    pub leading: Vec<&'a str>,
+    // Comment at the end of the last line.
+    pub suffix: Option<&'a str>,
+}
+
+pub(crate) struct Expr<'a> {
+    pub kind: ExprKind<'a>,
+    pub span: Range<usize>,
+}
+
+pub(crate) enum ExprKind<'a> {
+    Identifier(&'a str),
+    Int(&'a str),
+    Float(&'a str),
+    // The literal as written, including prefix and quotes.
+    String(&'a str),
+    List(Group<'a, Expr<'a>>),
+    Tuple(Group<'a, Expr<'a>>),
+    Dict(Group<'a, (Expr<'a>, Expr<'a>)>),
+    Call(Box<Expr<'a>>, Group<'a, Argument<'a>>),
+    Dot(Box<Expr<'a>>, &'a str),
+    Unary(&'a str, Box<Expr<'a>>),
+    Binary(Box<Expr<'a>>, &'a str, Box<Expr<'a>>),
+    // Comprehension, conditional expression, lambda, index or slice,
+    // parenthesized expression, or a tuple without parentheses.
+    Other,
+}
+
+// The contents of a pair of brackets.
+pub(crate) struct Group<'a, T> {
+    pub items: Vec<Item<'a, T>>,
+    // From the opening bracket through the closing bracket.
+    pub span: Range<usize>,
+    pub trailing_comma: bool,
+    // Comment on the same line as the opening bracket.
+    pub open_comment: Option<&'a str>,
+    // Comment lines after the last item.
+    pub trailing: Vec<&'a str>,
+}
+
+pub(crate) struct Item<'a, T> {
+    pub value: T,
+    pub comments: Comments<'a>,
+    // The item apart from its comments, including `name =` if this is a
+    // named argument.
+    pub span: Range<usize>,
+    // Offset of the comma after the item, if any.
+    pub comma: Option<usize>,
+}
+
+pub(crate) enum Argument<'a> {
+    Positional(Expr<'a>),
+    Named(&'a str, Expr<'a>),
+    Args(Expr<'a>),
+    Kwargs(Expr<'a>),
+}
+
+impl<'a, T> Group<'a, T> {
+    pub fn has_comments(&self) -> bool {
+        self.open_comment.is_some()
+            || !self.trailing.is_empty()
+            || self
+                .items
+                .iter()
+                .any(|item| !item.comments.leading.is_empty() || item.comments.suffix.is_some())
+    }
+}
+
+impl<'a, T> Item<'a, T> {
+    // Past the comma, if any.
+    pub fn end(&self) -> usize {
+        match self.comma {
+            Some(comma) => comma + 1,
+            None => self.span.end,
+        }
+    }
+}
+
+pub(crate) fn parse(source: &str) -> Result<Vec<Statement<'_>>, Error> {
+    let tokens = Lexer::new(source).collect::<Result<Vec<_>, _>>()?;
+    let mut line_starts = vec![0];
+    line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+    let mut parser = Parser {
+        source,
+        tokens,
+        line_starts,
+        pos: 0,
+        prev_end: 0,
+        stray_comment: false,
+    };
+    parser.module()
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token<'a>>,
+    line_starts: Vec<usize>,
+    pos: usize,
+    // End of the last token consumed, not counting comments.
+    prev_end: usize,
+    stray_comment: bool,
+}
+
+// Comment lines with no blank line between them.
+struct CommentGroup<'a> {
+    lines: Vec<&'a str>,
+    span: Range<usize>,
+    blank_line_before: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    fn column(&self, offset: usize) -> usize {
+        offset - self.line_starts[self.line(offset)]
+    }
+
+    fn error(&self, msg: &'static str, offset: usize) -> Error {
+        error::syntax(msg, self.source, offset)
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.peek() {
+            None => self.error("unexpected end of file", self.source.len()),
+            Some(token) => {
+                let msg = match token.kind {
+                    TokenKind::Newline => "unexpected end of line",
+                    TokenKind::Indent => "unexpected indentation",
+                    TokenKind::Identifier if ident::is_keyword(token.text) => "unexpected keyword",
+                    _ => "unexpected token",
+                };
+                self.error(msg, token.span.start)
+            }
+        }
+    }
+
+    // The next token including comments.
+    fn raw(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    // Index of the next token other than a comment.
+    fn next_index(&self) -> usize {
+        let mut i = self.pos;
+        while self
+            .tokens
+            .get(i)
+            .map_or(false, |t| t.kind == TokenKind::Comment)
+        {
+            i += 1;
+        }
+        i
+    }
+
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.next_index()).cloned()
+    }
+
+    // Consume the next token other than a comment. Comments skipped over here
+    // have nowhere to go in the syntax tree.
+    fn bump(&mut self) -> Token<'a> {
+        let i = self.next_index();
+        if i > self.pos {
+            self.stray_comment = true;
+        }
+        let token = self.tokens[i].clone();
+        self.pos = i + 1;
+        self.prev_end = token.span.end;
+        token
+    }
+
+    fn peek_is(&self, punctuation: &str) -> bool {
+        self.peek().map_or(false, |token| token.is(punctuation))
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        self.peek().map_or(false, |token| {
+            token.kind == TokenKind::Identifier && token.text == keyword
+        })
+    }
+
+    fn eat(&mut self, punctuation: &str) -> bool {
+        let found = self.peek_is(punctuation);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expect(&mut self, punctuation: &str) -> Result<(), Error> {
+        if self.eat(punctuation) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn node(&self, kind: ExprKind<'a>, start: usize) -> Expr<'a> {
+        Expr {
+            kind,
+            span: start..self.prev_end,
+        }
+    }
+
+    fn module(&mut self) -> Result<Vec<Statement<'a>>, Error> {
+        let mut statements = Vec::new();
+        loop {
+            let mut groups = self.comment_groups();
+            let start = match self.raw() {
+                Some(token) => token.span.start,
+                None => 0,
+            };
+            let attached = match groups.last() {
+                Some(group)
+                    if self.raw().is_some()
+                        && self.line(start) == self.line(group.span.end) + 1 =>
+                {
+                    groups.pop()
+                }
+                _ => None,
+            };
+            for group in groups {
+                statements.push(Statement {
+                    kind: StatementKind::Comment,
+                    comments: Comments {
+                        blank_line_before: group.blank_line_before,
+                        leading: group.lines,
+                        suffix: None,
+                    },
+                    span: group.span,
+                    stray_comment: false,
+                });
+            }
+            if self.raw().is_none() {
+                return Ok(statements);
+            }
+            statements.push(self.statement(attached)?);
+        }
+    }
+
+    // Comment lines between top-level statements, along with the ends of the
+    // blocks of indented statements before them.
+    fn comment_groups(&mut self) -> Vec<CommentGroup<'a>> {
+        let mut groups: Vec<CommentGroup> = Vec::new();
+        let mut last_line = self.line(self.prev_end);
+        while let Some(token) = self.raw() {
+            match token.kind {
+                TokenKind::Newline | TokenKind::Dedent => {}
+                TokenKind::Comment => {
+                    let line = self.line(token.span.start);
+                    match groups.last_mut() {
+                        Some(group) if line == last_line + 1 => {
+                            group.lines.push(token.text);
+                            group.span.end = token.span.end;
+                        }
+                        _ => groups.push(CommentGroup {
+                            lines: vec![token.text],
+                            span: token.span.clone(),
+                            blank_line_before: self.prev_end > 0 && line > last_line + 1,
+                        }),
+                    }
+                    last_line = line;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        groups
+    }
+
+    fn statement(&mut self, leading: Option<CommentGroup<'a>>) -> Result<Statement<'a>, Error> {
+        self.stray_comment = false;
+        let token = self.peek().unwrap();
+        let start = match &leading {
+            Some(group) => group.span.start,
+            None => token.span.start,
+        };
+        let blank_line_before = match &leading {
+            Some(group) => group.blank_line_before,
+            None => self.prev_end > 0 && self.line(token.span.start) > self.line(self.prev_end) + 1,
+        };
+        let mut comments = Comments {
+            blank_line_before,
+            leading: leading.map_or_else(Vec::new, |group| group.lines),
+            suffix: None,
+        };
+
+        let is_keyword = |keywords: &[&str]| {
+            token.kind == TokenKind::Identifier && keywords.contains(&token.text)
+        };
+        if is_keyword(&["def", "if", "for", "while"]) {
+            let end = self.block()?;
+            return Ok(Statement {
+                kind: StatementKind::Other,
+                comments,
+                span: start..end,
+                stray_comment: self.stray_comment,
+            });
+        }
+
+        let kind = if is_keyword(&["return", "pass", "break", "continue"]) {
+            self.rest_of_line();
+            StatementKind::Other
+        } else {
+            let expr = self.expression_list()?;
+            match self.peek() {
+                Some(token) if is_assignment_operator(&token) => {
+                    self.bump();
+                    let value = self.expression_list()?;
+                    StatementKind::Assignment {
+                        target: expr,
+                        op: token.text,
+                        value,
+                    }
+                }
+                _ => StatementKind::Expression(expr),
+            }
+        };
+
+        let mut end = self.prev_end;
+        if self.eat(";")
+            && !self
+                .peek()
+                .map_or(true, |token| token.kind == TokenKind::Newline)
+        {
+            // Another statement follows on the same line.
+            return Ok(Statement {
+                kind,
+                comments,
+                span: start..end,
+                stray_comment: self.stray_comment,
+            });
+        }
+        if let Some(token) = self.raw() {
+            if token.kind == TokenKind::Comment && self.line(token.span.start) == self.line(end) {
+                comments.suffix = Some(token.text);
+                end = token.span.end;
+                self.pos += 1;
+            }
+        }
+        match self.peek() {
+            None => {}
+            Some(token) if token.kind == TokenKind::Newline => {
+                self.pos = self.next_index() + 1;
+            }
+            Some(_) => return Err(self.unexpected()),
+        }
+        Ok(Statement {
+            kind,
+            comments,
+            span: start..end,
+            stray_comment: self.stray_comment,
+        })
+    }
+
+    // Skip the tokens of a simple statement up to its final newline, including
+    // a comment at the end of the line.
+    fn rest_of_line(&mut self) {
+        while let Some(token) = self.raw() {
+            if token.kind == TokenKind::Newline {
+                break;
+            }
+            self.prev_end = token.span.end;
+            self.pos += 1;
+        }
+    }
+
+    // Skip a compound statement, including any `elif` or `else` clauses, and
+    // return the end of its last line. Comment lines after the block belong
+    // to it only if they are indented.
+    fn block(&mut self) -> Result<usize, Error> {
+        loop {
+            self.rest_of_line();
+            if self.raw().is_some() {
+                self.pos += 1;
+            }
+            let mut end = self.prev_end;
+            let mut next = self.pos;
+            while self
+                .tokens
+                .get(next)
+                .map_or(false, |t| t.kind == TokenKind::Comment)
+            {
+                next += 1;
+            }
+            if self
+                .tokens
+                .get(next)
+                .map_or(true, |t| t.kind != TokenKind::Indent)
+            {
+                return Ok(end);
+            }
+            let mut depth = 0;
+            let mut last = next;
+            let mut i = next;
+            while let Some(token) = self.tokens.get(i) {
+                match token.kind {
+                    TokenKind::Indent => depth += 1,
+                    TokenKind::Dedent => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    TokenKind::Newline | TokenKind::Comment => {}
+                    _ => last = i,
+                }
+                i += 1;
+            }
+            end = self.tokens[last].span.end;
+            self.prev_end = end;
+            self.pos = last + 1;
+            while let Some(token) = self.raw() {
+                match token.kind {
+                    TokenKind::Newline => {}
+                    TokenKind::Comment if self.column(token.span.start) > 0 => {
+                        end = token.span.end;
+                    }
+                    _ => break,
+                }
+                self.pos += 1;
+            }
+            let resume = self.pos;
+            while self.raw().map_or(false, |t| {
+                matches!(t.kind, TokenKind::Comment | TokenKind::Dedent)
+            }) {
+                self.pos += 1;
+            }
+            let clause = self.raw().map_or(false, |t| {
+                t.kind == TokenKind::Identifier && (t.text == "elif" || t.text == "else")
+            });
+            if !clause {
+                self.pos = resume;
+                return Ok(end);
+            }
+        }
+    }
+
+    // An expression, or several separated by commas as on either side of an
+    // assignment.
+    fn expression_list(&mut self) -> Result<Expr<'a>, Error> {
+        let first = self.test()?;
+        if !self.peek_is(",") {
+            return Ok(first);
+        }
+        while self.eat(",") {
+            match self.peek() {
+                None => break,
+                Some(token)
+                    if token.kind == TokenKind::Newline
+                        || token.is(";")
+                        || is_assignment_operator(&token) =>
+                {
+                    break;
+                }
+                Some(_) => {
+                    self.test()?;
+                }
+            }
+        }
+        Ok(self.node(ExprKind::Other, first.span.start))
+    }
+
+    fn test(&mut self) -> Result<Expr<'a>, Error> {
+        let start = match self.peek() {
+            Some(token) => token.span.start,
+            None => return Err(self.unexpected()),
+        };
+        if self.eat_keyword("lambda") {
+            let mut depth = 0;
+            loop {
+                match self.peek() {
+                    Some(token) if token.kind != TokenKind::Newline => {}
+                    _ => return Err(self.unexpected()),
+                }
+                let token = self.bump();
+                match token.text {
+                    "(" | "[" | "{" => depth += 1,
+                    ")" | "]" | "}" => depth -= 1,
+                    ":" if depth == 0 => break,
+                    _ => {}
+                }
+            }
+            self.test()?;
+            return Ok(self.node(ExprKind::Other, start));
+        }
+        let expr = self.or_test()?;
+        if self.eat_keyword("if") {
+            self.or_test()?;
+            self.expect_keyword("else")?;
+            self.test()?;
+            return Ok(self.node(ExprKind::Other, start));
+        }
+        Ok(expr)
+    }
+
+    fn or_test(&mut self) -> Result<Expr<'a>, Error> {
+        let mut left = self.and_test()?;
+        while self.eat_keyword("or") {
+            let right = self.and_test()?;
+            left = self.binary_node(left, "or", right);
+        }
+        Ok(left)
+    }
+
+    fn and_test(&mut self) -> Result<Expr<'a>, Error> {
+        let mut left = self.not_test()?;
+        while self.eat_keyword("and") {
+            let right = self.not_test()?;
+            left = self.binary_node(left, "and", right);
+        }
+        Ok(left)
+    }
+
+    fn not_test(&mut self) -> Result<Expr<'a>, Error> {
+        let start = self.peek().map_or(0, |token| token.span.start);
+        if self.eat_keyword("not") {
+            let operand = self.not_test()?;
+            return Ok(self.node(ExprKind::Unary("not", Box::new(operand)), start));
+        }
+        self.binary(1)
+    }
+
+    fn binary_node(&self, left: Expr<'a>, op: &'a str, right: Expr<'a>) -> Expr<'a> {
+        let start = left.span.start;
+        self.node(ExprKind::Binary(Box::new(left), op, Box::new(right)), start)
+    }
+
+    // Comparisons and arithmetic, by precedence climbing.
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr<'a>, Error> {
+        let mut left = self.unary()?;
+        loop {
+            let (op, precedence) = match self.binary_operator() {
+                Some((op, precedence)) if precedence >= min_precedence => (op, precedence),
+                _ => return Ok(left),
+            };
+            self.bump();
+            if op == "not in" {
+                self.bump();
+            }
+            let right = self.binary(precedence + 1)?;
+            left = self.binary_node(left, op, right);
+        }
+    }
+
+    fn binary_operator(&self) -> Option<(&'static str, u8)> {
+        let token = self.peek()?;
+        let op = match token.kind {
+            TokenKind::Punctuation => token.text,
+            TokenKind::Identifier if token.text == "in" => "in",
+            TokenKind::Identifier if token.text == "not" => {
+                let next = self.tokens.get(self.next_index() + 1)?;
+                if next.kind == TokenKind::Identifier && next.text == "in" {
+                    "not in"
+                } else {
+                    return None;
+                }
+            }
+            _ => return None,
+        };
+        Some(match op {
+            "==" => ("==", 1),
+            "!=" => ("!=", 1),
+            "<" => ("<", 1),
+            ">" => (">", 1),
+            "<=" => ("<=", 1),
+            ">=" => (">=", 1),
+            "in" => ("in", 1),
+            "not in" => ("not in", 1),
+            "|" => ("|", 2),
+            "^" => ("^", 3),
+            "&" => ("&", 4),
+            "<<" => ("<<", 5),
+            ">>" => (">>", 5),
+            "+" => ("+", 6),
+            "-" => ("-", 6),
+            "*" => ("*", 7),
+            "/" => ("/", 7),
+            "//" => ("//", 7),
+            "%" => ("%", 7),
+            _ => return None,
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr<'a>, Error> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Err(self.unexpected()),
+        };
+        let op = match token.text {
+            "-" | "+" | "~" if token.kind == TokenKind::Punctuation => token.text,
+            _ => return self.primary(),
+        };
+        self.bump();
+        let operand = self.unary()?;
+        Ok(self.node(ExprKind::Unary(op, Box::new(operand)), token.span.start))
+    }
+
+    fn primary(&mut self) -> Result<Expr<'a>, Error> {
+        let mut expr = self.operand()?;
+        let start = expr.span.start;
+        loop {
+            if self.eat(".") {
+                let name = match self.peek() {
+                    Some(token) if token.kind == TokenKind::Identifier => self.bump().text,
+                    _ => return Err(self.unexpected()),
+                };
+                expr = self.node(ExprKind::Dot(Box::new(expr), name), start);
+            } else if self.eat("(") {
+                let args = self.items(")", false, Self::argument)?.unwrap();
+                expr = self.node(ExprKind::Call(Box::new(expr), args), start);
+            } else if self.eat("[") {
+                while !self.eat("]") {
+                    if !self.eat(":") && !self.eat(",") {
+                        self.test()?;
+                    }
+                }
+                expr = self.node(ExprKind::Other, start);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr<'a>, Error> {
+        let token = match self.peek() {
+            Some(token) => token,
+            None => return Err(self.unexpected()),
+        };
+        let start = token.span.start;
+        let kind = match token.kind {
+            TokenKind::Identifier if token.text == "load" || !ident::is_keyword(token.text) => {
+                ExprKind::Identifier(token.text)
+            }
+            TokenKind::Int => ExprKind::Int(token.text),
+            TokenKind::Float => ExprKind::Float(token.text),
+            TokenKind::String => ExprKind::String(token.text),
+            TokenKind::Punctuation if token.text == "(" => {
+                self.bump();
+                let items = self.items(")", false, Self::test)?.unwrap();
+                let paren = items.items.len() == 1 && !items.trailing_comma;
+                let kind = if paren {
+                    if items.has_comments() {
+                        self.stray_comment = true;
+                    }
+                    ExprKind::Other
+                } else {
+                    ExprKind::Tuple(items)
+                };
+                return Ok(self.node(kind, start));
+            }
+            TokenKind::Punctuation if token.text == "[" => {
+                self.bump();
+                let kind = match self.items("]", true, Self::test)? {
+                    Some(items) => ExprKind::List(items),
+                    None => ExprKind::Other,
+                };
+                return Ok(self.node(kind, start));
+            }
+            TokenKind::Punctuation if token.text == "{" => {
+                self.bump();
+                let kind = match self.items("}", true, Self::entry)? {
+                    Some(items) => ExprKind::Dict(items),
+                    None => ExprKind::Other,
+                };
+                return Ok(self.node(kind, start));
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.bump();
+        Ok(self.node(kind, start))
+    }
+
+    fn entry(&mut self) -> Result<(Expr<'a>, Expr<'a>), Error> {
+        let key = self.test()?;
+        self.expect(":")?;
+        let value = self.test()?;
+        Ok((key, value))
+    }
+
+    fn argument(&mut self) -> Result<Argument<'a>, Error> {
+        if self.eat("**") {
+            return self.test().map(Argument::Kwargs);
+        }
+        if self.eat("*") {
+            return self.test().map(Argument::Args);
+        }
+        let named = match (self.peek(), self.tokens.get(self.next_index() + 1)) {
+            (Some(name), Some(eq)) if name.kind == TokenKind::Identifier && eq.is("=") => {
+                Some(name.text)
+            }
+            _ => None,
+        };
+        match named {
+            Some(name) => {
+                self.bump();
+                self.bump();
+                Ok(Argument::Named(name, self.test()?))
+            }
+            None => self.test().map(Argument::Positional),
+        }
+    }
+
+    // The comma-separated items after an opening bracket, through the closing
+    // bracket. Returns None for a comprehension, if allowed.
+    fn items<T>(
+        &mut self,
+        close: &str,
+        comprehension: bool,
+        mut item: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Option<Group<'a, T>>, Error> {
+        let mut items = Group {
+            items: Vec::new(),
+            span: self.prev_end - 1..self.prev_end,
+            trailing_comma: false,
+            open_comment: None,
+            trailing: Vec::new(),
+        };
+        loop {
+            let mut comments = Comments::default();
+            let mut last_line = self.line(self.prev_end);
+            while let Some(token) = self.raw() {
+                if token.kind != TokenKind::Comment {
+                    break;
+                }
+                let line = self.line(token.span.start);
+                if line == last_line && comments.leading.is_empty() {
+                    // On the same line as the previous item, or as the
+                    // opening bracket.
+                    match items.items.last_mut() {
+                        Some(prev) if prev.comments.suffix.is_none() => {
+                            prev.comments.suffix = Some(token.text);
+                        }
+                        None if items.open_comment.is_none() => {
+                            items.open_comment = Some(token.text);
+                        }
+                        _ => self.stray_comment = true,
+                    }
+                } else {
+                    if comments.leading.is_empty() && line > last_line + 1 {
+                        comments.blank_line_before = !items.items.is_empty();
+                    }
+                    comments.leading.push(token.text);
+                }
+                last_line = line;
+                self.pos += 1;
+            }
+            if self.eat(close) {
+                items.span.end = self.prev_end;
+                items.trailing = comments.leading;
+                return Ok(Some(items));
+            }
+            if !items.items.is_empty() && !items.trailing_comma {
+                return Err(self.unexpected());
+            }
+            if comments.leading.is_empty() && !items.items.is_empty() {
+                let line = self.peek().map_or(last_line, |t| self.line(t.span.start));
+                comments.blank_line_before = line > last_line + 1;
+            }
+            let start = self.peek().map_or(self.prev_end, |token| token.span.start);
+            let value = item(self)?;
+            if comprehension && items.items.is_empty() && self.peek_keyword("for") {
+                self.comprehension(close)?;
+                return Ok(None);
+            }
+            let span = start..self.prev_end;
+            items.trailing_comma = self.eat(",");
+            let comma = if items.trailing_comma {
+                Some(self.prev_end - 1)
+            } else {
+                None
+            };
+            items.items.push(Item {
+                value,
+                comments,
+                span,
+                comma,
+            });
+        }
+    }
+
+    // The `for` and `if` clauses of a comprehension, through the closing
+    // bracket.
+    fn comprehension(&mut self, close: &str) -> Result<(), Error> {
+        loop {
+            if self.eat_keyword("for") {
+                while !self.eat_keyword("in") {
+                    match self.peek() {
+                        Some(token) if token.kind != TokenKind::Newline => {
+                            self.bump();
+                        }
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+                self.or_test()?;
+            } else if self.eat_keyword("if") {
+                self.or_test()?;
+            } else {
+                return self.expect(close);
+            }
+        }
+    }
+}
+
+fn is_assignment_operator(token: &Token) -> bool {
+    token.kind == TokenKind::Punctuation
+        && token.text.ends_with('=')
+        && !matches!(token.text, "==" | "!=" | "<=" | ">=")
+}
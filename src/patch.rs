@@ -0,0 +1,294 @@
+use crate::cst::{line_end, line_start, Cst, Target};
+use crate::parse::{Statement, StatementKind};
+use crate::{Config, Error};
+use serde::ser::Serialize;
+use std::ops::Range;
+
+impl Config {
+    /// Update previously generated Starlark in place with new versions of
+    /// some of its targets.
+    ///
+    /// `previous` is the text of a [`Document`], as produced by
+    /// [`to_string`][Config::to_string] and possibly edited since. Each of the
+    /// given statements is serialized and takes the place of the statement in
+    /// `previous` that has the same `name` argument. Statements whose name
+    /// does not appear in `previous`, or that have no name, are added at the
+    /// end. Everything else is kept exactly as it was, so a generator that
+    /// knows which targets changed can serialize just those, and the diff of
+    /// the resulting file contains nothing else.
+    ///
+    /// Targets are matched by the value of their name's string literal, so
+    /// `name = 'syn'` and `name = "syn"` are the same target. It is an error if
+    /// `previous` is not syntactically valid Starlark.
+    ///
+    /// ```
+    /// use serde_derive::Serialize;
+    /// use serde_starlark::Config;
+    ///
+    /// #[derive(Serialize)]
+    /// #[serde(rename = "rust_library")]
+    /// struct RustLibrary {
+    ///     name: &'static str,
+    ///     srcs: Vec<&'static str>,
+    /// }
+    ///
+    /// let previous = r#"rust_library(
+    ///     name = "quote",
+    ///     srcs = ["src/lib.rs"],
+    /// )
+    ///
+    /// ## Keep in sync with syn.
+    /// rust_library(
+    ///     name = "proc-macro2",
+    ///     srcs = ["src/lib.rs"],
+    /// )
+    /// "#;
+    ///
+    /// let update = RustLibrary {
+    ///     name: "quote",
+    ///     srcs: vec!["src/lib.rs", "src/format.rs"],
+    /// };
+    /// let patched = Config::new().patch(previous, [update]).unwrap();
+    /// let expected = r#"rust_library(
+    ///     name = "quote",
+    ///     srcs = [
+    ///         "src/lib.rs",
+    ///         "src/format.rs",
+    ///     ],
+    /// )
+    ///
+    /// ## Keep in sync with syn.
+    /// rust_library(
+    ///     name = "proc-macro2",
+    ///     srcs = ["src/lib.rs"],
+    /// )
+    /// "#;
+    /// assert_eq!(patched, expected);
+    /// ```
+    ///
+    /// [`Document`]: crate::Document
+    pub fn patch<I>(&self, previous: &str, statements: I) -> Result<String, Error>
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+    {
+        let cst = Cst::parse(previous)?;
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        let mut appended = Appended::default();
+        for statement in statements {
+            let text = self.to_string(&statement)?;
+            let name = statement_name(&text)?;
+            let existing = name.as_deref().and_then(|name| cst.target(name));
+            match existing {
+                Some(target) => {
+                    let range = statement_range(previous, target.statement);
+                    let text = text.strip_suffix('\n').unwrap_or(&text).to_owned();
+                    match edits.iter_mut().find(|(edit, _)| *edit == range) {
+                        Some(edit) => edit.1 = text,
+                        None => edits.push((range, text)),
+                    }
+                }
+                None => appended.push(name, text),
+            }
+        }
+        Ok(appended.finish(cst.print(edits)))
+    }
+
+    /// Update the targets that a generator owns within a file that is also
+    /// edited by hand.
+    ///
+    /// A statement of `previous` is owned by the generator if the comment lines
+    /// directly above it include `# <marker>`, or if its `name` is the name of
+    /// one of the given statements. Each of the given statements is serialized
+    /// and takes the place of the owned statement with the same name, keeping
+    /// the comment lines above it. Statements without a name take the places of
+    /// the owned statements without a name, in order. The rest are added at the
+    /// end. Every statement written this way gets the marker comment, so that
+    /// the next run recognizes it, and owned statements that are not replaced
+    /// are removed. Statements that are not owned are kept byte for byte. It is
+    /// an error if `previous` is not syntactically valid Starlark.
+    ///
+    /// ```
+    /// use serde_derive::Serialize;
+    /// use serde_starlark::{Compact, Config};
+    ///
+    /// #[derive(Serialize)]
+    /// #[serde(rename = "alias")]
+    /// struct Alias {
+    ///     name: &'static str,
+    ///     actual: &'static str,
+    /// }
+    ///
+    /// let previous = r#"# generated
+    /// alias(
+    ///     name = "syn",
+    ///     actual = "//third-party:syn-1.0.109",
+    /// )
+    ///
+    /// ## generated
+    /// alias(name = "proc-macro2", actual = "//third-party:proc-macro2-1.0.50")
+    ///
+    /// ## Not managed by the vendoring script.
+    /// alias(name = "libc", actual = "@libc")
+    /// "#;
+    ///
+    /// let statements = [
+    ///     Compact(Alias {
+    ///         name: "syn",
+    ///         actual: "//third-party:syn-2.0.48",
+    ///     }),
+    ///     Compact(Alias {
+    ///         name: "quote",
+    ///         actual: "//third-party:quote-1.0.35",
+    ///     }),
+    /// ];
+    /// let patched = Config::new().patch_owned(previous, "generated", statements).unwrap();
+    /// let expected = r#"# generated
+    /// alias(name = "syn", actual = "//third-party:syn-2.0.48")
+    ///
+    /// ## Not managed by the vendoring script.
+    /// alias(name = "libc", actual = "@libc")
+    ///
+    /// ## generated
+    /// alias(name = "quote", actual = "//third-party:quote-1.0.35")
+    /// "#;
+    /// assert_eq!(patched, expected);
+    /// ```
+    pub fn patch_owned<I>(
+        &self,
+        previous: &str,
+        marker: &str,
+        statements: I,
+    ) -> Result<String, Error>
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+    {
+        let marker = format!("# {}", marker);
+        let has_marker = |comments: &[&str]| comments.iter().any(|line| line.trim_end() == marker);
+        let cst = Cst::parse(previous)?;
+        let mut owned: Vec<Owned> = cst
+            .statements
+            .iter()
+            .filter(|statement| match statement.kind {
+                StatementKind::Comment => false,
+                StatementKind::Expression(_)
+                | StatementKind::Assignment { .. }
+                | StatementKind::Other => true,
+            })
+            .map(|statement| Owned {
+                statement,
+                name: Target::of(statement).and_then(|target| target.name()),
+                stale: has_marker(&statement.comments.leading),
+            })
+            .collect();
+        let mut edits = Vec::new();
+        let mut appended = Appended::default();
+        for statement in statements {
+            let text = self.to_string(&statement)?;
+            let name = statement_name(&text)?;
+            let existing = owned.iter_mut().find(|owned| match &name {
+                Some(name) => owned.name.as_ref() == Some(name),
+                None => owned.stale && owned.name.is_none(),
+            });
+            match existing {
+                Some(existing) => {
+                    let mut block = String::new();
+                    if !has_marker(&existing.statement.comments.leading) {
+                        block.push_str(&marker);
+                        block.push('\n');
+                    }
+                    block.push_str(text.strip_suffix('\n').unwrap_or(&text));
+                    let range = statement_range(previous, existing.statement);
+                    match edits.iter_mut().find(|(edit, _)| *edit == range) {
+                        Some(edit) => edit.1 = block,
+                        None => edits.push((range, block)),
+                    }
+                    existing.stale = false;
+                }
+                None => appended.push(name, format!("{}\n{}", marker, text)),
+            }
+        }
+        let mut removed = 0;
+        for owned in owned.iter().filter(|owned| owned.stale) {
+            let mut range = removal_range(previous, owned.statement);
+            range.start = range.start.max(removed);
+            removed = range.end;
+            edits.push((range, String::new()));
+        }
+        Ok(appended.finish(cst.print(edits)))
+    }
+}
+
+// A statement of the previous text for patch_owned, and whether it is owned by
+// the generator and not yet replaced.
+struct Owned<'t, 'a> {
+    statement: &'t Statement<'a>,
+    name: Option<String>,
+    stale: bool,
+}
+
+// Statements to add at the end, of which a later one with the same name takes
+// the place of an earlier one.
+#[derive(Default)]
+struct Appended {
+    blocks: Vec<(Option<String>, String)>,
+}
+
+impl Appended {
+    fn push(&mut self, name: Option<String>, text: String) {
+        let existing = match &name {
+            Some(_) => self.blocks.iter_mut().find(|block| block.0 == name),
+            None => None,
+        };
+        match existing {
+            Some(block) => block.1 = text,
+            None => self.blocks.push((name, text)),
+        }
+    }
+
+    // Add the statements to the end of the document, each after a blank line.
+    fn finish(self, mut output: String) -> String {
+        for (_name, text) in self.blocks {
+            if !output.is_empty() && !output.ends_with("\n\n") {
+                if !output.ends_with('\n') {
+                    output.push('\n');
+                }
+                output.push('\n');
+            }
+            output.push_str(&text);
+        }
+        output
+    }
+}
+
+// The name of the target that a serialized statement defines, if it is a call
+// with a string literal `name` argument.
+fn statement_name(text: &str) -> Result<Option<String>, Error> {
+    let cst = Cst::parse(text)?;
+    let name = cst.targets().next().and_then(|target| target.name());
+    Ok(name)
+}
+
+// The statement apart from the comment lines directly above it, which are
+// kept when it is replaced.
+fn statement_range(source: &str, statement: &Statement) -> Range<usize> {
+    let mut start = statement.span.start;
+    for _ in &statement.comments.leading {
+        start = line_end(source, start);
+    }
+    start..statement.span.end
+}
+
+// The statement with the comment lines directly above it, and the blank line
+// that separated it from the next statement.
+fn removal_range(source: &str, statement: &Statement) -> Range<usize> {
+    let mut start = line_start(source, statement.span.start);
+    let mut end = line_end(source, statement.span.end);
+    if source[end..].starts_with('\n') {
+        end += 1;
+    } else if source[..start].ends_with("\n\n") {
+        start -= 1;
+    }
+    start..end
+}
@@ -0,0 +1,25 @@
+use crate::SlashPath;
+use serde::ser::{Error, Serialize, Serializer};
+use std::path::Path;
+
+impl<P> Serialize for SlashPath<P>
+where
+    P: AsRef<Path>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let path = self.0.as_ref();
+        match path.to_str() {
+            Some(string) if string.contains('\\') => {
+                serializer.serialize_str(&string.replace('\\', "/"))
+            }
+            Some(string) => serializer.serialize_str(string),
+            None => Err(S::Error::custom(format_args!(
+                "path is not valid UTF-8: {}",
+                path.display(),
+            ))),
+        }
+    }
+}
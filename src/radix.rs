@@ -0,0 +1,29 @@
+use crate::{Bin, Hex, Oct};
+use serde::ser::{Serialize, Serializer};
+
+impl Serialize for Hex {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("0x", &self.0)
+    }
+}
+
+impl Serialize for Oct {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("0o", &self.0)
+    }
+}
+
+impl Serialize for Bin {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("0b", &self.0)
+    }
+}
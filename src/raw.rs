@@ -0,0 +1,60 @@
+use crate::{Raw, RawString};
+use serde::de::{Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+
+pub(crate) const RAW: &str = "*raw";
+
+impl<'a> Serialize for RawString<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("r\"", self.string)
+    }
+}
+
+impl Serialize for Raw {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("*verbatim", &self.0)
+    }
+}
+
+// The Starlark deserializer answers a newtype struct named "*raw" with the
+// source text of the expression, whatever kind of expression it is. Other
+// deserializers give a plain string.
+impl<'de> Deserialize<'de> for Raw {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct RawVisitor;
+
+        impl<'de> Visitor<'de> for RawVisitor {
+            type Value = Raw;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a Starlark expression")
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                String::deserialize(deserializer).map(Raw)
+            }
+
+            fn visit_str<E>(self, string: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(Raw(string.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(RAW, RawVisitor)
+    }
+}
@@ -0,0 +1,429 @@
+use crate::parse::{self, Comments, Expr, ExprKind, Group, Statement, StatementKind};
+use crate::{
+    Args, Assignment, Bin, BlockComment, Compact, Config, Error, Hex, Identifier, Kwargs,
+    LineComment, Oct, RawString, Spacer, TripleQuoted,
+};
+use serde::ser::{
+    Error as _, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple,
+    SerializeTupleStruct, Serializer,
+};
+
+impl Config {
+    /// Reformat Starlark source code in the style of this crate's output.
+    ///
+    /// Each top-level statement is parsed and written out again the way this
+    /// configuration serializes the equivalent value, so that a hand-edited
+    /// BUILD file comes out laid out, indented and quoted the same as a
+    /// generated one, with one blank line between statements. Comments are
+    /// kept with the statement, argument, list element or dict entry they
+    /// were written next to.
+    ///
+    /// Statements with no counterpart among the values this crate
+    /// serializes, such as `def` or `if`, are copied exactly as written. So is
+    /// any statement containing something that could not be reformatted
+    /// without changing its meaning or losing a comment. Within an otherwise
+    /// reformatted statement, expressions like `a if b else c` or `x[1:]` are
+    /// copied as written if they fit on one line.
+    ///
+    /// ```
+    /// use serde_starlark::Config;
+    ///
+    /// let source = r#"
+    /// load('@rules_rust//rust:defs.bzl', 'rust_library')
+    /// rust_library(name = 'syn', srcs = glob(['src/**/*.rs']),
+    ///     deps = [':proc-macro2', ':quote'],   # direct
+    /// )
+    /// "#;
+    ///
+    /// let expected = r#"load("@rules_rust//rust:defs.bzl", "rust_library")
+    ///
+    /// rust_library(
+    ///     name = "syn",
+    ///     srcs = glob(["src/**/*.rs"]),
+    ///     deps = [  # direct
+    ///         ":proc-macro2",
+    ///         ":quote",
+    ///     ],
+    /// )
+    /// "#;
+    /// assert_eq!(Config::new().fmt(source).unwrap(), expected);
+    /// ```
+    ///
+    /// Returns an error if `source` is not syntactically valid Starlark.
+    pub fn fmt(&self, source: &str) -> Result<String, Error> {
+        let statements = parse::parse(source)?;
+        let mut output = String::new();
+        for statement in &statements {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            let node = Node {
+                source,
+                node: statement,
+            };
+            let formatted = self.to_string(&node).and_then(|mut formatted| {
+                // The comment at the end of a statement goes after its last
+                // line, not after the opening bracket of a multi-line call.
+                if let Some(suffix) = statement.comments.suffix {
+                    let text = comment_text(suffix).map_err(Error::custom)?;
+                    formatted.pop();
+                    formatted.push_str("  #");
+                    if !text.is_empty() {
+                        formatted.push(' ');
+                        formatted.push_str(text);
+                    }
+                    formatted.push('\n');
+                }
+                Ok(formatted)
+            });
+            if let Ok(formatted) = formatted {
+                output.push_str(&formatted);
+            } else {
+                output.push_str(&source[statement.span.clone()]);
+                output.push('\n');
+            }
+        }
+        Ok(output)
+    }
+}
+
+// A node of the syntax tree together with the source text it came from.
+struct Node<'a, 'b, T> {
+    source: &'a str,
+    node: &'b T,
+}
+
+impl<'a, 'b, T> Node<'a, 'b, T> {
+    fn child<'c, U>(&self, node: &'c U) -> Node<'a, 'c, U> {
+        Node {
+            source: self.source,
+            node,
+        }
+    }
+}
+
+impl<'a, 'b> Serialize for Node<'a, 'b, Statement<'a>> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let statement = self.node;
+        if statement.stray_comment {
+            return Err(S::Error::custom("comment in an unsupported position"));
+        }
+        // The comment at the end of the statement is written by `fmt`.
+        let leading = Comments {
+            leading: statement.comments.leading.clone(),
+            ..Comments::default()
+        };
+        match &statement.kind {
+            StatementKind::Comment | StatementKind::Other => {
+                Err(S::Error::custom("statement is kept as written"))
+            }
+            StatementKind::Expression(expr) => {
+                let expr = self.child(expr);
+                WithComments(&leading, &expr).serialize(serializer)
+            }
+            StatementKind::Assignment { target, op, value } => match target.kind {
+                ExprKind::Identifier(name) if *op == "=" => {
+                    let value = self.child(value);
+                    let value = WithComments(&leading, &value);
+                    Assignment::new(name, value).serialize(serializer)
+                }
+                _ => Err(S::Error::custom("unsupported assignment")),
+            },
+        }
+    }
+}
+
+impl<'a, 'b> Serialize for Node<'a, 'b, Expr<'a>> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let expr = self.node;
+        let text = &self.source[expr.span.clone()];
+        match &expr.kind {
+            ExprKind::Identifier(name) => match *name {
+                "None" => serializer.serialize_none(),
+                "True" => serializer.serialize_bool(true),
+                "False" => serializer.serialize_bool(false),
+                _ => Identifier(name).serialize(serializer),
+            },
+            ExprKind::Int(literal) => int(literal, serializer),
+            ExprKind::Float(literal) => verbatim(literal, serializer),
+            ExprKind::Unary("-", operand) if matches!(operand.kind, ExprKind::Int(_)) => {
+                int(text, serializer)
+            }
+            ExprKind::String(literal) => string(literal, serializer),
+            ExprKind::List(items) | ExprKind::Tuple(items) => self.container(items, serializer),
+            ExprKind::Dict(items) => self.container(items, serializer),
+            ExprKind::Call(function, args) => {
+                let function = &self.source[function.span.clone()];
+                if function == "load" && !args.has_comments() {
+                    Compact(Contents(self)).serialize(serializer)
+                } else {
+                    self.container(args, serializer)
+                }
+            }
+            ExprKind::Dot(..) => match dotted_name(expr) {
+                Some(name) => Identifier(name).serialize(serializer),
+                None => verbatim(text, serializer),
+            },
+            ExprKind::Binary(_, "+", _) => {
+                let mut operands = Vec::new();
+                concatenation(expr, &mut operands);
+                let mut plus = serializer.serialize_tuple_struct("+", operands.len())?;
+                for operand in operands {
+                    plus.serialize_field(&self.child(operand))?;
+                }
+                plus.end()
+            }
+            _ => verbatim(text, serializer),
+        }
+    }
+}
+
+impl<'a, 'b> Node<'a, 'b, Expr<'a>> {
+    // A list, tuple, dict or function call, with the comment after its
+    // opening bracket if there is one.
+    fn container<T, S>(&self, items: &Group<'a, T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if !items.trailing.is_empty() {
+            return Err(S::Error::custom("comment in an unsupported position"));
+        }
+        match items.open_comment {
+            None => Contents(self).serialize(serializer),
+            Some(comment) => {
+                let comment = comment_text(comment).map_err(S::Error::custom)?;
+                LineComment::new(Contents(self), comment).serialize(serializer)
+            }
+        }
+    }
+}
+
+// The brackets and items of a list, tuple, dict or function call.
+struct Contents<'a, 'b, 'c>(&'c Node<'a, 'b, Expr<'a>>);
+
+impl<'a, 'b, 'c> Serialize for Contents<'a, 'b, 'c> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let node = self.0;
+        match &node.node.kind {
+            ExprKind::List(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.items.len()))?;
+                for (i, item) in items.items.iter().enumerate() {
+                    if i > 0 && item.comments.blank_line_before {
+                        seq.serialize_element(&Spacer::new())?;
+                    }
+                    let value = node.child(&item.value);
+                    seq.serialize_element(&WithComments(&item.comments, &value))?;
+                }
+                seq.end()
+            }
+            ExprKind::Tuple(items) => {
+                let mut tuple = serializer.serialize_tuple(items.items.len())?;
+                for item in &items.items {
+                    let value = node.child(&item.value);
+                    tuple.serialize_element(&WithComments(&item.comments, &value))?;
+                }
+                tuple.end()
+            }
+            ExprKind::Dict(items) => {
+                let mut map = serializer.serialize_map(Some(items.items.len()))?;
+                for item in &items.items {
+                    let (key, value) = &item.value;
+                    map.serialize_key(&node.child(key))?;
+                    let value = node.child(value);
+                    map.serialize_value(&WithComments(&item.comments, &value))?;
+                }
+                map.end()
+            }
+            ExprKind::Call(function, args) => {
+                let function = &node.source[function.span.clone()];
+                let positional = args.items.iter().all(|arg| match arg.value {
+                    parse::Argument::Positional(_) | parse::Argument::Args(_) => true,
+                    parse::Argument::Named(..) | parse::Argument::Kwargs(_) => false,
+                });
+                if positional {
+                    let mut call = serializer.serialize_tuple_struct("(", args.items.len())?;
+                    call.serialize_field(function)?;
+                    for arg in &args.items {
+                        let value = Argument(node, &arg.value);
+                        call.serialize_field(&WithComments(&arg.comments, &value))?;
+                    }
+                    return call.end();
+                }
+                let mut call = serializer.serialize_struct("(", args.items.len())?;
+                call.serialize_field("", function)?;
+                for arg in &args.items {
+                    let value = WithComments(&arg.comments, &Argument(node, &arg.value));
+                    if let parse::Argument::Named(name, _) = arg.value {
+                        call.serialize_field("*key", name)?;
+                        call.serialize_field("*value", &value)?;
+                    } else {
+                        call.serialize_field("", &value)?;
+                    }
+                }
+                call.end()
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+// The value of a function argument, with the `*` or `**` of an unpacked
+// one.
+struct Argument<'a, 'b, 'c>(&'c Node<'a, 'b, Expr<'a>>, &'c parse::Argument<'a>);
+
+impl<'a, 'b, 'c> Serialize for Argument<'a, 'b, 'c> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Argument(node, arg) = *self;
+        match arg {
+            parse::Argument::Positional(value) | parse::Argument::Named(_, value) => {
+                node.child(value).serialize(serializer)
+            }
+            parse::Argument::Args(value) => Args(node.child(value)).serialize(serializer),
+            parse::Argument::Kwargs(value) => Kwargs(node.child(value)).serialize(serializer),
+        }
+    }
+}
+
+// A value along with the comment lines above it and the comment at the end
+// of its line.
+struct WithComments<'a, 'b, T>(&'b Comments<'a>, &'b T);
+
+impl<'a, 'b, T> Serialize for WithComments<'a, 'b, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let WithComments(comments, value) = *self;
+        let mut leading = Vec::new();
+        for line in &comments.leading {
+            leading.push(comment_text(line).map_err(S::Error::custom)?);
+        }
+        let leading = leading.join("\n");
+        let suffix = match comments.suffix {
+            Some(suffix) => Some(comment_text(suffix).map_err(S::Error::custom)?),
+            None => None,
+        };
+        match (leading.is_empty(), suffix) {
+            (true, None) => value.serialize(serializer),
+            (true, Some(suffix)) => LineComment::new(value, suffix).serialize(serializer),
+            (false, None) => BlockComment::new(value, &leading).serialize(serializer),
+            (false, Some(suffix)) => {
+                BlockComment::new(LineComment::new(value, suffix), &leading).serialize(serializer)
+            }
+        }
+    }
+}
+
+fn verbatim<S>(text: &str, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    // Copying more than one line would keep the original indentation of the
+    // later lines.
+    if text.contains('\n') {
+        return Err(S::Error::custom(
+            "multi-line expression cannot be reformatted",
+        ));
+    }
+    serializer.serialize_newtype_struct("*verbatim", text)
+}
+
+// The text of a comment after its `#` and one space, if the serializer will
+// write it back the same way.
+fn comment_text(comment: &str) -> Result<&str, &'static str> {
+    let text = comment[1..].trim_end();
+    if text.is_empty() {
+        Ok(text)
+    } else if let Some(text) = text.strip_prefix(' ') {
+        Ok(text)
+    } else if text.starts_with(char::is_alphanumeric) {
+        Ok(text)
+    } else {
+        Err("comment cannot be reformatted")
+    }
+}
+
+fn int<S>(text: &str, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let (radix, digits) = match text.get(..2) {
+        Some("0x" | "0X") => (16, &text[2..]),
+        Some("0o" | "0O") => (8, &text[2..]),
+        Some("0b" | "0B") => (2, &text[2..]),
+        _ => (10, text),
+    };
+    if radix == 10 {
+        if let Ok(int) = text.parse::<i32>() {
+            return serializer.serialize_i32(int);
+        }
+    } else if let Ok(int) = u32::from_str_radix(digits, radix) {
+        return match radix {
+            16 => Hex(int).serialize(serializer),
+            8 => Oct(int).serialize(serializer),
+            _ => Bin(int).serialize(serializer),
+        };
+    }
+    verbatim(text, serializer)
+}
+
+fn string<S>(literal: &str, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let quote = literal.find(['"', '\'']).unwrap();
+    let prefix = &literal[..quote];
+    if prefix.contains(['b', 'B']) {
+        return verbatim(literal, serializer);
+    }
+    let body = &literal[quote..];
+    let triple = body.len() >= 6 && (body.starts_with("\"\"\"") || body.starts_with("'''"));
+    let value = crate::lex::unquote(literal);
+    if prefix.contains(['r', 'R']) {
+        RawString::new(&value).serialize(serializer)
+    } else if triple {
+        TripleQuoted::new(&value).serialize(serializer)
+    } else {
+        serializer.serialize_str(&value)
+    }
+}
+
+// The name written by an expression like `ctx.attr.srcs`.
+fn dotted_name(expr: &Expr) -> Option<String> {
+    match &expr.kind {
+        ExprKind::Identifier(name) => Some((*name).to_owned()),
+        ExprKind::Dot(object, name) => {
+            let mut dotted = dotted_name(object)?;
+            dotted.push('.');
+            dotted.push_str(name);
+            Some(dotted)
+        }
+        _ => None,
+    }
+}
+
+// The operands of `a + b + c`.
+fn concatenation<'a, 'b>(expr: &'b Expr<'a>, operands: &mut Vec<&'b Expr<'a>>) {
+    match &expr.kind {
+        ExprKind::Binary(left, "+", right) => {
+            concatenation(left, operands);
+            operands.push(right);
+        }
+        _ => operands.push(expr),
+    }
+}
@@ -0,0 +1,1315 @@
+//! Targets of commonly used rules that are built into Bazel.
+//!
+//! Each struct serializes as a call to the rule of the same name, with the
+//! attributes named the way Bazel expects. Attributes that are empty — an
+//! empty list, dict or string, `None`, or `false` — are left out so that
+//! Bazel's own default applies, which means most fields can be filled in with
+//! `..Default::default()`.
+//!
+//! For `.bzl` files defining rules of one's own, see [`RuleDefinition`] and
+//! [`Provider`].
+//!
+//! ```
+//! use serde_starlark::rules::{Filegroup, ShBinary};
+//! use serde_starlark::Document;
+//!
+//! let srcs = Filegroup {
+//!     name: "srcs".to_owned(),
+//!     srcs: vec!["src/lib.rs".to_owned(), "src/main.rs".to_owned()],
+//!     ..Default::default()
+//! };
+//! let run = ShBinary {
+//!     name: "run".to_owned(),
+//!     srcs: vec!["run.sh".to_owned()],
+//!     data: vec![":srcs".to_owned()],
+//!     visibility: vec!["//visibility:public".to_owned()],
+//!     ..Default::default()
+//! };
+//!
+//! let build = serde_starlark::to_string(&Document::new([
+//!     &srcs as &dyn erased_serde::Serialize,
+//!     &run,
+//! ]))
+//! .unwrap();
+//! let expected = r#"filegroup(
+//!     name = "srcs",
+//!     srcs = [
+//!         "src/lib.rs",
+//!         "src/main.rs",
+//!     ],
+//! )
+//!
+//! sh_binary(
+//!     name = "run",
+//!     srcs = ["run.sh"],
+//!     data = [":srcs"],
+//!     visibility = ["//visibility:public"],
+//! )
+//! "#;
+//! assert_eq!(build, expected);
+//! ```
+
+use crate::{Assignment, Identifier, TripleQuoted};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::collections::BTreeMap;
+
+/// [`filegroup`](https://bazel.build/reference/be/general#filegroup): a name
+/// for a collection of files.
+#[derive(Clone, Debug, Default)]
+pub struct Filegroup {
+    pub name: String,
+    pub srcs: Vec<String>,
+    pub data: Vec<String>,
+    /// Use the files of this output group of `srcs` instead of their default
+    /// outputs.
+    pub output_group: Option<String>,
+    pub visibility: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// [`alias`](https://bazel.build/reference/be/general#alias): another name
+/// for a target.
+///
+/// A repository that re-exports targets of other repositories under stable
+/// names, like the "hub" repository of a set of third-party crates, consists
+/// mostly of these.
+///
+/// ```
+/// use serde_starlark::rules::Alias;
+/// use serde_starlark::Document;
+///
+/// let crates = [("anyhow", "1.0.75"), ("syn", "2.0.39")];
+/// let aliases: Vec<Alias> = crates
+///     .iter()
+///     .map(|(name, version)| Alias {
+///         visibility: vec!["//visibility:public".to_owned()],
+///         ..Alias::new(*name, format!("@crates__{}-{}//:{}", name, version, name))
+///     })
+///     .collect();
+///
+/// let expected = r#"alias(
+///     name = "anyhow",
+///     actual = "@crates__anyhow-1.0.75//:anyhow",
+///     visibility = ["//visibility:public"],
+/// )
+///
+/// alias(
+///     name = "syn",
+///     actual = "@crates__syn-2.0.39//:syn",
+///     visibility = ["//visibility:public"],
+/// )
+/// "#;
+/// let build = serde_starlark::to_string(&Document::new(aliases)).unwrap();
+/// assert_eq!(build, expected);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Alias {
+    pub name: String,
+    /// The target that this name refers to.
+    pub actual: String,
+    pub visibility: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl Alias {
+    /// An alias with the default visibility and no tags.
+    pub fn new(name: impl Into<String>, actual: impl Into<String>) -> Self {
+        Alias {
+            name: name.into(),
+            actual: actual.into(),
+            visibility: Vec::new(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// [`genrule`](https://bazel.build/reference/be/general#genrule): generate
+/// files using a shell command.
+#[derive(Clone, Debug, Default)]
+pub struct Genrule {
+    pub name: String,
+    pub srcs: Vec<String>,
+    pub outs: Vec<String>,
+    /// The command to run.
+    pub cmd: Cmd,
+    /// Targets built for the execution platform, to be run by `cmd`.
+    pub tools: Vec<String>,
+    pub visibility: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// The command of a [`Genrule`].
+///
+/// Bazel expands `$(...)` and `$@`-style variables in the command before the
+/// shell sees it, so a `$` meant for the shell has to be written `$$`. Text
+/// added by [`shell`][Cmd::shell] is escaped this way, while
+/// [`location`][Cmd::location] and [`variable`][Cmd::variable] add the
+/// expansions that Bazel should perform. A command of more than one line is
+/// serialized as a triple-quoted string.
+///
+/// ```
+/// use serde_starlark::rules::{Cmd, Genrule};
+///
+/// let genrule = Genrule {
+///     name: "version".to_owned(),
+///     outs: vec!["version.txt".to_owned()],
+///     cmd: Cmd::new()
+///         .shell("set -eu\nVERSION=$(")
+///         .location(":describe")
+///         .shell(")\necho \"$VERSION\" > ")
+///         .variable("@")
+///         .shell("\n"),
+///     tools: vec![":describe".to_owned()],
+///     ..Default::default()
+/// };
+///
+/// let expected = r#"genrule(
+///     name = "version",
+///     outs = ["version.txt"],
+///     cmd = """\
+/// set -eu
+/// VERSION=$$($(location :describe))
+/// echo "$$VERSION" > $@
+/// """,
+///     tools = [":describe"],
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&genrule).unwrap(), expected);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cmd {
+    text: String,
+}
+
+impl Cmd {
+    /// An empty command.
+    #[must_use]
+    pub fn new() -> Self {
+        Cmd {
+            text: String::new(),
+        }
+    }
+
+    /// Append shell code, with every `$` escaped so that it reaches the
+    /// shell unchanged.
+    #[must_use]
+    pub fn shell(mut self, code: &str) -> Self {
+        for ch in code.chars() {
+            if ch == '$' {
+                self.text.push('$');
+            }
+            self.text.push(ch);
+        }
+        self
+    }
+
+    /// Append `$(location label)`, the path of the file built by `label`.
+    #[must_use]
+    pub fn location(mut self, label: &str) -> Self {
+        self.text.push_str("$(location ");
+        self.text.push_str(label);
+        self.text.push(')');
+        self
+    }
+
+    /// Append a variable for Bazel to expand: `$@` or `$<` for a
+    /// one-character name, otherwise `$(NAME)`, like `$(OUTS)` or
+    /// `$(RULEDIR)`.
+    #[must_use]
+    pub fn variable(mut self, name: &str) -> Self {
+        self.text.push('$');
+        if name.chars().count() == 1 {
+            self.text.push_str(name);
+        } else {
+            self.text.push('(');
+            self.text.push_str(name);
+            self.text.push(')');
+        }
+        self
+    }
+
+    /// Append text that is already written in Bazel's syntax, without
+    /// escaping it.
+    #[must_use]
+    pub fn raw(mut self, text: &str) -> Self {
+        self.text.push_str(text);
+        self
+    }
+
+    /// The command as Bazel will see it.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+}
+
+/// [`config_setting`](https://bazel.build/reference/be/general#config_setting):
+/// a configuration condition for use in `select`.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigSetting {
+    pub name: String,
+    /// Build settings that must have the given values, like
+    /// `{"compilation_mode": "opt"}`.
+    pub values: BTreeMap<String, String>,
+    /// Constraint values that the target platform must have.
+    pub constraint_values: Vec<String>,
+    /// User-defined build settings that must have the given values, keyed by
+    /// the label of the setting.
+    pub flag_values: BTreeMap<String, String>,
+    /// Values of `--define` flags.
+    pub define_values: BTreeMap<String, String>,
+    pub visibility: Vec<String>,
+}
+
+/// [`selects.config_setting_group`](https://github.com/bazelbuild/bazel-skylib/blob/main/docs/selects_doc.md#selectsconfig_setting_group):
+/// a condition that matches when any, or all, of several other conditions
+/// match.
+///
+/// This is a macro from bazel-skylib, so the BUILD file needs to load
+/// `selects` from `@bazel_skylib//lib:selects.bzl`. Exactly one of
+/// `match_any` and `match_all` should be set.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigSettingGroup {
+    pub name: String,
+    pub match_any: Vec<String>,
+    pub match_all: Vec<String>,
+    pub visibility: Vec<String>,
+}
+
+/// [`platform`](https://bazel.build/reference/be/platforms-and-toolchains#platform):
+/// a named collection of constraint values describing an environment.
+///
+/// Together with [`ConstraintSetting`], [`ConstraintValue`] and
+/// [`ConfigSetting`], this covers the targets that the conditions of a
+/// `select` refer to.
+///
+/// ```
+/// use serde_starlark::rules::{ConfigSetting, ConstraintSetting, ConstraintValue, Platform};
+/// use serde_starlark::Document;
+///
+/// let setting = ConstraintSetting {
+///     name: "libc".to_owned(),
+///     default_constraint_value: Some(":glibc".to_owned()),
+///     ..Default::default()
+/// };
+/// let glibc = ConstraintValue::new("glibc", ":libc");
+/// let musl = ConstraintValue::new("musl", ":libc");
+/// let platform = Platform {
+///     name: "linux_musl".to_owned(),
+///     constraint_values: vec![
+///         "@platforms//os:linux".to_owned(),
+///         ":musl".to_owned(),
+///     ],
+///     ..Default::default()
+/// };
+/// let condition = ConfigSetting {
+///     name: "is_musl".to_owned(),
+///     constraint_values: vec![":musl".to_owned()],
+///     ..Default::default()
+/// };
+///
+/// let build = serde_starlark::to_string(&Document::new([
+///     &setting as &dyn erased_serde::Serialize,
+///     &glibc,
+///     &musl,
+///     &platform,
+///     &condition,
+/// ]))
+/// .unwrap();
+/// let expected = r#"constraint_setting(
+///     name = "libc",
+///     default_constraint_value = ":glibc",
+/// )
+///
+/// constraint_value(
+///     name = "glibc",
+///     constraint_setting = ":libc",
+/// )
+///
+/// constraint_value(
+///     name = "musl",
+///     constraint_setting = ":libc",
+/// )
+///
+/// platform(
+///     name = "linux_musl",
+///     constraint_values = [
+///         "@platforms//os:linux",
+///         ":musl",
+///     ],
+/// )
+///
+/// config_setting(
+///     name = "is_musl",
+///     constraint_values = [":musl"],
+/// )
+/// "#;
+/// assert_eq!(build, expected);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Platform {
+    pub name: String,
+    pub constraint_values: Vec<String>,
+    /// A platform whose constraint values this one inherits.
+    pub parents: Vec<String>,
+    /// Properties that configure the remote execution of actions.
+    pub exec_properties: BTreeMap<String, String>,
+    pub visibility: Vec<String>,
+}
+
+/// [`constraint_setting`](https://bazel.build/reference/be/platforms-and-toolchains#constraint_setting):
+/// a dimension along which platforms differ, like the operating system.
+#[derive(Clone, Debug, Default)]
+pub struct ConstraintSetting {
+    pub name: String,
+    /// The value of the setting for platforms that do not specify one.
+    pub default_constraint_value: Option<String>,
+    pub visibility: Vec<String>,
+}
+
+/// [`constraint_value`](https://bazel.build/reference/be/platforms-and-toolchains#constraint_value):
+/// one of the possible values of a [`ConstraintSetting`].
+#[derive(Clone, Debug, Default)]
+pub struct ConstraintValue {
+    pub name: String,
+    /// Label of the setting that this is a value of.
+    pub constraint_setting: String,
+    pub visibility: Vec<String>,
+}
+
+impl ConstraintValue {
+    /// A value of the given setting, with the default visibility.
+    pub fn new(name: impl Into<String>, constraint_setting: impl Into<String>) -> Self {
+        ConstraintValue {
+            name: name.into(),
+            constraint_setting: constraint_setting.into(),
+            visibility: Vec::new(),
+        }
+    }
+}
+
+/// The rules_rust `config_setting` for each Rust target triple, for use as the
+/// conditions of a [`Select`][crate::Select].
+///
+/// rules_rust defines a condition named after each target triple that it
+/// supports, like `@rules_rust//rust/platform:x86_64-unknown-linux-gnu`.
+/// Triples that rules_rust does not know about, or that should map to a
+/// condition of one's own, are given a label with
+/// [`insert`][RustPlatforms::insert].
+///
+/// ```
+/// use serde_starlark::rules::RustPlatforms;
+/// use serde_starlark::Select;
+///
+/// let mut platforms = RustPlatforms::new();
+/// platforms.insert("x86_64-unknown-linux-musl", "//platforms:linux_musl");
+///
+/// let mut deps = Select::new();
+/// for triple in ["x86_64-unknown-linux-gnu", "x86_64-unknown-linux-musl"] {
+///     let condition = platforms.label(triple).unwrap();
+///     deps.insert(condition, vec![":libc"]);
+/// }
+/// assert_eq!(platforms.label("x86_64-unknown-haiku"), None);
+///
+/// let expected = r#"select({
+///     "//platforms:linux_musl": [":libc"],
+///     "@rules_rust//rust/platform:x86_64-unknown-linux-gnu": [":libc"],
+/// })
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RustPlatforms {
+    overrides: BTreeMap<String, String>,
+}
+
+// Target triples for which rules_rust defines a condition in
+// @rules_rust//rust/platform, from its triple_mappings.bzl.
+const RULES_RUST_TRIPLES: &[&str] = &[
+    "aarch64-apple-darwin",
+    "aarch64-apple-ios",
+    "aarch64-apple-ios-sim",
+    "aarch64-linux-android",
+    "aarch64-pc-windows-msvc",
+    "aarch64-unknown-fuchsia",
+    "aarch64-unknown-linux-gnu",
+    "aarch64-unknown-nixos-gnu",
+    "aarch64-unknown-nto-qnx710",
+    "aarch64-unknown-uefi",
+    "arm-unknown-linux-gnueabi",
+    "armv7-linux-androideabi",
+    "armv7-unknown-linux-gnueabi",
+    "i686-apple-darwin",
+    "i686-linux-android",
+    "i686-pc-windows-msvc",
+    "i686-unknown-freebsd",
+    "i686-unknown-linux-gnu",
+    "powerpc-unknown-linux-gnu",
+    "riscv32imc-unknown-none-elf",
+    "riscv64gc-unknown-none-elf",
+    "s390x-unknown-linux-gnu",
+    "thumbv7em-none-eabi",
+    "thumbv8m.main-none-eabi",
+    "wasm32-unknown-unknown",
+    "wasm32-wasi",
+    "wasm32-wasip1",
+    "x86_64-apple-darwin",
+    "x86_64-apple-ios",
+    "x86_64-linux-android",
+    "x86_64-pc-windows-msvc",
+    "x86_64-unknown-freebsd",
+    "x86_64-unknown-fuchsia",
+    "x86_64-unknown-linux-gnu",
+    "x86_64-unknown-nixos-gnu",
+    "x86_64-unknown-none",
+    "x86_64-unknown-uefi",
+];
+
+impl RustPlatforms {
+    /// The conditions defined by rules_rust, with no overrides.
+    #[must_use]
+    pub fn new() -> Self {
+        RustPlatforms {
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    /// Use `label` as the condition for `triple`, in place of the one from
+    /// rules_rust if there is one.
+    pub fn insert(&mut self, triple: impl Into<String>, label: impl Into<String>) -> &mut Self {
+        self.overrides.insert(triple.into(), label.into());
+        self
+    }
+
+    /// The condition for a target triple, or `None` if rules_rust does not
+    /// support the triple and no label has been inserted for it.
+    #[must_use]
+    pub fn label(&self, triple: &str) -> Option<String> {
+        if let Some(label) = self.overrides.get(triple) {
+            Some(label.clone())
+        } else if RULES_RUST_TRIPLES.binary_search(&triple).is_ok() {
+            Some(format!("@rules_rust//rust/platform:{}", triple))
+        } else {
+            None
+        }
+    }
+
+    /// Every triple that has a condition, in order.
+    pub fn triples(&self) -> impl Iterator<Item = &str> {
+        let mut triples: Vec<&str> = RULES_RUST_TRIPLES.to_vec();
+        triples.extend(self.overrides.keys().map(String::as_str));
+        triples.sort_unstable();
+        triples.dedup();
+        triples.into_iter()
+    }
+}
+
+/// [`sh_binary`](https://bazel.build/reference/be/shell#sh_binary): an
+/// executable shell script.
+#[derive(Clone, Debug, Default)]
+pub struct ShBinary {
+    pub name: String,
+    pub srcs: Vec<String>,
+    pub data: Vec<String>,
+    pub deps: Vec<String>,
+    /// Arguments passed when the script is run by `bazel run`.
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub visibility: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// [`sh_library`](https://bazel.build/reference/be/shell#sh_library): shell
+/// scripts and data for use by other shell rules.
+#[derive(Clone, Debug, Default)]
+pub struct ShLibrary {
+    pub name: String,
+    pub srcs: Vec<String>,
+    pub data: Vec<String>,
+    pub deps: Vec<String>,
+    pub visibility: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// [`sh_test`](https://bazel.build/reference/be/shell#sh_test): a test
+/// written as a shell script.
+#[derive(Clone, Debug, Default)]
+pub struct ShTest {
+    pub name: String,
+    pub srcs: Vec<String>,
+    pub data: Vec<String>,
+    pub deps: Vec<String>,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    /// `"small"`, `"medium"`, `"large"` or `"enormous"`.
+    pub size: Option<String>,
+    pub visibility: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+/// [`test_suite`](https://bazel.build/reference/be/general#test_suite): a
+/// set of tests to run together.
+#[derive(Clone, Debug, Default)]
+pub struct TestSuite {
+    pub name: String,
+    /// Labels of the tests in the suite. If empty, the suite contains every
+    /// test in the package that matches `tags`.
+    pub tests: Vec<String>,
+    /// Restrict the suite to tests that have these tags, or that do not have
+    /// the tags written with a leading `-`.
+    pub tags: Vec<String>,
+    pub visibility: Vec<String>,
+}
+
+impl TestSuite {
+    /// One suite per tag, named after the tag, containing every test that
+    /// has that tag. Each test is given as its name and its tags. The suites
+    /// are in order of tag name, and the tests of a suite are in the order
+    /// they were given.
+    ///
+    /// ```
+    /// use serde_starlark::rules::{ShTest, TestSuite};
+    /// use serde_starlark::Document;
+    ///
+    /// let tests = vec![
+    ///     ShTest {
+    ///         name: "parse".to_owned(),
+    ///         srcs: vec!["parse.sh".to_owned()],
+    ///         tags: vec!["unit".to_owned()],
+    ///         ..Default::default()
+    ///     },
+    ///     ShTest {
+    ///         name: "server".to_owned(),
+    ///         srcs: vec!["server.sh".to_owned()],
+    ///         tags: vec!["integration".to_owned(), "unit".to_owned()],
+    ///         ..Default::default()
+    ///     },
+    /// ];
+    /// let suites = TestSuite::by_tag(
+    ///     tests
+    ///         .iter()
+    ///         .map(|test| (test.name.as_str(), test.tags.as_slice())),
+    /// );
+    ///
+    /// let mut targets: Vec<Box<dyn erased_serde::Serialize>> = Vec::new();
+    /// targets.extend(tests.into_iter().map(|test| Box::new(test) as _));
+    /// targets.extend(suites.into_iter().map(|suite| Box::new(suite) as _));
+    ///
+    /// let expected = r#"sh_test(
+    ///     name = "parse",
+    ///     srcs = ["parse.sh"],
+    ///     tags = ["unit"],
+    /// )
+    ///
+    /// sh_test(
+    ///     name = "server",
+    ///     srcs = ["server.sh"],
+    ///     tags = [
+    ///         "integration",
+    ///         "unit",
+    ///     ],
+    /// )
+    ///
+    /// test_suite(
+    ///     name = "integration",
+    ///     tests = [":server"],
+    /// )
+    ///
+    /// test_suite(
+    ///     name = "unit",
+    ///     tests = [
+    ///         ":parse",
+    ///         ":server",
+    ///     ],
+    /// )
+    /// "#;
+    /// let build = serde_starlark::to_string(&Document::new(targets)).unwrap();
+    /// assert_eq!(build, expected);
+    /// ```
+    pub fn by_tag<'a, I, T>(tests: I) -> Vec<TestSuite>
+    where
+        I: IntoIterator<Item = (&'a str, T)>,
+        T: IntoIterator,
+        T::Item: AsRef<str>,
+    {
+        let mut suites: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, tags) in tests {
+            for tag in tags {
+                let label = format!(":{}", name);
+                let suite = suites.entry(tag.as_ref().to_owned()).or_default();
+                if !suite.contains(&label) {
+                    suite.push(label);
+                }
+            }
+        }
+        suites
+            .into_iter()
+            .map(|(tag, tests)| TestSuite {
+                name: tag,
+                tests,
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// A call to [`rule`](https://bazel.build/rules/lib/globals/bzl#rule),
+/// defining a custom rule in a `.bzl` file.
+///
+/// The definition is usually assigned to the name of the rule with
+/// [`Assignment`].
+///
+/// ```
+/// use serde_starlark::rules::{Attr, RuleDefinition};
+/// use serde_starlark::Assignment;
+///
+/// let mut definition = RuleDefinition::new("_rust_docs_impl");
+/// definition.doc = Some("Renders documentation of a crate.".to_owned());
+/// definition.attrs.insert(
+///     "crate".to_owned(),
+///     Attr::label().mandatory().providers(["CrateInfo"]),
+/// );
+/// definition.attrs.insert(
+///     "format".to_owned(),
+///     Attr::string().default("html").values(vec!["html", "json"]),
+/// );
+///
+/// let expected = r#"rust_docs = rule(
+///     implementation = _rust_docs_impl,
+///     attrs = {
+///         "crate": attr.label(
+///             mandatory = True,
+///             providers = [CrateInfo],
+///         ),
+///         "format": attr.string(
+///             default = "html",
+///             values = [
+///                 "html",
+///                 "json",
+///             ],
+///         ),
+///     },
+///     doc = "Renders documentation of a crate.",
+/// )
+/// "#;
+/// let assignment = Assignment::new("rust_docs", definition);
+/// assert_eq!(serde_starlark::to_string(&assignment).unwrap(), expected);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RuleDefinition {
+    /// Name of the function that implements the rule, written as an
+    /// identifier rather than a string.
+    pub implementation: String,
+    pub attrs: BTreeMap<String, Attr>,
+    pub doc: Option<String>,
+    /// Whether the rule creates an executable that can be run by `bazel run`.
+    pub executable: bool,
+    /// Whether the rule is a test, run by `bazel test`.
+    pub test: bool,
+    /// Providers that the implementation must return, written as
+    /// identifiers.
+    pub provides: Vec<String>,
+    /// Labels of the toolchain types that the rule uses.
+    pub toolchains: Vec<String>,
+}
+
+impl RuleDefinition {
+    /// A rule with the given implementation function and no attributes.
+    pub fn new(implementation: impl Into<String>) -> Self {
+        RuleDefinition {
+            implementation: implementation.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A call to [`provider`](https://bazel.build/rules/lib/globals/bzl#provider),
+/// assigned to the name of the provider.
+///
+/// ```
+/// use serde_starlark::rules::Provider;
+///
+/// let mut provider = Provider::new("CrateInfo");
+/// provider.doc = Some("A compiled Rust crate.".to_owned());
+/// provider.fields.insert("name".to_owned(), "The crate name.".to_owned());
+/// provider.fields.insert("edition".to_owned(), "The Rust edition.".to_owned());
+///
+/// let expected = r#"CrateInfo = provider(
+///     doc = "A compiled Rust crate.",
+///     fields = {
+///         "edition": "The Rust edition.",
+///         "name": "The crate name.",
+///     },
+/// )
+/// "#;
+/// assert_eq!(serde_starlark::to_string(&provider).unwrap(), expected);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Provider {
+    /// The name that the provider is assigned to, like `CcInfo`.
+    pub name: String,
+    /// The documentation of each field of the provider, keyed by field name.
+    pub fields: BTreeMap<String, String>,
+    pub doc: Option<String>,
+}
+
+impl Provider {
+    /// A provider with no fields.
+    pub fn new(name: impl Into<String>) -> Self {
+        Provider {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// An attribute of a [`RuleDefinition`], created by one of the `attr` module
+/// functions such as
+/// [`attr.label_list`](https://bazel.build/rules/lib/toplevel/attr#label_list).
+///
+/// Each constructor corresponds to one of those functions, and the other
+/// methods add its keyword arguments.
+#[derive(Clone, Debug)]
+pub struct Attr {
+    function: &'static str,
+    default: Option<AttrValue>,
+    doc: Option<String>,
+    mandatory: bool,
+    values: Option<AttrValue>,
+    allow_empty: Option<bool>,
+    allow_files: Option<AttrValue>,
+    allow_single_file: Option<AttrValue>,
+    providers: Vec<String>,
+    executable: bool,
+    cfg: Option<String>,
+}
+
+macro_rules! attr_functions {
+    ($($name:ident => $function:literal,)*) => {
+        $(
+            #[doc = concat!("[`", $function, "`](https://bazel.build/rules/lib/toplevel/attr#", stringify!($name), ")")]
+            pub fn $name() -> Self {
+                Attr::new($function)
+            }
+        )*
+    };
+}
+
+impl Attr {
+    attr_functions! {
+        bool => "attr.bool",
+        int => "attr.int",
+        int_list => "attr.int_list",
+        label => "attr.label",
+        label_keyed_string_dict => "attr.label_keyed_string_dict",
+        label_list => "attr.label_list",
+        output => "attr.output",
+        output_list => "attr.output_list",
+        string => "attr.string",
+        string_dict => "attr.string_dict",
+        string_list => "attr.string_list",
+        string_list_dict => "attr.string_list_dict",
+    }
+
+    fn new(function: &'static str) -> Self {
+        Attr {
+            function,
+            default: None,
+            doc: None,
+            mandatory: false,
+            values: None,
+            allow_empty: None,
+            allow_files: None,
+            allow_single_file: None,
+            providers: Vec::new(),
+            executable: false,
+            cfg: None,
+        }
+    }
+
+    /// The value of the attribute when a target does not set it.
+    #[must_use]
+    pub fn default(mut self, default: impl Into<AttrValue>) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+
+    #[must_use]
+    pub fn doc(mut self, doc: impl Into<String>) -> Self {
+        self.doc = Some(doc.into());
+        self
+    }
+
+    /// Require every target to set the attribute.
+    #[must_use]
+    pub fn mandatory(mut self) -> Self {
+        self.mandatory = true;
+        self
+    }
+
+    /// The values that the attribute is allowed to have.
+    #[must_use]
+    pub fn values(mut self, values: impl Into<AttrValue>) -> Self {
+        self.values = Some(values.into());
+        self
+    }
+
+    /// Whether a list or dict attribute may be empty.
+    #[must_use]
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = Some(allow_empty);
+        self
+    }
+
+    /// Allow source files as well as targets: either `true`, or a list of
+    /// file extensions such as `[".rs"]`.
+    #[must_use]
+    pub fn allow_files(mut self, allow_files: impl Into<AttrValue>) -> Self {
+        self.allow_files = Some(allow_files.into());
+        self
+    }
+
+    /// Like [`allow_files`][Attr::allow_files], but for a label that must
+    /// refer to exactly one file.
+    #[must_use]
+    pub fn allow_single_file(mut self, allow_single_file: impl Into<AttrValue>) -> Self {
+        self.allow_single_file = Some(allow_single_file.into());
+        self
+    }
+
+    /// Providers that the targets in the attribute must return, by the name
+    /// of the provider in the `.bzl` file.
+    #[must_use]
+    pub fn providers<I>(mut self, providers: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.providers.extend(providers.into_iter().map(Into::into));
+        self
+    }
+
+    /// Require the target in the attribute to be executable.
+    #[must_use]
+    pub fn executable(mut self) -> Self {
+        self.executable = true;
+        self
+    }
+
+    /// The configuration to build the attribute's targets in, usually
+    /// `"exec"` for tools run during the build.
+    #[must_use]
+    pub fn cfg(mut self, cfg: impl Into<String>) -> Self {
+        self.cfg = Some(cfg.into());
+        self
+    }
+}
+
+/// A value passed to an [`Attr`], such as its default.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttrValue {
+    None,
+    Bool(bool),
+    Int(i64),
+    String(String),
+    IntList(Vec<i64>),
+    StringList(Vec<String>),
+    StringDict(BTreeMap<String, String>),
+}
+
+impl From<bool> for AttrValue {
+    fn from(value: bool) -> Self {
+        AttrValue::Bool(value)
+    }
+}
+
+impl From<i64> for AttrValue {
+    fn from(value: i64) -> Self {
+        AttrValue::Int(value)
+    }
+}
+
+impl From<i32> for AttrValue {
+    fn from(value: i32) -> Self {
+        AttrValue::Int(i64::from(value))
+    }
+}
+
+impl From<&str> for AttrValue {
+    fn from(value: &str) -> Self {
+        AttrValue::String(value.to_owned())
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(value: String) -> Self {
+        AttrValue::String(value)
+    }
+}
+
+impl From<Vec<i64>> for AttrValue {
+    fn from(value: Vec<i64>) -> Self {
+        AttrValue::IntList(value)
+    }
+}
+
+impl From<Vec<&str>> for AttrValue {
+    fn from(value: Vec<&str>) -> Self {
+        AttrValue::StringList(value.into_iter().map(str::to_owned).collect())
+    }
+}
+
+impl From<Vec<String>> for AttrValue {
+    fn from(value: Vec<String>) -> Self {
+        AttrValue::StringList(value)
+    }
+}
+
+impl From<BTreeMap<String, String>> for AttrValue {
+    fn from(value: BTreeMap<String, String>) -> Self {
+        AttrValue::StringDict(value)
+    }
+}
+
+// An attribute value that is left out of the target when it is empty.
+trait Attribute: Serialize {
+    fn is_empty(&self) -> bool;
+}
+
+impl Attribute for String {
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T: Serialize> Attribute for Vec<T> {
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<K: Serialize, V: Serialize> Attribute for BTreeMap<K, V> {
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl Attribute for bool {
+    fn is_empty(&self) -> bool {
+        !*self
+    }
+}
+
+impl<T: Serialize> Attribute for Option<T> {
+    fn is_empty(&self) -> bool {
+        self.is_none()
+    }
+}
+
+fn attribute<S, T>(rule: &mut S, key: &'static str, value: &T) -> Result<(), S::Error>
+where
+    S: SerializeStruct,
+    T: Attribute,
+{
+    if value.is_empty() {
+        rule.skip_field(key)
+    } else {
+        rule.serialize_field(key, value)
+    }
+}
+
+impl Serialize for Filegroup {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("filegroup", 6)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "srcs", &self.srcs)?;
+        attribute(&mut rule, "data", &self.data)?;
+        attribute(&mut rule, "output_group", &self.output_group)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        attribute(&mut rule, "tags", &self.tags)?;
+        rule.end()
+    }
+}
+
+impl Serialize for Alias {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("alias", 4)?;
+        rule.serialize_field("name", &self.name)?;
+        rule.serialize_field("actual", &self.actual)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        attribute(&mut rule, "tags", &self.tags)?;
+        rule.end()
+    }
+}
+
+impl Serialize for Genrule {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("genrule", 7)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "srcs", &self.srcs)?;
+        rule.serialize_field("outs", &self.outs)?;
+        rule.serialize_field("cmd", &self.cmd)?;
+        attribute(&mut rule, "tools", &self.tools)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        attribute(&mut rule, "tags", &self.tags)?;
+        rule.end()
+    }
+}
+
+impl Serialize for Cmd {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        TripleQuoted::new(&self.text).serialize(serializer)
+    }
+}
+
+impl Serialize for ConfigSetting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("config_setting", 6)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "values", &self.values)?;
+        attribute(&mut rule, "constraint_values", &self.constraint_values)?;
+        attribute(&mut rule, "flag_values", &self.flag_values)?;
+        attribute(&mut rule, "define_values", &self.define_values)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        rule.end()
+    }
+}
+
+impl Serialize for ConfigSettingGroup {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("selects.config_setting_group", 4)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "match_any", &self.match_any)?;
+        attribute(&mut rule, "match_all", &self.match_all)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        rule.end()
+    }
+}
+
+impl Serialize for Platform {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("platform", 5)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "constraint_values", &self.constraint_values)?;
+        attribute(&mut rule, "parents", &self.parents)?;
+        attribute(&mut rule, "exec_properties", &self.exec_properties)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        rule.end()
+    }
+}
+
+impl Serialize for ConstraintSetting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("constraint_setting", 3)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(
+            &mut rule,
+            "default_constraint_value",
+            &self.default_constraint_value,
+        )?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        rule.end()
+    }
+}
+
+impl Serialize for ConstraintValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("constraint_value", 3)?;
+        rule.serialize_field("name", &self.name)?;
+        rule.serialize_field("constraint_setting", &self.constraint_setting)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        rule.end()
+    }
+}
+
+impl Serialize for ShBinary {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("sh_binary", 8)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "srcs", &self.srcs)?;
+        attribute(&mut rule, "data", &self.data)?;
+        attribute(&mut rule, "deps", &self.deps)?;
+        attribute(&mut rule, "args", &self.args)?;
+        attribute(&mut rule, "env", &self.env)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        attribute(&mut rule, "tags", &self.tags)?;
+        rule.end()
+    }
+}
+
+impl Serialize for ShLibrary {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("sh_library", 6)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "srcs", &self.srcs)?;
+        attribute(&mut rule, "data", &self.data)?;
+        attribute(&mut rule, "deps", &self.deps)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        attribute(&mut rule, "tags", &self.tags)?;
+        rule.end()
+    }
+}
+
+impl Serialize for ShTest {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("sh_test", 9)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "srcs", &self.srcs)?;
+        attribute(&mut rule, "data", &self.data)?;
+        attribute(&mut rule, "deps", &self.deps)?;
+        attribute(&mut rule, "args", &self.args)?;
+        attribute(&mut rule, "env", &self.env)?;
+        attribute(&mut rule, "size", &self.size)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        attribute(&mut rule, "tags", &self.tags)?;
+        rule.end()
+    }
+}
+
+impl Serialize for TestSuite {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut rule = serializer.serialize_struct("test_suite", 4)?;
+        rule.serialize_field("name", &self.name)?;
+        attribute(&mut rule, "tests", &self.tests)?;
+        attribute(&mut rule, "tags", &self.tags)?;
+        attribute(&mut rule, "visibility", &self.visibility)?;
+        rule.end()
+    }
+}
+
+impl Serialize for RuleDefinition {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let provides: Vec<Identifier<&str>> = self
+            .provides
+            .iter()
+            .map(|name| Identifier(name.as_str()))
+            .collect();
+        let mut rule = serializer.serialize_struct("rule", 7)?;
+        rule.serialize_field("implementation", &Identifier(&self.implementation))?;
+        attribute(&mut rule, "attrs", &self.attrs)?;
+        attribute(&mut rule, "doc", &self.doc)?;
+        attribute(&mut rule, "executable", &self.executable)?;
+        attribute(&mut rule, "test", &self.test)?;
+        attribute(&mut rule, "provides", &provides)?;
+        attribute(&mut rule, "toolchains", &self.toolchains)?;
+        rule.end()
+    }
+}
+
+impl Serialize for Provider {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Assignment::new(&self.name, ProviderCall(self)).serialize(serializer)
+    }
+}
+
+// The right-hand side of the provider's assignment.
+struct ProviderCall<'a>(&'a Provider);
+
+impl<'a> Serialize for ProviderCall<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut provider = serializer.serialize_struct("provider", 2)?;
+        attribute(&mut provider, "doc", &self.0.doc)?;
+        provider.serialize_field("fields", &self.0.fields)?;
+        provider.end()
+    }
+}
+
+impl Serialize for Attr {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let providers: Vec<Identifier<&str>> = self
+            .providers
+            .iter()
+            .map(|name| Identifier(name.as_str()))
+            .collect();
+        let len = usize::from(self.default.is_some())
+            + usize::from(self.doc.is_some())
+            + usize::from(self.mandatory)
+            + usize::from(self.values.is_some())
+            + usize::from(self.allow_empty.is_some())
+            + usize::from(self.allow_files.is_some())
+            + usize::from(self.allow_single_file.is_some())
+            + usize::from(!providers.is_empty())
+            + usize::from(self.executable)
+            + usize::from(self.cfg.is_some());
+        let mut attr = serializer.serialize_struct(self.function, len)?;
+        attribute(&mut attr, "default", &self.default)?;
+        attribute(&mut attr, "doc", &self.doc)?;
+        attribute(&mut attr, "mandatory", &self.mandatory)?;
+        attribute(&mut attr, "values", &self.values)?;
+        attribute(&mut attr, "allow_empty", &self.allow_empty)?;
+        attribute(&mut attr, "allow_files", &self.allow_files)?;
+        attribute(&mut attr, "allow_single_file", &self.allow_single_file)?;
+        attribute(&mut attr, "providers", &providers)?;
+        attribute(&mut attr, "executable", &self.executable)?;
+        attribute(&mut attr, "cfg", &self.cfg)?;
+        attr.end()
+    }
+}
+
+impl Serialize for AttrValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            AttrValue::None => serializer.serialize_none(),
+            AttrValue::Bool(value) => serializer.serialize_bool(*value),
+            AttrValue::Int(value) => serializer.serialize_i64(*value),
+            AttrValue::String(value) => serializer.serialize_str(value),
+            AttrValue::IntList(value) => value.serialize(serializer),
+            AttrValue::StringList(value) => value.serialize(serializer),
+            AttrValue::StringDict(value) => value.serialize(serializer),
+        }
+    }
+}
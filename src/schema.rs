@@ -0,0 +1,509 @@
+//! Descriptions of the rules defined in `.bzl` files, for generating the Rust
+//! structs that serialize as their targets.
+//!
+//! The usual source of a [`Schema`] is the documentation that
+//! [Stardoc](https://github.com/bazelbuild/stardoc) extracts from a rule set,
+//! in the JSON form of its `ModuleInfo` output. Regenerating the structs from
+//! that whenever the rule set is upgraded keeps their attribute names and
+//! types in sync with the `.bzl` files, for example from a build script or a
+//! test that compares against the checked-in code.
+//!
+//! See [`Schema::from_stardoc_json`] for an example.
+//!
+//! A schema can also catch targets that Bazel would reject, such as one whose
+//! `name` is empty or one that leaves out a mandatory attribute, by
+//! [checking][Schema::check] the generated file before it is written.
+
+use crate::cst::{arg_name, arg_value, Cst};
+use crate::error;
+use crate::lex;
+use crate::parse::{Argument, ExprKind};
+use crate::Error;
+use std::fmt::Write as _;
+
+/// The rules of a rule set.
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    pub rules: Vec<Rule>,
+}
+
+/// A rule, like `rust_library`, and the attributes its targets may set.
+#[derive(Clone, Debug, Default)]
+pub struct Rule {
+    pub name: String,
+    pub doc: String,
+    pub attributes: Vec<Attribute>,
+}
+
+/// An attribute of a [`Rule`].
+#[derive(Clone, Debug)]
+pub struct Attribute {
+    pub name: String,
+    pub doc: String,
+    pub kind: AttributeType,
+    /// Whether every target must set the attribute.
+    pub mandatory: bool,
+    /// The value of the attribute when a target does not set it, as Starlark
+    /// source code, like `[]` or `"\"\""`.
+    pub default: Option<String>,
+}
+
+/// The type of an [`Attribute`], matching the functions of Bazel's `attr`
+/// module.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttributeType {
+    /// The `name` that every target has.
+    Name,
+    Int,
+    Label,
+    String,
+    StringList,
+    IntList,
+    LabelList,
+    Bool,
+    LabelStringDict,
+    StringDict,
+    StringListDict,
+    Output,
+    OutputList,
+    LabelDictUnary,
+    LabelListDict,
+    Unknown,
+}
+
+impl Schema {
+    /// Rust source code of a struct for each rule, serializing as a target of
+    /// that rule.
+    ///
+    /// Attributes that are optional are left out of the serialized target
+    /// when they hold the empty value of their type, if that is also the
+    /// rule's default, so that a struct filled in with `..Default::default()`
+    /// writes only what it sets. Optional attributes with any other default
+    /// are generated as `Option`, and left out when `None`. Attributes of
+    /// unknown type have no field.
+    ///
+    /// The generated code uses `serde::Serialize`, which requires the "derive"
+    /// feature of serde.
+    #[must_use]
+    pub fn to_rust(&self) -> String {
+        let mut rust = String::new();
+        rust.push_str("// Generated from the documentation of Starlark rules. Do not edit.\n\n");
+        rust.push_str("use serde::Serialize;\n");
+        let uses_map = self.rules.iter().any(|rule| {
+            rule.attributes
+                .iter()
+                .any(|attribute| attribute.kind.rust_type().contains("BTreeMap"))
+        });
+        if uses_map {
+            rust.push_str("use std::collections::BTreeMap;\n");
+        }
+        for rule in &self.rules {
+            rust.push('\n');
+            write_rule(&mut rust, rule);
+        }
+        rust
+    }
+}
+
+impl Schema {
+    /// Check the targets in a file of Starlark, like one produced by
+    /// [`to_string`][crate::to_string], before handing it to Bazel.
+    ///
+    /// Every top-level function call with a `name` argument must give a valid
+    /// target name, and every call to a rule in the schema must set all of
+    /// the rule's mandatory attributes. A call that unpacks `**kwargs` may be
+    /// setting any attribute, so only its name is checked. A `name` that is
+    /// not a string literal is not checked.
+    ///
+    /// The error gives the position of the offending target in `starlark`.
+    ///
+    /// ```
+    /// use serde_starlark::schema::{Attribute, AttributeType, Rule, Schema};
+    /// use serde_starlark::FunctionCall;
+    /// use std::collections::BTreeMap;
+    ///
+    /// let schema = Schema {
+    ///     rules: vec![Rule {
+    ///         name: "alias".to_owned(),
+    ///         doc: String::new(),
+    ///         attributes: vec![Attribute {
+    ///             name: "actual".to_owned(),
+    ///             doc: String::new(),
+    ///             kind: AttributeType::Label,
+    ///             mandatory: true,
+    ///             default: None,
+    ///         }],
+    ///     }],
+    /// };
+    ///
+    /// let alias = FunctionCall::new("alias", BTreeMap::from([("name", "syn")]));
+    /// let build = serde_starlark::to_string(&alias).unwrap();
+    /// let error = schema.check(&build).unwrap_err();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "alias target \"syn\" is missing mandatory attribute `actual` at line 1 column 1",
+    /// );
+    /// ```
+    pub fn check(&self, starlark: &str) -> Result<(), Error> {
+        let cst = Cst::parse(starlark)?;
+        for target in cst.targets() {
+            let name = target.arg("name").map(arg_value);
+            let name = match name.map(|name| (name, &name.kind)) {
+                Some((name, ExprKind::String(literal))) => {
+                    let value = lex::unquote(literal);
+                    if let Some(reason) = invalid_target_name(&value) {
+                        let error = error::invalid_target_name(&value, reason);
+                        return Err(error.at(starlark, name.span.start));
+                    }
+                    Some(value)
+                }
+                _ => None,
+            };
+            let rule = match self.rules.iter().find(|rule| rule.name == target.function) {
+                Some(rule) => rule,
+                None => continue,
+            };
+            let kwargs = target.args.items.iter().any(|arg| match arg.value {
+                Argument::Kwargs(_) => true,
+                Argument::Positional(_) | Argument::Named(..) | Argument::Args(_) => false,
+            });
+            if kwargs {
+                continue;
+            }
+            for attribute in &rule.attributes {
+                let set = || {
+                    let mut names = target.args.items.iter().filter_map(arg_name);
+                    names.any(|name| name == attribute.name)
+                };
+                if attribute.mandatory && !set() {
+                    let error =
+                        error::missing_attribute(&rule.name, name.as_deref(), &attribute.name);
+                    return Err(error.at(starlark, target.call.span.start));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Bazel's rules for the name of a target, the part of a label after the colon.
+fn invalid_target_name(name: &str) -> Option<&'static str> {
+    const PUNCTUATION: &str = "!%-@^_\"#$&'()*+,;<=>?[]{|}~/.";
+    if name.is_empty() {
+        Some("target names must not be empty")
+    } else if !name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || PUNCTUATION.contains(ch))
+    {
+        Some("target names may only contain letters, digits, and certain punctuation")
+    } else if name.starts_with('/') || name.ends_with('/') || name.contains("//") {
+        Some("target names must not begin or end with `/`, or contain `//`")
+    } else if name
+        .split('/')
+        .any(|segment| segment == "." || segment == "..")
+    {
+        Some("target names must not contain a `.` or `..` path segment")
+    } else {
+        None
+    }
+}
+
+fn write_rule(rust: &mut String, rule: &Rule) {
+    write_doc(rust, "", &rule.doc);
+    rust.push_str("#[derive(Serialize, Clone, Debug, Default)]\n");
+    let _ = writeln!(rust, "#[serde(rename = \"{}\")]", rule.name);
+    let _ = writeln!(rust, "pub struct {} {{", struct_name(&rule.name));
+    for attribute in &rule.attributes {
+        if attribute.kind == AttributeType::Unknown {
+            let _ = writeln!(
+                rust,
+                "    // `{}` has a type that is not known.",
+                attribute.name
+            );
+            continue;
+        }
+        write_doc(rust, "    ", &attribute.doc);
+        let rust_type = attribute.kind.rust_type();
+        let default = attribute.default.as_deref();
+        let (rust_type, skip) = if attribute.mandatory || attribute.kind == AttributeType::Name {
+            (rust_type.to_owned(), None)
+        } else if let Some(skip) = attribute.kind.skip_default(default) {
+            (rust_type.to_owned(), Some(skip))
+        } else {
+            (format!("Option<{}>", rust_type), Some("Option::is_none"))
+        };
+        let field = field_name(&attribute.name);
+        let mut serde = Vec::new();
+        if field.trim_start_matches("r#") != attribute.name {
+            serde.push(format!("rename = \"{}\"", attribute.name));
+        }
+        if let Some(skip) = skip {
+            serde.push(format!("skip_serializing_if = \"{}\"", skip));
+        }
+        if !serde.is_empty() {
+            let _ = writeln!(rust, "    #[serde({})]", serde.join(", "));
+        }
+        let _ = writeln!(rust, "    pub {}: {},", field, rust_type);
+    }
+    rust.push_str("}\n");
+}
+
+fn write_doc(rust: &mut String, indent: &str, doc: &str) {
+    for line in doc.trim().lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            let _ = writeln!(rust, "{}///", indent);
+        } else {
+            let _ = writeln!(rust, "{}/// {}", indent, line);
+        }
+    }
+}
+
+impl AttributeType {
+    fn rust_type(self) -> &'static str {
+        match self {
+            AttributeType::Name
+            | AttributeType::Label
+            | AttributeType::String
+            | AttributeType::Output
+            | AttributeType::Unknown => "String",
+            AttributeType::Int => "i64",
+            AttributeType::Bool => "bool",
+            AttributeType::StringList | AttributeType::LabelList | AttributeType::OutputList => {
+                "Vec<String>"
+            }
+            AttributeType::IntList => "Vec<i64>",
+            AttributeType::LabelStringDict
+            | AttributeType::StringDict
+            | AttributeType::LabelDictUnary => "BTreeMap<String, String>",
+            AttributeType::StringListDict | AttributeType::LabelListDict => {
+                "BTreeMap<String, Vec<String>>"
+            }
+        }
+    }
+
+    // The function that tells whether a field holds the empty value of its
+    // type, if that is the attribute's default.
+    fn skip_default(self, default: Option<&str>) -> Option<&'static str> {
+        match (self, default?) {
+            (AttributeType::String, "\"\"") => Some("String::is_empty"),
+            (AttributeType::Bool, "False") => Some("std::ops::Not::not"),
+            (
+                AttributeType::StringList
+                | AttributeType::IntList
+                | AttributeType::LabelList
+                | AttributeType::OutputList,
+                "[]",
+            ) => Some("Vec::is_empty"),
+            (
+                AttributeType::LabelStringDict
+                | AttributeType::StringDict
+                | AttributeType::StringListDict
+                | AttributeType::LabelDictUnary
+                | AttributeType::LabelListDict,
+                "{}",
+            ) => Some("BTreeMap::is_empty"),
+            _ => None,
+        }
+    }
+}
+
+// `rust_library` -> `RustLibrary`.
+fn struct_name(rule: &str) -> String {
+    let mut name = String::new();
+    for word in rule.split('_') {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+    }
+    name
+}
+
+fn field_name(attribute: &str) -> String {
+    match attribute {
+        // Keywords that cannot be raw identifiers.
+        "crate" | "self" | "super" | "Self" => format!("{}_", attribute),
+        "abstract" | "as" | "async" | "await" | "become" | "box" | "break" | "const"
+        | "continue" | "do" | "dyn" | "else" | "enum" | "extern" | "false" | "final" | "fn"
+        | "for" | "if" | "impl" | "in" | "let" | "loop" | "macro" | "match" | "mod" | "move"
+        | "mut" | "override" | "priv" | "pub" | "ref" | "return" | "static" | "struct"
+        | "trait" | "true" | "try" | "type" | "typeof" | "unsafe" | "unsized" | "use"
+        | "virtual" | "where" | "while" | "yield" => format!("r#{}", attribute),
+        _ => attribute.to_owned(),
+    }
+}
+
+#[cfg(feature = "stardoc")]
+mod stardoc {
+    use super::{Attribute, AttributeType, Rule, Schema};
+    use crate::error;
+    use crate::Error;
+    use serde_json::{Map, Value};
+
+    #[cfg_attr(docsrs, doc(cfg(feature = "stardoc")))]
+    impl Schema {
+        /// Read the rules documented in Stardoc's output for a `.bzl` file, in
+        /// the JSON form of its `ModuleInfo` protobuf message.
+        ///
+        /// Both the camelCase field names of the protobuf JSON mapping and the
+        /// original snake_case ones are accepted. Rules whose name begins with
+        /// an underscore are private to the `.bzl` file, and are skipped.
+        ///
+        /// ```
+        /// use serde_starlark::schema::Schema;
+        ///
+        /// let json = r#"{
+        ///   "ruleInfo": [{
+        ///     "ruleName": "rust_library",
+        ///     "docString": "Builds a Rust library crate.",
+        ///     "attribute": [
+        ///       {"name": "name", "type": "NAME", "mandatory": true},
+        ///       {"name": "srcs", "type": "LABEL_LIST", "defaultValue": "[]"},
+        ///       {"name": "edition", "type": "STRING", "defaultValue": "\"\""}
+        ///     ]
+        ///   }]
+        /// }"#;
+        ///
+        /// let schema = Schema::from_stardoc_json(json).unwrap();
+        /// let expected = r#"// Generated from the documentation of Starlark rules. Do not edit.
+        ///
+        /// use serde::Serialize;
+        ///
+        /// /// Builds a Rust library crate.
+        /// #[derive(Serialize, Clone, Debug, Default)]
+        /// #[serde(rename = "rust_library")]
+        /// pub struct RustLibrary {
+        ///     pub name: String,
+        ///     #[serde(skip_serializing_if = "Vec::is_empty")]
+        ///     pub srcs: Vec<String>,
+        ///     #[serde(skip_serializing_if = "String::is_empty")]
+        ///     pub edition: String,
+        /// }
+        /// "#;
+        /// assert_eq!(schema.to_rust(), expected);
+        /// ```
+        pub fn from_stardoc_json(json: &str) -> Result<Self, Error> {
+            let module: Value = serde_json::from_str(json).map_err(error::stardoc_json)?;
+            let module = object(&module, "at the top level")?;
+            let mut rules = Vec::new();
+            for rule in array(module, "ruleInfo", "rule_info")? {
+                let rule = object(rule, "in `ruleInfo`")?;
+                let name = string(rule, "ruleName", "rule_name")?;
+                if name.is_empty() {
+                    return Err(error::stardoc_expected(
+                        "a `ruleName` for every rule".to_owned(),
+                    ));
+                }
+                if name.starts_with('_') {
+                    continue;
+                }
+                let mut attributes = Vec::new();
+                for attribute in array(rule, "attribute", "attribute")? {
+                    attributes.push(read_attribute(object(attribute, "in `attribute`")?)?);
+                }
+                rules.push(Rule {
+                    name: name.to_owned(),
+                    doc: string(rule, "docString", "doc_string")?.to_owned(),
+                    attributes,
+                });
+            }
+            Ok(Schema { rules })
+        }
+    }
+
+    fn read_attribute(attribute: &Map<String, Value>) -> Result<Attribute, Error> {
+        let name = string(attribute, "name", "name")?;
+        if !crate::ident::is_identifier(name) {
+            return Err(error::stardoc_expected(format!(
+                "an identifier as the name of an attribute, found {:?}",
+                name,
+            )));
+        }
+        let kind = match field(attribute, "type", "type") {
+            None => AttributeType::Unknown,
+            Some(Value::String(kind)) => match kind.as_str() {
+                "NAME" => AttributeType::Name,
+                "INT" => AttributeType::Int,
+                "LABEL" => AttributeType::Label,
+                "STRING" => AttributeType::String,
+                "STRING_LIST" => AttributeType::StringList,
+                "INT_LIST" => AttributeType::IntList,
+                "LABEL_LIST" => AttributeType::LabelList,
+                "BOOLEAN" => AttributeType::Bool,
+                "LABEL_STRING_DICT" => AttributeType::LabelStringDict,
+                "STRING_DICT" => AttributeType::StringDict,
+                "STRING_LIST_DICT" => AttributeType::StringListDict,
+                "OUTPUT" => AttributeType::Output,
+                "OUTPUT_LIST" => AttributeType::OutputList,
+                "LABEL_DICT_UNARY" => AttributeType::LabelDictUnary,
+                "LABEL_LIST_DICT" => AttributeType::LabelListDict,
+                _ => AttributeType::Unknown,
+            },
+            Some(_) => return Err(expected("a string", "type")),
+        };
+        let mandatory = match field(attribute, "mandatory", "mandatory") {
+            None => false,
+            Some(Value::Bool(mandatory)) => *mandatory,
+            Some(_) => return Err(expected("a bool", "mandatory")),
+        };
+        let default = string(attribute, "defaultValue", "default_value")?;
+        Ok(Attribute {
+            name: name.to_owned(),
+            doc: string(attribute, "docString", "doc_string")?.to_owned(),
+            kind,
+            mandatory,
+            default: if default.is_empty() {
+                None
+            } else {
+                Some(default.to_owned())
+            },
+        })
+    }
+
+    fn field<'a>(
+        object: &'a Map<String, Value>,
+        camel_case: &str,
+        snake_case: &str,
+    ) -> Option<&'a Value> {
+        object.get(camel_case).or_else(|| object.get(snake_case))
+    }
+
+    fn object<'a>(value: &'a Value, place: &str) -> Result<&'a Map<String, Value>, Error> {
+        match value {
+            Value::Object(object) => Ok(object),
+            _ => Err(error::stardoc_expected(format!("an object {}", place))),
+        }
+    }
+
+    fn expected(kind: &str, field: &str) -> Error {
+        error::stardoc_expected(format!("{} as `{}`", kind, field))
+    }
+
+    // Protobuf's JSON mapping leaves out fields that have their default value,
+    // so a missing array is empty and a missing string is "".
+    fn array<'a>(
+        object: &'a Map<String, Value>,
+        camel_case: &str,
+        snake_case: &str,
+    ) -> Result<&'a [Value], Error> {
+        match field(object, camel_case, snake_case) {
+            None => Ok(&[]),
+            Some(Value::Array(array)) => Ok(array),
+            Some(_) => Err(expected("an array", camel_case)),
+        }
+    }
+
+    fn string<'a>(
+        object: &'a Map<String, Value>,
+        camel_case: &str,
+        snake_case: &str,
+    ) -> Result<&'a str, Error> {
+        match field(object, camel_case, snake_case) {
+            None => Ok(""),
+            Some(Value::String(string)) => Ok(string),
+            Some(_) => Err(expected("a string", camel_case)),
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use crate::error;
+use crate::{BranchOrder, Error, FunctionCall, Select};
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeTuple, SerializeTupleStruct, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+
+const DEFAULT_CONDITION: &str = "//conditions:default";
+
+impl<T> Select<T> {
+    /// Add a branch for one condition, returning the value that the
+    /// condition previously had, if any.
+    pub fn insert(&mut self, condition: impl Into<String>, value: T) -> Option<T> {
+        self.insert_branch(vec![condition.into()], value)
+    }
+
+    /// Add a branch that applies when any of the conditions matches,
+    /// returning the value that the same group of conditions previously had,
+    /// if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `conditions` is empty.
+    pub fn insert_or<I>(&mut self, conditions: I, value: T) -> Option<T>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let conditions: Vec<String> = conditions.into_iter().map(Into::into).collect();
+        assert!(!conditions.is_empty(), "select branch with no conditions");
+        self.insert_branch(conditions, value)
+    }
+
+    fn insert_branch(&mut self, conditions: Vec<String>, value: T) -> Option<T> {
+        if let Some(previous) = self.get_mut(&conditions) {
+            Some(mem::replace(previous, value))
+        } else {
+            self.branches.push((conditions, value));
+            None
+        }
+    }
+
+    fn get(&self, conditions: &[String]) -> Option<&T> {
+        self.branches
+            .iter()
+            .find(|branch| branch.0 == conditions)
+            .map(|branch| &branch.1)
+    }
+
+    fn get_mut(&mut self, conditions: &[String]) -> Option<&mut T> {
+        self.branches
+            .iter_mut()
+            .find(|branch| branch.0 == conditions)
+            .map(|branch| &mut branch.1)
+    }
+
+    /// Choose the order in which the branches are written.
+    ///
+    /// ```
+    /// use serde_starlark::{BranchOrder, Select};
+    ///
+    /// let mut deps = Select::new();
+    /// deps.insert("//conditions:default", vec![]);
+    /// deps.insert(":b", vec![":b"]);
+    /// deps.insert(":a", vec![":a"]);
+    ///
+    /// let expected = r#"select({
+    ///     ":a": [":a"],
+    ///     ":b": [":b"],
+    ///     "//conditions:default": [],
+    /// })
+    /// "#;
+    /// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+    ///
+    /// deps.branch_order(BranchOrder::Insertion);
+    /// let expected = r#"select({
+    ///     "//conditions:default": [],
+    ///     ":b": [":b"],
+    ///     ":a": [":a"],
+    /// })
+    /// "#;
+    /// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+    /// ```
+    pub fn branch_order(&mut self, order: BranchOrder) -> &mut Self {
+        self.order = order;
+        self
+    }
+
+    /// Set the value that is concatenated in front of every branch, written
+    /// as `base + select({…})`.
+    pub fn base(&mut self, base: T) -> &mut Self {
+        self.base = Some(base);
+        self
+    }
+
+    /// Qualify conditions that are written as a bare name, such as a target
+    /// triple, by putting `prefix` in front of them.
+    ///
+    /// The prefix is applied when the select is serialized, so the same
+    /// branches can be written for different rule sets by changing only the
+    /// prefix. A condition that is already a label, starting with `//`, `@`
+    /// or `:`, is written unchanged.
+    ///
+    /// ```
+    /// use serde_starlark::Select;
+    ///
+    /// let mut deps = Select::new();
+    /// deps.insert("x86_64-unknown-linux-gnu", vec![":libc"]);
+    /// deps.insert("//conditions:default", vec![]);
+    /// deps.condition_prefix("@rules_rust//rust/platform:");
+    ///
+    /// let expected = r#"select({
+    ///     "@rules_rust//rust/platform:x86_64-unknown-linux-gnu": [":libc"],
+    ///     "//conditions:default": [],
+    /// })
+    /// "#;
+    /// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+    /// ```
+    pub fn condition_prefix(&mut self, prefix: impl Into<String>) -> &mut Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Concatenate another select onto this one, as if the two were added
+    /// with `+` in Starlark.
+    ///
+    /// The result has a branch for every condition of either select. Where
+    /// only one of them has a branch for a condition, the other contributes
+    /// the value of its `//conditions:default` branch, or nothing. This is the
+    /// same as Starlark's `+` provided that at most one condition of the
+    /// result matches in any configuration, as is the case when every
+    /// condition is a different platform. The condition prefix of `other` is
+    /// ignored.
+    ///
+    /// ```
+    /// use serde_starlark::Select;
+    ///
+    /// let mut deps = Select::new();
+    /// deps.base(vec![":common"]);
+    /// deps.insert("@platforms//os:linux", vec![":linux"]);
+    /// deps.insert("//conditions:default", vec![":other"]);
+    ///
+    /// let mut features = Select::new();
+    /// features.insert("@platforms//os:linux", vec![":epoll"]);
+    /// features.insert("@platforms//os:macos", vec![":kqueue"]);
+    ///
+    /// deps.merge(features);
+    /// let expected = r#"[":common"] + select({
+    ///     "@platforms//os:linux": [
+    ///         ":linux",
+    ///         ":epoll",
+    ///     ],
+    ///     "@platforms//os:macos": [
+    ///         ":other",
+    ///         ":kqueue",
+    ///     ],
+    ///     "//conditions:default": [":other"],
+    /// })
+    /// "#;
+    /// assert_eq!(serde_starlark::to_string(&deps).unwrap(), expected);
+    /// ```
+    pub fn merge(&mut self, other: Select<T>)
+    where
+        T: Clone + Default + IntoIterator + Extend<<T as IntoIterator>::Item>,
+    {
+        let default = [DEFAULT_CONDITION.to_owned()];
+        let self_default = self.get(&default).cloned();
+        let other_default = other.get(&default).cloned();
+
+        for (conditions, value) in &mut self.branches {
+            if other.get(conditions).is_none() {
+                value.extend(other_default.clone().unwrap_or_default());
+            }
+        }
+        for (conditions, value) in other.branches {
+            if let Some(existing) = self.get_mut(&conditions) {
+                existing.extend(value);
+            } else {
+                let mut merged = self_default.clone().unwrap_or_default();
+                merged.extend(value);
+                self.branches.push((conditions, merged));
+            }
+        }
+
+        match (&mut self.base, other.base) {
+            (Some(base), Some(other_base)) => base.extend(other_base),
+            (base @ None, other_base) => *base = other_base,
+            (Some(_), None) => {}
+        }
+    }
+
+    /// Add the branches of another select whose conditions do not overlap
+    /// with this one's.
+    ///
+    /// It is not an error for both selects to have a branch for the same
+    /// conditions if the two branches have the same value. Only the branches
+    /// are combined; the base and condition prefix of `other` are ignored.
+    ///
+    /// ```
+    /// use serde_starlark::Select;
+    ///
+    /// let mut edition = Select::new();
+    /// edition.insert(":legacy", "2018");
+    ///
+    /// let mut other = Select::new();
+    /// other.insert(":legacy", "2015");
+    ///
+    /// let error = edition.union(other).unwrap_err();
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "conflicting values in select for condition :legacy",
+    /// );
+    /// ```
+    pub fn union(&mut self, other: Select<T>) -> Result<(), Error>
+    where
+        T: PartialEq,
+    {
+        for (conditions, value) in other.branches {
+            match self.get(&conditions) {
+                Some(existing) if *existing != value => {
+                    return Err(error::select_conflict(&conditions));
+                }
+                Some(_) => {}
+                None => self.branches.push((conditions, value)),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> Serialize for Select<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let function = if self.branches.iter().any(|branch| branch.0.len() > 1) {
+            "selects.with_or"
+        } else {
+            "select"
+        };
+        let branches = Branches {
+            entries: &self.branches,
+            prefix: self.prefix.as_deref(),
+            order: self.order,
+        };
+        let select = FunctionCall::new(function, (branches,));
+        match &self.base {
+            None => select.serialize(serializer),
+            Some(base) if self.branches.is_empty() => base.serialize(serializer),
+            Some(base) => {
+                let mut plus = serializer.serialize_tuple_struct("+", 2)?;
+                plus.serialize_field(base)?;
+                plus.serialize_field(&select)?;
+                plus.end()
+            }
+        }
+    }
+}
+
+// Branches are kept in the order they are written, so that a select that is
+// deserialized and serialized again comes out the same.
+impl<'de, T> Deserialize<'de> for Select<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SelectVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for SelectVisitor<T>
+        where
+            T: Deserialize<'de>,
+        {
+            type Value = Select<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a select")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut select = Select::new();
+                select.branch_order(BranchOrder::Insertion);
+                while let Some((BranchKey(conditions), value)) = map.next_entry()? {
+                    select.branches.push((conditions, value));
+                }
+                Ok(select)
+            }
+        }
+
+        deserializer.deserialize_map(SelectVisitor(PhantomData))
+    }
+}
+
+// The key of a branch: one condition, or a tuple of them in selects.with_or.
+struct BranchKey(Vec<String>);
+
+impl<'de> Deserialize<'de> for BranchKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ConditionsVisitor;
+
+        impl<'de> Visitor<'de> for ConditionsVisitor {
+            type Value = BranchKey;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a condition or tuple of conditions")
+            }
+
+            fn visit_str<E>(self, condition: &str) -> Result<Self::Value, E> {
+                Ok(BranchKey(vec![condition.to_owned()]))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let mut conditions = Vec::new();
+                while let Some(condition) = seq.next_element()? {
+                    conditions.push(condition);
+                }
+                Ok(BranchKey(conditions))
+            }
+        }
+
+        deserializer.deserialize_any(ConditionsVisitor)
+    }
+}
+
+struct Branches<'a, T> {
+    entries: &'a [(Vec<String>, T)],
+    prefix: Option<&'a str>,
+    order: BranchOrder,
+}
+
+impl<'a, T> Serialize for Branches<'a, T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut branches: Vec<(Vec<String>, &T)> = self
+            .entries
+            .iter()
+            .map(|(conditions, value)| {
+                let labels = conditions
+                    .iter()
+                    .map(|condition| self.label(condition))
+                    .collect();
+                (labels, value)
+            })
+            .collect();
+        match self.order {
+            BranchOrder::Alphabetical => branches.sort_by(|a, b| {
+                let a_default = a.0 == [DEFAULT_CONDITION];
+                let b_default = b.0 == [DEFAULT_CONDITION];
+                (a_default, &a.0).cmp(&(b_default, &b.0))
+            }),
+            BranchOrder::Insertion => {}
+            BranchOrder::By(compare) => branches.sort_by(|a, b| compare(&a.0, &b.0)),
+        }
+
+        let mut map = serializer.serialize_map(Some(branches.len()))?;
+        for (labels, value) in &branches {
+            map.serialize_entry(&Conditions(labels), value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'a, T> Branches<'a, T> {
+    // The condition as written, with the prefix in front if it is a bare name
+    // rather than a label.
+    fn label(&self, condition: &str) -> String {
+        match self.prefix {
+            Some(prefix) if !is_label(condition) => format!("{}{}", prefix, condition),
+            _ => condition.to_owned(),
+        }
+    }
+}
+
+fn is_label(condition: &str) -> bool {
+    condition.starts_with("//") || condition.starts_with('@') || condition.starts_with(':')
+}
+
+// The key of a branch: a string, or a tuple of strings for a branch that
+// applies under any of several conditions.
+struct Conditions<'a>(&'a [String]);
+
+impl<'a> Serialize for Conditions<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if let [condition] = self.0 {
+            return serializer.serialize_str(condition);
+        }
+        let mut tuple = serializer.serialize_tuple(self.0.len())?;
+        for condition in self.0 {
+            tuple.serialize_element(condition)?;
+        }
+        tuple.end()
+    }
+}
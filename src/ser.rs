@@ -1,40 +1,666 @@
-use crate::error;
-use crate::Error;
+use crate::document::{self, Separators};
+use crate::error::{self, Segment};
+use crate::ident;
+use crate::key::{DictKey, KeySerializer};
+use crate::{
+    ArgumentOrder, ArgumentPolicy, Config, Container, Error, FunctionCall, IntegerOverflow,
+    QuoteStyle,
+};
 use serde::ser::{
-    Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple,
-    SerializeTupleStruct,
+    Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant,
 };
+use std::borrow::Cow;
+use std::cmp::{self, Ordering};
+use std::collections::BTreeSet;
 use std::fmt::Write;
+use std::io;
 use std::iter;
+use std::mem;
+use std::ops::Range;
 
-pub struct WriteStarlark {
+pub struct WriteStarlark<'c> {
     output: String,
     indent: usize,
+    // A newline followed by at least `indent` spaces, from which the start of
+    // each line is copied.
+    newline: String,
     line_comment: Option<String>,
+    radix: Option<Radix>,
+    layout: Option<Layout>,
+    unsplittable: usize,
+    element_start: Option<usize>,
+    spacer: Option<Separator>,
+    keep_sorted: Option<bool>,
+    // Whether the value being written is inside a select, where Bazel does not
+    // allow another select.
+    in_select: bool,
+    // Position just after the `=` of the assignment whose value is being
+    // written, where a long sum may be opened with a parenthesis.
+    assignment_value: Option<usize>,
+    // Position at which the argument of a function call that is being written
+    // begins, the only place a NamedArg, Args, or Kwargs may be written.
+    argument_start: Option<usize>,
+    // Set by a NamedArg, Args, or Kwargs for the function call whose argument
+    // it is.
+    argument_kind: Option<ArgumentKind>,
+    // Position at which the value of a named argument that is being written
+    // begins, the only place a Positional may be written.
+    positional_start: Option<usize>,
+    // Set by a Positional to the span of each of its elements, relative to
+    // the start of the value.
+    positional: Option<Vec<Range<usize>>>,
+    // The ArgumentPolicy of a named argument whose value begins at this
+    // position, for the list or dict written there.
+    policy: Option<(usize, ArgumentPolicy)>,
+    config: &'c Config,
+    // Destination of to_writer, to which complete top-level statements of a
+    // Document are written as soon as they are done.
+    sink: Option<&'c mut dyn io::Write>,
+}
+
+// A group separator requested by a Spacer, to be written in place of the list
+// element that requested it.
+enum Separator {
+    BlankLine,
+    Header(String),
+}
+
+// The kinds of argument of a function call, in the order that Starlark
+// requires them to be written: `*args` after all positional and named
+// arguments, and `**kwargs` last.
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+enum ArgumentKind {
+    Positional,
+    Named,
+    Args,
+    Kwargs,
+}
+
+impl ArgumentKind {
+    // Check that an argument of kind `next` may follow one of this kind.
+    fn then(self, next: ArgumentKind) -> Result<ArgumentKind, Error> {
+        match (self, next) {
+            (ArgumentKind::Kwargs, _) => Err(error::argument_after_kwargs()),
+            (ArgumentKind::Args, ArgumentKind::Kwargs) => Ok(next),
+            (ArgumentKind::Args, _) => Err(error::argument_after_args()),
+            (ArgumentKind::Named, ArgumentKind::Positional) => Err(error::positional_after_named()),
+            _ => Ok(next),
+        }
+    }
 }
 
-impl WriteStarlark {
-    pub(crate) fn new() -> Serializer<Self> {
+// The base in which to write the next integer, as requested by a Hex, Oct, or
+// Bin wrapper.
+#[derive(Copy, Clone)]
+enum Radix {
+    Hex,
+    Oct,
+    Bin,
+}
+
+// Position of a container that has been written in multi-line format, so that
+// it can be collapsed onto one line at the end if it fits within max_width.
+#[derive(Copy, Clone)]
+struct Wrap {
+    start: usize,
+    open: usize,
+    unsplittable: usize,
+}
+
+#[derive(Copy, Clone)]
+enum Layout {
+    Compact,
+    Expanded,
+}
+
+impl<'c> WriteStarlark<'c> {
+    pub(crate) fn new(config: &'c Config) -> Serializer<Self> {
+        Self::with_sink(config, None)
+    }
+
+    pub(crate) fn with_sink(
+        config: &'c Config,
+        sink: Option<&'c mut dyn io::Write>,
+    ) -> Serializer<Self> {
         Serializer {
             write: WriteStarlark {
                 output: String::new(),
                 indent: 0,
+                newline: String::from("\n"),
                 line_comment: None,
+                radix: None,
+                layout: None,
+                unsplittable: 0,
+                element_start: None,
+                spacer: None,
+                keep_sorted: None,
+                in_select: false,
+                assignment_value: None,
+                argument_start: None,
+                argument_kind: None,
+                positional_start: None,
+                positional: None,
+                policy: None,
+                config,
+                sink,
             },
         }
     }
 
+    // Serialize onto the end of an existing string, reusing its allocation. On
+    // error the string is restored to its original contents.
+    pub(crate) fn append<T>(config: &'c Config, output: &mut String, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let len = output.len();
+        let mut write = Self::new(config).write;
+        write.output = mem::take(output);
+        let result = value.serialize(Serializer { write: &mut write });
+        if result.is_ok() {
+            write.newline();
+        } else {
+            write.output.truncate(len);
+        }
+        *output = write.output;
+        result
+    }
+
+    // Serialize a value for insertion into existing Starlark at a position
+    // where lines are indented by `indent` spaces. The text has no trailing
+    // newline; a line comment requested at the end of the value is returned
+    // separately for the caller to place after whatever punctuation follows.
+    pub(crate) fn fragment<T>(
+        config: &'c Config,
+        value: &T,
+        indent: usize,
+    ) -> Result<(String, Option<String>), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let mut write = Self::new(config).write;
+        while write.newline.len() <= indent {
+            write.newline.push(' ');
+        }
+        write.indent = indent;
+        value.serialize(Serializer { write: &mut write })?;
+        Ok((write.output, write.line_comment.take()))
+    }
+
+    fn quote(&self, v: &str) -> char {
+        match self.config.quote_style {
+            QuoteStyle::Double => '"',
+            QuoteStyle::Single => '\'',
+            QuoteStyle::FewestEscapes => {
+                let double = v.matches('"').count();
+                let single = v.matches('\'').count();
+                if single < double {
+                    '\''
+                } else {
+                    '"'
+                }
+            }
+        }
+    }
+
+    // Serialize a value into a separate buffer instead of the output, as if it
+    // were at the start of a new line following the given text. The preceding
+    // text matters for max_width to see the right column.
+    fn serialize_detached<T>(
+        &mut self,
+        preceding: &[&str],
+        value: &T,
+    ) -> Result<(String, String), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut buffer = self.newline[..=self.indent].to_owned();
+        let line_start = buffer.len();
+        buffer.extend(preceding.iter().copied());
+        let preceding_len = buffer.len() - line_start;
+        let outer = mem::replace(&mut self.output, buffer);
+        let result = value.serialize(Serializer { write: &mut *self });
+        let buffer = mem::replace(&mut self.output, outer);
+        result?;
+        // Block comments may have been inserted in front of the preceding
+        // text. Those are returned separately from the value.
+        let mut value_start = line_start;
+        while buffer[value_start..].starts_with('#') {
+            value_start += buffer[value_start..].find('\n').unwrap() + line_start;
+        }
+        let comment = buffer[line_start..value_start].to_owned();
+        let value = buffer[value_start + preceding_len..].to_owned();
+        Ok((comment, value))
+    }
+
+    // Note the start of a call to the given function. Returns whether the call
+    // is within a select, to be restored when the call ends.
+    fn enter_call(&mut self, function: &str) -> Result<bool, Error> {
+        let outer = self.in_select;
+        if function == "select" || function == "selects.with_or" {
+            if outer {
+                return Err(error::nested_select());
+            }
+            self.in_select = true;
+        }
+        Ok(outer)
+    }
+
+    // The name under which a struct field or FunctionCall map key is written as
+    // a named argument.
+    fn argument_name<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        let mut name = Cow::Borrowed(key);
+        for (from, to) in &self.config.argument_renames {
+            if from == key {
+                name = Cow::Owned(to.clone());
+            }
+        }
+        if self.config.escape_keywords && ident::is_keyword(&name) {
+            name.to_mut().push('_');
+        }
+        name
+    }
+
+    // The policy of the named argument whose value is about to be written at
+    // `start`, if the configuration has one for the name.
+    fn set_policy(&mut self, name: &str, start: usize) {
+        let policies = &self.config.argument_policies;
+        let policy = policies.iter().find(|(existing, _)| existing == name);
+        self.policy = policy.map(|&(_, policy)| (start, policy));
+    }
+
+    // The policy for a list or dict that begins at `start`.
+    fn take_policy(&mut self, start: usize) -> ArgumentPolicy {
+        match self.policy.take() {
+            Some((position, policy)) if position == start => policy,
+            _ => ArgumentPolicy::default(),
+        }
+    }
+
+    // Whether a named argument with this serialized value is to be omitted.
+    fn is_skipped(&self, value: &str) -> bool {
+        match value {
+            "None" => self.config.skip_none,
+            "[]" | "{}" => self.config.skip_empty,
+            _ => false,
+        }
+    }
+
+    // Decide whether a container whose text begins at `start` and whose opening
+    // bracket has just been written is going to be multi-line. An explicit
+    // ONELINE or MULTILINE length hint takes precedence over the StarlarkFormat
+    // hook, which takes precedence over max_width.
+    fn container(
+        &self,
+        kind: Option<Container>,
+        start: usize,
+        len: Option<usize>,
+        default: bool,
+        explicit: bool,
+    ) -> (bool, Option<Wrap>) {
+        if let Some(multiline) = kind.and_then(|kind| self.format(kind, len, explicit)) {
+            return (multiline, None);
+        }
+        match self.layout {
+            Some(Layout::Compact) => (false, None),
+            Some(Layout::Expanded) => (true, None),
+            None if explicit || self.config.max_width.is_none() => (default, None),
+            None => {
+                let wrap = Wrap {
+                    start,
+                    open: self.output.len(),
+                    unsplittable: self.unsplittable,
+                };
+                (true, Some(wrap))
+            }
+        }
+    }
+
+    // The layout that the StarlarkFormat hook chooses for a container, unless
+    // it is already decided by something more specific.
+    fn format(&self, kind: Container, len: Option<usize>, explicit: bool) -> Option<bool> {
+        let format = self.config.format.as_ref()?;
+        if explicit || self.layout.is_some() {
+            return None;
+        }
+        match format.0.layout(kind, len) {
+            crate::Layout::Auto => None,
+            crate::Layout::Oneline => Some(false),
+            crate::Layout::Multiline => Some(true),
+        }
+    }
+
+    // Revisit the layout of a function call once its name is known, for a call
+    // whose name is written from its first field.
+    fn format_call(
+        &self,
+        function: &str,
+        len: usize,
+        multiline: &mut bool,
+        wrap: &mut Option<Wrap>,
+    ) {
+        let explicit = len == crate::ONELINE || len == crate::MULTILINE;
+        if let Some(format) = self.format(Container::Call(function), Some(len), explicit) {
+            *multiline = format;
+            *wrap = None;
+        }
+    }
+
+    // A sum on the right-hand side of an assignment is written with one operand
+    // per line inside of parentheses, to be joined back onto the assignment's
+    // line by collapse_sum if it fits within max_width.
+    fn parenthesize(&self, start: usize) -> (bool, Option<Wrap>) {
+        if self.assignment_value != Some(start)
+            || self.layout.is_some()
+            || self.config.max_width.is_none()
+        {
+            return (false, None);
+        }
+        let wrap = Wrap {
+            start,
+            open: start + 1,
+            unsplittable: self.unsplittable,
+        };
+        (true, Some(wrap))
+    }
+
+    // Make room in the output for a container of `len` elements, based on the
+    // least space each element can take up. The length hint comes from the
+    // Serialize impl and is not trusted to be accurate, so the reservation is
+    // capped.
+    fn reserve(&mut self, len: usize, multiline: bool) {
+        const MAX_RESERVE: usize = 1 << 20;
+        let per_element = if multiline { self.indent + 7 } else { 3 };
+        let additional = len.saturating_mul(per_element);
+        self.output.reserve(cmp::min(additional, MAX_RESERVE));
+    }
+
+    // Rewrite a multi-line container that has just been closed onto one line,
+    // provided that none of its elements span multiple lines, it contains no
+    // comments, and the result fits within max_width.
+    fn collapse(&mut self, wrap: Option<Wrap>, tuple: bool) {
+        let (wrap, max_width) = match (wrap, self.config.max_width) {
+            (Some(wrap), Some(max_width)) if wrap.unsplittable == self.unsplittable => {
+                (wrap, max_width)
+            }
+            _ => return,
+        };
+        let mut lines = self.output[wrap.open..].split('\n');
+        if lines.next() != Some("") {
+            return;
+        }
+        let lines: Vec<&str> = lines.collect();
+        let (close, elements) = match lines.split_last() {
+            Some((close, elements)) if !elements.is_empty() => (close, elements),
+            _ => return,
+        };
+        let close = match strip_indent(close, self.indent) {
+            Some(close) => close,
+            None => return,
+        };
+        let mut collapsed = String::new();
+        for line in elements {
+            let element = match strip_indent(line, self.indent + 4) {
+                Some(element) if element.ends_with(',') => &element[..element.len() - 1],
+                _ => return,
+            };
+            if !collapsed.is_empty() {
+                collapsed.push_str(", ");
+            }
+            collapsed.push_str(element);
+        }
+        if tuple && elements.len() == 1 {
+            collapsed.push(',');
+        }
+        collapsed.push_str(close);
+        let line_start = self.output[..wrap.start].rfind('\n').map_or(0, |i| i + 1);
+        let width = self.output[line_start..wrap.open].chars().count() + collapsed.chars().count();
+        if width <= max_width {
+            self.output.truncate(wrap.open);
+            self.output.push_str(&collapsed);
+        }
+    }
+
     fn newline(&mut self) {
         if let Some(line_comment) = self.line_comment.take() {
+            self.unsplittable += 1;
             self.output.push_str("  # ");
             self.output.push_str(&line_comment);
         }
-        let indent = iter::repeat(' ').take(self.indent);
-        self.output.extend(iter::once('\n').chain(indent));
+        self.output.push_str(&self.newline[..=self.indent]);
+    }
+
+    fn write_str(&mut self, v: &str) {
+        self.write_quoted(v, false);
+    }
+
+    fn write_triple_quoted_str(&mut self, v: &str) {
+        let triple = v.contains('\n');
+        self.write_quoted(v, triple);
+    }
+
+    fn write_quoted(&mut self, v: &str, triple: bool) {
+        // Reference:
+        // https://github.com/bazelbuild/starlark/blob/master/spec.md#string-literals
+        let quote = self.quote(v);
+        let quotes = if triple { 3 } else { 1 };
+        self.output.reserve(v.len() + 2 * quotes);
+        self.output.extend(iter::repeat(quote).take(quotes));
+        if triple {
+            self.unsplittable += 1;
+            // Begin with an escaped newline so that the first line of content
+            // lines up with the rest instead of trailing after the quotes.
+            self.output.push_str("\\\n");
+        }
+        let mut rest = v;
+        loop {
+            // Copy the longest run of characters that need no escaping in one
+            // go, then handle the character after it individually.
+            let plain = rest
+                .bytes()
+                .position(|b| !PLAIN[b as usize] || b == quote as u8)
+                .unwrap_or(rest.len());
+            self.output.push_str(&rest[..plain]);
+            let mut chars = rest[plain..].chars();
+            let ch = match chars.next() {
+                Some(ch) => ch,
+                None => break,
+            };
+            rest = chars.as_str();
+            let next = chars.next();
+            if triple && ch == '\n' {
+                self.output.push(ch);
+            } else if triple && ch == quote {
+                // Inside triple quotes, a quote only needs escaping if it could
+                // be part of a sequence of three that ends the literal.
+                if next.map_or(true, |next| next == quote) {
+                    self.output.push('\\');
+                }
+                self.output.push(ch);
+            } else if let Some(escape) = match ch {
+                _ if ch == quote => Some(quote),
+                '\x07' => Some('a'), // alert or bell
+                '\x08' => Some('b'), // backspace
+                '\x0C' => Some('f'), // form feed
+                '\n' => Some('n'),   // line feed
+                '\r' => Some('r'),   // carriage return
+                '\t' => Some('t'),   // horizontal tab
+                '\x0B' => Some('v'), // vertical tab
+                '\\' => Some('\\'),
+                _ => None,
+            } {
+                self.output.push('\\');
+                self.output.push(escape);
+            } else if ch.is_ascii_control()
+                && !self.config.hex_escapes
+                && (ch as u8 >= 0o100 || next.map_or(true, |next| !next.is_digit(8)))
+            {
+                // Starlark has variable-width octal escapes: \0 through \177.
+                // In order to use it we need to make sure the next character is
+                // not going to be an octal digit.
+                write!(self.output, "\\{:o}", ch as u8).unwrap();
+            } else if ch.is_control() || (self.config.ascii_only && !ch.is_ascii()) {
+                if ch <= '\x7F' {
+                    write!(self.output, "\\x{:02X}", ch as u8).unwrap();
+                } else if ch <= '\u{FFFF}' {
+                    write!(self.output, "\\u{:04X}", ch as u16).unwrap();
+                } else {
+                    write!(self.output, "\\U{:08X}", ch as u32).unwrap();
+                }
+            } else {
+                self.output.push(ch);
+            }
+        }
+        self.output.extend(iter::repeat(quote).take(quotes));
+    }
+
+    fn write_raw_str(&mut self, v: &str) {
+        if self.config.ascii_only && !v.is_ascii() {
+            return self.write_str(v);
+        }
+        let quote = match self.config.quote_style {
+            QuoteStyle::Double => '"',
+            QuoteStyle::Single => '\'',
+            QuoteStyle::FewestEscapes => {
+                if is_raw_representable(v, '"') {
+                    '"'
+                } else {
+                    '\''
+                }
+            }
+        };
+        if !is_raw_representable(v, quote) {
+            return self.write_str(v);
+        }
+        self.output.reserve(v.len() + 3);
+        self.output.push('r');
+        self.output.push(quote);
+        self.output.push_str(v);
+        self.output.push(quote);
+    }
+
+    fn write_int(&mut self, negative: bool, magnitude: u128) {
+        let sign = if negative { "-" } else { "" };
+        if let Some(radix) = self.radix.take() {
+            match radix {
+                Radix::Hex => write!(self.output, "{}0x{:X}", sign, magnitude),
+                Radix::Oct => write!(self.output, "{}0o{:o}", sign, magnitude),
+                Radix::Bin => write!(self.output, "{}0b{:b}", sign, magnitude),
+            }
+            .unwrap();
+            return;
+        }
+        // Nearly every integer fits in 32 bits, for which formatting is
+        // considerably faster than for u128.
+        let mut buffer = itoa::Buffer::new();
+        self.output.push_str(sign);
+        self.output.push_str(match u32::try_from(magnitude) {
+            Ok(magnitude) => buffer.format(magnitude),
+            Err(_) => buffer.format(magnitude),
+        });
+    }
+
+    fn write_non_finite_float(&mut self, v: f64) {
+        if v.is_nan() {
+            self.output.push_str("float(");
+            self.write_str("nan");
+        } else {
+            self.output.push_str(if v.is_sign_negative() {
+                "-float("
+            } else {
+                "float("
+            });
+            self.write_str("inf");
+        }
+        self.output.push(')');
+    }
+
+    fn write_comment_lines(&mut self, comment: &str) {
+        self.unsplittable += 1;
+        for (i, line) in comment.split('\n').enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+            self.output.push('#');
+            if !line.is_empty() {
+                self.output.push(' ');
+                self.output.push_str(line);
+            }
+        }
+    }
+
+    fn add_line_comment(&mut self, comment: &str) {
+        self.line_comment = Some(match self.line_comment.take() {
+            // For example a comment on a dict key followed by a comment on its
+            // value.
+            Some(mut pending) => {
+                pending.push_str("; ");
+                pending.push_str(comment);
+                pending
+            }
+            None => comment.to_owned(),
+        });
+    }
+
+    // Insert comment lines in front of the line currently being written, at the
+    // same indentation as that line.
+    fn insert_block_comment(&mut self, comment: &str) {
+        let line_start = self.output.rfind('\n').map_or(0, |i| i + 1);
+        let line = &self.output[line_start..];
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let mut lines = String::new();
+        for comment_line in comment.split('\n') {
+            lines.push('#');
+            if !comment_line.is_empty() {
+                lines.push(' ');
+                lines.push_str(comment_line);
+            }
+            lines.push('\n');
+            lines.extend(iter::repeat(' ').take(indent));
+        }
+        self.output.insert_str(line_start + indent, &lines);
+        self.unsplittable += 1;
+    }
+
+    fn begin_spacer(&mut self, separator: Separator) -> Result<(), Error> {
+        if self.element_start != Some(self.output.len()) {
+            return Err(error::unsupported_spacer());
+        }
+        self.spacer = Some(separator);
+        Ok(())
+    }
+
+    fn write_spacer(&mut self, blank_line: bool, separator: Separator) {
+        self.unsplittable += 1;
+        if blank_line {
+            self.newline();
+            let len = self.output.trim_end_matches(' ').len();
+            self.output.truncate(len);
+        }
+        if let Separator::Header(header) = separator {
+            self.newline();
+            self.output.push_str("# ");
+            self.output.push_str(&header);
+        }
+    }
+
+    // Hand off everything written so far to the sink. Only valid when nothing
+    // refers back to a position in the output, i.e. between statements of a
+    // top-level Document.
+    fn flush(&mut self) -> Result<(), Error> {
+        if let Some(sink) = &mut self.sink {
+            sink.write_all(self.output.as_bytes()).map_err(error::io)?;
+            self.output.clear();
+        }
+        Ok(())
     }
 
     fn indent(&mut self) {
         self.indent += 4;
+        if self.newline.len() <= self.indent {
+            self.newline.push_str("    ");
+        }
     }
 
     fn unindent(&mut self) {
@@ -43,48 +669,291 @@ impl WriteStarlark {
     }
 }
 
-pub trait MutableWriteStarlark {
+// The newtype structs whose name is a marker understood by the serializer,
+// each of which adjusts how its one field is written.
+impl<'c> WriteStarlark<'c> {
+    fn write_radix<T>(&mut self, radix: Radix, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.radix = Some(radix);
+        let result = value.serialize(Serializer { write: &mut *self });
+        self.radix = None;
+        result
+    }
+
+    // A string written other than as an ordinary string literal.
+    fn write_bare<T>(&mut self, value: &T, write: fn(&mut Self, &str)) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(BareStringSerializer::new(|string| write(self, string)))
+    }
+
+    fn write_layout<T>(&mut self, layout: Layout, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let outer = self.layout.replace(layout);
+        let result = value.serialize(Serializer { write: &mut *self });
+        self.layout = outer;
+        result
+    }
+
+    fn write_keep_sorted<T>(&mut self, sort: bool, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.keep_sorted = Some(sort);
+        let result = value.serialize(Serializer { write: &mut *self });
+        self.keep_sorted = None;
+        result
+    }
+
+    // Keys are always written on one line, even a tuple.
+    fn write_dict_key<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let outer = self.layout.replace(Layout::Compact);
+        let result = value.serialize(KeySerializer {
+            delegate: Serializer { write: &mut *self },
+        });
+        self.layout = outer;
+        result
+    }
+
+    fn write_header<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let header = value.serialize(BareStringSerializer::new(str::to_owned))?;
+        self.begin_spacer(Separator::Header(header))
+    }
+
+    fn write_ident<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(BareStringSerializer::with_error(
+            |string| {
+                if !ident::is_dotted_identifier(string) {
+                    return Err(error::invalid_name(string));
+                }
+                self.output.push_str(string);
+                Ok(())
+            },
+            error::expected_string,
+        ))?
+    }
+
+    // `*args` or `**kwargs` in the arguments of a function call.
+    fn write_unpacked<T>(&mut self, prefix: &str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let (kind, wrapper) = if prefix == "*" {
+            (ArgumentKind::Args, "Args")
+        } else {
+            (ArgumentKind::Kwargs, "Kwargs")
+        };
+        if self.argument_start.take() != Some(self.output.len()) {
+            return Err(error::unsupported_argument(wrapper));
+        }
+        self.output.push_str(prefix);
+        value.serialize(Serializer { write: &mut *self })?;
+        self.argument_kind = Some(kind);
+        Ok(())
+    }
+}
+
+// See parenthesize. Each operand is moved back out by one level of
+// indentation, and the parentheses are dropped, if every line then fits.
+fn collapse_sum(write: &mut WriteStarlark, wrap: Option<Wrap>, operands: &[(usize, usize)]) {
+    let (wrap, max_width) = match (wrap, write.config.max_width) {
+        (Some(wrap), Some(max_width)) if wrap.unsplittable == write.unsplittable => {
+            (wrap, max_width)
+        }
+        _ => return,
+    };
+    let mut joined = String::new();
+    for &(start, end) in operands {
+        if !joined.is_empty() {
+            joined.push_str(" + ");
+        }
+        let mut lines = write.output[start..end].split('\n');
+        joined.push_str(lines.next().unwrap_or_default());
+        for line in lines {
+            joined.push('\n');
+            joined.push_str(line.get(4..).unwrap_or_default());
+        }
+    }
+    let line_start = write.output[..wrap.start].rfind('\n').map_or(0, |i| i + 1);
+    let mut width = write.output[line_start..wrap.start].chars().count();
+    for line in joined.split('\n') {
+        if width + line.chars().count() > max_width {
+            return;
+        }
+        width = 0;
+    }
+    write.output.truncate(wrap.start);
+    write.output.push_str(&joined);
+}
+
+// The rest of the line after exactly `indent` spaces, if it is not blank and
+// not indented any further.
+fn strip_indent(line: &str, indent: usize) -> Option<&str> {
+    let rest = line.get(indent..)?;
+    if line[..indent].bytes().all(|b| b == b' ') && !rest.is_empty() && !rest.starts_with(' ') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+// ASCII bytes that are written as is inside a string literal, other than the
+// quote character in use.
+static PLAIN: [bool; 256] = {
+    let mut table = [false; 256];
+    let mut b = b' ';
+    while b < 0x7F {
+        table[b as usize] = b != b'\\';
+        b += 1;
+    }
+    table
+};
+
+fn is_raw_representable(v: &str, quote: char) -> bool {
+    // In a raw string literal a backslash still prevents the character after it
+    // from closing the literal, but both characters are retained in the value.
+    // Anything that a raw literal cannot express falls back to an ordinary
+    // escaped string.
+    let mut chars = v.chars();
+    while let Some(ch) = chars.next() {
+        let representable = match ch {
+            '\\' => chars.next().map_or(false, |next| !next.is_control()),
+            _ => ch != quote && !ch.is_control(),
+        };
+        if !representable {
+            return false;
+        }
+    }
+    true
+}
+
+pub trait WriteOutput {
     type Ok;
-    fn mutable(&mut self) -> &mut WriteStarlark;
     fn output(self) -> Self::Ok;
 }
 
-impl MutableWriteStarlark for WriteStarlark {
+// The lifetime is that of the Config, which is borrowed for the whole
+// serialization rather than cloned.
+pub trait MutableWriteStarlark<'c>: WriteOutput {
+    fn mutable(&mut self) -> &mut WriteStarlark<'c>;
+}
+
+impl<'c> WriteOutput for WriteStarlark<'c> {
     type Ok = String;
-    fn mutable(&mut self) -> &mut WriteStarlark {
-        self
-    }
     fn output(mut self) -> Self::Ok {
         self.newline();
         self.output
     }
 }
 
-impl MutableWriteStarlark for &mut WriteStarlark {
-    type Ok = ();
-    fn mutable(&mut self) -> &mut WriteStarlark {
+impl<'c> MutableWriteStarlark<'c> for WriteStarlark<'c> {
+    fn mutable(&mut self) -> &mut WriteStarlark<'c> {
         self
     }
+}
+
+impl<'a, 'c> WriteOutput for &'a mut WriteStarlark<'c> {
+    type Ok = ();
     fn output(self) -> Self::Ok {}
 }
 
+impl<'a, 'c> MutableWriteStarlark<'c> for &'a mut WriteStarlark<'c> {
+    fn mutable(&mut self) -> &mut WriteStarlark<'c> {
+        self
+    }
+}
+
+// Appends the finished output to a string owned by the caller, for serializer
+// adapters that require an Ok type of (). The string is untouched unless
+// serialization succeeds.
+pub struct WriteInto<'a, 'c> {
+    write: WriteStarlark<'c>,
+    target: &'a mut String,
+}
+
+impl<'a, 'c> WriteInto<'a, 'c> {
+    pub(crate) fn new(config: &'c Config, target: &'a mut String) -> Serializer<Self> {
+        Serializer {
+            write: WriteInto {
+                write: WriteStarlark::new(config).write,
+                target,
+            },
+        }
+    }
+}
+
+impl<'a, 'c> WriteOutput for WriteInto<'a, 'c> {
+    type Ok = ();
+    fn output(self) -> Self::Ok {
+        self.target.push_str(&self.write.output());
+    }
+}
+
+impl<'a, 'c> MutableWriteStarlark<'c> for WriteInto<'a, 'c> {
+    fn mutable(&mut self) -> &mut WriteStarlark<'c> {
+        &mut self.write
+    }
+}
+
 pub(crate) struct Serializer<W> {
     write: W,
 }
 
-impl<W> serde::Serializer for Serializer<W>
+impl<'c, W> Serializer<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    fn serialize_overflow(
+        mut self,
+        negative: bool,
+        magnitude: u128,
+        type_name: &'static str,
+        error: impl FnOnce() -> Error,
+    ) -> Result<W::Ok, Error> {
+        let write = self.write.mutable();
+        match write.config.integer_overflow {
+            IntegerOverflow::Error => return Err(error()),
+            IntegerOverflow::Emit => write.write_int(negative, magnitude),
+            IntegerOverflow::Stringify => {
+                write.radix = None;
+                let sign = if negative { "-" } else { "" };
+                write.write_str(&format!("{}{}", sign, magnitude));
+                if write.line_comment.is_none() {
+                    write.line_comment = Some(type_name.to_owned());
+                }
+            }
+        }
+        Ok(self.write.output())
+    }
+}
+
+impl<'c, W> serde::Serializer for Serializer<W>
 where
-    W: MutableWriteStarlark,
+    W: MutableWriteStarlark<'c>,
 {
     type Ok = W::Ok;
     type Error = Error;
     type SerializeSeq = WriteSeq<W>;
     type SerializeTuple = WriteTuple<W>;
     type SerializeTupleStruct = WriteTupleStruct<W>;
-    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = WriteTupleStruct<W>;
     type SerializeMap = WriteMap<W>;
     type SerializeStruct = WriteStruct<W>;
-    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = WriteStruct<W>;
 
     fn serialize_bool(mut self, v: bool) -> Result<Self::Ok, Self::Error> {
         let write = self.write.mutable();
@@ -102,21 +971,25 @@ where
 
     fn serialize_i32(mut self, v: i32) -> Result<Self::Ok, Self::Error> {
         let write = self.write.mutable();
-        write!(write.output, "{}", v).unwrap();
+        write.write_int(v < 0, u128::from(v.unsigned_abs()));
         Ok(self.write.output())
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
         match i32::try_from(v) {
             Ok(v) => self.serialize_i32(v),
-            Err(_) => Err(error::unsupported_i64(v)),
+            Err(_) => self.serialize_overflow(v < 0, u128::from(v.unsigned_abs()), "i64", || {
+                error::unsupported_i64(v)
+            }),
         }
     }
 
     fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
         match i32::try_from(v) {
             Ok(v) => self.serialize_i32(v),
-            Err(_) => Err(error::unsupported_i128(v)),
+            Err(_) => self.serialize_overflow(v < 0, v.unsigned_abs(), "i128", || {
+                error::unsupported_i128(v)
+            }),
         }
     }
 
@@ -131,80 +1004,65 @@ where
     fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
         match i32::try_from(v) {
             Ok(v) => self.serialize_i32(v),
-            Err(_) => Err(error::unsupported_u32(v)),
+            Err(_) => {
+                self.serialize_overflow(false, u128::from(v), "u32", || error::unsupported_u32(v))
+            }
         }
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
         match i32::try_from(v) {
             Ok(v) => self.serialize_i32(v),
-            Err(_) => Err(error::unsupported_u64(v)),
+            Err(_) => {
+                self.serialize_overflow(false, u128::from(v), "u64", || error::unsupported_u64(v))
+            }
         }
     }
 
     fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
         match i32::try_from(v) {
             Ok(v) => self.serialize_i32(v),
-            Err(_) => Err(error::unsupported_u128(v)),
+            Err(_) => self.serialize_overflow(false, v, "u128", || error::unsupported_u128(v)),
         }
     }
 
-    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_f32(v))
-    }
-
-    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_f64(v))
-    }
-
-    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_char(v))
-    }
-
-    fn serialize_str(mut self, v: &str) -> Result<Self::Ok, Self::Error> {
+    fn serialize_f32(mut self, v: f32) -> Result<Self::Ok, Self::Error> {
         let write = self.write.mutable();
-
-        // Reference:
-        // https://github.com/bazelbuild/starlark/blob/master/spec.md#string-literals
-        write.output.reserve(v.len() + 2);
-        write.output.push('"');
-        let mut chars = v.chars().peekable();
-        while let Some(ch) = chars.next() {
-            if let Some(escape) = match ch {
-                '\x07' => Some('a'), // alert or bell
-                '\x08' => Some('b'), // backspace
-                '\x0C' => Some('f'), // form feed
-                '\n' => Some('n'),   // line feed
-                '\r' => Some('r'),   // carriage return
-                '\t' => Some('t'),   // horizontal tab
-                '\x0B' => Some('v'), // vertical tab
-                '"' => Some('"'),
-                '\\' => Some('\\'),
-                _ => None,
-            } {
-                write.output.push('\\');
-                write.output.push(escape);
-            } else if ch.is_ascii_control()
-                && (ch as u8 >= 0o100 || chars.peek().map_or(true, |next| !next.is_digit(8)))
-            {
-                // Starlark has variable-width octal escapes: \0 through \177.
-                // In order to use it we need to make sure the next character is
-                // not going to be an octal digit.
-                write!(write.output, "\\{:o}", ch as u8).unwrap();
-            } else if ch.is_control() {
-                if ch <= '\x7F' {
-                    write!(write.output, "\\x{:02X}", ch as u8).unwrap();
-                } else if ch <= '\u{FFFF}' {
-                    write!(write.output, "\\u{:04X}", ch as u16).unwrap();
-                } else {
-                    write!(write.output, "\\U{:08X}", ch as u32).unwrap();
-                }
-            } else {
-                write.output.push(ch);
-            }
+        if !write.config.floats {
+            return Err(error::unsupported_f32(v));
+        }
+        if v.is_finite() {
+            write!(write.output, "{:?}", v).unwrap();
+        } else if write.config.non_finite_floats {
+            write.write_non_finite_float(f64::from(v));
+        } else {
+            return Err(error::non_finite_f32(v));
+        }
+        Ok(self.write.output())
+    }
+
+    fn serialize_f64(mut self, v: f64) -> Result<Self::Ok, Self::Error> {
+        let write = self.write.mutable();
+        if !write.config.floats {
+            return Err(error::unsupported_f64(v));
+        }
+        if v.is_finite() {
+            write!(write.output, "{:?}", v).unwrap();
+        } else if write.config.non_finite_floats {
+            write.write_non_finite_float(v);
+        } else {
+            return Err(error::non_finite_f64(v));
         }
-        write.output.push('"');
+        Ok(self.write.output())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Err(error::unsupported_char(v))
+    }
 
+    fn serialize_str(mut self, v: &str) -> Result<Self::Ok, Self::Error> {
+        let write = self.write.mutable();
+        write.write_str(v);
         Ok(self.write.output())
     }
 
@@ -225,12 +1083,22 @@ where
         value.serialize(self)
     }
 
-    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_unit())
+    fn serialize_unit(mut self) -> Result<Self::Ok, Self::Error> {
+        if !self.write.mutable().config.unit_as_none {
+            return Err(error::unsupported_unit());
+        }
+        self.serialize_none()
     }
 
     fn serialize_unit_struct(mut self, name: &'static str) -> Result<Self::Ok, Self::Error> {
         let write = self.write.mutable();
+        if name == "*spacer" {
+            write.begin_spacer(Separator::BlankLine)?;
+            return Ok(self.write.output());
+        }
+        if name == "PhantomData" && write.config.unit_as_none {
+            return self.serialize_none();
+        }
         write.output.push_str(name);
         Ok(self.write.output())
     }
@@ -245,49 +1113,102 @@ where
     }
 
     fn serialize_newtype_struct<T>(
-        self,
+        mut self,
         name: &'static str,
         value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize + ?Sized,
     {
-        let mut tuple = self.serialize_tuple_struct(name, 1)?;
-        tuple.serialize_field(value)?;
-        tuple.end()
+        let write = self.write.mutable();
+        match name {
+            "0x" => write.write_radix(Radix::Hex, value)?,
+            "0o" => write.write_radix(Radix::Oct, value)?,
+            "0b" => write.write_radix(Radix::Bin, value)?,
+            "r\"" => write.write_bare(value, WriteStarlark::write_raw_str)?,
+            "\"\"\"" => write.write_bare(value, WriteStarlark::write_triple_quoted_str)?,
+            "*compact" => write.write_layout(Layout::Compact, value)?,
+            "*expanded" => write.write_layout(Layout::Expanded, value)?,
+            "*keep_sorted" | "*sort" => write.write_keep_sorted(name == "*sort", value)?,
+            "*dict_key" => write.write_dict_key(value)?,
+            "*banner" => write.write_bare(value, WriteStarlark::write_comment_lines)?,
+            "*spacer" => write.write_header(value)?,
+            "*ident" => write.write_ident(value)?,
+            "*" | "**" => write.write_unpacked(name, value)?,
+            // Source text being reformatted, for a construct that is copied
+            // as it was written.
+            "*verbatim" => {
+                write.write_bare(value, |write, string| write.output.push_str(string))?;
+            }
+            _ => {
+                let mut tuple = self.serialize_tuple_struct(name, 1)?;
+                SerializeTupleStruct::serialize_field(&mut tuple, value)?;
+                return SerializeTupleStruct::end(tuple);
+            }
+        }
+        Ok(self.write.output())
     }
 
     fn serialize_newtype_variant<T>(
         self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        _value: &T,
+        value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize + ?Sized,
     {
-        Err(error::unsupported_enum(name, variant))
+        FunctionCall::new(variant, value).serialize(self)
     }
 
     fn serialize_seq(mut self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        let multiline = len.map_or(true, |len| len > 1);
         let write = self.write.mutable();
+        let start = write.output.len();
+        let policy = write.take_policy(start);
+        let len = length_hint(len, policy);
         write.output.push('[');
+        let explicit = len == Some(crate::ONELINE) || len == Some(crate::MULTILINE);
+        let default = len.map_or(true, |len| len > 1);
+        let (multiline, wrap) =
+            write.container(Some(Container::List), start, len, default, explicit);
+        if !explicit {
+            write.reserve(len.unwrap_or(0), multiline);
+        }
+        let keep_sorted = write.keep_sorted.take();
+        if multiline && keep_sorted.is_some() {
+            write.add_line_comment("keep sorted");
+        }
+        let sorted = if keep_sorted == Some(true) || policy.sort {
+            Some(Sorted::default())
+        } else {
+            None
+        };
         Ok(WriteSeq {
             write: self.write,
             multiline,
+            wrap,
             len: 0,
+            index: 0,
+            sorted,
         })
     }
 
     fn serialize_tuple(mut self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        let multiline = len == crate::MULTILINE;
         let write = self.write.mutable();
+        let start = write.output.len();
         write.output.push('(');
+        let explicit = len == crate::ONELINE || len == crate::MULTILINE;
+        let default = len == crate::MULTILINE;
+        let (multiline, wrap) =
+            write.container(Some(Container::Tuple), start, Some(len), default, explicit);
+        if !explicit {
+            write.reserve(len, multiline);
+        }
         Ok(WriteTuple {
             write: self.write,
             multiline,
+            wrap,
             len: 0,
         })
     }
@@ -301,41 +1222,132 @@ where
         let rename = name == "(";
         let plus = name == "+";
         let line_comment = name == "#";
-        let multiline = len > 1 && !plus;
-        if !assignment && !rename && !plus && !line_comment {
-            let write = self.write.mutable();
+        let block_comment = name == "*block";
+        let document = name == "*document";
+        let def = name == "*def";
+        let constants = name == "*constants";
+        let section = name == "*section";
+        let named = name == "*named";
+        let positional = name == "*positional";
+        let write = self.write.mutable();
+        let start = write.output.len();
+        if named && write.argument_start.take() != Some(start) {
+            return Err(error::unsupported_argument("NamedArg"));
+        }
+        if positional && write.positional_start.take() != Some(start) {
+            return Err(error::unsupported_positional());
+        }
+        let stream = document && start == 0 && write.indent == 0;
+        let mut outer_select = write.in_select;
+        if !assignment
+            && !rename
+            && !plus
+            && !line_comment
+            && !block_comment
+            && !document
+            && !def
+            && !constants
+            && !section
+            && !named
+            && !positional
+        {
+            if !ident::is_function_name(name) {
+                return Err(error::invalid_function_name(name));
+            }
+            outer_select = write.enter_call(name)?;
             write.output.push_str(name);
             write.output.push('(');
         }
+        let explicit = len == crate::ONELINE || len == crate::MULTILINE;
+        let (multiline, wrap) = if plus {
+            write.parenthesize(start)
+        } else if document || def || constants || section || named || positional {
+            (false, None)
+        } else if assignment || line_comment || block_comment {
+            (len > 1, None)
+        } else {
+            let kind = if rename {
+                None
+            } else {
+                Some(Container::Call(name))
+            };
+            write.container(kind, start, Some(len), len > 1, explicit)
+        };
+        // A line comment wrapped around a whole assignment belongs at the end
+        // of the statement, not on the first line of a multi-line value.
+        let suffix = if assignment {
+            write.line_comment.take()
+        } else {
+            None
+        };
         Ok(WriteTupleStruct {
             write: self.write,
             multiline,
+            wrap,
             assignment,
-            rename,
+            rename: if rename { Some(len) } else { None },
             plus,
             line_comment,
+            block_comment,
+            document,
+            def,
+            constants,
+            section,
+            named,
+            positional,
+            separators: Separators::decode(len),
+            previous_load: false,
+            stream,
+            outer_select,
+            suffix,
+            operands: Vec::new(),
+            last: ArgumentKind::Positional,
             len: 0,
         })
     }
 
     fn serialize_tuple_variant(
         self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        Err(error::unsupported_enum(name, variant))
+        self.serialize_tuple_struct(variant, len)
     }
 
     fn serialize_map(mut self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        let multiline = len.map_or(true, |len| len > 0);
         let write = self.write.mutable();
+        let start = write.output.len();
+        let policy = write.take_policy(start);
+        let len = length_hint(len, policy);
         write.output.push('{');
+        let explicit = len == Some(crate::ONELINE) || len == Some(crate::MULTILINE);
+        let default = len.map_or(true, |len| len > 0);
+        let (multiline, wrap) =
+            write.container(Some(Container::Dict), start, len, default, explicit);
+        if !explicit {
+            write.reserve(len.unwrap_or(0), multiline);
+        }
+        let sorted = if write.config.sort_map_keys || policy.sort {
+            Some(Sorted::default())
+        } else {
+            None
+        };
+        let keys = if write.config.deny_duplicate_keys {
+            Some(BTreeSet::new())
+        } else {
+            None
+        };
         Ok(WriteMap {
             write: self.write,
             multiline,
+            wrap,
             len: 0,
+            key: None,
+            keys,
+            sorted,
+            sorted_key: None,
         })
     }
 
@@ -345,40 +1357,151 @@ where
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
         let rename = name == "(";
-        let multiline = len >= 1;
+        let write = self.write.mutable();
+        let start = write.output.len();
+        let mut outer_select = write.in_select;
         if !rename {
-            let write = self.write.mutable();
+            if !ident::is_function_name(name) {
+                return Err(error::invalid_function_name(name));
+            }
+            outer_select = write.enter_call(name)?;
             write.output.push_str(name);
             write.output.push('(');
         }
+        let explicit = len == crate::ONELINE || len == crate::MULTILINE;
+        let kind = if rename {
+            None
+        } else {
+            Some(Container::Call(name))
+        };
+        let (multiline, wrap) = write.container(kind, start, Some(len), len >= 1, explicit);
+        if !explicit {
+            write.reserve(len, multiline);
+        }
+        let sorted = match write.config.argument_order {
+            ArgumentOrder::Declaration => None,
+            ArgumentOrder::NameFirst => Some(Sorted::default()),
+        };
         Ok(WriteStruct {
             write: self.write,
             multiline,
-            rename,
+            wrap,
+            rename: if rename { Some(len) } else { None },
+            outer_select,
             len: 0,
+            checkpoint: None,
+            map_key: None,
+            sorted,
+            last: ArgumentKind::Positional,
+            deferred: Vec::new(),
         })
     }
 
     fn serialize_struct_variant(
         self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
         variant: &'static str,
-        _len: usize,
+        len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Err(error::unsupported_enum(name, variant))
+        self.serialize_struct(variant, len)
     }
 }
 
 pub struct WriteSeq<W> {
     write: W,
     multiline: bool,
+    wrap: Option<Wrap>,
     len: usize,
+    index: usize,
+    sorted: Option<Sorted>,
+}
+
+impl<'c, W> WriteSeq<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    fn serialize_sorted<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        let sorted = self.sorted.as_mut().unwrap();
+        if sorted.entries.is_empty() {
+            sorted.line_comment = write.line_comment.take();
+        }
+        let indent = write.indent;
+        if self.multiline && self.len == 0 {
+            write.indent();
+        }
+        // Position of the element within the detached buffer, which begins
+        // with a newline and indentation.
+        write.element_start = Some(1 + write.indent);
+        let result = write.serialize_detached(&[], value);
+        write.indent = indent;
+        let index = self.index;
+        self.index += 1;
+        let (block_comment, value) = result.map_err(|error| error.within(Segment::Index(index)))?;
+        let line_comment = write.line_comment.take();
+        if let Some(separator) = write.spacer.take() {
+            // Groups separated by a spacer are sorted independently.
+            let sorted = self.sorted.replace(Sorted::default());
+            self.write_sorted(sorted, compare_values);
+            let write = self.write.mutable();
+            if self.multiline {
+                if self.len == 0 {
+                    write.indent();
+                }
+                self.len += 1;
+                write.write_spacer(self.len > 1, separator);
+            }
+            return Ok(());
+        }
+        sorted.entries.push(SortedEntry {
+            block_comment,
+            key: String::new(),
+            value,
+            line_comment,
+        });
+        Ok(())
+    }
+}
+
+impl<'c, W> WriteSorted<'c> for WriteSeq<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    fn starlark(&mut self) -> &mut WriteStarlark<'c> {
+        self.write.mutable()
+    }
+
+    fn write_entry(&mut self, entry: &SortedEntry) -> bool {
+        let write = self.write.mutable();
+        if self.multiline {
+            if self.len == 0 {
+                write.indent();
+            }
+            write.newline();
+        } else if self.len > 0 {
+            write.output.push_str(", ");
+        }
+        self.len += 1;
+        write.output.push_str(&entry.block_comment);
+        write.output.push_str(&entry.value);
+        if self.multiline {
+            write.output.push(',');
+        }
+        true
+    }
+}
+
+fn compare_values(a: &SortedEntry, b: &SortedEntry) -> Ordering {
+    a.value.cmp(&b.value)
 }
 
-impl<W> SerializeSeq for WriteSeq<W>
+impl<'c, W> SerializeSeq for WriteSeq<W>
 where
-    W: MutableWriteStarlark,
+    W: MutableWriteStarlark<'c>,
 {
     type Ok = W::Ok;
     type Error = Error;
@@ -387,7 +1510,12 @@ where
     where
         T: Serialize + ?Sized,
     {
+        if self.sorted.is_some() {
+            return self.serialize_sorted(value);
+        }
         let write = self.write.mutable();
+        let before = write.output.len();
+        let line_comment = write.line_comment.clone();
         if self.multiline {
             if self.len == 0 {
                 write.indent();
@@ -397,19 +1525,35 @@ where
             write.output.push_str(", ");
         }
         self.len += 1;
-        value.serialize(Serializer { write: &mut *write })?;
-        if self.multiline {
+        write.element_start = Some(write.output.len());
+        let index = self.index;
+        self.index += 1;
+        value
+            .serialize(Serializer { write: &mut *write })
+            .map_err(|error| error.within(Segment::Index(index)))?;
+        if let Some(separator) = write.spacer.take() {
+            write.output.truncate(before);
+            write.line_comment = line_comment;
+            if self.multiline {
+                write.write_spacer(self.len > 1, separator);
+            } else {
+                self.len -= 1;
+            }
+        } else if self.multiline {
             write.output.push(',');
         }
         Ok(())
     }
 
     fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let sorted = self.sorted.take();
+        self.write_sorted(sorted, compare_values);
         let write = self.write.mutable();
         if self.len != 0 && self.multiline {
             write.unindent();
         }
         write.output.push(']');
+        write.collapse(self.wrap, false);
         Ok(self.write.output())
     }
 }
@@ -417,63 +1561,373 @@ where
 pub struct WriteTuple<W> {
     write: W,
     multiline: bool,
+    wrap: Option<Wrap>,
+    len: usize,
+}
+
+impl<'c, W> SerializeTuple for WriteTuple<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    type Ok = W::Ok;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        if self.multiline {
+            if self.len == 0 {
+                write.indent();
+            }
+            write.newline();
+        } else if self.len > 0 {
+            write.output.push_str(", ");
+        }
+        let index = self.len;
+        self.len += 1;
+        value
+            .serialize(Serializer { write: &mut *write })
+            .map_err(|error| error.within(Segment::Index(index)))?;
+        if self.multiline {
+            write.output.push(',');
+        }
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let write = self.write.mutable();
+        if self.len == 1 && !self.multiline {
+            write.output.push(',');
+        }
+        if self.len != 0 && self.multiline {
+            write.unindent();
+        }
+        write.output.push(')');
+        write.collapse(self.wrap, true);
+        Ok(self.write.output())
+    }
+}
+
+pub struct WriteTupleStruct<W> {
+    write: W,
+    multiline: bool,
+    wrap: Option<Wrap>,
+    assignment: bool,
+    // The length hint of a function call whose name is yet to be written from
+    // the first field.
+    rename: Option<usize>,
+    plus: bool,
+    line_comment: bool,
+    block_comment: bool,
+    document: bool,
+    def: bool,
+    constants: bool,
+    section: bool,
+    named: bool,
+    positional: bool,
+    separators: Separators,
+    previous_load: bool,
+    stream: bool,
+    outer_select: bool,
+    suffix: Option<String>,
+    operands: Vec<(usize, usize)>,
+    // Kind of the last argument of a function call.
+    last: ArgumentKind,
     len: usize,
 }
 
-impl<W> SerializeTuple for WriteTuple<W>
-where
-    W: MutableWriteStarlark,
-{
-    type Ok = W::Ok;
-    type Error = Error;
+impl<'c, W> WriteTupleStruct<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    fn assignment_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        if self.len == 0 {
+            self.len += 1;
+            value.serialize(BareStringSerializer::new(|string| {
+                if !ident::is_identifier(string) {
+                    return Err(error::invalid_name(string));
+                }
+                if write.config.uppercase_assignments && !ident::is_uppercase(string) {
+                    return Err(error::lowercase_assignment(string));
+                }
+                write.output.push_str(string);
+                write.output.push_str(" = ");
+                write.assignment_value = Some(write.output.len());
+                Ok(())
+            }))?
+        } else {
+            assert_eq!(self.len, 1);
+            self.len += 1;
+            value.serialize(Serializer { write: &mut *write })
+        }
+    }
+
+    fn function_name<T>(&mut self, hint: usize, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        value.serialize(BareStringSerializer::new(|string| {
+            if string == "+" {
+                self.plus = true;
+                (self.multiline, self.wrap) = write.parenthesize(write.output.len());
+            } else if !ident::is_function_name(string) {
+                return Err(error::invalid_function_name(string));
+            } else {
+                self.outer_select = write.enter_call(string)?;
+                write.output.push_str(string);
+                write.output.push('(');
+                if let Some(wrap) = &mut self.wrap {
+                    wrap.open = write.output.len();
+                }
+                write.format_call(string, hint, &mut self.multiline, &mut self.wrap);
+            }
+            Ok(())
+        }))??;
+        self.rename = None;
+        Ok(())
+    }
+
+    fn document_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        let index = self.len;
+        self.len += 1;
+        let separators = self.separators;
+        if index > 0 {
+            write.newline();
+            if !separators.group_loads {
+                for _ in 0..separators.blank_lines {
+                    write.output.push('\n');
+                }
+            }
+            if self.stream {
+                write.flush()?;
+            }
+        }
+        let start = write.output.len();
+        value
+            .serialize(Serializer { write: &mut *write })
+            .map_err(|error| error.within(Segment::Index(index)))?;
+        if separators.group_loads {
+            // Whether there are blank lines before this statement depends
+            // on whether it is a load, so they go in after it is written.
+            let load = document::is_load(&write.output[start..]);
+            if index > 0 && !(load && self.previous_load) {
+                for _ in 0..separators.blank_lines {
+                    write.output.insert(start, '\n');
+                }
+            }
+            self.previous_load = load;
+        }
+        Ok(())
+    }
+
+    // The function name, then its parameters, then one statement of
+    // the body per field.
+    fn def_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        let index = self.len;
+        self.len += 1;
+        match index {
+            0 => value.serialize(BareStringSerializer::new(|string| {
+                if !ident::is_identifier(string) {
+                    return Err(error::invalid_name(string));
+                }
+                write.output.push_str("def ");
+                write.output.push_str(string);
+                Ok(())
+            }))?,
+            1 => value.serialize(BareStringSerializer::new(|string| {
+                write.output.push('(');
+                write.output.push_str(string);
+                write.output.push_str("):");
+                write.indent();
+            })),
+            _ => {
+                write.newline();
+                value
+                    .serialize(Serializer { write: &mut *write })
+                    .map_err(|error| error.within(Segment::Index(index - 2)))
+            }
+        }
+    }
+
+    // The label, which only matters to the Document, then the statement.
+    fn section_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        let index = self.len;
+        self.len += 1;
+        if index == 0 {
+            Ok(())
+        } else {
+            value.serialize(Serializer { write: &mut *write })
+        }
+    }
+
+    // Assignments one per line, with a blank line around any that
+    // spans more than one line.
+    fn constants_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        let index = self.len;
+        self.len += 1;
+        if index > 0 {
+            write.newline();
+        }
+        let start = write.output.len();
+        value
+            .serialize(Serializer { write: &mut *write })
+            .map_err(|error| error.within(Segment::Index(index)))?;
+        let multiline = write.output[start..].contains('\n');
+        if index > 0 && (multiline || self.multiline) {
+            write.output.insert(start - write.indent, '\n');
+        }
+        self.multiline = multiline;
+        Ok(())
+    }
+
+    fn block_comment_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        if self.len == 0 {
+            self.len += 1;
+            value.serialize(BareStringSerializer::new(|string| {
+                write.insert_block_comment(string);
+            }))
+        } else {
+            assert_eq!(self.len, 1);
+            self.len += 1;
+            value.serialize(Serializer { write: &mut *write })
+        }
+    }
+
+    fn line_comment_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        if self.len == 0 {
+            self.len += 1;
+            value.serialize(BareStringSerializer::new(|string| {
+                // A comment that does not fit on one line goes above the
+                // value instead.
+                if string.contains('\n') {
+                    write.insert_block_comment(string);
+                } else {
+                    write.add_line_comment(string);
+                }
+            }))
+        } else {
+            assert_eq!(self.len, 1);
+            self.len += 1;
+            value.serialize(Serializer { write: &mut *write })
+        }
+    }
+
+    // The name of the argument, then its value.
+    fn named_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        if self.len == 0 {
+            self.len += 1;
+            value.serialize(BareStringSerializer::new(|string| {
+                let name = write.argument_name(string);
+                if !ident::is_identifier(&name) {
+                    return Err(error::invalid_argument_name(&name));
+                }
+                write.output.push_str(&name);
+                write.output.push_str(" = ");
+                Ok(())
+            }))?
+        } else {
+            assert_eq!(self.len, 1);
+            self.len += 1;
+            value.serialize(Serializer { write: &mut *write })
+        }
+    }
+
+    // The elements are written back to back, to be pulled apart by
+    // the function call.
+    fn positional_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        let index = self.len;
+        self.len += 1;
+        let start = write.output.len();
+        value
+            .serialize(Serializer { write: &mut *write })
+            .map_err(|error| error.within(Segment::Index(index)))?;
+        self.operands.push((start, write.output.len()));
+        Ok(())
+    }
 
-    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    // An argument of a function call, or an operand of `+`.
+    fn argument_field<T>(&mut self, value: &T) -> Result<(), Error>
     where
         T: Serialize + ?Sized,
     {
         let write = self.write.mutable();
         if self.multiline {
             if self.len == 0 {
+                if self.plus {
+                    write.output.push('(');
+                }
                 write.indent();
+            } else if self.plus {
+                write.output.push_str(" +");
             }
             write.newline();
         } else if self.len > 0 {
-            write.output.push_str(", ");
+            write.output.push_str(if self.plus { " + " } else { ", " });
         }
+        let index = self.len;
         self.len += 1;
-        value.serialize(Serializer { write: &mut *write })?;
+        let start = write.output.len();
+        if !self.plus {
+            write.argument_start = Some(start);
+        }
+        let result = value.serialize(Serializer { write: &mut *write });
+        write.argument_start = None;
+        result.map_err(|error| error.within(Segment::Index(index)))?;
+        let kind = write.argument_kind.take();
+        self.last = self.last.then(kind.unwrap_or(ArgumentKind::Positional))?;
         if self.multiline {
-            write.output.push(',');
+            if self.plus {
+                self.operands.push((start, write.output.len()));
+            } else {
+                write.output.push(',');
+            }
         }
         Ok(())
     }
-
-    fn end(mut self) -> Result<Self::Ok, Self::Error> {
-        let write = self.write.mutable();
-        if self.len == 1 && !self.multiline {
-            write.output.push(',');
-        }
-        if self.len != 0 && self.multiline {
-            write.unindent();
-        }
-        write.output.push(')');
-        Ok(self.write.output())
-    }
 }
 
-pub struct WriteTupleStruct<W> {
-    write: W,
-    multiline: bool,
-    assignment: bool,
-    rename: bool,
-    plus: bool,
-    line_comment: bool,
-    len: usize,
-}
-
-impl<W> SerializeTupleStruct for WriteTupleStruct<W>
+impl<'c, W> SerializeTupleStruct for WriteTupleStruct<W>
 where
-    W: MutableWriteStarlark,
+    W: MutableWriteStarlark<'c>,
 {
     type Ok = W::Ok;
     type Error = Error;
@@ -482,84 +1936,159 @@ where
     where
         T: Serialize + ?Sized,
     {
-        let write = self.write.mutable();
         if self.assignment {
-            return if self.len == 0 {
-                self.len += 1;
-                value.serialize(BareStringSerializer::new(|string| {
-                    write.output.push_str(string);
-                    write.output.push_str(" = ");
-                }))
-            } else {
-                assert_eq!(self.len, 1);
-                self.len += 1;
-                value.serialize(Serializer { write: &mut *write })
-            };
+            return self.assignment_field(value);
         }
-        if self.rename {
-            value.serialize(BareStringSerializer::new(|string| {
-                if string == "+" {
-                    self.plus = true;
-                    self.multiline = false;
-                } else {
-                    write.output.push_str(string);
-                    write.output.push('(');
-                }
-            }))?;
-            self.rename = false;
-            return Ok(());
+        if let Some(hint) = self.rename {
+            return self.function_name(hint, value);
+        }
+        if self.document {
+            return self.document_field(value);
+        }
+        if self.def {
+            return self.def_field(value);
+        }
+        if self.section {
+            return self.section_field(value);
+        }
+        if self.constants {
+            return self.constants_field(value);
+        }
+        if self.block_comment {
+            return self.block_comment_field(value);
         }
         if self.line_comment {
-            return if self.len == 0 {
-                self.len += 1;
-                value.serialize(BareStringSerializer::new(|string| {
-                    write.line_comment = Some(string.to_owned());
-                }))
-            } else {
-                assert_eq!(self.len, 1);
-                self.len += 1;
-                value.serialize(Serializer { write: &mut *write })
-            };
+            return self.line_comment_field(value);
         }
-        if self.multiline {
-            if self.len == 0 {
-                write.indent();
-            }
-            write.newline();
-        } else if self.len > 0 {
-            write.output.push_str(if self.plus { " + " } else { ", " });
+        if self.named {
+            return self.named_field(value);
         }
-        self.len += 1;
-        value.serialize(Serializer { write: &mut *write })?;
-        if self.multiline {
-            write.output.push(',');
+        if self.positional {
+            return self.positional_field(value);
         }
-        Ok(())
+        self.argument_field(value)
     }
 
     fn end(mut self) -> Result<Self::Ok, Self::Error> {
         let write = self.write.mutable();
-        if !self.assignment && !self.line_comment {
+        write.in_select = self.outer_select;
+        if self.assignment {
+            write.assignment_value = None;
+        }
+        if let Some(suffix) = self.suffix.take() {
+            write.add_line_comment(&suffix);
+        }
+        if self.def {
+            write.indent -= 4;
+        } else if self.named {
+            write.argument_kind = Some(ArgumentKind::Named);
+        } else if self.positional {
+            let base = self
+                .operands
+                .first()
+                .map_or(write.output.len(), |&(start, _)| start);
+            let elements = self.operands.iter();
+            write.positional = Some(
+                elements
+                    .map(|&(start, end)| start - base..end - base)
+                    .collect(),
+            );
+        } else if !self.assignment
+            && !self.line_comment
+            && !self.block_comment
+            && !self.document
+            && !self.constants
+            && !self.section
+        {
             if self.len != 0 && self.multiline {
                 write.unindent();
             }
             if !self.plus {
                 write.output.push(')');
+                write.collapse(self.wrap, false);
+            } else if self.multiline {
+                write.output.push(')');
+                collapse_sum(write, self.wrap, &self.operands);
             }
         }
         Ok(self.write.output())
     }
 }
 
+impl<'c, W> SerializeTupleVariant for WriteTupleStruct<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    type Ok = W::Ok;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        SerializeTupleStruct::serialize_field(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeTupleStruct::end(self)
+    }
+}
+
 pub struct WriteMap<W> {
     write: W,
     multiline: bool,
+    wrap: Option<Wrap>,
     len: usize,
+    key: Option<String>,
+    keys: Option<BTreeSet<String>>,
+    sorted: Option<Sorted>,
+    sorted_key: Option<(String, String)>,
+}
+
+impl<W> WriteMap<W> {
+    fn check_duplicate(&mut self, key: &str) -> Result<(), Error> {
+        if let Some(keys) = &mut self.keys {
+            if !keys.insert(key.to_owned()) {
+                return Err(error::duplicate_key(key));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'c, W> WriteSorted<'c> for WriteMap<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    fn starlark(&mut self) -> &mut WriteStarlark<'c> {
+        self.write.mutable()
+    }
+
+    fn write_entry(&mut self, entry: &SortedEntry) -> bool {
+        let write = self.write.mutable();
+        if self.multiline {
+            if self.len == 0 {
+                write.indent();
+            }
+            write.newline();
+        } else if self.len > 0 {
+            write.output.push_str(", ");
+        }
+        self.len += 1;
+        write.output.push_str(&entry.block_comment);
+        write.output.push_str(&entry.key);
+        write.output.push_str(": ");
+        write.output.push_str(&entry.value);
+        if self.multiline {
+            write.output.push(',');
+        }
+        true
+    }
 }
 
-impl<W> SerializeMap for WriteMap<W>
+impl<'c, W> SerializeMap for WriteMap<W>
 where
-    W: MutableWriteStarlark,
+    W: MutableWriteStarlark<'c>,
 {
     type Ok = W::Ok;
     type Error = Error;
@@ -569,6 +2098,21 @@ where
         T: Serialize + ?Sized,
     {
         let write = self.write.mutable();
+        if let Some(sorted) = &mut self.sorted {
+            if sorted.entries.is_empty() {
+                sorted.line_comment = write.line_comment.take();
+            }
+            let indent = write.indent;
+            if self.multiline {
+                write.indent();
+            }
+            let result = write.serialize_detached(&[], &DictKey(key));
+            write.indent = indent;
+            let (block_comment, key) = result?;
+            self.check_duplicate(&key)?;
+            self.sorted_key = Some((block_comment, key));
+            return Ok(());
+        }
         if self.multiline {
             if self.len == 0 {
                 write.indent();
@@ -578,8 +2122,16 @@ where
             write.output.push_str(", ");
         }
         self.len += 1;
-        key.serialize(Serializer { write: &mut *write })?;
+        let key_start = write.output.len();
+        DictKey(key).serialize(Serializer { write: &mut *write })?;
+        // A block comment on the key is inserted in front of it on the same
+        // line, so only the last line is the key itself.
+        let key = &write.output[key_start..];
+        let key = key.rsplit('\n').next().unwrap().trim_start_matches(' ');
+        let key = key.to_owned();
         write.output.push_str(": ");
+        self.check_duplicate(&key)?;
+        self.key = Some(key);
         Ok(())
     }
 
@@ -588,7 +2140,29 @@ where
         T: Serialize + ?Sized,
     {
         let write = self.write.mutable();
-        value.serialize(Serializer { write: &mut *write })?;
+        if let Some(sorted) = &mut self.sorted {
+            let (mut block_comment, key) = self.sorted_key.take().unwrap();
+            let indent = write.indent;
+            if self.multiline {
+                write.indent();
+            }
+            let result = write.serialize_detached(&[&key, ": "], value);
+            write.indent = indent;
+            let (value_comment, value) =
+                result.map_err(|error| error.within(Segment::Key(key.clone())))?;
+            block_comment.push_str(&value_comment);
+            sorted.entries.push(SortedEntry {
+                block_comment,
+                key,
+                value,
+                line_comment: write.line_comment.take(),
+            });
+            return Ok(());
+        }
+        let key = self.key.take().unwrap_or_default();
+        value
+            .serialize(Serializer { write: &mut *write })
+            .map_err(|error| error.within(Segment::Key(key)))?;
         if self.multiline {
             write.output.push(',');
         }
@@ -596,11 +2170,14 @@ where
     }
 
     fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let sorted = self.sorted.take();
+        self.write_sorted(sorted, |a, b| a.key.cmp(&b.key));
         let write = self.write.mutable();
         if self.len != 0 && self.multiline {
             write.unindent();
         }
         write.output.push('}');
+        write.collapse(self.wrap, false);
         Ok(self.write.output())
     }
 }
@@ -608,14 +2185,131 @@ where
 pub struct WriteStruct<W> {
     write: W,
     multiline: bool,
-    rename: bool,
+    wrap: Option<Wrap>,
+    // The length hint of a function call whose name is yet to be written from
+    // the first field.
+    rename: Option<usize>,
+    outer_select: bool,
     len: usize,
+    checkpoint: Option<Checkpoint>,
+    map_key: Option<String>,
+    sorted: Option<Sorted>,
+    // Kind of the last argument that has been written.
+    last: ArgumentKind,
+    // Args and Kwargs held back to go after the sorted named arguments.
+    deferred: Vec<String>,
+}
+
+// Named arguments or map entries that have been serialized but are held back
+// until the end of the function call or map, in order to be written in sorted
+// order.
+#[derive(Default)]
+struct Sorted {
+    line_comment: Option<String>,
+    entries: Vec<SortedEntry>,
 }
 
-impl<W> WriteStruct<W>
+struct SortedEntry {
+    block_comment: String,
+    key: String,
+    value: String,
+    line_comment: Option<String>,
+}
+
+// A function call, map, or list that holds back its entries in a Sorted, and
+// writes them at the end in the order given by a comparison of the entries.
+trait WriteSorted<'c> {
+    fn starlark(&mut self) -> &mut WriteStarlark<'c>;
+
+    // Returns false if the entry was skipped rather than written.
+    fn write_entry(&mut self, entry: &SortedEntry) -> bool;
+
+    fn write_sorted<F>(&mut self, sorted: Option<Sorted>, compare: F)
+    where
+        F: FnMut(&SortedEntry, &SortedEntry) -> Ordering,
+    {
+        let sorted = match sorted {
+            Some(sorted) => sorted,
+            None => return,
+        };
+        let mut entries = sorted.entries;
+        entries.sort_by(compare);
+        if sorted.line_comment.is_some() {
+            self.starlark().line_comment = sorted.line_comment;
+        }
+        for entry in entries {
+            if self.write_entry(&entry) && entry.line_comment.is_some() {
+                self.starlark().line_comment = entry.line_comment;
+            }
+        }
+    }
+}
+
+// State to restore in order to retract a named argument that has already been
+// written, when it turns out its value should be skipped.
+struct Checkpoint {
+    output_len: usize,
+    line_comment: Option<String>,
+    value_start: usize,
+}
+
+impl<'c, W> WriteStruct<W>
 where
-    W: MutableWriteStarlark,
+    W: MutableWriteStarlark<'c>,
 {
+    fn checkpoint(&mut self) {
+        let write = self.write.mutable();
+        self.checkpoint = Some(Checkpoint {
+            output_len: write.output.len(),
+            line_comment: write.line_comment.clone(),
+            value_start: 0,
+        });
+    }
+
+    fn begin_value(&mut self) {
+        let write = self.write.mutable();
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.value_start = write.output.len();
+        }
+    }
+
+    // Returns true if the named argument that was just written got retracted.
+    fn skip_value(&mut self) -> bool {
+        let checkpoint = match self.checkpoint.take() {
+            Some(checkpoint) => checkpoint,
+            None => return false,
+        };
+        let write = self.write.mutable();
+        let value = &write.output[checkpoint.value_start..];
+        if !write.is_skipped(value) {
+            return false;
+        }
+        self.retract(checkpoint);
+        true
+    }
+
+    fn retract(&mut self, checkpoint: Checkpoint) {
+        let write = self.write.mutable();
+        write.output.truncate(checkpoint.output_len);
+        write.line_comment = checkpoint.line_comment;
+        self.len -= 1;
+        if self.len == 0 && self.multiline {
+            write.indent -= 4;
+        }
+    }
+
+    // Write the elements of a Positional, which were serialized in place of
+    // the value of a named argument, as positional arguments instead.
+    fn write_positional(&mut self, value: &str, elements: Vec<Range<usize>>) -> Result<(), Error> {
+        for element in elements {
+            self.last = self.last.then(ArgumentKind::Positional)?;
+            self.pre_key();
+            self.write.mutable().output.push_str(&value[element]);
+            self.post_value();
+        }
+        Ok(())
+    }
+
     fn pre_key(&mut self) {
         let write = self.write.mutable();
         if self.multiline {
@@ -635,11 +2329,150 @@ where
             write.output.push(',');
         }
     }
+
+    fn serialize_positional<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let before = self.write.mutable().output.len();
+        self.pre_key();
+        let index = self.len - 1;
+        let write = self.write.mutable();
+        let start = write.output.len();
+        write.argument_start = Some(start);
+        let result = value.serialize(Serializer { write: &mut *write });
+        write.argument_start = None;
+        result.map_err(|error| error.within(Segment::Index(index)))?;
+        let kind = write
+            .argument_kind
+            .take()
+            .unwrap_or(ArgumentKind::Positional);
+        self.last = self.last.then(kind)?;
+        if kind < ArgumentKind::Args || self.sorted.is_none() {
+            self.post_value();
+            return Ok(());
+        }
+        // Named arguments being sorted have not been written yet. The spread
+        // needs to come after them.
+        let spread = write.output.split_off(start);
+        write.output.truncate(before);
+        self.len -= 1;
+        if self.len == 0 && self.multiline {
+            write.indent -= 4;
+        }
+        self.deferred.push(spread);
+        Ok(())
+    }
+
+    fn serialize_named<T>(&mut self, key: &str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let last = self.last.then(ArgumentKind::Named)?;
+        let key = self.write.mutable().argument_name(key);
+        let key = key.as_ref();
+        if !ident::is_identifier(key) {
+            return Err(error::invalid_argument_name(key));
+        }
+        if self.sorted.is_some() {
+            return self.serialize_sorted(key, value);
+        }
+        self.checkpoint();
+        self.pre_key();
+        let write = self.write.mutable();
+        write.output.push_str(key);
+        write.output.push_str(" = ");
+        self.begin_value();
+        let write = self.write.mutable();
+        let value_start = write.output.len();
+        write.positional_start = Some(value_start);
+        write.set_policy(key, value_start);
+        let result = value.serialize(Serializer { write: &mut *write });
+        write.positional_start = None;
+        write.policy = None;
+        result.map_err(|error| error.within(Segment::Field(key.to_owned())))?;
+        if let Some(elements) = write.positional.take() {
+            let checkpoint = self.checkpoint.take().unwrap();
+            let value = write.output.split_off(checkpoint.value_start);
+            self.retract(checkpoint);
+            return self.write_positional(&value, elements);
+        }
+        if !self.skip_value() {
+            self.post_value();
+        }
+        self.last = last;
+        Ok(())
+    }
+
+    fn serialize_sorted<T>(&mut self, key: &str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let write = self.write.mutable();
+        let sorted = self.sorted.as_mut().unwrap();
+        if sorted.entries.is_empty() {
+            sorted.line_comment = write.line_comment.take();
+        }
+
+        let indent = write.indent;
+        if self.multiline && self.len == 0 {
+            write.indent();
+        }
+        // Position of the value within the detached buffer, which begins with
+        // a newline and indentation.
+        let value_start = 1 + write.indent + key.len() + 3;
+        write.positional_start = Some(value_start);
+        write.set_policy(key, value_start);
+        let result = write.serialize_detached(&[key, " = "], value);
+        write.positional_start = None;
+        write.policy = None;
+        write.indent = indent;
+        let (block_comment, value) =
+            result.map_err(|error| error.within(Segment::Field(key.to_owned())))?;
+        if let Some(elements) = write.positional.take() {
+            // Positional arguments are not sorted. They are written right
+            // away, ahead of the named arguments being held back.
+            if sorted.entries.is_empty() {
+                write.line_comment = sorted.line_comment.take();
+            }
+            return self.write_positional(&value, elements);
+        }
+        sorted.entries.push(SortedEntry {
+            block_comment,
+            key: key.to_owned(),
+            value,
+            line_comment: write.line_comment.take(),
+        });
+        Ok(())
+    }
+}
+
+impl<'c, W> WriteSorted<'c> for WriteStruct<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    fn starlark(&mut self) -> &mut WriteStarlark<'c> {
+        self.write.mutable()
+    }
+
+    fn write_entry(&mut self, entry: &SortedEntry) -> bool {
+        if self.write.mutable().is_skipped(&entry.value) {
+            return false;
+        }
+        self.pre_key();
+        let write = self.write.mutable();
+        write.output.push_str(&entry.block_comment);
+        write.output.push_str(&entry.key);
+        write.output.push_str(" = ");
+        write.output.push_str(&entry.value);
+        self.post_value();
+        true
+    }
 }
 
-impl<W> SerializeStruct for WriteStruct<W>
+impl<'c, W> SerializeStruct for WriteStruct<W>
 where
-    W: MutableWriteStarlark,
+    W: MutableWriteStarlark<'c>,
 {
     type Ok = W::Ok;
     type Error = Error;
@@ -648,54 +2481,104 @@ where
     where
         T: Serialize + ?Sized,
     {
-        if self.rename {
+        if let Some(hint) = self.rename {
             let write = self.write.mutable();
-            value.serialize(BareStringSerializer::new(|string| {
-                write.output.push_str(string);
-            }))?;
+            let function = value.serialize(BareStringSerializer::new(str::to_owned))?;
+            if !ident::is_function_name(&function) {
+                return Err(error::invalid_function_name(&function));
+            }
+            self.outer_select = write.enter_call(&function)?;
+            write.output.push_str(&function);
             write.output.push('(');
-            self.rename = false;
+            if let Some(wrap) = &mut self.wrap {
+                wrap.open = write.output.len();
+            }
+            write.format_call(&function, hint, &mut self.multiline, &mut self.wrap);
+            self.rename = None;
         } else if key.is_empty() {
-            self.pre_key();
-            let write = self.write.mutable();
-            value.serialize(Serializer { write: &mut *write })?;
-            self.post_value();
+            self.serialize_positional(value)?;
         } else if key == "*key" {
-            self.pre_key();
-            let write = self.write.mutable();
-            value.serialize(BareStringSerializer::new(|string| {
-                if !string.is_empty() {
-                    write.output.push_str(string);
-                    write.output.push_str(" = ");
-                }
-            }))?;
+            self.map_key = Some(value.serialize(BareStringSerializer::new(str::to_owned))?);
         } else if key == "*value" {
-            let write = self.write.mutable();
-            value.serialize(Serializer { write: &mut *write })?;
-            self.post_value();
+            match self.map_key.take() {
+                Some(key) if !key.is_empty() => self.serialize_named(&key, value)?,
+                _ => self.serialize_positional(value)?,
+            }
         } else {
-            self.pre_key();
-            let write = self.write.mutable();
-            write.output.push_str(key);
-            write.output.push_str(" = ");
-            value.serialize(Serializer { write: &mut *write })?;
-            self.post_value();
+            self.serialize_named(key, value)?;
         }
         Ok(())
     }
 
     fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let sorted = self.sorted.take();
+        let argument_order = self.write.mutable().config.argument_order;
+        self.write_sorted(sorted, |a, b| match argument_order {
+            ArgumentOrder::Declaration => Ordering::Equal,
+            ArgumentOrder::NameFirst => (a.key != "name", &a.key).cmp(&(b.key != "name", &b.key)),
+        });
+        for spread in mem::take(&mut self.deferred) {
+            self.pre_key();
+            self.write.mutable().output.push_str(&spread);
+            self.post_value();
+        }
         let write = self.write.mutable();
+        write.in_select = self.outer_select;
         if self.len != 0 && self.multiline {
             write.unindent();
         }
         write.output.push(')');
+        write.collapse(self.wrap, false);
         Ok(self.write.output())
     }
 }
 
+impl<'c, W> SerializeStructVariant for WriteStruct<W>
+where
+    W: MutableWriteStarlark<'c>,
+{
+    type Ok = W::Ok;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeStruct::end(self)
+    }
+}
+
+// The length hint of a list or dict, as overridden by the policy of the named
+// argument whose value it is.
+fn length_hint(len: Option<usize>, policy: ArgumentPolicy) -> Option<usize> {
+    if len == Some(crate::ONELINE) || len == Some(crate::MULTILINE) {
+        return len;
+    }
+    match policy.layout {
+        crate::Layout::Auto => len,
+        crate::Layout::Oneline => Some(crate::ONELINE),
+        crate::Layout::Multiline => Some(crate::MULTILINE),
+    }
+}
+
+// The string that a value used as a name serializes as.
+pub(crate) fn bare_string<T>(value: &T) -> Result<String, Error>
+where
+    T: ?Sized + Serialize,
+{
+    value.serialize(BareStringSerializer::with_error(
+        str::to_owned,
+        error::expected_string,
+    ))
+}
+
 struct BareStringSerializer<F> {
     serialize_str: F,
+    error: fn() -> Error,
 }
 
 impl<F, R> BareStringSerializer<F>
@@ -703,7 +2586,17 @@ where
     F: FnOnce(&str) -> R,
 {
     fn new(serialize_str: F) -> Self {
-        BareStringSerializer { serialize_str }
+        BareStringSerializer {
+            serialize_str,
+            error: error::unsupported_call,
+        }
+    }
+
+    fn with_error(serialize_str: F, error: fn() -> Error) -> Self {
+        BareStringSerializer {
+            serialize_str,
+            error,
+        }
     }
 }
 
@@ -722,59 +2615,59 @@ where
     type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
 
     fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_i128(self, _v: i128) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_u128(self, _v: u128) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_str(self, string: &str) -> Result<Self::Ok, Self::Error> {
@@ -782,26 +2675,26 @@ where
     }
 
     fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
     where
         T: Serialize + ?Sized,
     {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_unit_variant(
@@ -821,7 +2714,7 @@ where
     where
         T: Serialize + ?Sized,
     {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_newtype_variant<T>(
@@ -834,15 +2727,15 @@ where
     where
         T: Serialize + ?Sized,
     {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_tuple_struct(
@@ -850,7 +2743,7 @@ where
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_tuple_variant(
@@ -860,11 +2753,11 @@ where
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_struct(
@@ -872,7 +2765,7 @@ where
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 
     fn serialize_struct_variant(
@@ -882,6 +2775,6 @@ where
         _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        Err(error::unsupported_call())
+        Err((self.error)())
     }
 }
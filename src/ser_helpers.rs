@@ -0,0 +1,91 @@
+//! Functions for `#[serde(serialize_with = "...")]` that apply this crate's
+//! wrappers to a single field of a derived struct.
+//!
+//! Each one does the same as serializing the field wrapped in the
+//! corresponding type, without having to change the type of the field.
+//!
+//! ```
+//! use serde_derive::Serialize;
+//! use serde_starlark::ser_helpers;
+//!
+//! #[derive(Serialize)]
+//! #[serde(rename = "rust_library")]
+//! struct RustLibrary {
+//!     name: String,
+//!     #[serde(serialize_with = "ser_helpers::oneline_list")]
+//!     srcs: Vec<String>,
+//!     #[serde(serialize_with = "ser_helpers::multiline_list")]
+//!     deps: Vec<String>,
+//!     #[serde(serialize_with = "ser_helpers::identifier")]
+//!     visibility: String,
+//! }
+//!
+//! let rust_library = RustLibrary {
+//!     name: "syn".to_owned(),
+//!     srcs: vec!["lib.rs".to_owned(), "parse.rs".to_owned()],
+//!     deps: vec![":quote".to_owned()],
+//!     visibility: "PUBLIC".to_owned(),
+//! };
+//! let expected = r#"rust_library(
+//!     name = "syn",
+//!     srcs = ["lib.rs", "parse.rs"],
+//!     deps = [
+//!         ":quote",
+//!     ],
+//!     visibility = PUBLIC,
+//! )
+//! "#;
+//! assert_eq!(serde_starlark::to_string(&rust_library).unwrap(), expected);
+//! ```
+
+use crate::Identifier;
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+/// Serialize a string or unit enum variant as a bare identifier, like
+/// [`Identifier`].
+pub fn identifier<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: ?Sized + Serialize,
+    S: Serializer,
+{
+    Identifier(value).serialize(serializer)
+}
+
+/// Serialize a collection as a list on one line, like the [`ONELINE`] length
+/// hint.
+///
+/// [`ONELINE`]: crate::ONELINE
+pub fn oneline_list<C, T, S>(value: &C, serializer: S) -> Result<S::Ok, S::Error>
+where
+    for<'a> &'a C: IntoIterator<Item = &'a T>,
+    T: Serialize,
+    S: Serializer,
+{
+    list(value, crate::ONELINE, serializer)
+}
+
+/// Serialize a collection as a list with each element on its own line, like
+/// [`MultilineArray`].
+///
+/// [`MultilineArray`]: crate::MultilineArray
+pub fn multiline_list<C, T, S>(value: &C, serializer: S) -> Result<S::Ok, S::Error>
+where
+    for<'a> &'a C: IntoIterator<Item = &'a T>,
+    T: Serialize,
+    S: Serializer,
+{
+    list(value, crate::MULTILINE, serializer)
+}
+
+fn list<C, T, S>(value: &C, len: usize, serializer: S) -> Result<S::Ok, S::Error>
+where
+    for<'a> &'a C: IntoIterator<Item = &'a T>,
+    T: Serialize,
+    S: Serializer,
+{
+    let mut array = serializer.serialize_seq(Some(len))?;
+    for element in value {
+        array.serialize_element(element)?;
+    }
+    array.end()
+}
@@ -0,0 +1,298 @@
+use crate::document::{banner, Banner};
+use crate::error::{self, Segment};
+use crate::ident;
+use crate::lex::{Lexer, Token, TokenKind};
+use crate::parse::{self, Expr, ExprKind, StatementKind};
+use crate::{Compact, Config, Document, Error, FunctionCall, Shard, Sharding};
+use serde::ser::Serialize;
+use std::collections::BTreeSet;
+use std::mem;
+use std::ops::Range;
+
+impl Config {
+    /// Split a [`Document`] into several files, each within the limits given
+    /// by `sharding`.
+    ///
+    /// Bazel copes poorly with a single generated file that is hundreds of
+    /// megabytes. This divides the statements of the document, in order,
+    /// among as many files as necessary. Every shard begins with the
+    /// document's banner, if any, and with any `load` statements of the
+    /// document. A shard that refers to a top-level name assigned in an
+    /// earlier shard loads it from there, like `load(":defs_0.bzl", "VERSION")`.
+    ///
+    /// References are found among the identifiers of the Starlark text that
+    /// are not the name of an argument or attribute, nor a parameter or local
+    /// variable of a `def`, comprehension or lambda. Names beginning with an
+    /// underscore are private to the file that assigns them in Bazel and
+    /// cannot be loaded, so using one in a later shard is an error.
+    ///
+    /// ```
+    /// use serde_starlark::{Config, Document, FunctionCall, Sharding};
+    ///
+    /// let statements = vec![
+    ///     FunctionCall::new("exports_files", [&["LICENSE"][..]]),
+    ///     FunctionCall::new("exports_files", [&["README.md"][..]]),
+    ///     FunctionCall::new("exports_files", [&["build.rs"][..]]),
+    /// ];
+    /// let document = Document::new(statements);
+    /// let sharding = Sharding::new("defs").max_statements(2);
+    /// let shards = Config::new().to_shards(&document, &sharding).unwrap();
+    /// assert_eq!(shards.len(), 2);
+    /// assert_eq!(shards[1].file_name, "defs_1.bzl");
+    /// assert_eq!(shards[1].contents, "exports_files([\"build.rs\"])\n");
+    /// ```
+    pub fn to_shards<T, S>(
+        &self,
+        document: &Document<T>,
+        sharding: &Sharding,
+    ) -> Result<Vec<Shard>, Error>
+    where
+        for<'b> &'b T: IntoIterator<Item = &'b S>,
+        S: Serialize,
+    {
+        let mut index = 0;
+        let mut header = Vec::new();
+        if let Some((tool, regenerate)) = document.banner {
+            let banner = banner(tool, regenerate);
+            header.push(self.to_string(&Banner(&banner))?);
+            index += 1;
+        }
+
+        let mut groups = Vec::new();
+        let mut current: Vec<(String, Names)> = Vec::new();
+        let mut bytes = 0;
+        for statement in document.rearranged() {
+            let statement = self
+                .to_string(&statement)
+                .map_err(|error| error.within(Segment::Index(index)))?;
+            let names = names(&statement)?;
+            index += 1;
+            if names.load {
+                header.push(statement);
+                continue;
+            }
+            let blank_lines = if current.is_empty() {
+                0
+            } else {
+                document.separators.blank_lines
+            };
+            let separated = bytes + blank_lines + statement.len();
+            let full = !current.is_empty()
+                && (sharding
+                    .max_statements
+                    .map_or(false, |max| current.len() >= max)
+                    || sharding.max_bytes.map_or(false, |max| separated > max));
+            if full {
+                groups.push(mem::take(&mut current));
+                bytes = statement.len();
+            } else {
+                bytes = separated;
+            }
+            current.push((statement, names));
+        }
+        if !current.is_empty() || groups.is_empty() {
+            groups.push(current);
+        }
+
+        let file_names: Vec<String> = (0..groups.len())
+            .map(|i| format!("{}_{}.bzl", sharding.file_stem, i))
+            .collect();
+        let mut defined: Vec<BTreeSet<&str>> = Vec::new();
+        let mut shards = Vec::new();
+        for (i, group) in groups.iter().enumerate() {
+            let mut referenced = BTreeSet::new();
+            for (_, names) in group {
+                referenced.extend(names.references.iter().map(String::as_str));
+            }
+            let mut contents = Vec::new();
+            contents.extend(header.iter().cloned());
+            for (earlier, names) in defined.iter().enumerate() {
+                let mut args = vec![format!(":{}", file_names[earlier])];
+                for name in names.intersection(&referenced) {
+                    if name.starts_with('_') {
+                        return Err(error::private_name(name));
+                    }
+                    args.push((*name).to_owned());
+                }
+                if args.len() > 1 {
+                    let load = FunctionCall::new("load", args);
+                    contents.push(self.to_string(&Compact(load))?);
+                }
+            }
+            contents.extend(group.iter().map(|(statement, _)| statement.clone()));
+            let mut joined = String::new();
+            let mut previous = None;
+            for statement in &contents {
+                document.separators.push(&mut joined, previous, statement);
+                previous = Some(statement);
+            }
+            shards.push(Shard {
+                file_name: file_names[i].clone(),
+                contents: joined,
+            });
+            defined.push(
+                group
+                    .iter()
+                    .flat_map(|(_, names)| names.assigned.iter().map(String::as_str))
+                    .collect(),
+            );
+        }
+        Ok(shards)
+    }
+}
+
+// What a statement means to the other shards: whether it is a `load`, which
+// every shard repeats, the top-level names it assigns or defines with `def`,
+// and the names it refers to.
+#[derive(Default)]
+struct Names {
+    load: bool,
+    assigned: Vec<String>,
+    references: BTreeSet<String>,
+}
+
+fn names(statement: &str) -> Result<Names, Error> {
+    let mut names = Names::default();
+    let mut calls = 0;
+    let mut loads = 0;
+    for parsed in parse::parse(statement)? {
+        match &parsed.kind {
+            StatementKind::Expression(Expr {
+                kind: ExprKind::Call(function, _),
+                ..
+            }) => {
+                calls += 1;
+                if let ExprKind::Identifier("load") = function.kind {
+                    loads += 1;
+                }
+            }
+            StatementKind::Assignment {
+                target:
+                    Expr {
+                        kind: ExprKind::Identifier(name),
+                        ..
+                    },
+                op: "=",
+                ..
+            } => names.assigned.push((*name).to_owned()),
+            _ => calls += 1,
+        }
+    }
+    names.load = loads > 0 && loads == calls;
+
+    let tokens = Lexer::new(statement)
+        .filter(|token| !matches!(token, Ok(token) if token.kind == TokenKind::Comment))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::Indent => depth += 1,
+            TokenKind::Dedent => depth -= 1,
+            TokenKind::Identifier if depth == 0 && token.text == "def" => {
+                if let Some(name) = tokens.get(i + 1) {
+                    names.assigned.push(name.text.to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+    references(&tokens, &mut names.references);
+    Ok(names)
+}
+
+// Collect every identifier used as a value in the statement, apart from
+// attribute names following a `.`, names of arguments and of assignment
+// targets, which are followed by `=`, and names that are local to a `def`, a
+// comprehension or a lambda.
+fn references(tokens: &[Token], names: &mut BTreeSet<String>) {
+    let locals = locals(tokens);
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Identifier || ident::is_keyword(token.text) {
+            continue;
+        }
+        let previous = i.checked_sub(1).map(|i| &tokens[i]);
+        let attribute = previous.map_or(false, |previous| previous.is("."));
+        let defined = previous.map_or(false, |previous| {
+            previous.kind == TokenKind::Identifier && previous.text == "def"
+        });
+        let argument = tokens.get(i + 1).map_or(false, |next| next.is("="));
+        let local = locals
+            .iter()
+            .any(|(name, scope)| *name == token.text && scope.contains(&i));
+        if !attribute && !defined && !argument && !local {
+            names.insert(token.text.to_owned());
+        }
+    }
+}
+
+// The names bound by a `def`, a comprehension or a lambda, each with the range
+// of token indices in which it refers to the local rather than to a top-level
+// name. Parameters and local variables of a `def` are local to the whole
+// statement. The variables of a comprehension and the parameters of a lambda
+// are local to the brackets around them.
+fn locals<'a>(tokens: &[Token<'a>]) -> Vec<(&'a str, Range<usize>)> {
+    let mut close = vec![tokens.len(); tokens.len()];
+    let mut open = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is("(") || token.is("[") || token.is("{") {
+            open.push(i);
+        } else if token.is(")") || token.is("]") || token.is("}") {
+            if let Some(start) = open.pop() {
+                close[start] = i;
+            }
+        }
+    }
+
+    let mut locals = Vec::new();
+    let mut def = false;
+    let mut open = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is("(") || token.is("[") || token.is("{") {
+            open.push(i);
+        } else if token.is(")") || token.is("]") || token.is("}") {
+            open.pop();
+        }
+        let scope = match open.last() {
+            Some(&start) => start..close[start] + 1,
+            None => 0..tokens.len(),
+        };
+        let keyword = |keyword: &str| token.kind == TokenKind::Identifier && token.text == keyword;
+        if keyword("def") && tokens.get(i + 2).map_or(false, |token| token.is("(")) {
+            def = true;
+            // The parameters, but not what is inside of their default values.
+            let mut j = i + 3;
+            while j < close[i + 2] {
+                let token = &tokens[j];
+                if token.kind == TokenKind::Identifier
+                    && ["(", ",", "*", "**"].iter().any(|p| tokens[j - 1].is(p))
+                {
+                    locals.push((token.text, 0..tokens.len()));
+                }
+                if token.is("(") || token.is("[") || token.is("{") {
+                    j = close[j];
+                }
+                j += 1;
+            }
+        } else if keyword("lambda") || keyword("for") {
+            // Up to the `:` after the parameters of a lambda, or the `in`
+            // after the variables of a comprehension or loop.
+            let end = if keyword("lambda") { ":" } else { "in" };
+            for token in &tokens[i + 1..] {
+                if token.text == end {
+                    break;
+                }
+                if token.kind == TokenKind::Identifier {
+                    locals.push((token.text, scope.clone()));
+                }
+            }
+        } else if def
+            && open.is_empty()
+            && token.kind == TokenKind::Identifier
+            && tokens.get(i + 1).map_or(false, |next| next.is("="))
+        {
+            // Assignment to a local variable in the body of the `def`.
+            locals.push((token.text, 0..tokens.len()));
+        }
+    }
+    locals
+}
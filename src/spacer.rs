@@ -0,0 +1,14 @@
+use crate::Spacer;
+use serde::ser::{Serialize, Serializer};
+
+impl<'a> Serialize for Spacer<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.header {
+            None => serializer.serialize_unit_struct("*spacer"),
+            Some(header) => serializer.serialize_newtype_struct("*spacer", header),
+        }
+    }
+}
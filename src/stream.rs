@@ -0,0 +1,110 @@
+use crate::lex::Lexer;
+use crate::{de, error, parse, Error, StreamDeserializer};
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::mem;
+
+impl<R, T> StreamDeserializer<R, T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    pub(crate) fn new(reader: R) -> Self {
+        StreamDeserializer {
+            reader: io::BufReader::new(reader),
+            lookahead: String::new(),
+            line: 0,
+            offset: 0,
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    // Read lines up to the start of the next top-level statement after the
+    // current one, and deserialize the statements read. If the reader fails,
+    // the statements that were read completely come before the error.
+    fn read_chunk(&mut self) {
+        let mut chunk = mem::take(&mut self.lookahead);
+        let mut failure = None;
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(error) => {
+                    failure = Some(error::io(error));
+                    break;
+                }
+            }
+            if starts_statement(&line) && is_complete(&chunk) {
+                self.lookahead = line;
+                break;
+            }
+            chunk.push_str(&line);
+        }
+        self.done = self.lookahead.is_empty();
+
+        let (line, offset) = (self.line, self.offset);
+        self.line += chunk.matches('\n').count();
+        self.offset += chunk.len();
+        if failure.is_none() || is_complete(&chunk) {
+            match parse::parse(&chunk) {
+                Ok(statements) => {
+                    for statement in &statements {
+                        if let parse::StatementKind::Comment = statement.kind {
+                            continue;
+                        }
+                        let value = de::statement(&chunk, statement);
+                        self.pending
+                            .push_back(value.map_err(|error| error.after(line, offset)));
+                    }
+                }
+                Err(error) => {
+                    self.done = true;
+                    self.pending.push_back(Err(error.after(line, offset)));
+                }
+            }
+        }
+        if let Some(error) = failure {
+            self.pending.push_back(Err(error));
+        }
+    }
+}
+
+impl<R, T> Iterator for StreamDeserializer<R, T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(next) = self.pending.pop_front() {
+                return Some(next);
+            }
+            if self.done {
+                return None;
+            }
+            self.read_chunk();
+        }
+    }
+}
+
+// Whether a line at the top level begins a new statement, as opposed to
+// continuing the previous one, like the body or `else` clause of an `if`.
+fn starts_statement(line: &str) -> bool {
+    let word = line
+        .split(|ch: char| !(ch == '_' || ch.is_alphanumeric()))
+        .next()
+        .unwrap();
+    line.starts_with(|ch: char| !ch.is_whitespace() && ch != '#')
+        && !matches!(word, "elif" | "else")
+}
+
+// Whether the lines read so far end at the end of a statement, outside of any
+// bracket or string literal.
+fn is_complete(chunk: &str) -> bool {
+    Lexer::new(chunk).all(|token| token.is_ok()) && !chunk.trim_end().ends_with('\\')
+}
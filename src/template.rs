@@ -0,0 +1,386 @@
+use crate::error::{self, Segment};
+use crate::lex::{Lexer, TokenKind};
+use crate::ser::{self, WriteStarlark};
+use crate::{Config, Error};
+use serde::ser::{Impossible, Serialize, SerializeMap, SerializeStruct, Serializer};
+use std::ops::Range;
+
+impl Config {
+    /// Fill in the placeholders of a template with serialized values.
+    ///
+    /// A placeholder is an identifier in curly braces, like `{deps}`, written
+    /// where a Starlark expression would go. Each one is replaced by the
+    /// value of the same name from `values`, which must serialize as a map or
+    /// struct: typically a struct deriving `Serialize` whose fields have the
+    /// types the template expects, or a map. The value is serialized the same
+    /// way as by [`to_string`][Config::to_string], indented to match the line
+    /// that the placeholder is on.
+    ///
+    /// Curly braces inside of string literals, such as those of a pattern
+    /// passed to `str.format`, are not placeholders, and neither are dict
+    /// literals. Values that the template does not use are ignored.
+    ///
+    /// ```
+    /// use serde_derive::Serialize;
+    /// use serde_starlark::Config;
+    ///
+    /// const TEMPLATE: &str = r#"load("@rules_rust//rust:defs.bzl", "rust_library")
+    ///
+    /// rust_library(
+    ///     name = {name},
+    ///     srcs = glob(["src/**/*.rs"]),
+    ///     deps = {deps},
+    ///     rustc_env = {"CARGO_PKG_NAME": "{}".format({name})},
+    /// )
+    /// "#;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Values {
+    ///     name: &'static str,
+    ///     deps: Vec<&'static str>,
+    /// }
+    ///
+    /// let values = Values {
+    ///     name: "syn",
+    ///     deps: vec![":proc-macro2", ":quote"],
+    /// };
+    /// let build = Config::new().render_template(TEMPLATE, &values).unwrap();
+    /// let expected = r#"load("@rules_rust//rust:defs.bzl", "rust_library")
+    ///
+    /// rust_library(
+    ///     name = "syn",
+    ///     srcs = glob(["src/**/*.rs"]),
+    ///     deps = [
+    ///         ":proc-macro2",
+    ///         ":quote",
+    ///     ],
+    ///     rustc_env = {"CARGO_PKG_NAME": "{}".format("syn")},
+    /// )
+    /// "#;
+    /// assert_eq!(build, expected);
+    /// ```
+    ///
+    /// Returns an error if the template is not lexically valid Starlark, or
+    /// if it contains a placeholder for which there is no value.
+    pub fn render_template<T>(&self, template: &str, values: &T) -> Result<String, Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let placeholders = placeholders(template)?;
+        let mut rendered = Vec::new();
+        rendered.resize_with(placeholders.len(), || None);
+        values.serialize(&mut Values {
+            config: self,
+            placeholders: &placeholders,
+            rendered: &mut rendered,
+            key: None,
+        })?;
+
+        let mut output = String::with_capacity(template.len());
+        let mut line_comment = None;
+        let mut offset = 0;
+        for (placeholder, rendered) in placeholders.iter().zip(rendered) {
+            let (text, comment) = match rendered {
+                Some(rendered) => rendered,
+                None => return Err(error::missing_placeholder(placeholder.name)),
+            };
+            push_literal(
+                &mut output,
+                &template[offset..placeholder.span.start],
+                &mut line_comment,
+            );
+            output.push_str(&text);
+            if let Some(comment) = comment {
+                line_comment = Some(match line_comment {
+                    Some(pending) => pending + "; " + &comment,
+                    None => comment,
+                });
+            }
+            offset = placeholder.span.end;
+        }
+        push_literal(&mut output, &template[offset..], &mut line_comment);
+        if let Some(comment) = line_comment {
+            push_comment(&mut output, &comment);
+        }
+        Ok(output)
+    }
+}
+
+struct Placeholder<'a> {
+    name: &'a str,
+    span: Range<usize>,
+    // Indentation of the line the placeholder is on.
+    indent: usize,
+}
+
+fn placeholders(template: &str) -> Result<Vec<Placeholder<'_>>, Error> {
+    let tokens = Lexer::new(template).collect::<Result<Vec<_>, _>>()?;
+    let mut placeholders = Vec::new();
+    for window in tokens.windows(3) {
+        let (open, name, close) = (&window[0], &window[1], &window[2]);
+        if open.is("{")
+            && name.kind == TokenKind::Identifier
+            && close.is("}")
+            && open.span.end == name.span.start
+            && name.span.end == close.span.start
+        {
+            let line_start = template[..open.span.start].rfind('\n').map_or(0, |i| i + 1);
+            let line = &template[line_start..];
+            placeholders.push(Placeholder {
+                name: name.text,
+                span: open.span.start..close.span.end,
+                indent: line.len() - line.trim_start_matches(' ').len(),
+            });
+        }
+    }
+    Ok(placeholders)
+}
+
+// Copy text from the template, placing a line comment left over from the last
+// value at the end of the line that the value was on.
+fn push_literal(output: &mut String, literal: &str, line_comment: &mut Option<String>) {
+    match literal.find('\n') {
+        Some(newline) if line_comment.is_some() => {
+            let comment = line_comment.take().unwrap();
+            output.push_str(&literal[..newline]);
+            push_comment(output, &comment);
+            output.push_str(&literal[newline..]);
+        }
+        _ => output.push_str(literal),
+    }
+}
+
+fn push_comment(output: &mut String, comment: &str) {
+    output.push_str("  # ");
+    output.push_str(comment);
+}
+
+struct Values<'a, 'b> {
+    config: &'a Config,
+    placeholders: &'b [Placeholder<'b>],
+    rendered: &'b mut [Option<(String, Option<String>)>],
+    key: Option<String>,
+}
+
+impl<'a, 'b> Values<'a, 'b> {
+    fn render<T>(&mut self, name: &str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let placeholders = self.placeholders.iter().zip(self.rendered.iter_mut());
+        for (placeholder, rendered) in placeholders {
+            if placeholder.name == name {
+                let fragment = WriteStarlark::fragment(self.config, value, placeholder.indent)?;
+                *rendered = Some(fragment);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b, 'c> Serializer for &'c mut Values<'a, 'b> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(error::template_values())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(error::template_values())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(error::template_values())
+    }
+}
+
+impl<'a, 'b, 'c> SerializeMap for &'c mut Values<'a, 'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.key = Some(ser::bare_string(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        let key = self.key.take().unwrap();
+        self.render(&key, value)
+            .map_err(|error| error.within(Segment::Key(key)))
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, 'c> SerializeStruct for &'c mut Values<'a, 'b> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.render(key, value)
+            .map_err(|error| error.within(Segment::Field(key.to_owned())))
+    }
+
+    fn end(self) -> Result<(), Error> {
+        Ok(())
+    }
+}
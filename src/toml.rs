@@ -0,0 +1,67 @@
+use crate::error;
+use crate::{Assignment, Config, Document, Error};
+use serde::ser::{Serialize, Serializer};
+use toml::{Table, Value};
+
+#[cfg_attr(docsrs, doc(cfg(feature = "toml")))]
+impl Config {
+    /// Convert a TOML document into a file of Starlark constants using this
+    /// configuration.
+    ///
+    /// Each top-level key becomes an [`Assignment`] to a constant of that
+    /// name, in the order written, so every top-level key needs to be a
+    /// Starlark identifier. Tables become dicts, and arrays become lists.
+    /// Datetimes, which Starlark has no literal for, are written as strings in
+    /// their TOML syntax. Integers outside the range of a Starlark `int` are
+    /// handled according to [`integer_overflow`][Config::integer_overflow],
+    /// and floats are subject to [`floats`][Config::floats].
+    ///
+    /// ```
+    /// use serde_starlark::Config;
+    ///
+    /// let toml = r#"
+    /// [RELEASE]
+    /// version = "1.0.0"
+    /// date = 2024-03-01
+    /// targets = ["x86_64", "aarch64"]
+    /// "#;
+    /// let expected = r#"RELEASE = {
+    ///     "version": "1.0.0",
+    ///     "date": "2024-03-01",
+    ///     "targets": [
+    ///         "x86_64",
+    ///         "aarch64",
+    ///     ],
+    /// }
+    /// "#;
+    /// assert_eq!(Config::new().toml_to_string(toml).unwrap(), expected);
+    /// ```
+    pub fn toml_to_string(&self, toml: &str) -> Result<String, Error> {
+        let table: Table = toml.parse().map_err(error::toml)?;
+        let mut statements = Vec::new();
+        for (key, value) in &table {
+            statements.push(Assignment::try_new(key, Toml(value))?);
+        }
+        self.to_string(&Document::new(statements))
+    }
+}
+
+struct Toml<'a>(&'a Value);
+
+impl<'a> Serialize for Toml<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            Value::String(string) => serializer.serialize_str(string),
+            Value::Integer(int) => serializer.serialize_i64(*int),
+            Value::Float(float) => serializer.serialize_f64(*float),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            // toml serializes a datetime as a struct with a private field.
+            Value::Datetime(datetime) => serializer.collect_str(datetime),
+            Value::Array(array) => serializer.collect_seq(array.iter().map(Toml)),
+            Value::Table(table) => serializer.collect_map(table.iter().map(|(k, v)| (k, Toml(v)))),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+use crate::TripleQuoted;
+use serde::ser::{Serialize, Serializer};
+
+impl<'a> Serialize for TripleQuoted<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct("\"\"\"", self.string)
+    }
+}
@@ -0,0 +1,334 @@
+use crate::ser::bare_string;
+use crate::{Identifier, Kwargs, WrapperMacro};
+use serde::ser::{
+    Error, Impossible, Serialize, SerializeMap, SerializeStruct, SerializeTupleStruct, Serializer,
+};
+
+impl<'a, D> Serialize for WrapperMacro<'a, D>
+where
+    D: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.defaults.serialize(DefaultsSerializer {
+            name: self.name,
+            rule: self.rule,
+            delegate: serializer,
+        })
+    }
+}
+
+struct DefaultsSerializer<'a, S> {
+    name: &'a str,
+    rule: &'a str,
+    delegate: S,
+}
+
+impl<'a, S> DefaultsSerializer<'a, S>
+where
+    S: Serializer,
+{
+    const UNSUPPORTED: &'static str = "unsupported macro defaults type, expected a map or struct";
+
+    fn def(self) -> Result<Defaults<'a, S::SerializeTupleStruct>, S::Error> {
+        let mut def = self.delegate.serialize_tuple_struct("*def", 0)?;
+        def.serialize_field(self.name)?;
+        def.serialize_field("name, **kwargs")?;
+        Ok(Defaults {
+            rule: self.rule,
+            def,
+            key: None,
+        })
+    }
+}
+
+impl<'a, S> Serializer for DefaultsSerializer<'a, S>
+where
+    S: Serializer,
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+    type SerializeTuple = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeMap = Defaults<'a, S::SerializeTupleStruct>;
+    type SerializeStruct = Defaults<'a, S::SerializeTupleStruct>;
+    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_i128(self, _v: i128) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_u128(self, _v: u128) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        SerializeStruct::end(self.def()?)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        self.def()
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.def()
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(Error::custom(Self::UNSUPPORTED))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.delegate.is_human_readable()
+    }
+}
+
+struct Defaults<'a, S> {
+    rule: &'a str,
+    def: S,
+    key: Option<String>,
+}
+
+impl<'a, S> Defaults<'a, S>
+where
+    S: SerializeTupleStruct,
+{
+    fn set_default<T>(&mut self, key: &str, value: &T) -> Result<(), S::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.def.serialize_field(&SetDefault { key, value })
+    }
+}
+
+impl<'a, S> SerializeMap for Defaults<'a, S>
+where
+    S: SerializeTupleStruct,
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.key = Some(bare_string(key).map_err(Error::custom)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self.key.take().unwrap_or_default();
+        self.set_default(&key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        SerializeStruct::end(self)
+    }
+}
+
+impl<'a, S> SerializeStruct for Defaults<'a, S>
+where
+    S: SerializeTupleStruct,
+{
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.set_default(key, value)
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        self.def.serialize_field(&RuleCall { rule: self.rule })?;
+        self.def.end()
+    }
+}
+
+// `kwargs.setdefault(key, value)`, on one line however long the value.
+struct SetDefault<'a, T: ?Sized> {
+    key: &'a str,
+    value: &'a T,
+}
+
+impl<'a, T> Serialize for SetDefault<'a, T>
+where
+    T: Serialize + ?Sized,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut call = serializer.serialize_tuple_struct("(", crate::ONELINE)?;
+        call.serialize_field("kwargs.setdefault")?;
+        call.serialize_field(self.key)?;
+        call.serialize_field(self.value)?;
+        call.end()
+    }
+}
+
+// The call of the wrapped rule: `rule(name = name, **kwargs)`.
+struct RuleCall<'a> {
+    rule: &'a str,
+}
+
+impl<'a> Serialize for RuleCall<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut call = serializer.serialize_struct("(", 2)?;
+        call.serialize_field("", self.rule)?;
+        call.serialize_field("name", &Identifier("name"))?;
+        call.serialize_field("", &Kwargs(Identifier("kwargs")))?;
+        call.end()
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error;
+use crate::{Assignment, Config, Document, Error};
+use serde::ser::{Serialize, Serializer};
+use serde_norway::Value;
+
+#[cfg_attr(docsrs, doc(cfg(feature = "yaml")))]
+impl Config {
+    /// Convert a YAML document into a file of Starlark constants using this
+    /// configuration.
+    ///
+    /// The top level of the YAML must be a mapping. Each of its entries
+    /// becomes an [`Assignment`] to a constant named by the key, so every
+    /// top-level key needs to be a Starlark identifier. Values are converted
+    /// as follows:
+    ///
+    /// - `~` and `null` become `None`.
+    /// - Aliases of anchors are replaced by a copy of the anchored value, and
+    ///   `<<` merge keys are applied.
+    /// - Keys of nested mappings keep their type, so `1: x` becomes the int key
+    ///   `1` rather than the string `"1"`.
+    /// - Tags like `!Custom` are ignored in favor of the tagged value.
+    /// - Floats are subject to [`floats`][Config::floats], and integers to
+    ///   [`integer_overflow`][Config::integer_overflow], as usual.
+    ///
+    /// ```
+    /// use serde_starlark::Config;
+    ///
+    /// let yaml = "
+    /// DEFAULTS: &defaults
+    ///   edition: 2021
+    ///   license: ~
+    /// SYN:
+    ///   <<: *defaults
+    ///   version: 2.0.0
+    /// ";
+    /// let expected = r#"DEFAULTS = {
+    ///     "edition": 2021,
+    ///     "license": None,
+    /// }
+    ///
+    /// SYN = {
+    ///     "version": "2.0.0",
+    ///     "edition": 2021,
+    ///     "license": None,
+    /// }
+    /// "#;
+    /// assert_eq!(Config::new().yaml_to_string(yaml).unwrap(), expected);
+    /// ```
+    pub fn yaml_to_string(&self, yaml: &str) -> Result<String, Error> {
+        let mut value: Value = serde_norway::from_str(yaml).map_err(error::yaml)?;
+        value.apply_merge().map_err(error::yaml)?;
+        let mapping = match value {
+            Value::Mapping(mapping) => mapping,
+            Value::Null => return Ok(String::new()),
+            _ => return Err(error::yaml_top_level()),
+        };
+        let mut statements = Vec::new();
+        for (key, value) in &mapping {
+            let identifier = if let Value::String(identifier) = key {
+                identifier
+            } else {
+                let key = serde_norway::to_string(key).map_err(error::yaml)?;
+                return Err(error::invalid_name(key.trim_end()));
+            };
+            statements.push(Assignment::try_new(identifier, Yaml(value))?);
+        }
+        self.to_string(&Document::new(statements))
+    }
+}
+
+struct Yaml<'a>(&'a Value);
+
+impl<'a> Serialize for Yaml<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.0 {
+            // serde_norway serializes null as (), which is not `None` by default.
+            Value::Null => serializer.serialize_none(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Number(number) => number.serialize(serializer),
+            Value::String(string) => serializer.serialize_str(string),
+            Value::Sequence(sequence) => serializer.collect_seq(sequence.iter().map(Yaml)),
+            Value::Mapping(mapping) => {
+                serializer.collect_map(mapping.iter().map(|(k, v)| (Yaml(k), Yaml(v))))
+            }
+            Value::Tagged(tagged) => Yaml(&tagged.value).serialize(serializer),
+        }
+    }
+}
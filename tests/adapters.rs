@@ -0,0 +1,212 @@
+use expect_test::expect;
+use serde_derive::Serialize;
+use serde_starlark::{
+    Assignment, Bin, BlockComment, Commented, Compact, Config, Dict, Document, Expanded,
+    FunctionCall, Hex, Identifier, KeepSorted, LineComment, Oct, RawString, Spacer, Suppress,
+    TripleQuoted,
+};
+use std::collections::BTreeMap;
+
+type Statement = Box<dyn erased_serde::Serialize>;
+
+#[derive(Serialize)]
+#[serde(rename = "rust_library")]
+struct RustLibrary {
+    name: BlockComment<'static, &'static str>,
+    srcs: Compact<Vec<&'static str>>,
+    deps: KeepSorted<Vec<&'static str>>,
+    data: Vec<Element>,
+    rustc_flags: Expanded<Vec<&'static str>>,
+    rustc_env: Dict<Env>,
+    crate_features: Vec<Commented<&'static str>>,
+    mode: Oct,
+    mask: Hex,
+    bits: Bin,
+    visibility: Identifier<&'static str>,
+    build_script: TripleQuoted<'static>,
+    pattern: RawString<'static>,
+    tags: Suppress<'static, Vec<&'static str>>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Element {
+    Label(LineComment<'static, &'static str>),
+    Spacer(Spacer<'static>),
+}
+
+#[derive(Serialize)]
+struct Env {
+    #[serde(rename = "CARGO_PKG_NAME")]
+    cargo_pkg_name: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Rule {
+    ExportsFiles(Vec<&'static str>),
+}
+
+fn statements() -> Vec<Statement> {
+    vec![
+        Box::new(Compact(FunctionCall::new(
+            "load",
+            ["@rules_rust//rust:defs.bzl", "rust_library"],
+        ))),
+        Box::new(Assignment::new("VERSION", "1.0.0")),
+        Box::new(RustLibrary {
+            name: BlockComment::new("syn", "Parser for Rust source code."),
+            srcs: Compact(vec!["src/lib.rs", "src/parse.rs"]),
+            deps: KeepSorted::sorted(vec![":quote", ":proc-macro2"]),
+            data: vec![
+                Element::Label(LineComment::new("README.md", "docs")),
+                Element::Spacer(Spacer::with_header("licenses")),
+                Element::Label(LineComment::new("LICENSE", "Apache-2.0")),
+            ],
+            rustc_flags: Expanded(vec!["--cap-lints=allow"]),
+            rustc_env: Dict(Env {
+                cargo_pkg_name: "syn",
+            }),
+            crate_features: vec![Commented::new("full", "enabled by default")],
+            mode: Oct(0o755),
+            mask: Hex(0xFF),
+            bits: Bin(0b101),
+            visibility: Identifier("PUBLIC"),
+            build_script: TripleQuoted::new("echo \"building\"\n"),
+            pattern: RawString::new(r"\d+"),
+            tags: Suppress::new(vec!["manual"], "list-append"),
+        }),
+        Box::new(Rule::ExportsFiles(vec!["LICENSE"])),
+        Box::new(FunctionCall::new(
+            "alias",
+            BTreeMap::from([("name", "parser"), ("actual", ":syn")]),
+        )),
+    ]
+}
+
+#[test]
+fn test_erased() {
+    let document = Document::new(statements()).banner("cargo-bazel", "bazel run //:vendor");
+    let config = Config::new();
+    let expected = config.to_string(&document).unwrap();
+
+    let mut output = String::new();
+    let serializer = config.serializer_into(&mut output);
+    let mut erased: Box<dyn erased_serde::Serializer> =
+        Box::new(<dyn erased_serde::Serializer>::erase(serializer));
+    let document: Box<dyn erased_serde::Serialize> = Box::new(document);
+    document.erased_serialize(&mut erased).unwrap();
+    drop(erased);
+    assert_eq!(output, expected);
+
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel; run bazel run //:vendor to
+        # regenerate.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        VERSION = "1.0.0"
+
+        rust_library(
+            # Parser for Rust source code.
+            name = "syn",
+            srcs = ["src/lib.rs", "src/parse.rs"],
+            deps = [  # keep sorted
+                ":proc-macro2",
+                ":quote",
+            ],
+            data = [
+                "README.md",  # docs
+
+                # licenses
+                "LICENSE",  # Apache-2.0
+            ],
+            rustc_flags = [
+                "--cap-lints=allow",
+            ],
+            rustc_env = {
+                "CARGO_PKG_NAME": "syn",
+            },
+            crate_features = ["full"],  # enabled by default
+            mode = 0o755,
+            mask = 0xFF,
+            bits = 0b101,
+            visibility = PUBLIC,
+            build_script = """\
+        echo "building"
+        """,
+            pattern = r"\d+",
+            # buildifier: disable=list-append
+            tags = ["manual"],
+        )
+
+        exports_files("LICENSE")
+
+        alias(
+            actual = ":syn",
+            name = "parser",
+        )
+    "#]];
+    expected.assert_eq(&output);
+}
+
+#[test]
+fn test_erased_error() {
+    let config = Config::new();
+    let mut output = String::from("# unchanged\n");
+    let serializer = config.serializer_into(&mut output);
+    let mut erased: Box<dyn erased_serde::Serializer> =
+        Box::new(<dyn erased_serde::Serializer>::erase(serializer));
+    let value: Box<dyn erased_serde::Serialize> = Box::new(vec![1u64, 1 << 40]);
+    let error = value.erased_serialize(&mut erased).unwrap_err();
+    drop(erased);
+    let expected = expect!["[1]: unsupported integer: 1099511627776, Starlark only supports up to 32-bit signed integers"];
+    expected.assert_eq(&error.to_string());
+    assert_eq!(output, "# unchanged\n");
+}
+
+#[test]
+fn test_transcode() {
+    let json = r#"{
+        "name": "syn",
+        "version": "2.0.0",
+        "features": ["full", "parsing"],
+        "default": true,
+        "edition": 2021,
+        "license": null
+    }"#;
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let mut config = Config::new();
+    config.sort_map_keys(true);
+    // JSON null is deserialized as unit.
+    config.unit_as_none(true);
+    let starlark = serde_transcode::transcode(&mut deserializer, config.serializer()).unwrap();
+    let expected = expect![[r#"
+        {
+            "default": True,
+            "edition": 2021,
+            "features": [
+                "full",
+                "parsing",
+            ],
+            "license": None,
+            "name": "syn",
+            "version": "2.0.0",
+        }
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let mut output = String::new();
+    let mut deserializer = serde_json::Deserializer::from_str("[[1, 2], []]");
+    serde_transcode::transcode(&mut deserializer, config.serializer_into(&mut output)).unwrap();
+    let expected = expect![[r"
+        [
+            [
+                1,
+                2,
+            ],
+            [],
+        ]
+    "]];
+    expected.assert_eq(&output);
+}
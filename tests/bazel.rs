@@ -1,3 +1,5 @@
+#![allow(clippy::useless_vec)]
+
 use expect_test::expect;
 use itertools::Itertools;
 use serde::ser::{Serialize, SerializeStruct, SerializeTupleStruct, Serializer};
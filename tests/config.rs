@@ -0,0 +1,899 @@
+use expect_test::expect;
+use serde_derive::Serialize;
+use serde_starlark::{
+    ArgumentOrder, ArgumentPolicy, Arguments, Assignment, Compact, Config, Container, FunctionCall,
+    Hex, Identifier, IntegerOverflow, Layout, LineComment, MultilineArray, Positional, QuoteStyle,
+    RawString, Select, TripleQuoted,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::marker::PhantomData;
+
+#[test]
+fn test_quote_style() {
+    let value = BTreeMap::from([
+        ("plain", "//:lib"),
+        ("double", "say \"hi\""),
+        ("single", "it's"),
+        ("both", "it's \"it\""),
+    ]);
+
+    let mut config = Config::new();
+    config.quote_style(QuoteStyle::Single);
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        {
+            'both': 'it\'s "it"',
+            'double': 'say "hi"',
+            'plain': '//:lib',
+            'single': 'it\'s',
+        }
+    "#]];
+    expected.assert_eq(&starlark);
+
+    config.quote_style(QuoteStyle::FewestEscapes);
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        {
+            "both": 'it\'s "it"',
+            "double": 'say "hi"',
+            "plain": "//:lib",
+            "single": "it's",
+        }
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_quote_style_raw_and_triple() {
+    let mut config = Config::new();
+    config.quote_style(QuoteStyle::Single);
+    let raw = config.to_string(&RawString::new(r#"\d+"\w"#)).unwrap();
+    let triple = config.to_string(&TripleQuoted::new("echo '''\n")).unwrap();
+    let expected = expect![[r#"
+        r'\d+"\w'
+        '''\
+        echo \'\''
+        '''
+    "#]];
+    expected.assert_eq(&(raw + &triple));
+}
+
+#[test]
+#[allow(clippy::octal_escapes)]
+fn test_hex_escapes() {
+    let mut config = Config::new();
+    config.hex_escapes(true);
+    let starlark = config
+        .to_string("\0\00 \x01\x010 \x0E\x0E0 \x7F\x7F0 \u{80}")
+        .unwrap();
+    let expected = expect![[r#"
+        "\x00\x000 \x01\x010 \x0E\x0E0 \x7F\x7F0 \u0080"
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_ascii_only() {
+    let mut config = Config::new();
+    config.ascii_only(true);
+    let strings = ["\u{41}\u{414}\u{754c}\u{1f600}", "ünïcödé"];
+    let raw = RawString::new(r"C:\Users\Zoë");
+    let starlark = config.to_string(&(strings, raw)).unwrap();
+    let expected = expect![[r#"
+        (("A\u0414\u754C\U0001F600", "\u00FCn\u00EFc\u00F6d\u00E9"), "C:\\Users\\Zo\u00EB")
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_integer_overflow() {
+    #[derive(Serialize)]
+    struct Limits {
+        small: u64,
+        large: u64,
+        negative: i64,
+        mask: Hex,
+    }
+
+    let value = Limits {
+        small: 1,
+        large: 1 << 32,
+        negative: i64::MIN,
+        mask: Hex(u32::MAX),
+    };
+
+    let error = Config::new().to_string(&value).unwrap_err();
+    let expected = expect![
+        "large: unsupported integer: 4294967296, Starlark only supports up to 32-bit signed integers"
+    ];
+    expected.assert_eq(&error.to_string());
+
+    let mut config = Config::new();
+    config.integer_overflow(IntegerOverflow::Emit);
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r"
+        Limits(
+            small = 1,
+            large = 4294967296,
+            negative = -9223372036854775808,
+            mask = 0xFFFFFFFF,
+        )
+    "]];
+    expected.assert_eq(&starlark);
+
+    let mut config = Config::new();
+    config.integer_overflow(IntegerOverflow::Stringify);
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        Limits(
+            small = 1,
+            large = "4294967296",  # u64
+            negative = "-9223372036854775808",  # i64
+            mask = "4294967295",  # u32
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_floats() {
+    let value: &[f64] = &[
+        1.0,
+        -0.5,
+        1e-7,
+        1e100,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NAN,
+    ];
+
+    let error = Config::new().to_string(&value[..1]).unwrap_err();
+    let expected = expect!["[0]: serialization of floating point is not supported: 1"];
+    expected.assert_eq(&error.to_string());
+
+    let mut config = Config::new();
+    config.floats(true);
+    let starlark = config.to_string(value).unwrap();
+    let expected = expect![[r#"
+        [
+            1.0,
+            -0.5,
+            1e-7,
+            1e100,
+            float("inf"),
+            -float("inf"),
+            float("nan"),
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+
+    config.non_finite_floats(false);
+    let error = config.to_string(value).unwrap_err();
+    let expected = expect!["[4]: serialization of non-finite floating point is disabled: inf"];
+    expected.assert_eq(&error.to_string());
+
+    let starlark = config.to_string(&0.1f32).unwrap();
+    let expected = expect![[r"
+        0.1
+    "]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_unit_as_none() {
+    #[derive(Serialize)]
+    struct Placeholder<T> {
+        name: &'static str,
+        reserved: (),
+        marker: PhantomData<T>,
+    }
+
+    let value = Placeholder::<u8> {
+        name: "lib",
+        reserved: (),
+        marker: PhantomData,
+    };
+
+    let error = Config::new().to_string(&value).unwrap_err();
+    let expected = expect![
+        "reserved: serialization of () is not supported; use serialize_none to produce `None`"
+    ];
+    expected.assert_eq(&error.to_string());
+
+    let mut config = Config::new();
+    config.unit_as_none(true);
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        Placeholder(
+            name = "lib",
+            reserved = None,
+            marker = None,
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_skip_none() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        version: LineComment<'static, Option<&'static str>>,
+        edition: Option<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    struct Package {
+        #[serde(flatten)]
+        common: Common,
+        features: Option<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    struct Common {
+        default_visibility: Option<&'static str>,
+    }
+
+    let value = [
+        RustLibrary {
+            name: "syn",
+            version: LineComment::new(Some("2.0.0"), "latest"),
+            edition: None,
+        },
+        RustLibrary {
+            name: "quote",
+            version: LineComment::new(None, "unknown"),
+            edition: Some("2018"),
+        },
+    ];
+
+    let mut config = Config::new();
+    config.skip_none(true);
+    let starlark = config.to_string(&value[..]).unwrap();
+    let expected = expect![[r#"
+        [
+            rust_library(
+                name = "syn",
+                version = "2.0.0",  # latest
+            ),
+            rust_library(
+                name = "quote",
+                edition = "2018",
+            ),
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let package = Package {
+        common: Common {
+            default_visibility: None,
+        },
+        features: None,
+    };
+    let call = FunctionCall::new("package", &package);
+    let starlark = config.to_string(&call).unwrap();
+    let expected = expect![[r"
+        package()
+    "]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_skip_empty() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        srcs: BTreeSet<&'static str>,
+        deps: Vec<&'static str>,
+        rustc_env: BTreeMap<&'static str, &'static str>,
+        args: (),
+    }
+
+    let value = RustLibrary {
+        name: "syn",
+        srcs: BTreeSet::from(["src/lib.rs"]),
+        deps: Vec::new(),
+        rustc_env: BTreeMap::new(),
+        args: (),
+    };
+
+    let mut config = Config::new();
+    config.skip_empty(true).unit_as_none(true);
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            srcs = ["src/lib.rs"],
+            args = None,
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_max_width() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        srcs: Vec<&'static str>,
+        deps: Vec<&'static str>,
+        version: LineComment<'static, &'static str>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "alias")]
+    struct Alias {
+        name: &'static str,
+        actual: &'static str,
+    }
+
+    let rust_library = RustLibrary {
+        name: "syn",
+        srcs: vec!["src/lib.rs", "src/parse.rs"],
+        deps: vec![
+            "//third-party/rust:proc-macro2",
+            "//third-party/rust:quote",
+            "//third-party/rust:unicode-ident",
+        ],
+        version: LineComment::new("2.0.0", "latest"),
+    };
+    let alias = Alias {
+        name: "syn",
+        actual: "//third-party/rust:syn-2.0.0",
+    };
+
+    let mut config = Config::new();
+    config.max_width(Some(60));
+    let starlark = config.to_string(&rust_library).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            srcs = ["src/lib.rs", "src/parse.rs"],
+            deps = [
+                "//third-party/rust:proc-macro2",
+                "//third-party/rust:quote",
+                "//third-party/rust:unicode-ident",
+            ],
+            version = "2.0.0",  # latest
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let starlark = config.to_string(&alias).unwrap();
+    let expected = expect![[r#"
+        alias(name = "syn", actual = "//third-party/rust:syn-2.0.0")
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let starlark = config.to_string(&[&alias][..]).unwrap();
+    let expected = expect![[r#"
+        [
+            alias(
+                name = "syn",
+                actual = "//third-party/rust:syn-2.0.0",
+            ),
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_max_width_assignment() {
+    let mut config = Config::new();
+    config.max_width(Some(60));
+
+    let mut deps = Select::new();
+    deps.base(vec!["//third-party/rust:libc"]);
+    deps.insert(":windows", vec!["//third-party/rust:windows-sys"]);
+    let starlark = config.to_string(&Assignment::new("DEPS", &deps)).unwrap();
+    let expected = expect![[r#"
+        DEPS = (
+            ["//third-party/rust:libc"] +
+            select({":windows": ["//third-party/rust:windows-sys"]})
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let srcs = FunctionCall::new(
+        "+",
+        (
+            vec!["src/lib.rs", "src/parse.rs", "src/print.rs", "src/token.rs"],
+            vec!["build.rs"],
+        ),
+    );
+    let starlark = config.to_string(&Assignment::new("SRCS", &srcs)).unwrap();
+    let expected = expect![[r#"
+        SRCS = [
+            "src/lib.rs",
+            "src/parse.rs",
+            "src/print.rs",
+            "src/token.rs",
+        ] + ["build.rs"]
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let mut deps = Select::new();
+    deps.base(vec!["//common"]);
+    deps.insert(":windows", vec!["//windows"]);
+    let starlark = config.to_string(&Assignment::new("DEPS", &deps)).unwrap();
+    let expected = expect![[r#"
+        DEPS = ["//common"] + select({":windows": ["//windows"]})
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_argument_order() {
+    #[derive(Serialize)]
+    #[serde(rename = "register_toolchains")]
+    struct RegisterToolchains {
+        dev: bool,
+        toolchains: Positional<Vec<&'static str>>,
+        name: &'static str,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        srcs: Vec<&'static str>,
+        name: &'static str,
+        version: LineComment<'static, &'static str>,
+        edition: Option<&'static str>,
+        #[serde(flatten)]
+        common: Common,
+    }
+
+    #[derive(Serialize)]
+    struct Common {
+        visibility: Vec<&'static str>,
+        deps: Vec<&'static str>,
+    }
+
+    let rust_library = RustLibrary {
+        srcs: vec!["src/lib.rs", "src/parse.rs"],
+        name: "syn",
+        version: LineComment::new("2.0.0", "latest"),
+        edition: None,
+        common: Common {
+            visibility: vec!["//visibility:public"],
+            deps: vec![],
+        },
+    };
+
+    let mut config = Config::new();
+    config
+        .argument_order(ArgumentOrder::NameFirst)
+        .skip_none(true);
+    let call = FunctionCall::new("rust_library", &rust_library);
+    let starlark = config.to_string(&call).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            deps = [],
+            srcs = [
+                "src/lib.rs",
+                "src/parse.rs",
+            ],
+            version = "2.0.0",  # latest
+            visibility = ["//visibility:public"],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let args = Arguments::new()
+        .named("srcs", Identifier("SRCS"))
+        .named("name", Identifier("name"))
+        .args(Identifier("args"))
+        .kwargs(Identifier("kwargs"));
+    let starlark = config
+        .to_string(&FunctionCall::new("rust_library", args))
+        .unwrap();
+    let expected = expect![[r"
+        rust_library(
+            name = name,
+            srcs = SRCS,
+            *args,
+            **kwargs,
+        )
+    "]];
+    expected.assert_eq(&starlark);
+
+    let register = RegisterToolchains {
+        dev: true,
+        toolchains: Positional(vec!["//a", "//b"]),
+        name: "toolchains",
+    };
+    let starlark = config.to_string(&register).unwrap();
+    let expected = expect![[r#"
+        register_toolchains(
+            "//a",
+            "//b",
+            name = "toolchains",
+            dev = True,
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_argument_policy() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        srcs: MultilineArray<Vec<&'static str>>,
+        deps: Vec<&'static str>,
+        rustc_env: BTreeMap<&'static str, &'static str>,
+        tags: Vec<&'static str>,
+    }
+
+    let mut config = Config::new();
+    let multiline = ArgumentPolicy {
+        layout: Layout::Multiline,
+        sort: true,
+    };
+    let oneline = ArgumentPolicy {
+        layout: Layout::Oneline,
+        ..ArgumentPolicy::default()
+    };
+    config
+        .argument_policy("srcs", oneline)
+        .argument_policy("deps", multiline)
+        .argument_policy("rustc_env", oneline)
+        .argument_policy("tags", multiline);
+
+    let rust_library = RustLibrary {
+        name: "syn",
+        srcs: MultilineArray(vec!["src/lib.rs"]),
+        deps: vec![":quote"],
+        rustc_env: BTreeMap::from([("A", "1"), ("B", "2")]),
+        tags: vec![],
+    };
+    let starlark = config.to_string(&rust_library).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            srcs = [
+                "src/lib.rs",
+            ],
+            deps = [
+                ":quote",
+            ],
+            rustc_env = {"A": "1", "B": "2"},
+            tags = [],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let args = Arguments::new()
+        .named("name", vec!["b", "a"])
+        .named("deps", vec!["b", "a"]);
+    let call = FunctionCall::new("f", args);
+    config.argument_order(ArgumentOrder::NameFirst);
+    let starlark = config.to_string(&call).unwrap();
+    let expected = expect![[r#"
+        f(
+            name = [
+                "b",
+                "a",
+            ],
+            deps = [
+                "a",
+                "b",
+            ],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_format() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        srcs: Vec<&'static str>,
+        deps: Vec<&'static str>,
+        rustc_env: BTreeMap<&'static str, &'static str>,
+        tags: Compact<Vec<&'static str>>,
+    }
+
+    let mut config = Config::new();
+    config
+        .format(|container: Container, len: Option<usize>| match container {
+            Container::List if len == Some(1) => Layout::Multiline,
+            Container::List | Container::Call("package") => Layout::Oneline,
+            Container::Dict => Layout::Multiline,
+            Container::Tuple | Container::Call(_) => Layout::Auto,
+        })
+        .argument_policy(
+            "deps",
+            ArgumentPolicy {
+                layout: Layout::Multiline,
+                sort: false,
+            },
+        );
+
+    let rust_library = RustLibrary {
+        name: "syn",
+        srcs: vec!["src/lib.rs"],
+        deps: vec![":quote", ":proc-macro2"],
+        rustc_env: BTreeMap::from([("A", "1")]),
+        tags: Compact(vec!["a", "b"]),
+    };
+    let starlark = config.to_string(&rust_library).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            srcs = [
+                "src/lib.rs",
+            ],
+            deps = [
+                ":quote",
+                ":proc-macro2",
+            ],
+            rustc_env = {
+                "A": "1",
+            },
+            tags = ["a", "b"],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let args = Arguments::new().named("default_visibility", vec!["//visibility:public"]);
+    let package = FunctionCall::new("package", args);
+    let starlark = config.to_string(&package).unwrap();
+    let expected = expect![[r#"
+        package(default_visibility = [
+            "//visibility:public",
+        ])
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let value = (vec!["a", "b"], ["c"]);
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        (["a", "b"], ("c",))
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_sort_map_keys() {
+    let mut rustc_env = HashMap::new();
+    for (i, key) in ["CARGO_PKG_NAME", "OUT_DIR", "CARGO_MANIFEST_DIR", "RUSTC"]
+        .iter()
+        .enumerate()
+    {
+        rustc_env.insert(*key, i);
+    }
+
+    let mut config = Config::new();
+    config.sort_map_keys(true);
+    let starlark = config.to_string(&rustc_env).unwrap();
+    let expected = expect![[r#"
+        {
+            "CARGO_MANIFEST_DIR": 2,
+            "CARGO_PKG_NAME": 0,
+            "OUT_DIR": 1,
+            "RUSTC": 3,
+        }
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_deny_duplicate_keys() {
+    struct Select(&'static [(&'static str, &'static str)]);
+
+    impl serde::Serialize for Select {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            serializer.collect_map(self.0.iter().copied())
+        }
+    }
+
+    let value = Select(&[
+        ("@platforms//os:linux", "//:libc"),
+        ("@platforms//os:macos", "//:libc"),
+        ("@platforms//os:linux", "//:nix"),
+    ]);
+
+    let mut config = Config::new();
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        {
+            "@platforms//os:linux": "//:libc",
+            "@platforms//os:macos": "//:libc",
+            "@platforms//os:linux": "//:nix",
+        }
+    "#]];
+    expected.assert_eq(&starlark);
+
+    config.deny_duplicate_keys(true);
+    let error = config.to_string(&value).unwrap_err();
+    let expected = expect![[r#"duplicate key in dict: "@platforms//os:linux""#]];
+    expected.assert_eq(&error.to_string());
+
+    config.sort_map_keys(true);
+    let error = config.to_string(&value).unwrap_err();
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_uppercase_assignments() {
+    let mut config = Config::new();
+    config.uppercase_assignments(true);
+    let starlark = config
+        .to_string(&Assignment::new("RUST_EDITION_2021", "2021"))
+        .unwrap();
+    let expected = expect![[r#"
+        RUST_EDITION_2021 = "2021"
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let error = config
+        .to_string(&Assignment::new("rust_edition", "2021"))
+        .unwrap_err();
+    let expected = expect![[r#"assignment to "rust_edition" must be in ALL_CAPS like a constant"#]];
+    expected.assert_eq(&error.to_string());
+
+    let error = config
+        .to_string(&Assignment::new("MY VAR", "2021"))
+        .unwrap_err();
+    let expected = expect![[r#"invalid identifier: "MY VAR" is not a Starlark identifier"#]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_escape_keywords() {
+    #[derive(Serialize)]
+    #[serde(rename = "platform_rule")]
+    struct PlatformRule {
+        name: &'static str,
+        r#if: &'static str,
+        r#type: &'static str,
+        class: &'static str,
+    }
+
+    let value = PlatformRule {
+        name: "linux",
+        r#if: "@platforms//os:linux",
+        r#type: "os",
+        class: "unix",
+    };
+
+    let error = Config::new().to_string(&value).unwrap_err();
+    let expected =
+        expect![[r#"invalid named argument: "if" is not a valid Starlark parameter name"#]];
+    expected.assert_eq(&error.to_string());
+
+    let mut config = Config::new();
+    config.escape_keywords(true);
+    config.rename_argument("type", "kind");
+    config.rename_argument("class", "for");
+    let starlark = config.to_string(&value).unwrap();
+    let expected = expect![[r#"
+        platform_rule(
+            name = "linux",
+            if_ = "@platforms//os:linux",
+            kind = "os",
+            for_ = "unix",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_to_string_into() {
+    let config = Config::new();
+    let mut output = String::from("# BUILD\n");
+    let exports = FunctionCall::new("exports_files", [vec!["LICENSE"]]);
+    config.to_string_into(&mut output, &exports).unwrap();
+    let error = config.to_string_into(&mut output, &[u64::MAX]).unwrap_err();
+    let expected = expect![
+        "[0]: unsupported integer: 18446744073709551615, Starlark only supports up to 32-bit signed integers"
+    ];
+    expected.assert_eq(&error.to_string());
+    let expected = expect![[r#"
+        # BUILD
+        exports_files(["LICENSE"])
+    "#]];
+    expected.assert_eq(&output);
+}
+
+#[test]
+fn test_patch_owned() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        srcs: Vec<&'static str>,
+    }
+
+    let previous = r#"# @generated
+load("@rules_rust//rust:defs.bzl", "rust_library")
+
+# Hand-written, but the generator took it over.
+rust_library(
+    name = "quote",
+    srcs = glob(["src/**"]),
+)
+
+rust_library(name='local',
+             srcs=['lib.rs'])   # odd formatting
+
+# @generated
+rust_library(
+    name = "removed",
+    srcs = ["src/lib.rs"],
+)
+"#;
+
+    let load = Compact(FunctionCall::new(
+        "load",
+        ["@rules_rust//rust:defs.bzl", "rust_library"],
+    ));
+    let quote = RustLibrary {
+        name: "quote",
+        srcs: vec!["src/lib.rs"],
+    };
+    let statements: Vec<&dyn erased_serde::Serialize> = vec![&quote, &load];
+    let patched = Config::new()
+        .patch_owned(previous, "@generated", statements)
+        .unwrap();
+    let expected = expect![[r#"
+        # @generated
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        # Hand-written, but the generator took it over.
+        # @generated
+        rust_library(
+            name = "quote",
+            srcs = ["src/lib.rs"],
+        )
+
+        rust_library(name='local',
+                     srcs=['lib.rs'])   # odd formatting
+    "#]];
+    expected.assert_eq(&patched);
+}
+
+#[test]
+fn test_patch_owned_statements() {
+    let previous = r#"# @generated
+exports_files(["LICENSE"])
+VERSION = "1.0"
+# @generated
+DOC = """
+# @generated
+
+exports_files(["README.md"])
+"""
+# @generated
+exports_files(["AUTHORS"])
+exports_files(["COPYRIGHT"])  # by hand
+"#;
+
+    let statements = [
+        Compact(FunctionCall::new("exports_files", [&["LICENSE-MIT"][..]])),
+        Compact(FunctionCall::new("exports_files", [&["NOTICE"][..]])),
+    ];
+    let patched = Config::new()
+        .patch_owned(previous, "@generated", statements)
+        .unwrap();
+    let expected = expect![[r#"
+        # @generated
+        exports_files(["LICENSE-MIT"])
+        VERSION = "1.0"
+        # @generated
+        exports_files(["NOTICE"])
+        exports_files(["COPYRIGHT"])  # by hand
+    "#]];
+    expected.assert_eq(&patched);
+}
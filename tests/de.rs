@@ -0,0 +1,513 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_starlark::{CommentPlacement, Config, Glob, IntegerOverflow, Raw, Select};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read as _};
+
+#[test]
+fn test_values() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Crate<'a> {
+        name: &'a str,
+        version: u8,
+        edition: i32,
+        features: Vec<String>,
+        escaped: String,
+        proc_macro: bool,
+        links: Option<String>,
+    }
+
+    let starlark = r#"{
+    "name": "syn",
+    "version": 2,
+    "edition": -2018,
+    "features": ["default", 'full'],
+    "escaped": "say \"hi\"",
+    "proc_macro": False,
+    "links": None,
+}
+"#;
+
+    let krate: Crate = serde_starlark::from_str(starlark).unwrap();
+    let expected = Crate {
+        name: "syn",
+        version: 2,
+        edition: -2018,
+        features: vec!["default".to_owned(), "full".to_owned()],
+        escaped: "say \"hi\"".to_owned(),
+        proc_macro: false,
+        links: None,
+    };
+    assert_eq!(krate, expected);
+}
+
+#[test]
+fn test_select() {
+    let starlark = r#"selects.with_or({
+    ("@platforms//os:linux", "@platforms//os:macos"): [":unix"],
+    "@platforms//os:windows": [":windows"],
+    "//conditions:default": [],
+})
+"#;
+    let select: Select<Vec<String>> = serde_starlark::from_str(starlark).unwrap();
+    assert_eq!(serde_starlark::to_string(&select).unwrap(), starlark);
+
+    let starlark = r#"select({
+    "//conditions:default": [],
+    "@platforms//os:linux": [":libc"],
+})
+"#;
+    let select: BTreeMap<String, Vec<String>> = serde_starlark::from_str(starlark).unwrap();
+    let expected = BTreeMap::from([
+        ("//conditions:default".to_owned(), vec![]),
+        ("@platforms//os:linux".to_owned(), vec![":libc".to_owned()]),
+    ]);
+    assert_eq!(select, expected);
+}
+
+#[test]
+fn test_glob() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: String,
+        srcs: ReadmeGlob,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename = "glob")]
+    struct ReadmeGlob(BTreeSet<String>);
+
+    let glob: Glob = serde_starlark::from_str(r#"glob(["**/*.rs"])"#).unwrap();
+    assert_eq!(glob.include, ["**/*.rs"]);
+    assert!(glob.exclude.is_empty());
+    assert_eq!(
+        serde_starlark::to_string(&glob).unwrap(),
+        "glob([\"**/*.rs\"])\n",
+    );
+
+    let starlark = r#"glob(
+    include = ["src/**/*.rs"],
+    exclude = ["src/bin/**"],
+)
+"#;
+    let glob: Glob = serde_starlark::from_str(starlark).unwrap();
+    assert_eq!(serde_starlark::to_string(&glob).unwrap(), starlark);
+
+    let starlark = r#"rust_library(
+    name = "syn",
+    srcs = glob(["**/*.rs"]),
+)
+"#;
+    let rust_library: RustLibrary = serde_starlark::from_str(starlark).unwrap();
+    let expected = RustLibrary {
+        name: "syn".to_owned(),
+        srcs: ReadmeGlob(BTreeSet::from(["**/*.rs".to_owned()])),
+    };
+    assert_eq!(rust_library, expected);
+
+    let error = serde_starlark::from_str::<Glob>(r#"native.glob(["*"])"#).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "expected a call to glob, found native.glob at line 1 column 1"
+    );
+}
+
+#[test]
+fn test_calls() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename = "Label")]
+    struct Label(String, bool);
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Statement {
+        #[serde(rename = "load")]
+        Load(Vec<String>),
+        #[serde(rename = "package")]
+        Package { default_visibility: Vec<String> },
+        #[serde(rename = "rust_library")]
+        RustLibrary(RustLibrary),
+        #[serde(rename = "exports_files")]
+        ExportsFiles(Vec<String>),
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: String,
+        #[serde(default)]
+        deps: Vec<String>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Unset;
+
+    let starlark = r#"# Generated.
+load("@rules_rust//rust:defs.bzl", "rust_library")
+
+package(default_visibility = ["//visibility:public"])
+
+rust_library(
+    name = "syn",
+    deps = [":proc-macro2"],
+)
+
+rust_library(name = "proc-macro2")
+
+exports_files(["LICENSE"])
+"#;
+    let statements: Vec<Statement> = serde_starlark::from_str(starlark).unwrap();
+    let expected = vec![
+        Statement::Load(vec![
+            "@rules_rust//rust:defs.bzl".to_owned(),
+            "rust_library".to_owned(),
+        ]),
+        Statement::Package {
+            default_visibility: vec!["//visibility:public".to_owned()],
+        },
+        Statement::RustLibrary(RustLibrary {
+            name: "syn".to_owned(),
+            deps: vec![":proc-macro2".to_owned()],
+        }),
+        Statement::RustLibrary(RustLibrary {
+            name: "proc-macro2".to_owned(),
+            deps: Vec::new(),
+        }),
+        Statement::ExportsFiles(vec!["LICENSE".to_owned()]),
+    ];
+    assert_eq!(statements, expected);
+
+    let libraries: Vec<RustLibrary> =
+        serde_starlark::from_str("rust_library(name = \"syn\")\n").unwrap();
+    assert_eq!(libraries.len(), 1);
+
+    let label: Label = serde_starlark::from_str(r#"Label("//:lib", True)"#).unwrap();
+    assert_eq!(label, Label("//:lib".to_owned(), true));
+
+    let error = serde_starlark::from_str::<Label>(r#"Label("//:lib", exists = True)"#);
+    assert_eq!(
+        error.unwrap_err().to_string(),
+        "unexpected named argument exists in call with positional arguments at line 1 column 17",
+    );
+
+    let unset: Unset = serde_starlark::from_str("Unset").unwrap();
+    assert_eq!(unset, Unset);
+}
+
+#[test]
+fn test_tuples() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Version(u8, u8, u8);
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(rename = "Label")]
+    struct Label(String);
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(rename = "pair")]
+    struct Pair(Label, Option<i32>);
+
+    let tuple: (String, i32, bool) = serde_starlark::from_str(r#"("a", 1, True)"#).unwrap();
+    assert_eq!(tuple, ("a".to_owned(), 1, true));
+
+    let single = ("only".to_owned(),);
+    let starlark = serde_starlark::to_string(&single).unwrap();
+    assert_eq!(starlark, "(\"only\",)\n");
+    let roundtrip: (String,) = serde_starlark::from_str(&starlark).unwrap();
+    assert_eq!(roundtrip, single);
+
+    let version: Version = serde_starlark::from_str("(1, 70, 0)").unwrap();
+    assert_eq!(version, Version(1, 70, 0));
+
+    let pair = Pair(Label("//:a".to_owned()), None);
+    let starlark = serde_starlark::to_string(&pair).unwrap();
+    assert_eq!(starlark, "pair(\n    Label(\"//:a\"),\n    None,\n)\n");
+    let roundtrip: Pair = serde_starlark::from_str(&starlark).unwrap();
+    assert_eq!(roundtrip, pair);
+
+    let error = serde_starlark::from_str::<(i32, i32)>("(1, 2, 3)").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid length 3, expected a tuple of size 2 at line 1 column 1",
+    );
+
+    let error = serde_starlark::from_str::<Version>("Version(1, 70)").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid length 2, expected tuple struct Version at line 1 column 1",
+    );
+}
+
+#[test]
+fn test_integer_width() {
+    let mut config = Config::new();
+    config.integer_overflow(IntegerOverflow::Emit);
+
+    let int: i64 = config.from_str("-0x80000001").unwrap();
+    assert_eq!(int, -0x8000_0001);
+    let int: u64 = config.from_str("18446744073709551615").unwrap();
+    assert_eq!(int, u64::MAX);
+    let int: i128 = config.from_str("-18446744073709551616").unwrap();
+    assert_eq!(int, -1 << 64);
+    let int: u128 = config.from_str(&u128::MAX.to_string()).unwrap();
+    assert_eq!(int, u128::MAX);
+    let int: i32 = config.from_str("7").unwrap();
+    assert_eq!(int, 7);
+
+    let error = config.from_str::<i32>("4294967296").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid value: integer `4294967296`, expected i32 at line 1 column 1",
+    );
+
+    let error = serde_starlark::from_str::<u128>(&u128::MAX.to_string()).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "unsupported integer: 340282366920938463463374607431768211455, Starlark only supports up to 32-bit signed integers at line 1 column 1",
+    );
+}
+
+#[test]
+fn test_raw() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Attr {
+        Str(String),
+        List(Vec<String>),
+        Other(Raw),
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename = "cc_library")]
+    struct CcLibrary {
+        name: String,
+        hdrs: Attr,
+        copts: Attr,
+    }
+
+    let raw: Raw = serde_starlark::from_str("[x for x in y]").unwrap();
+    assert_eq!(raw, Raw("[x for x in y]".to_owned()));
+    assert_eq!(serde_starlark::to_string(&raw).unwrap(), "[x for x in y]\n");
+
+    let build = r#"load(":defs.bzl", "COPTS")
+
+HDRS = glob(["*.h"])  # headers
+
+cc_library(
+    name = "z",
+    hdrs = HDRS + ["zconf.h"],
+    copts = COPTS if True else [],
+)
+
+def helper():
+    pass
+"#;
+    let error = serde_starlark::from_str::<Vec<Raw>>(build).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "expected the input to consist of one Starlark expression at line 3 column 1",
+    );
+
+    let mut config = Config::new();
+    config.lenient(true);
+    let statements: Vec<Raw> = config.from_str(build).unwrap();
+    assert_eq!(statements.len(), 4);
+    assert_eq!(statements[1].0, r#"HDRS = glob(["*.h"])"#);
+    assert_eq!(statements[3].0, "def helper():\n    pass");
+
+    let cc_library: CcLibrary = config.from_str(&statements[2].0).unwrap();
+    let expected = CcLibrary {
+        name: "z".to_owned(),
+        hdrs: Attr::Other(Raw(r#"HDRS + ["zconf.h"]"#.to_owned())),
+        copts: Attr::Other(Raw("COPTS if True else []".to_owned())),
+    };
+    assert_eq!(cc_library, expected);
+
+    let error = serde_starlark::from_str::<CcLibrary>(&statements[2].0).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "unsupported expression for deserialization: HDRS + [\"zconf.h\"] at line 3 column 12",
+    );
+}
+
+#[test]
+fn test_comments() {
+    #[derive(Deserialize)]
+    #[serde(rename = "alias")]
+    struct Alias {
+        #[allow(dead_code)]
+        name: String,
+        #[allow(dead_code)]
+        actual: BTreeMap<String, String>,
+    }
+
+    let starlark = r#"# Top of file.
+
+alias(  # first
+    name = "a",
+    actual = select({
+        # Linux only.
+        "@platforms//os:linux": ":a-linux",
+        "//conditions:default": ":a",  # fallback
+        # More to come.
+    }),
+)
+
+# Between.
+
+# Second.
+alias(name = "b", actual = {})  # second
+"#;
+    let (aliases, comments) =
+        serde_starlark::from_str_with_comments::<Vec<Alias>>(starlark).unwrap();
+    assert_eq!(aliases.len(), 2);
+
+    let comments: Vec<_> = comments
+        .iter()
+        .map(|comment| {
+            let path = comment.path.as_str();
+            (
+                comment.text,
+                comment.line,
+                comment.statement,
+                path,
+                comment.placement,
+            )
+        })
+        .collect();
+    assert_eq!(
+        comments,
+        [
+            ("# Top of file.", 1, None, "", CommentPlacement::Detached),
+            ("# first", 3, Some(0), "", CommentPlacement::Suffix),
+            (
+                "# Linux only.",
+                6,
+                Some(0),
+                "actual[0][\"@platforms//os:linux\"]",
+                CommentPlacement::Above,
+            ),
+            (
+                "# fallback",
+                8,
+                Some(0),
+                "actual[0][\"//conditions:default\"]",
+                CommentPlacement::Suffix,
+            ),
+            (
+                "# More to come.",
+                9,
+                Some(0),
+                "actual[0]",
+                CommentPlacement::Detached,
+            ),
+            ("# Between.", 13, Some(0), "", CommentPlacement::Detached),
+            ("# Second.", 15, Some(1), "", CommentPlacement::Above),
+            ("# second", 16, Some(1), "", CommentPlacement::Suffix),
+        ],
+    );
+}
+
+#[test]
+fn test_errors() {
+    #[derive(Deserialize, Debug)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        #[allow(dead_code)]
+        name: String,
+        #[allow(dead_code)]
+        deps: Vec<String>,
+    }
+
+    let error = serde_starlark::from_str::<i32>("1\n2\n").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "expected the input to consist of one Starlark expression at line 2 column 1",
+    );
+
+    let error = serde_starlark::from_str::<i32>("2147483648").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "unsupported integer: 2147483648, Starlark only supports up to 32-bit signed integers at line 1 column 1",
+    );
+
+    let error = serde_starlark::from_str::<Vec<String>>("[x for x in y]").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "unsupported expression for deserialization: [x for x in y] at line 1 column 1",
+    );
+
+    let starlark = r#"rust_library(name = "a", deps = [])
+
+rust_library(
+    name = "b",
+    deps = [":a", 1],
+)
+"#;
+    let error = serde_starlark::from_str::<Vec<RustLibrary>>(starlark).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid type: integer `1`, expected a string at line 5 column 19",
+    );
+    assert_eq!(error.offset(), starlark.find("1]"));
+    assert_eq!(error.snippet(), Some("    deps = [\":a\", 1],"));
+
+    let error =
+        serde_starlark::from_str::<Vec<RustLibrary>>("rust_library(name = \"a\")").unwrap_err();
+    assert_eq!(error.to_string(), "missing field `deps` at line 1 column 1");
+
+    let error = serde_starlark::to_string(&'x').unwrap_err();
+    assert_eq!(error.line(), None);
+}
+
+#[test]
+fn test_from_reader() {
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(rename = "exports_files")]
+    struct ExportsFiles(Vec<String>);
+
+    let build = r#"# Files.
+exports_files(["LICENSE"])
+
+exports_files([
+    "README.md",
+])
+exports_files(["""
+x"""])
+"#;
+    let statements: Vec<ExportsFiles> = serde_starlark::from_reader(build.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let expected = [
+        ExportsFiles(vec!["LICENSE".to_owned()]),
+        ExportsFiles(vec!["README.md".to_owned()]),
+        ExportsFiles(vec!["\nx".to_owned()]),
+    ];
+    assert_eq!(statements, expected);
+
+    // The first statement is available before the rest of the input is read.
+    let reader = build.as_bytes()[..36].chain(FailingReader);
+    let mut statements = serde_starlark::from_reader::<_, ExportsFiles>(reader);
+    assert_eq!(statements.next().unwrap().unwrap(), expected[0]);
+    let error = statements.next().unwrap().unwrap_err();
+    assert_eq!(error.to_string(), "failed");
+    assert!(statements.next().is_none());
+
+    let build = "exports_files([\"a\"])\n\nexports_files([\"b\"]) )\n";
+    let mut statements = serde_starlark::from_reader::<_, ExportsFiles>(build.as_bytes());
+    assert!(statements.next().unwrap().is_ok());
+    let error = statements.next().unwrap().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "unmatched closing bracket at line 3 column 22",
+    );
+    assert_eq!(error.offset(), build.rfind(')'));
+    assert!(statements.next().is_none());
+}
+
+struct FailingReader;
+
+impl io::Read for FailingReader {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::Other, "failed"))
+    }
+}
@@ -1,6 +1,15 @@
 use expect_test::expect;
 use serde_derive::Serialize;
-use serde_starlark::FunctionCall;
+use serde_starlark::{
+    ser_helpers, Args, Arguments, Assignment, Bin, BlockComment, BranchOrder, Commented, Compact,
+    Constants, Dict, Document, Expanded, FunctionCall, Hex, Identifier, KeepSorted, Kwargs,
+    LineComment, MultilineArray, NamedArg, Oct, Positional, Raw, RawString, Section, Select,
+    Sharding, SlashPath, Spacer, StatementOrder, Suppress, TripleQuoted, WrapperMacro,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write as _};
+use std::io;
+use std::path::PathBuf;
 
 #[test]
 #[allow(clippy::octal_escapes)]
@@ -68,3 +77,2385 @@ fn test_function_call_positional() {
     "#]];
     expected.assert_eq(&starlark);
 }
+
+#[test]
+fn test_function_call_mixed_arguments() {
+    let outputs = vec![Identifier("out")];
+    let args = Arguments::new()
+        .named(
+            "executable",
+            &Identifier("exe") as &dyn erased_serde::Serialize,
+        )
+        .pos(&Identifier("inputs"))
+        .named("mnemonic", &"Generate")
+        .pos(&outputs);
+    let function_call = FunctionCall::new("ctx.actions.run", args);
+    let starlark = serde_starlark::to_string(&function_call).unwrap();
+    let expected = expect![[r#"
+        ctx.actions.run(
+            inputs,
+            [out],
+            executable = exe,
+            mnemonic = "Generate",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let args = Arguments::new().pos("a").pos("b").named("c", "d");
+    let function_call = Compact(FunctionCall::new("f", args));
+    let starlark = serde_starlark::to_string(&function_call).unwrap();
+    let expected = expect![[r#"
+        f("a", "b", c = "d")
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let args = Arguments::new().pos("a").named("not valid", "b");
+    let error = serde_starlark::to_string(&FunctionCall::new("f", args)).unwrap_err();
+    let expected =
+        expect![[r#"invalid named argument: "not valid" is not a valid Starlark parameter name"#]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_named_arg() {
+    #[derive(Serialize)]
+    #[serde(rename = "glob")]
+    struct Glob(Vec<&'static str>, NamedArg<'static, bool>);
+
+    let glob = Compact(Glob(vec!["*.rs"], NamedArg::new("allow_empty", true)));
+    let starlark = serde_starlark::to_string(&glob).unwrap();
+    let expected = expect![[r#"
+        glob(["*.rs"], allow_empty = True)
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let call = FunctionCall::new("f", (NamedArg::new("x", 1), "src"));
+    let error = serde_starlark::to_string(&call).unwrap_err();
+    let expected = expect!["no positional argument may follow a named argument in a function call"];
+    expected.assert_eq(&error.to_string());
+
+    let call = FunctionCall::new("f", (NamedArg::new("not valid", 1),));
+    let error = serde_starlark::to_string(&call).unwrap_err();
+    let expected = expect![[
+        r#"[0]: invalid named argument: "not valid" is not a valid Starlark parameter name"#
+    ]];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::to_string(&vec![NamedArg::new("x", 1)]).unwrap_err();
+    let expected =
+        expect!["[0]: NamedArg is only supported directly among the arguments of a function call"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_args() {
+    let args = Arguments::new()
+        .kwargs(Identifier("kwargs"))
+        .args(Identifier("args"))
+        .named("name", Identifier("name"))
+        .pos(Identifier("srcs"));
+    let function_call = FunctionCall::new("rust_library", args);
+    let starlark = serde_starlark::to_string(&function_call).unwrap();
+    let expected = expect![[r"
+        rust_library(
+            srcs,
+            name = name,
+            *args,
+            **kwargs,
+        )
+    "]];
+    expected.assert_eq(&starlark);
+
+    let call = FunctionCall::new("f", (Args(Identifier("args")), "src"));
+    let error = serde_starlark::to_string(&call).unwrap_err();
+    let expected = expect!["only **kwargs may follow *args in a function call"];
+    expected.assert_eq(&error.to_string());
+
+    let call = FunctionCall::new(
+        "f",
+        (Kwargs(Identifier("kwargs")), Args(Identifier("args"))),
+    );
+    let error = serde_starlark::to_string(&call).unwrap_err();
+    let expected = expect!["no argument may follow **kwargs in a function call"];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::to_string(&Args(vec!["src"])).unwrap_err();
+    let expected =
+        expect!["Args is only supported directly among the arguments of a function call"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_kwargs() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        #[serde(rename = "")]
+        kwargs: Kwargs<Identifier<&'static str>>,
+    }
+
+    let rust_library = RustLibrary {
+        name: "syn",
+        kwargs: Kwargs(Identifier("kwargs")),
+    };
+    let starlark = serde_starlark::to_string(&rust_library).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            **kwargs,
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let args = Arguments::new().named("srcs", Kwargs(Identifier("SRCS")));
+    let error = serde_starlark::to_string(&FunctionCall::new("f", args)).unwrap_err();
+    let expected =
+        expect!["srcs: Kwargs is only supported directly among the arguments of a function call"];
+    expected.assert_eq(&error.to_string());
+
+    let args = Arguments::new()
+        .kwargs(BTreeMap::from([("edition", "2021")]))
+        .named("name", BTreeMap::new());
+    let starlark = serde_starlark::to_string(&Compact(FunctionCall::new("f", args))).unwrap();
+    let expected = expect![[r#"
+        f(name = {}, **{"edition": "2021"})
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let call = FunctionCall::new("f", (Kwargs(Identifier("CONFIG")), "src"));
+    let error = serde_starlark::to_string(&call).unwrap_err();
+    let expected = expect!["no argument may follow **kwargs in a function call"];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::to_string(&vec![Kwargs("x")]).unwrap_err();
+    let expected =
+        expect!["[0]: Kwargs is only supported directly among the arguments of a function call"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_positional() {
+    #[derive(Serialize)]
+    #[serde(rename = "register_toolchains")]
+    struct RegisterToolchains {
+        toolchains: Positional<Vec<&'static str>>,
+        dev: bool,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "register_toolchains")]
+    struct Misordered {
+        dev: bool,
+        toolchains: Positional<Vec<&'static str>>,
+    }
+
+    let register = RegisterToolchains {
+        toolchains: Positional(vec!["//a", "//b"]),
+        dev: true,
+    };
+    let starlark = serde_starlark::to_string(&register).unwrap();
+    let expected = expect![[r#"
+        register_toolchains(
+            "//a",
+            "//b",
+            dev = True,
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let register = Compact(RegisterToolchains {
+        toolchains: Positional(vec!["//a"]),
+        dev: false,
+    });
+    let starlark = serde_starlark::to_string(&register).unwrap();
+    let expected = expect![[r#"
+        register_toolchains("//a", dev = False)
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let misordered = Misordered {
+        dev: true,
+        toolchains: Positional(vec!["//a"]),
+    };
+    let error = serde_starlark::to_string(&misordered).unwrap_err();
+    let expected = expect!["no positional argument may follow a named argument in a function call"];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::to_string(&Positional(vec!["//a"])).unwrap_err();
+    let expected =
+        expect!["Positional is only supported as the value of a named argument of a function call"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_raw_string() {
+    let strings: &[RawString] = &[
+        RawString::new(r"C:\Users\dtolnay\.cargo\registry"),
+        RawString::new(r#"\"(\w+)\""#),
+        RawString::new(r#"say "hi""#),
+        RawString::new("trailing\\"),
+        RawString::new("line\nbreak"),
+    ];
+
+    let starlark = serde_starlark::to_string(strings).unwrap();
+    let expected = expect![[r#"
+        [
+            r"C:\Users\dtolnay\.cargo\registry",
+            r"\"(\w+)\"",
+            "say \"hi\"",
+            "trailing\\",
+            "line\nbreak",
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_triple_quoted() {
+    let strings: &[TripleQuoted] = &[
+        TripleQuoted::new("one line"),
+        TripleQuoted::new("if [ -n \"$$1\" ]; then\n\techo \"\"\"$$1\"\"\"\nfi\n"),
+        TripleQuoted::new("no trailing newline\\\nends with a quote\""),
+    ];
+
+    let starlark = serde_starlark::to_string(strings).unwrap();
+    let expected = expect![[r#"
+        [
+            "one line",
+            """\
+        if [ -n "$$1" ]; then
+        \techo \"\""$$1\"\""
+        fi
+        """,
+            """\
+        no trailing newline\\
+        ends with a quote\"""",
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_radix() {
+    let starlark =
+        serde_starlark::to_string(&(Hex(0x00C0_FFEE), Oct(0o644), Bin(0b1010), Hex(0))).unwrap();
+    let expected = expect![[r"
+        (0xC0FFEE, 0o644, 0b1010, 0x0)
+    "]];
+    expected.assert_eq(&starlark);
+
+    let error = serde_starlark::to_string(&Hex(u32::MAX)).unwrap_err();
+    let expected = expect![
+        "unsupported integer: 4294967295, Starlark only supports up to 32-bit signed integers"
+    ];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_newtype_variant() {
+    #[derive(Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Rule {
+        RustLibrary(RustLibrary),
+        Exports((&'static str, &'static str)),
+    }
+
+    #[derive(Serialize)]
+    struct RustLibrary {
+        #[serde(flatten)]
+        common: RustCommon,
+        proc_macro: bool,
+    }
+
+    #[derive(Serialize)]
+    struct RustCommon {
+        name: &'static str,
+    }
+
+    let rules = [
+        Rule::RustLibrary(RustLibrary {
+            common: RustCommon { name: "syn" },
+            proc_macro: false,
+        }),
+        Rule::Exports(("lib.rs", "build.rs")),
+    ];
+    let starlark = serde_starlark::to_string(&rules[..]).unwrap();
+    let expected = expect![[r#"
+        [
+            rust_library(
+                name = "syn",
+                proc_macro = False,
+            ),
+            exports(
+                "lib.rs",
+                "build.rs",
+            ),
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_identifier() {
+    #[derive(Serialize)]
+    enum Visibility {
+        #[serde(rename = "PUBLIC")]
+        Public,
+    }
+
+    let value = (Identifier(Visibility::Public), Identifier("ALL_TARGETS"));
+    let starlark = serde_starlark::to_string(&value).unwrap();
+    let expected = expect![[r"
+        (PUBLIC, ALL_TARGETS)
+    "]];
+    expected.assert_eq(&starlark);
+
+    let error = serde_starlark::to_string(&Identifier(1)).unwrap_err();
+    let expected = expect!["expected a string or unit enum variant"];
+    expected.assert_eq(&error.to_string());
+
+    let starlark = serde_starlark::to_string(&Identifier("config.VERSION")).unwrap();
+    let expected = expect![[r"
+        config.VERSION
+    "]];
+    expected.assert_eq(&starlark);
+
+    let error = serde_starlark::to_string(&Identifier("VERSION + 1")).unwrap_err();
+    let expected = expect![[r#"invalid identifier: "VERSION + 1" is not a Starlark identifier"#]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_argument_name() {
+    #[derive(Serialize)]
+    #[serde(rename = "config_setting")]
+    struct ConfigSetting {
+        #[serde(rename = "if")]
+        condition: &'static str,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "alias")]
+    struct Alias {
+        #[serde(rename = "foo-bar")]
+        actual: &'static str,
+    }
+
+    let value = ConfigSetting { condition: "linux" };
+    let error = serde_starlark::to_string(&value).unwrap_err();
+    let expected =
+        expect![[r#"invalid named argument: "if" is not a valid Starlark parameter name"#]];
+    expected.assert_eq(&error.to_string());
+
+    let value = Alias { actual: "//:syn" };
+    let error = serde_starlark::to_string(&value).unwrap_err();
+    let expected =
+        expect![[r#"invalid named argument: "foo-bar" is not a valid Starlark parameter name"#]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_function_name() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust library")]
+    struct RustLibrary {
+        name: &'static str,
+    }
+
+    let value = FunctionCall::new("native.glob", (vec!["*.rs"],));
+    let starlark = serde_starlark::to_string(&value).unwrap();
+    let expected = expect![[r#"
+        native.glob(["*.rs"])
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let function = ["rust", "library"].join("_");
+    let value = FunctionCall::new(function, (vec!["syn"],));
+    let starlark = serde_starlark::to_string(&value).unwrap();
+    let expected = expect![[r#"
+        rust_library(["syn"])
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let error = serde_starlark::to_string(&FunctionCall::new("", ("syn",))).unwrap_err();
+    let expected = expect![[r#"invalid function name: "" is not a Starlark identifier"#]];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::to_string(&RustLibrary { name: "syn" }).unwrap_err();
+    let expected =
+        expect![[r#"invalid function name: "rust library" is not a Starlark identifier"#]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_dynamic_function_name() {
+    #[derive(Serialize)]
+    struct Target {
+        kind: &'static str,
+        name: &'static str,
+        srcs: Vec<&'static str>,
+    }
+
+    let targets = [
+        Target {
+            kind: "rust_library",
+            name: "syn",
+            srcs: vec!["src/lib.rs"],
+        },
+        Target {
+            kind: "rust_test",
+            name: "syn_test",
+            srcs: vec![],
+        },
+    ];
+    let calls: Vec<_> = targets
+        .iter()
+        .map(|target| FunctionCall::dynamic("kind", target))
+        .collect();
+    let starlark = serde_starlark::to_string(&Document::new(calls)).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            srcs = ["src/lib.rs"],
+        )
+
+        rust_test(
+            name = "syn_test",
+            srcs = [],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let map = BTreeMap::from([("kind", "filegroup"), ("name", "srcs")]);
+    let starlark = serde_starlark::to_string(&FunctionCall::dynamic("kind", map)).unwrap();
+    let expected = expect![[r#"
+        filegroup(
+            name = "srcs",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let call = FunctionCall::dynamic("name", &targets[0]);
+    let error = serde_starlark::to_string(&call).unwrap_err();
+    let expected = expect!["expected the function name in field `name`, which must come first"];
+    expected.assert_eq(&error.to_string());
+
+    let call = FunctionCall::dynamic("kind", ("rust_library", "syn"));
+    let error = serde_starlark::to_string(&call).unwrap_err();
+    let expected =
+        expect!["function call whose name is given by a field must have named arguments"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_struct_variant() {
+    #[derive(Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Rule {
+        RustLibrary {
+            name: &'static str,
+            srcs: &'static [&'static str],
+        },
+        Package {},
+    }
+
+    let rules = [
+        Rule::RustLibrary {
+            name: "syn",
+            srcs: &["lib.rs"],
+        },
+        Rule::Package {},
+    ];
+    let starlark = serde_starlark::to_string(&rules[..]).unwrap();
+    let expected = expect![[r#"
+        [
+            rust_library(
+                name = "syn",
+                srcs = ["lib.rs"],
+            ),
+            package(),
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_tuple_variant() {
+    #[derive(Serialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Stmt {
+        Load(&'static str, &'static str),
+    }
+
+    let stmt = Stmt::Load("@rules_rust//rust:defs.bzl", "rust_library");
+    let starlark = serde_starlark::to_string(&stmt).unwrap();
+    let expected = expect![[r#"
+        load(
+            "@rules_rust//rust:defs.bzl",
+            "rust_library",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_tagged_enum_dict() {
+    #[derive(Serialize)]
+    #[serde(tag = "kind", rename_all = "snake_case")]
+    enum Internal {
+        Archive(Archive),
+    }
+
+    #[derive(Serialize)]
+    #[serde(tag = "kind", content = "attrs", rename_all = "snake_case")]
+    enum Adjacent {
+        Local { path: &'static str },
+    }
+
+    #[derive(Serialize)]
+    struct Archive {
+        url: &'static str,
+        build_file: Label,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "Label")]
+    struct Label(&'static str);
+
+    let internal = Dict(Internal::Archive(Archive {
+        url: "https://example.com/zlib.tar.gz",
+        build_file: Label("//third_party:zlib.BUILD"),
+    }));
+    let starlark = serde_starlark::to_string(&internal).unwrap();
+    let expected = expect![[r#"
+        {
+            "kind": "archive",
+            "url": "https://example.com/zlib.tar.gz",
+            "build_file": Label("//third_party:zlib.BUILD"),
+        }
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let adjacent = Dict(Adjacent::Local { path: "/opt/zlib" });
+    let starlark = serde_starlark::to_string(&adjacent).unwrap();
+    let expected = expect![[r#"
+        {
+            "kind": "local",
+            "attrs": local(
+                path = "/opt/zlib",
+            ),
+        }
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_compact() {
+    let deps = BTreeMap::from([
+        ("@platforms//os:linux", vec![":libc", ":pthread"]),
+        ("//conditions:default", vec![]),
+    ]);
+    let select = Compact(FunctionCall::new("select", (deps,)));
+    let starlark = serde_starlark::to_string(&select).unwrap();
+    let expected = expect![[r#"
+        select({"//conditions:default": [], "@platforms//os:linux": [":libc", ":pthread"]})
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_expanded() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        srcs: Vec<&'static str>,
+        aliases: BTreeMap<&'static str, &'static str>,
+        data: (&'static str,),
+    }
+
+    let rust_library = Expanded(RustLibrary {
+        name: "syn",
+        srcs: vec!["src/lib.rs"],
+        aliases: BTreeMap::new(),
+        data: ("README.md",),
+    });
+    let starlark = serde_starlark::to_string(&rust_library).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            srcs = [
+                "src/lib.rs",
+            ],
+            aliases = {},
+            data = (
+                "README.md",
+            ),
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_spacer() {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Element {
+        Dep(LineComment<'static, &'static str>),
+        Spacer(Spacer<'static>),
+    }
+
+    let deps = [
+        Element::Spacer(Spacer::with_header("workspace")),
+        Element::Dep(LineComment::new("//common", "shared")),
+        Element::Spacer(Spacer::new()),
+        Element::Dep(LineComment::new("//util", "internal")),
+    ];
+    let starlark = serde_starlark::to_string(&deps[..]).unwrap();
+    let expected = expect![[r#"
+        [
+            # workspace
+            "//common",  # shared
+
+            "//util",  # internal
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let error = serde_starlark::to_string(&(Spacer::new(),)).unwrap_err();
+    let expected = expect!["[0]: Spacer is only supported as an element of a list"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_block_comment() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: BlockComment<'static, &'static str>,
+        srcs: Vec<BlockComment<'static, &'static str>>,
+        rustc_env: BTreeMap<&'static str, BlockComment<'static, &'static str>>,
+    }
+
+    let library = RustLibrary {
+        name: BlockComment::new("demo", "Generated target.\n\nDo not edit."),
+        srcs: vec![
+            BlockComment::new("lib.rs", "Crate root."),
+            BlockComment::new("more.rs", "Other."),
+        ],
+        rustc_env: BTreeMap::from([(
+            "CARGO_PKG_NAME",
+            BlockComment::new("demo", "From Cargo.toml"),
+        )]),
+    };
+    let starlark = serde_starlark::to_string(&library).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            # Generated target.
+            #
+            # Do not edit.
+            name = "demo",
+            srcs = [
+                # Crate root.
+                "lib.rs",
+                # Other.
+                "more.rs",
+            ],
+            rustc_env = {
+                # From Cargo.toml
+                "CARGO_PKG_NAME": "demo",
+            },
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_multiline_line_comment() {
+    let deps = [
+        LineComment::new("//common", "shared"),
+        LineComment::new("//util", "internal\nsee README"),
+    ];
+    let starlark = serde_starlark::to_string(&deps[..]).unwrap();
+    let expected = expect![[r#"
+        [
+            "//common",  # shared
+            # internal
+            # see README
+            "//util",
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_line_comment_prefix() {
+    let deps = [
+        LineComment::new("//common", "# shared"),
+        LineComment::new("//util", "##internal"),
+    ];
+    let starlark = serde_starlark::to_string(&deps[..]).unwrap();
+    let expected = expect![[r#"
+        [
+            "//common",  # shared
+            "//util",  # internal
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let error = LineComment::try_new("//util", "#internal").err().unwrap();
+    let expected = expect![[
+        r##"comment must not begin with '#', it is inserted automatically: "#internal""##
+    ]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_assignment_line_comment() {
+    let statements = [
+        LineComment::new(Assignment::new("VERSION", vec!["1.2.3"]), "from Cargo.toml"),
+        LineComment::new(
+            Assignment::new("FEATURES", vec!["default", "std"]),
+            "from Cargo.toml",
+        ),
+    ];
+    let starlark = serde_starlark::to_string(&Document::new(statements)).unwrap();
+    let expected = expect![[r#"
+        VERSION = ["1.2.3"]  # from Cargo.toml
+
+        FEATURES = [
+            "default",
+            "std",
+        ]  # from Cargo.toml
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let edition = LineComment::new(
+        Assignment::new("EDITION", LineComment::new("2021", "package.edition")),
+        "from Cargo.toml",
+    );
+    let starlark = serde_starlark::to_string(&edition).unwrap();
+    let expected = expect![[r#"
+        EDITION = "2021"  # package.edition; from Cargo.toml
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_map_key_comment() {
+    struct Select;
+
+    impl serde::Serialize for Select {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            use serde::ser::SerializeMap;
+
+            let mut map = serializer.serialize_map(Some(3))?;
+            map.serialize_entry(
+                &LineComment::new("@platforms//os:linux", "cfg(unix)"),
+                &LineComment::new("//:libc", "0.2"),
+            )?;
+            map.serialize_entry(
+                &LineComment::new("@platforms//os:windows", "cfg(windows)"),
+                &["//:windows-sys", "//:winapi"][..],
+            )?;
+            map.serialize_entry(
+                &BlockComment::new("//conditions:default", "Everything else."),
+                &Vec::<&str>::new(),
+            )?;
+            map.end()
+        }
+    }
+
+    let starlark = serde_starlark::to_string(&FunctionCall::new("select", (Select,))).unwrap();
+    let expected = expect![[r#"
+        select({
+            "@platforms//os:linux": "//:libc",  # cfg(unix); 0.2
+            "@platforms//os:windows": [  # cfg(windows)
+                "//:windows-sys",
+                "//:winapi",
+            ],
+            # Everything else.
+            "//conditions:default": [],
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_non_string_key() {
+    let ints = BTreeMap::from([(1u32, "one"), (2, "two")]);
+    let bools = BTreeMap::from([(false, "off"), (true, "on")]);
+    let tuples = BTreeMap::from([
+        ((1u32, "x86_64", true), Hex(0x1F)),
+        ((2, "aarch64", false), Hex(0)),
+    ]);
+    let starlark = serde_starlark::to_string(&(ints, bools, tuples)).unwrap();
+    let expected = expect![[r#"
+        ({
+            1: "one",
+            2: "two",
+        }, {
+            False: "off",
+            True: "on",
+        }, {
+            (1, "x86_64", True): 0x1F,
+            (2, "aarch64", False): 0x0,
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let lists = BTreeMap::from([(vec!["//:a"], 0)]);
+    let error = serde_starlark::to_string(&lists).unwrap_err();
+    let expected = expect!["unsupported dict key: a list is not hashable in Starlark"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_commented_argument() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        edition: Commented<&'static str>,
+        deps: Commented<Vec<&'static str>>,
+    }
+
+    let value = RustLibrary {
+        name: "syn",
+        edition: Commented::new("2021", String::from("from Cargo.toml")),
+        deps: Commented::new(vec!["//:proc-macro2"], "resolved\nby cargo metadata"),
+    };
+    let starlark = serde_starlark::to_string(&value).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            name = "syn",
+            edition = "2021",  # from Cargo.toml
+            # resolved
+            # by cargo metadata
+            deps = ["//:proc-macro2"],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_suppress() {
+    #[derive(Serialize)]
+    #[serde(rename = "cc_library")]
+    struct CcLibrary {
+        name: &'static str,
+        srcs: Suppress<'static, FunctionCall<'static, [&'static [&'static str]; 1]>>,
+    }
+
+    let value = [
+        Suppress::new(
+            CcLibrary {
+                name: "a",
+                srcs: Suppress::new(FunctionCall::new("glob", [&["a/*.cc"][..]]), "native-glob"),
+            },
+            "native-cc",
+        ),
+        Suppress::new(
+            CcLibrary {
+                name: "b",
+                srcs: Suppress::new(FunctionCall::new("glob", [&["b/*.cc"][..]]), "native-glob"),
+            },
+            "native-cc",
+        ),
+    ];
+    let starlark = serde_starlark::to_string(&value[..]).unwrap();
+    let expected = expect![[r#"
+        [
+            # buildifier: disable=native-cc
+            cc_library(
+                name = "a",
+                # buildifier: disable=native-glob
+                srcs = glob(["a/*.cc"]),
+            ),
+            # buildifier: disable=native-cc
+            cc_library(
+                name = "b",
+                # buildifier: disable=native-glob
+                srcs = glob(["b/*.cc"]),
+            ),
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_keep_sorted_after_spacer() {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Element {
+        List(Expanded<Vec<&'static str>>),
+        Spacer(Spacer<'static>),
+    }
+
+    let value = KeepSorted::sorted(vec![
+        Element::List(Expanded(vec!["b"])),
+        Element::List(Expanded(vec!["a"])),
+        Element::Spacer(Spacer::new()),
+        Element::List(Expanded(vec!["d", "e"])),
+        Element::List(Expanded(vec!["c"])),
+    ]);
+    let starlark = serde_starlark::to_string(&value).unwrap();
+    let expected = expect![[r#"
+        [  # keep sorted
+            [
+                "a",
+            ],
+            [
+                "b",
+            ],
+
+            [
+                "c",
+            ],
+            [
+                "d",
+                "e",
+            ],
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_keep_sorted() {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Element {
+        Dep(&'static str),
+        Commented(LineComment<'static, &'static str>),
+        Spacer(Spacer<'static>),
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        srcs: KeepSorted<Vec<&'static str>>,
+        deps: KeepSorted<Vec<Element>>,
+        data: KeepSorted<Vec<&'static str>>,
+    }
+
+    let value = RustLibrary {
+        srcs: KeepSorted::new(vec!["a.rs", "b.rs"]),
+        deps: KeepSorted::sorted(vec![
+            Element::Dep("//:b"),
+            Element::Commented(LineComment::new("//:a", "first")),
+            Element::Spacer(Spacer::with_header("third-party")),
+            Element::Dep("//third-party:z"),
+            Element::Dep("//third-party:y"),
+        ]),
+        data: KeepSorted::sorted(vec!["data.txt"]),
+    };
+    let starlark = serde_starlark::to_string(&value).unwrap();
+    let expected = expect![[r#"
+        rust_library(
+            srcs = [  # keep sorted
+                "a.rs",
+                "b.rs",
+            ],
+            deps = [  # keep sorted
+                "//:a",  # first
+                "//:b",
+
+                # third-party
+                "//third-party:y",
+                "//third-party:z",
+            ],
+            data = ["data.txt"],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_document() {
+    let statements = [
+        FunctionCall::new("package", [&[] as &[&str]]),
+        FunctionCall::new("exports_files", [&["LICENSE"][..]]),
+    ];
+    let document = Document::new(statements).banner(
+        "crate_universe",
+        "bazel run //third-party:vendor -- --repin --lockfile=third-party/Cargo.Bazel.lock",
+    );
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by crate_universe; run
+        # bazel run //third-party:vendor -- --repin --lockfile=third-party/Cargo.Bazel.lock
+        # to regenerate.
+
+        package([])
+
+        exports_files(["LICENSE"])
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_document_loads() {
+    #[derive(Serialize)]
+    #[serde(rename = "load")]
+    struct Load {
+        #[serde(rename = "")]
+        bzl: &'static str,
+        #[serde(rename = "")]
+        symbol: &'static str,
+        upstream_rust_test: &'static str,
+    }
+
+    let rust = FunctionCall::new("load", ["@rules_rust//rust:defs.bzl", "rust_library"]);
+    let skylib = FunctionCall::new("load", ["@bazel_skylib//lib:selects.bzl", "selects"]);
+    let a = FunctionCall::new("rust_library", BTreeMap::from([("name", "a")]));
+    let more_rust = FunctionCall::new(
+        "load",
+        ["@rules_rust//rust:defs.bzl", "rust_test", "rust_library"],
+    );
+    let b = FunctionCall::new("rust_test", BTreeMap::from([("name", "b")]));
+    let aliased = Load {
+        bzl: "@rules_rust//rust:defs.bzl",
+        symbol: "rust_library",
+        upstream_rust_test: "rust_test",
+    };
+    let statements: Vec<&dyn erased_serde::Serialize> =
+        vec![&rust, &skylib, &a, &more_rust, &b, &aliased];
+    let starlark = serde_starlark::to_string(&Document::new(statements)).unwrap();
+    let expected = expect![[r#"
+        load("@rules_rust//rust:defs.bzl", "rust_library", "rust_test", upstream_rust_test = "rust_test")
+
+        load(
+            "@bazel_skylib//lib:selects.bzl",
+            "selects",
+        )
+
+        rust_library(
+            name = "a",
+        )
+
+        rust_test(
+            name = "b",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_document_separators() {
+    let rust = Compact(FunctionCall::new(
+        "load",
+        ["@rules_rust//rust:defs.bzl", "rust_library"],
+    ));
+    let skylib = Compact(FunctionCall::new(
+        "load",
+        ["@bazel_skylib//lib:selects.bzl", "selects"],
+    ));
+    let license = FunctionCall::new("exports_files", [&["LICENSE"][..]]);
+    let readme = FunctionCall::new("exports_files", [&["README.md"][..]]);
+    let statements: Vec<&(dyn erased_serde::Serialize + Sync)> =
+        vec![&rust, &skylib, &license, &readme];
+    let document = Document::new(statements.clone())
+        .banner("cargo-bazel", "bazel run //:vendor")
+        .blank_lines(2)
+        .group_loads(true);
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel; run bazel run //:vendor to
+        # regenerate.
+
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+        load("@bazel_skylib//lib:selects.bzl", "selects")
+
+
+        exports_files(["LICENSE"])
+
+
+        exports_files(["README.md"])
+    "#]];
+    expected.assert_eq(&starlark);
+
+    #[cfg(feature = "rayon")]
+    assert_eq!(
+        serde_starlark::to_string_parallel(&document).unwrap(),
+        starlark,
+    );
+
+    let document = Document::new(statements).blank_lines(0);
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    let expected = expect![[r#"
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+        load("@bazel_skylib//lib:selects.bzl", "selects")
+        exports_files(["LICENSE"])
+        exports_files(["README.md"])
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_document_order() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        srcs: Vec<&'static str>,
+    }
+
+    let load = Compact(FunctionCall::new(
+        "load",
+        ["@rules_rust//rust:defs.bzl", "rust_library", "rust_test"],
+    ));
+    let version = Assignment::new("VERSION", "2.0.0");
+    let syn = RustLibrary {
+        name: "syn",
+        srcs: vec!["src/lib.rs"],
+    };
+    let syn_test = LineComment::new(
+        FunctionCall::new("rust_test", BTreeMap::from([("name", "proc_macro2_test")])),
+        "unit tests",
+    );
+    let quote = RustLibrary {
+        name: "quote",
+        srcs: vec!["src/lib.rs"],
+    };
+    let statements: Vec<&dyn erased_serde::Serialize> =
+        vec![&syn_test, &load, &syn, &version, &quote];
+
+    let document = Document::new(statements.clone()).order(StatementOrder::Name);
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    let expected = expect![[r#"
+        load("@rules_rust//rust:defs.bzl", "rust_library", "rust_test")
+
+        VERSION = "2.0.0"
+
+        rust_test(  # unit tests
+            name = "proc_macro2_test",
+        )
+
+        rust_library(
+            name = "quote",
+            srcs = ["src/lib.rs"],
+        )
+
+        rust_library(
+            name = "syn",
+            srcs = ["src/lib.rs"],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let document = Document::new(statements).order(StatementOrder::KindAndName);
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    let expected = expect![[r#"
+        load("@rules_rust//rust:defs.bzl", "rust_library", "rust_test")
+
+        VERSION = "2.0.0"
+
+        rust_library(
+            name = "quote",
+            srcs = ["src/lib.rs"],
+        )
+
+        rust_library(
+            name = "syn",
+            srcs = ["src/lib.rs"],
+        )
+
+        rust_test(  # unit tests
+            name = "proc_macro2_test",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_document_sections() {
+    let load = Compact(FunctionCall::new(
+        "load",
+        ["@rules_rust//rust:defs.bzl", "rust_library"],
+    ));
+    let syn = Section::new(
+        FunctionCall::new("alias", BTreeMap::from([("name", "syn")])),
+        "direct dependencies",
+    );
+    let unicode_ident = Section::new(
+        FunctionCall::new("alias", BTreeMap::from([("name", "unicode-ident")])),
+        "transitive dependencies",
+    );
+    let version = Section::new(
+        Assignment::new("VERSION", "2.0.0"),
+        "transitive dependencies",
+    );
+    let quote = Section::new(
+        LineComment::new(
+            FunctionCall::new("alias", BTreeMap::from([("name", "quote")])),
+            "1.0.35",
+        ),
+        "direct dependencies",
+    );
+    let exports = FunctionCall::new("exports_files", [&["LICENSE"][..]]);
+    let statements: Vec<&dyn erased_serde::Serialize> =
+        vec![&syn, &unicode_ident, &load, &version, &quote, &exports];
+
+    let document = Document::new(statements)
+        .order(StatementOrder::Name)
+        .section_order(&["transitive dependencies", "direct dependencies"]);
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    let expected = expect![[r#"
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        exports_files(["LICENSE"])
+
+        # ==== transitive dependencies ====
+
+        VERSION = "2.0.0"
+
+        alias(
+            name = "unicode-ident",
+        )
+
+        # ==== direct dependencies ====
+
+        alias(  # 1.0.35
+            name = "quote",
+        )
+
+        alias(
+            name = "syn",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_wrapper_macro() {
+    let mut defaults = BTreeMap::new();
+    defaults.insert("tags", vec!["manual"]);
+    defaults.insert("deps", vec!["//third-party:anyhow", "//third-party:serde"]);
+    let statements = [
+        WrapperMacro::new("manual_rust_test", "rust_test", Some(defaults)),
+        WrapperMacro::new("plain_rust_test", "rust_test", None),
+    ];
+    let starlark = serde_starlark::to_string(&Document::new(statements)).unwrap();
+    let expected = expect![[r#"
+        def manual_rust_test(name, **kwargs):
+            kwargs.setdefault("deps", [
+                "//third-party:anyhow",
+                "//third-party:serde",
+            ])
+            kwargs.setdefault("tags", ["manual"])
+            rust_test(
+                name = name,
+                **kwargs,
+            )
+
+        def plain_rust_test(name, **kwargs):
+            rust_test(
+                name = name,
+                **kwargs,
+            )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let wrapper = WrapperMacro::new("rust-test", "rust_test", ());
+    let error = serde_starlark::to_string(&wrapper).unwrap_err();
+    expect![[r#"invalid identifier: "rust-test" is not a Starlark identifier"#]]
+        .assert_eq(&error.to_string());
+
+    let wrapper = WrapperMacro::new("rust_test2", "rust_test", ["manual"]);
+    let error = serde_starlark::to_string(&wrapper).unwrap_err();
+    expect!["unsupported macro defaults type, expected a map or struct"]
+        .assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_constants() {
+    let mut crates = BTreeMap::new();
+    crates.insert("anyhow", "1.0.75");
+    crates.insert("serde", "1.0.188");
+    let mut constants = Constants::new();
+    let insert = |constants: &mut Constants<_>, identifier, value| {
+        constants.insert(identifier, Dict(value)).unwrap();
+    };
+    insert(&mut constants, "_NAME", BTreeMap::from([("name", "serde")]));
+    insert(&mut constants, "_CRATES", crates);
+    insert(
+        &mut constants,
+        "_EDITION",
+        BTreeMap::from([("edition", "2018")]),
+    );
+    insert(&mut constants, "_EMPTY", BTreeMap::new());
+    let starlark = serde_starlark::to_string(&constants).unwrap();
+    let expected = expect![[r#"
+        _NAME = {
+            "name": "serde",
+        }
+
+        _CRATES = {
+            "anyhow": "1.0.75",
+            "serde": "1.0.188",
+        }
+
+        _EDITION = {
+            "edition": "2018",
+        }
+
+        _EMPTY = {}
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let constants = Constants::from_map([("A", 1), ("B", 2), ("C", 3)]).unwrap();
+    let starlark = serde_starlark::to_string(&constants).unwrap();
+    let expected = expect![[r"
+        A = 1
+        B = 2
+        C = 3
+    "]];
+    expected.assert_eq(&starlark);
+
+    let error = Constants::from_map([("A", 1), ("A", 2)]).err().unwrap();
+    expect!["duplicate assignment to A"].assert_eq(&error.to_string());
+
+    let error = Constants::from_map([("crates-io", 1)]).err().unwrap();
+    expect![[r#"invalid identifier: "crates-io" is not a Starlark identifier"#]]
+        .assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_select() {
+    let mut select = Select::new();
+    select.insert("//conditions:default", 0);
+    select.insert(":opt", 3);
+    let starlark = serde_starlark::to_string(&select).unwrap();
+    let expected = expect![[r#"
+        select({
+            ":opt": 3,
+            "//conditions:default": 0,
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let mut select = Select::new();
+    select.insert_or([":a", ":b"], vec!["ab"]);
+    select.insert_or([":c"], vec!["c"]);
+    assert_eq!(select.insert_or([":a", ":b"], vec!["AB"]), Some(vec!["ab"]));
+    let starlark = serde_starlark::to_string(&select).unwrap();
+    let expected = expect![[r#"
+        selects.with_or({
+            (":a", ":b"): ["AB"],
+            ":c": ["c"],
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_select_arithmetic() {
+    let mut deps = Select::new();
+    deps.base(vec!["//:base"]);
+    let starlark = serde_starlark::to_string(&deps).unwrap();
+    let expected = expect![[r#"
+        ["//:base"]
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let mut unix = Select::new();
+    unix.insert_or([":linux", ":macos"], vec!["//:unix"]);
+    deps.merge(unix);
+    let mut windows = Select::new();
+    windows.base(vec!["//:extra"]);
+    windows.insert(":windows", vec!["//:windows"]);
+    deps.merge(windows);
+    let starlark = serde_starlark::to_string(&deps).unwrap();
+    let expected = expect![[r#"
+        [
+            "//:base",
+            "//:extra",
+        ] + selects.with_or({
+            (":linux", ":macos"): ["//:unix"],
+            ":windows": ["//:windows"],
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let mut edition = Select::new();
+    edition.insert(":legacy", "2018");
+    let mut other = Select::new();
+    other.insert(":legacy", "2018");
+    other.insert("//conditions:default", "2021");
+    edition.union(other).unwrap();
+    let starlark = serde_starlark::to_string(&edition).unwrap();
+    let expected = expect![[r#"
+        select({
+            ":legacy": "2018",
+            "//conditions:default": "2021",
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let mut other = Select::new();
+    other.insert_or([":a", ":b"], "2015");
+    edition.insert_or([":a", ":b"], "2018");
+    let error = edition.union(other).unwrap_err();
+    expect!["conflicting values in select for condition :a or :b"].assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_select_condition_prefix() {
+    let mut deps = Select::new();
+    deps.insert_or(["aarch64-apple-darwin", ":custom"], vec![":mac"]);
+    deps.insert("@platforms//os:windows", vec![":windows"]);
+    deps.condition_prefix("@rules_rust//rust/platform:");
+    let starlark = serde_starlark::to_string(&deps).unwrap();
+    let expected = expect![[r#"
+        selects.with_or({
+            "@platforms//os:windows": [":windows"],
+            ("@rules_rust//rust/platform:aarch64-apple-darwin", ":custom"): [":mac"],
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+
+    deps.condition_prefix("//platforms:");
+    let starlark = serde_starlark::to_string(&deps).unwrap();
+    let expected = expect![[r#"
+        selects.with_or({
+            ("//platforms:aarch64-apple-darwin", ":custom"): [":mac"],
+            "@platforms//os:windows": [":windows"],
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_select_branch_order() {
+    let mut select = Select::new();
+    select.insert(":long_condition", 1);
+    select.insert("//conditions:default", 0);
+    select.insert(":short", 2);
+    select.insert(":long_condition", 3);
+
+    select.branch_order(BranchOrder::Insertion);
+    let starlark = serde_starlark::to_string(&Compact(&select)).unwrap();
+    let expected = expect![[r#"
+        select({":long_condition": 3, "//conditions:default": 0, ":short": 2})
+    "#]];
+    expected.assert_eq(&starlark);
+
+    select.branch_order(BranchOrder::By(|a, b| a[0].len().cmp(&b[0].len())));
+    let starlark = serde_starlark::to_string(&Compact(&select)).unwrap();
+    let expected = expect![[r#"
+        select({":short": 2, ":long_condition": 3, "//conditions:default": 0})
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_nested_select() {
+    #[derive(Serialize)]
+    #[serde(rename = "select")]
+    struct UserSelect(BTreeMap<&'static str, Select<Vec<&'static str>>>);
+
+    let mut inner = Select::new();
+    inner.insert(":opt", vec!["-O"]);
+    let mut outer = Select::new();
+    outer.insert(":linux", inner.clone());
+    let error = serde_starlark::to_string(&outer).unwrap_err();
+    expect![[r#"[0][":linux"]: select cannot be used within another select"#]]
+        .assert_eq(&error.to_string());
+
+    let mut branches = BTreeMap::new();
+    branches.insert("//conditions:default", inner.clone());
+    let error = serde_starlark::to_string(&UserSelect(branches)).unwrap_err();
+    expect![[r#"[0]["//conditions:default"]: select cannot be used within another select"#]]
+        .assert_eq(&error.to_string());
+
+    // Selects side by side are fine.
+    let starlark = serde_starlark::to_string(&Compact([&inner, &inner])).unwrap();
+    let expected = expect![[r#"
+        (select({":opt": ["-O"]}), select({":opt": ["-O"]}))
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_to_string_parallel() {
+    let statements: Vec<_> = (0..100)
+        .map(|i| FunctionCall::new("exports_files", [vec![format!("file_{}.rs", i)]]))
+        .collect();
+    let document = Document::new(statements).banner("cargo-bazel", "bazel run //:vendor");
+    let starlark = serde_starlark::to_string_parallel(&document).unwrap();
+    assert_eq!(starlark, serde_starlark::to_string(&document).unwrap());
+
+    let statements = vec![vec![1, 2], vec![3, i64::MAX], vec![i64::MIN]];
+    let document = Document::new(statements);
+    let error = serde_starlark::to_string_parallel(&document).unwrap_err();
+    let expected = expect!["[1][1]: unsupported integer: 9223372036854775807, Starlark only supports up to 32-bit signed integers"];
+    expected.assert_eq(&error.to_string());
+    assert_eq!(
+        error.to_string(),
+        serde_starlark::to_string(&document)
+            .unwrap_err()
+            .to_string(),
+    );
+}
+
+#[test]
+fn test_patch() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        deps: Vec<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "alias")]
+    struct Alias {
+        name: &'static str,
+        actual: &'static str,
+    }
+
+    let previous = concat!(
+        "# DO NOT EDIT\n",
+        "\n",
+        "alias(name = \"syn\", actual = \":syn-2\")\n",
+        "\n",
+        "rust_library(\n",
+        "    name = \"syn-2\",\n",
+        "    deps = [\n",
+        "        \":proc-macro2\",\n",
+        "\n",
+        "        \":quote\",\n",
+        "    ],\n",
+        ")\n",
+        "\n",
+        "exports_files([\"LICENSE\"])\n",
+    );
+    let alias = Alias {
+        name: "syn",
+        actual: ":syn-3",
+    };
+    let patched = serde_starlark::patch(previous, [alias]).unwrap();
+    let library = RustLibrary {
+        name: "syn-3",
+        deps: vec![":proc-macro2", ":quote"],
+    };
+    let patched = serde_starlark::patch(&patched, [library]).unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT
+
+        alias(
+            name = "syn",
+            actual = ":syn-3",
+        )
+
+        rust_library(
+            name = "syn-2",
+            deps = [
+                ":proc-macro2",
+
+                ":quote",
+            ],
+        )
+
+        exports_files(["LICENSE"])
+
+        rust_library(
+            name = "syn-3",
+            deps = [
+                ":proc-macro2",
+                ":quote",
+            ],
+        )
+    "#]];
+    expected.assert_eq(&patched);
+}
+
+#[test]
+fn test_patch_statements() {
+    #[derive(Serialize)]
+    #[serde(rename = "alias")]
+    struct Alias {
+        name: &'static str,
+        actual: &'static str,
+    }
+
+    let previous = concat!(
+        "VERSION = 1\n",
+        "alias(name = 'syn', actual = ':syn-2')\n",
+        "DOC = \"\"\"\n",
+        "\n",
+        "    name = \"syn\",\n",
+        "\"\"\"\n",
+        "genrule(name = \"proc-macro2\")  # keep\n",
+    );
+    let alias = Alias {
+        name: "syn",
+        actual: ":syn-3",
+    };
+    let patched = serde_starlark::patch(previous, [alias]).unwrap();
+    let expected = expect![[r#"
+        VERSION = 1
+        alias(
+            name = "syn",
+            actual = ":syn-3",
+        )
+        DOC = """
+
+            name = "syn",
+        """
+        genrule(name = "proc-macro2")  # keep
+    "#]];
+    expected.assert_eq(&patched);
+}
+
+#[test]
+fn test_render_template() {
+    #[derive(Serialize)]
+    #[serde(rename = "alias")]
+    struct Alias {
+        name: &'static str,
+        actual: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Values {
+        aliases: Document<'static, Vec<Alias>>,
+        name: LineComment<'static, &'static str>,
+        deps: Vec<&'static str>,
+        unused: bool,
+    }
+
+    let template = concat!(
+        "# {aliases} in a comment is not a placeholder.\n",
+        "\n",
+        "{aliases}\n",
+        "\n",
+        "def crate_deps(features = {}):\n",
+        "    deps = {deps}\n",
+        "    return deps + [\"{}-sys\".format({name})]\n",
+    );
+    let values = Values {
+        aliases: Document::new(vec![
+            Alias {
+                name: "syn",
+                actual: ":syn-2",
+            },
+            Alias {
+                name: "quote",
+                actual: ":quote-1",
+            },
+        ]),
+        name: LineComment::new("syn", "crate name"),
+        deps: vec![":proc-macro2", ":quote"],
+        unused: true,
+    };
+    let rendered = serde_starlark::render_template(template, &values).unwrap();
+    let expected = expect![[r#"
+        # {aliases} in a comment is not a placeholder.
+
+        alias(
+            name = "syn",
+            actual = ":syn-2",
+        )
+
+        alias(
+            name = "quote",
+            actual = ":quote-1",
+        )
+
+        def crate_deps(features = {}):
+            deps = [
+                ":proc-macro2",
+                ":quote",
+            ]
+            return deps + ["{}-sys".format("syn")]  # crate name
+    "#]];
+    expected.assert_eq(&rendered);
+
+    let mut values = BTreeMap::new();
+    values.insert("deps", vec![":proc-macro2"]);
+    let error = serde_starlark::render_template(template, &values).unwrap_err();
+    let expected = expect!["no value for template placeholder {aliases}"];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::render_template("x = {x}\n", &[1]).unwrap_err();
+    let expected = expect!["template values must be a map or struct"];
+    expected.assert_eq(&error.to_string());
+
+    let mut values = BTreeMap::new();
+    values.insert("x", u64::MAX);
+    let error = serde_starlark::render_template("x = {x}\n", &values).unwrap_err();
+    let expected = expect!["[x]: unsupported integer: 18446744073709551615, Starlark only supports up to 32-bit signed integers"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_to_shards() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        version: Identifier<&'static str>,
+        crate_features: Vec<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Statement {
+        Load(Compact<FunctionCall<'static, [&'static str; 2]>>),
+        Constant(serde_starlark::Assignment<'static, &'static str>),
+        Library(RustLibrary),
+    }
+
+    let statements = vec![
+        Statement::Load(Compact(FunctionCall::new(
+            "load",
+            ["@rules_rust//rust:defs.bzl", "rust_library"],
+        ))),
+        Statement::Constant(serde_starlark::Assignment::new("SYN_VERSION", "2.0.0")),
+        Statement::Constant(serde_starlark::Assignment::new("QUOTE_VERSION", "1.0.0")),
+        Statement::Library(RustLibrary {
+            name: "syn",
+            version: Identifier("SYN_VERSION"),
+            crate_features: vec!["QUOTE_VERSION"],
+        }),
+    ];
+    let document = Document::new(statements).banner("cargo-bazel", "bazel run //:vendor");
+    let sharding = Sharding::new("defs").max_statements(2);
+    let shards = serde_starlark::to_shards(&document, &sharding).unwrap();
+    assert_eq!(shards.len(), 2);
+    assert_eq!(shards[0].file_name, "defs_0.bzl");
+    assert_eq!(shards[1].file_name, "defs_1.bzl");
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel; run bazel run //:vendor to
+        # regenerate.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        load(":defs_0.bzl", "SYN_VERSION")
+
+        rust_library(
+            name = "syn",
+            version = SYN_VERSION,
+            crate_features = ["QUOTE_VERSION"],
+        )
+    "#]];
+    expected.assert_eq(&shards[1].contents);
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn test_yaml_to_string() {
+    let yaml = concat!(
+        "PLATFORMS: &platforms\n",
+        "  - linux\n",
+        "  - macos\n",
+        "TARGETS:\n",
+        "  x86_64: *platforms\n",
+        "  aarch64: [~, null]\n",
+        "EXIT_CODES:\n",
+        "  0: success\n",
+        "  -1: ~\n",
+        "RELEASE: !Tag true\n",
+    );
+    let expected = expect![[r#"
+        PLATFORMS = [
+            "linux",
+            "macos",
+        ]
+
+        TARGETS = {
+            "x86_64": [
+                "linux",
+                "macos",
+            ],
+            "aarch64": [
+                None,
+                None,
+            ],
+        }
+
+        EXIT_CODES = {
+            0: "success",
+            -1: None,
+        }
+
+        RELEASE = True
+    "#]];
+    expected.assert_eq(&serde_starlark::yaml_to_string(yaml).unwrap());
+
+    let error = serde_starlark::yaml_to_string("- linux\n- macos\n").unwrap_err();
+    let expected = expect!["expected a mapping at the top level of the YAML"];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::yaml_to_string("1: one\n").unwrap_err();
+    let expected = expect![[r#"invalid identifier: "1" is not a Starlark identifier"#]];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::yaml_to_string("a: [b\n").unwrap_err();
+    let expected = expect!["invalid YAML: did not find expected ',' or ']' at line 2 column 1, while parsing a flow sequence at line 1 column 4"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn test_toml_to_string() {
+    let toml = concat!(
+        "EDITION = 2021\n",
+        "\n",
+        "[METADATA.bazel]\n",
+        "gen_build_script = false\n",
+        "published = 1979-05-27T07:32:00Z\n",
+        "\n",
+        "[[METADATA.targets]]\n",
+        "triple = \"x86_64-unknown-linux-gnu\"\n",
+        "rustflags = []\n",
+    );
+    let expected = expect![[r#"
+        EDITION = 2021
+
+        METADATA = {
+            "bazel": {
+                "gen_build_script": False,
+                "published": "1979-05-27T07:32:00Z",
+            },
+            "targets": [{
+                "triple": "x86_64-unknown-linux-gnu",
+                "rustflags": [],
+            }],
+        }
+    "#]];
+    expected.assert_eq(&serde_starlark::toml_to_string(toml).unwrap());
+
+    let error = serde_starlark::toml_to_string("SEED = 4294967296\n").unwrap_err();
+    let expected = expect![
+        "[0]: unsupported integer: 4294967296, Starlark only supports up to 32-bit signed integers"
+    ];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::toml_to_string("build-dependencies = {}\n").unwrap_err();
+    let expected =
+        expect![[r#"invalid identifier: "build-dependencies" is not a Starlark identifier"#]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_schema_check() {
+    use serde_starlark::schema::{Attribute, AttributeType, Rule, Schema};
+
+    let attribute = |name: &str, kind, mandatory| Attribute {
+        name: name.to_owned(),
+        doc: String::new(),
+        kind,
+        mandatory,
+        default: None,
+    };
+    let schema = Schema {
+        rules: vec![Rule {
+            name: "rust_library".to_owned(),
+            doc: String::new(),
+            attributes: vec![
+                attribute("name", AttributeType::Name, true),
+                attribute("srcs", AttributeType::LabelList, true),
+                attribute("deps", AttributeType::LabelList, false),
+            ],
+        }],
+    };
+
+    let build = r#"load("@rules_rust//rust:defs.bzl", "rust_library")
+
+rust_library(
+    name = "syn",
+    srcs = glob(["src/**/*.rs"]),
+)
+
+rust_library(
+    name = "quote",
+    **COMMON_ATTRS
+)
+
+filegroup(
+    name = "srcs/all",
+)
+
+[rust_library(name = name) for name in NAMES]
+"#;
+    schema.check(build).unwrap();
+
+    let check = |build: &str| schema.check(build).unwrap_err().to_string();
+
+    let expected = expect![[
+        r#"rust_library target "syn" is missing mandatory attribute `srcs` at line 1 column 1"#
+    ]];
+    expected.assert_eq(&check(
+        "rust_library(\n    name = \"syn\",\n    deps = [],\n)\n",
+    ));
+
+    let expected =
+        expect!["rust_library target is missing mandatory attribute `name` at line 1 column 1"];
+    expected.assert_eq(&check("rust_library(srcs = [])\n"));
+
+    let expected =
+        expect![[r#"invalid target name "": target names must not be empty at line 1 column 18"#]];
+    expected.assert_eq(&check("filegroup(name = \"\")\n"));
+
+    let expected = expect![[
+        r#"invalid target name "a:b": target names may only contain letters, digits, and certain punctuation at line 1 column 18"#
+    ]];
+    expected.assert_eq(&check("filegroup(name = \"a:b\")\n"));
+
+    let expected = expect![[
+        r#"invalid target name "srcs/": target names must not begin or end with `/`, or contain `//` at line 1 column 18"#
+    ]];
+    expected.assert_eq(&check("filegroup(name = \"srcs/\")\n"));
+
+    let expected = expect![[
+        r#"invalid target name "../srcs": target names must not contain a `.` or `..` path segment at line 1 column 18"#
+    ]];
+    expected.assert_eq(&check("filegroup(name = \"../srcs\")\n"));
+}
+
+#[cfg(feature = "stardoc")]
+#[test]
+fn test_schema_from_stardoc_json() {
+    use serde_starlark::schema::Schema;
+
+    let json = r#"{
+      "file": "//rust:defs.bzl",
+      "ruleInfo": [
+        {
+          "ruleName": "rust_binary",
+          "docString": "Builds a Rust binary.\n\nThe crate root is `src/main.rs`.",
+          "attribute": [
+            {"name": "name", "docString": "A unique name for this target.", "type": "NAME", "mandatory": true},
+            {"name": "crate_root", "type": "LABEL", "defaultValue": "None"},
+            {"name": "deps", "type": "LABEL_LIST", "defaultValue": "[]"},
+            {"name": "rustc_env", "type": "STRING_DICT", "defaultValue": "{}"},
+            {"name": "type", "type": "STRING", "mandatory": true},
+            {"name": "crate", "type": "LABEL"},
+            {"name": "opt_level", "type": "INT", "defaultValue": "0"},
+            {"name": "stamp", "type": "BOOLEAN", "defaultValue": "False"},
+            {"name": "lto", "type": "BOOLEAN", "defaultValue": "True"},
+            {"name": "features", "type": "STRING_LIST", "defaultValue": "[\"std\"]"},
+            {"name": "aspect_hints", "type": "UNKNOWN"}
+          ]
+        },
+        {
+          "rule_name": "_rust_toolchain_impl",
+          "attribute": [{"name": "name", "type": "NAME", "mandatory": true}]
+        },
+        {
+          "rule_name": "rust_test",
+          "attribute": [
+            {"name": "name", "type": "NAME", "mandatory": true},
+            {"name": "env", "type": "STRING_LIST_DICT", "default_value": "{}"}
+          ]
+        }
+      ]
+    }"#;
+    let schema = Schema::from_stardoc_json(json).unwrap();
+    let expected = expect![[r#"
+        // Generated from the documentation of Starlark rules. Do not edit.
+
+        use serde::Serialize;
+        use std::collections::BTreeMap;
+
+        /// Builds a Rust binary.
+        ///
+        /// The crate root is `src/main.rs`.
+        #[derive(Serialize, Clone, Debug, Default)]
+        #[serde(rename = "rust_binary")]
+        pub struct RustBinary {
+            /// A unique name for this target.
+            pub name: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub crate_root: Option<String>,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            pub deps: Vec<String>,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            pub rustc_env: BTreeMap<String, String>,
+            pub r#type: String,
+            #[serde(rename = "crate", skip_serializing_if = "Option::is_none")]
+            pub crate_: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub opt_level: Option<i64>,
+            #[serde(skip_serializing_if = "std::ops::Not::not")]
+            pub stamp: bool,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub lto: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub features: Option<Vec<String>>,
+            // `aspect_hints` has a type that is not known.
+        }
+
+        #[derive(Serialize, Clone, Debug, Default)]
+        #[serde(rename = "rust_test")]
+        pub struct RustTest {
+            pub name: String,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            pub env: BTreeMap<String, Vec<String>>,
+        }
+    "#]];
+    expected.assert_eq(&schema.to_rust());
+
+    let error = Schema::from_stardoc_json("[]").unwrap_err();
+    let expected = expect!["invalid Stardoc JSON: expected an object at the top level"];
+    expected.assert_eq(&error.to_string());
+
+    let error = Schema::from_stardoc_json(
+        r#"{"ruleInfo": [{"ruleName": "x", "attribute": [{"name": "not valid"}]}]}"#,
+    )
+    .unwrap_err();
+    let expected = expect![[
+        r#"invalid Stardoc JSON: expected an identifier as the name of an attribute, found "not valid""#
+    ]];
+    expected.assert_eq(&error.to_string());
+
+    let error = Schema::from_stardoc_json("{").unwrap_err();
+    let expected = expect!["invalid Stardoc JSON: EOF while parsing an object at line 1 column 1"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[cfg(feature = "starlark-interop")]
+#[test]
+fn test_evaluated() {
+    use serde_starlark::{Config, Evaluated};
+    use starlark::environment::{GlobalsBuilder, LibraryExtension, Module};
+    use starlark::eval::Evaluator;
+    use starlark::syntax::{AstModule, Dialect};
+
+    let source = concat!(
+        "CRATE = struct(\n",
+        "    name = 'syn',\n",
+        "    edition = 2021,\n",
+        "    opt_level = 2.5,\n",
+        "    license = None,\n",
+        "    features = ['full'] + ['visit'],\n",
+        ")\n",
+        "SEED = 1 << 40\n",
+        "def helper():\n",
+        "    pass\n",
+    );
+    let ast = AstModule::parse("crate.bzl", source.to_owned(), &Dialect::Extended).unwrap();
+    let globals = GlobalsBuilder::extended_by(&[LibraryExtension::StructType]).build();
+    let module = Module::new();
+    let mut eval = Evaluator::new(&module);
+    eval.eval_module(ast, &globals).unwrap();
+
+    let mut config = Config::new();
+    config.floats(true);
+    let krate = Evaluated(module.get("CRATE").unwrap());
+    let expected = expect![[r#"
+        struct(
+            name = "syn",
+            edition = 2021,
+            opt_level = 2.5,
+            license = None,
+            features = [
+                "full",
+                "visit",
+            ],
+        )
+    "#]];
+    expected.assert_eq(&config.to_string(&krate).unwrap());
+
+    let seed = Evaluated(module.get("SEED").unwrap());
+    let error = config.to_string(&seed).unwrap_err();
+    let expected = expect![
+        "unsupported integer: 1099511627776, Starlark only supports up to 32-bit signed integers"
+    ];
+    expected.assert_eq(&error.to_string());
+
+    let helper = Evaluated(module.get("helper").unwrap());
+    let error = config.to_string(&helper).unwrap_err();
+    let expected = expect!["unsupported starlark-rust value of type function: crate.bzl.helper"];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_to_shards_names() {
+    #[derive(Serialize)]
+    struct Defaults {
+        deps: Identifier<&'static str>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Statement {
+        Load(BlockComment<'static, Compact<FunctionCall<'static, [&'static str; 2]>>>),
+        Constant(Assignment<'static, Vec<&'static str>>),
+        Macro(WrapperMacro<'static, Defaults>),
+    }
+
+    let load = Compact(FunctionCall::new(
+        "load",
+        ["@rules_rust//rust:defs.bzl", "rust_library"],
+    ));
+    let statements = vec![
+        Statement::Load(BlockComment::new(load, "Rules.")),
+        Statement::Constant(Assignment::new("COMMON_DEPS", vec![":a"])),
+        Statement::Macro(WrapperMacro::new(
+            "strict_rust_library",
+            "rust_library",
+            Defaults {
+                deps: Identifier("COMMON_DEPS"),
+            },
+        )),
+    ];
+    let document = Document::new(statements);
+    let sharding = Sharding::new("defs").max_statements(1);
+    let shards = serde_starlark::to_shards(&document, &sharding).unwrap();
+    let expected = expect![[r#"
+        # Rules.
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        load(":defs_0.bzl", "COMMON_DEPS")
+
+        def strict_rust_library(name, **kwargs):
+            kwargs.setdefault("deps", COMMON_DEPS)
+            rust_library(
+                name = name,
+                **kwargs,
+            )
+    "#]];
+    expected.assert_eq(&shards[1].contents);
+
+    let statements = [
+        Assignment::new("_PRIVATE", Identifier("True")),
+        Assignment::new("PUBLIC", Identifier("_PRIVATE")),
+    ];
+    let document = Document::new(statements);
+    let error = serde_starlark::to_shards(&document, &sharding).unwrap_err();
+    let expected = expect![[
+        r#""_PRIVATE" is private to the shard that assigns it and cannot be loaded into another"#
+    ]];
+    expected.assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_to_shards_comprehension() {
+    let statements = [
+        Assignment::new("DOCS", Raw("[\"README\"]".to_owned())),
+        Assignment::new("F", Raw("\"LICENSE\"".to_owned())),
+        Assignment::new("SRCS", Raw("[F + \".md\" for F in DOCS]".to_owned())),
+    ];
+    let document = Document::new(statements);
+    let sharding = Sharding::new("defs").max_statements(2);
+    let shards = serde_starlark::to_shards(&document, &sharding).unwrap();
+    let expected = expect![[r#"
+        load(":defs_0.bzl", "DOCS")
+
+        SRCS = [F + ".md" for F in DOCS]
+    "#]];
+    expected.assert_eq(&shards[1].contents);
+}
+
+#[test]
+fn test_to_writer() {
+    // Records each write separately, to observe when the serializer flushes.
+    struct Writes(Vec<String>);
+
+    impl io::Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let statements = [
+        FunctionCall::new("exports_files", [&["LICENSE"][..]]),
+        FunctionCall::new("exports_files", [&["README.md", "Cargo.toml"][..]]),
+    ];
+    let document = Document::new(statements);
+    let mut writes = Writes(Vec::new());
+    serde_starlark::to_writer(&mut writes, &document).unwrap();
+    let expected = expect![[r#"
+        [
+            "exports_files([\"LICENSE\"])\n\n",
+            "exports_files([\n    \"README.md\",\n    \"Cargo.toml\",\n])\n",
+        ]
+    "#]];
+    expected.assert_debug_eq(&writes.0);
+
+    let mut output = Vec::new();
+    serde_starlark::to_writer(&mut output, &document).unwrap();
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), starlark);
+
+    // Anything other than a top-level Document is written in one piece.
+    let mut writes = Writes(Vec::new());
+    let call = FunctionCall::new("exports_files", [&["LICENSE", "README.md"][..]]);
+    serde_starlark::to_writer(&mut writes, &call).unwrap();
+    assert_eq!(writes.0.len(), 1);
+}
+
+#[test]
+fn test_display() {
+    // Records each write separately, to observe when the serializer flushes.
+    struct Writes(Vec<String>);
+
+    impl fmt::Write for Writes {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.push(s.to_owned());
+            Ok(())
+        }
+    }
+
+    let statements = [
+        FunctionCall::new("exports_files", [&["LICENSE"][..]]),
+        FunctionCall::new("exports_files", [&["README.md", "Cargo.toml"][..]]),
+    ];
+    let document = Document::new(statements);
+    let mut writes = Writes(Vec::new());
+    write!(writes, "{}", serde_starlark::display(&document)).unwrap();
+    let expected = expect![[r#"
+        [
+            "exports_files([\"LICENSE\"])\n\n",
+            "exports_files([\n    \"README.md\",\n    \"Cargo.toml\",\n])\n",
+        ]
+    "#]];
+    expected.assert_debug_eq(&writes.0);
+
+    let starlark = serde_starlark::to_string(&document).unwrap();
+    assert_eq!(serde_starlark::display(&document).to_string(), starlark);
+
+    let mut output = String::new();
+    let result = write!(output, "{}", serde_starlark::display(&i64::MAX));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_slash_path() {
+    let srcs = vec![
+        SlashPath(PathBuf::from("src\\lib.rs")),
+        SlashPath(PathBuf::from("src/parse.rs")),
+    ];
+    let starlark = serde_starlark::to_string(&srcs).unwrap();
+    let expected = expect![[r#"
+        [
+            "src/lib.rs",
+            "src/parse.rs",
+        ]
+    "#]];
+    expected.assert_eq(&starlark);
+
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt as _;
+
+        let path = SlashPath(OsStr::from_bytes(b"src/\xFF.rs"));
+        let error = serde_starlark::to_string(&path).unwrap_err();
+        let expected = expect!["path is not valid UTF-8: src/�.rs"];
+        expected.assert_eq(&error.to_string());
+    }
+}
+
+#[test]
+fn test_multiline_array() {
+    let deps = BTreeMap::from([
+        (
+            "@rules_rust//rust/platform:x86_64-pc-windows-msvc",
+            MultilineArray(BTreeSet::from([Commented::new(
+                "//third-party/rust:windows-sys",
+                "cfg(windows)",
+            )])),
+        ),
+        (
+            "@rules_rust//rust/platform:x86_64-unknown-linux-gnu",
+            MultilineArray(BTreeSet::from([
+                Commented::new("//third-party/rust:libc", "cfg(unix)"),
+                Commented::new("//third-party/rust:libc", "cfg(target_os = \"wasi\")"),
+            ])),
+        ),
+        ("//conditions:default", MultilineArray(BTreeSet::new())),
+    ]);
+    let select = FunctionCall::new("select", (deps,));
+    let starlark = serde_starlark::to_string(&select).unwrap();
+    let expected = expect![[r#"
+        select({
+            "//conditions:default": [],
+            "@rules_rust//rust/platform:x86_64-pc-windows-msvc": [
+                "//third-party/rust:windows-sys",  # cfg(windows)
+            ],
+            "@rules_rust//rust/platform:x86_64-unknown-linux-gnu": [
+                "//third-party/rust:libc",  # cfg(target_os = "wasi")
+                "//third-party/rust:libc",  # cfg(unix)
+            ],
+        })
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_ser_helpers() {
+    #[derive(Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    enum LinkStyle {
+        StaticLibrary,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "cc_library")]
+    struct CcLibrary {
+        name: &'static str,
+        #[serde(serialize_with = "ser_helpers::identifier")]
+        link_style: LinkStyle,
+        #[serde(serialize_with = "ser_helpers::oneline_list")]
+        srcs: BTreeSet<&'static str>,
+        #[serde(serialize_with = "ser_helpers::multiline_list")]
+        deps: Vec<&'static str>,
+    }
+
+    let cc_library = CcLibrary {
+        name: "zlib",
+        link_style: LinkStyle::StaticLibrary,
+        srcs: BTreeSet::from(["deflate.c", "adler32.c", "crc32.c"]),
+        deps: vec![":zconf"],
+    };
+    let starlark = serde_starlark::to_string(&cc_library).unwrap();
+    let expected = expect![[r#"
+        cc_library(
+            name = "zlib",
+            link_style = STATIC_LIBRARY,
+            srcs = ["adler32.c", "crc32.c", "deflate.c"],
+            deps = [
+                ":zconf",
+            ],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_error_path() {
+    #[derive(Serialize)]
+    #[serde(rename = "rust_library")]
+    struct RustLibrary {
+        name: &'static str,
+        rustc_flags: FunctionCall<'static, (BTreeMap<&'static str, Vec<i64>>,)>,
+    }
+
+    let mut select = BTreeMap::new();
+    select.insert("//conditions:default", vec![0]);
+    select.insert("@platforms//os:linux", vec![1, i64::MAX]);
+    let value = RustLibrary {
+        name: "syn",
+        rustc_flags: FunctionCall::new("select", (select,)),
+    };
+    let error = serde_starlark::to_string(&value).unwrap_err();
+    let expected = expect![[r#"
+        rustc_flags[0]["@platforms//os:linux"][1]: unsupported integer: 9223372036854775807, Starlark only supports up to 32-bit signed integers"#]];
+    expected.assert_eq(&error.to_string());
+}
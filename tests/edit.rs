@@ -0,0 +1,285 @@
+use expect_test::expect;
+use serde_starlark::edit;
+use serde_starlark::{Config, FunctionCall, LineComment, QuoteStyle};
+use std::collections::BTreeMap;
+
+const BUILD: &str = r#"# DO NOT EDIT -- generated by cargo-bazel.
+
+load("@rules_rust//rust:defs.bzl", "rust_library")
+
+# The parser.
+rust_library(
+    name = "syn",
+    srcs = glob(["src/**/*.rs"]),
+    deps = [
+        ":proc-macro2",
+
+        # Hand-written addition.
+        ":quote",
+    ],
+    rustc_flags   =   ['--cap-lints=allow'],  # spacing preserved
+    edition = "2021"
+)
+
+alias(name = 'syn-2', actual = ":syn")
+
+def _helper(name):
+    native.filegroup(name = "syn")
+"#;
+
+#[test]
+fn test_add_attr() {
+    let build = edit::add_attr(BUILD, "syn", "deps", ":unicode-ident").unwrap();
+    let build = edit::add_attr(&build, "syn", "rustc_flags", "-Dwarnings").unwrap();
+    let build = edit::add_attr(&build, "syn", "data", "README.md").unwrap();
+    let build = edit::add_attr(&build, "syn-2", "tags", "manual").unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        # The parser.
+        rust_library(
+            name = "syn",
+            srcs = glob(["src/**/*.rs"]),
+            deps = [
+                ":proc-macro2",
+
+                # Hand-written addition.
+                ":quote",
+                ":unicode-ident",
+            ],
+            rustc_flags   =   ['--cap-lints=allow', "-Dwarnings"],  # spacing preserved
+            edition = "2021",
+            data = ["README.md"],
+        )
+
+        alias(name = 'syn-2', actual = ":syn", tags = ["manual"])
+
+        def _helper(name):
+            native.filegroup(name = "syn")
+    "#]];
+    expected.assert_eq(&build);
+
+    // Adding an element that is already present changes nothing, even if it
+    // is quoted differently.
+    let again = edit::add_attr(&build, "syn", "deps", ":quote").unwrap();
+    assert_eq!(again, build);
+    let again = edit::add_attr(&build, "syn", "rustc_flags", "--cap-lints=allow").unwrap();
+    assert_eq!(again, build);
+}
+
+#[test]
+fn test_set_attr() {
+    let build = edit::set_attr(BUILD, "syn", "edition", "2018").unwrap();
+    let build = edit::set_attr(&build, "syn", "srcs", &vec!["src/lib.rs", "src/parse.rs"]).unwrap();
+    let build = edit::set_attr(
+        &build,
+        "syn",
+        "crate_root",
+        &LineComment::new("src/lib.rs", "crate root"),
+    )
+    .unwrap();
+    let actual = FunctionCall::new(
+        "select",
+        [BTreeMap::from([("//conditions:default", ":syn")])],
+    );
+    let build = edit::set_attr(&build, "syn-2", "actual", &actual).unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        # The parser.
+        rust_library(
+            name = "syn",
+            srcs = [
+                "src/lib.rs",
+                "src/parse.rs",
+            ],
+            deps = [
+                ":proc-macro2",
+
+                # Hand-written addition.
+                ":quote",
+            ],
+            rustc_flags   =   ['--cap-lints=allow'],  # spacing preserved
+            edition = "2018",
+            crate_root = "src/lib.rs",  # crate root
+        )
+
+        alias(name = 'syn-2', actual = select({
+            "//conditions:default": ":syn",
+        }))
+
+        def _helper(name):
+            native.filegroup(name = "syn")
+    "#]];
+    expected.assert_eq(&build);
+}
+
+#[test]
+fn test_set_attr_replaces_comment() {
+    let value = LineComment::new(vec!["-Dwarnings"], "why");
+    let build = edit::set_attr(BUILD, "syn", "rustc_flags", &value).unwrap();
+    let expected = expect![[r#"    rustc_flags   =   ["-Dwarnings"],  # why"#]];
+    expected.assert_eq(build.lines().nth(14).unwrap());
+}
+
+#[test]
+fn test_remove() {
+    let build = edit::remove_attr(BUILD, "syn", "deps").unwrap();
+    let build = edit::remove_attr(&build, "syn", "nonexistent").unwrap();
+    let build = edit::remove_attr(&build, "syn-2", "name").unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        # The parser.
+        rust_library(
+            name = "syn",
+            srcs = glob(["src/**/*.rs"]),
+            rustc_flags   =   ['--cap-lints=allow'],  # spacing preserved
+            edition = "2021"
+        )
+
+        alias(actual = ":syn")
+
+        def _helper(name):
+            native.filegroup(name = "syn")
+    "#]];
+    expected.assert_eq(&build);
+
+    let build = edit::remove_target(BUILD, "syn").unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        alias(name = 'syn-2', actual = ":syn")
+
+        def _helper(name):
+            native.filegroup(name = "syn")
+    "#]];
+    expected.assert_eq(&build);
+
+    let build = edit::remove_target(BUILD, "syn-2").unwrap();
+    let expected = expect![[r#"
+        # DO NOT EDIT -- generated by cargo-bazel.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library")
+
+        # The parser.
+        rust_library(
+            name = "syn",
+            srcs = glob(["src/**/*.rs"]),
+            deps = [
+                ":proc-macro2",
+
+                # Hand-written addition.
+                ":quote",
+            ],
+            rustc_flags   =   ['--cap-lints=allow'],  # spacing preserved
+            edition = "2021"
+        )
+
+        def _helper(name):
+            native.filegroup(name = "syn")
+    "#]];
+    expected.assert_eq(&build);
+}
+
+#[test]
+fn test_remove_shared_line() {
+    let build = "foo(\n    name = \"x\", srcs = [],\n    deps = [],\n)\n";
+    let expected = expect![[r#"
+        foo(
+            name = "x",
+            deps = [],
+        )
+    "#]];
+    expected.assert_eq(&edit::remove_attr(build, "x", "srcs").unwrap());
+    let expected = expect![[r"
+        foo(
+            srcs = [],
+            deps = [],
+        )
+    "]];
+    expected.assert_eq(&edit::remove_attr(build, "x", "name").unwrap());
+
+    let build = "foo(name = \"x\"); bar(name = \"y\")  # both\n";
+    let expected = expect![[r#"
+        bar(name = "y")  # both
+    "#]];
+    expected.assert_eq(&edit::remove_target(build, "x").unwrap());
+    let expected = expect![[r#"
+        foo(name = "x")  # both
+    "#]];
+    expected.assert_eq(&edit::remove_target(build, "y").unwrap());
+}
+
+#[test]
+fn test_lossless() {
+    let build = r#"x = [  1,2 ]   # spacing
+if x:
+    pass
+cc_library ( name='c' ,
+  hdrs = ["a.h"] +
+         glob(["*.h"]),   # concatenation
+  copts=[ ]   ,)
+"#;
+    let edited = edit::set_attr(build, "c", "copts", &vec!["-O2"]).unwrap();
+    let expected = expect![[r#"
+        x = [  1,2 ]   # spacing
+        if x:
+            pass
+        cc_library ( name='c' ,
+          hdrs = ["a.h"] +
+                 glob(["*.h"]),   # concatenation
+          copts=["-O2"]   ,)
+    "#]];
+    expected.assert_eq(&edited);
+
+    let unchanged = edit::remove_attr(build, "c", "srcs").unwrap();
+    assert_eq!(unchanged, build);
+}
+
+#[test]
+fn test_editor_config() {
+    let mut config = Config::new();
+    config.quote_style(QuoteStyle::Single);
+    let editor = edit::Editor::new(&config);
+    let build = editor
+        .add_attr(BUILD, "syn-2", "visibility", "//visibility:public")
+        .unwrap();
+    let expected = expect![[
+        r#"alias(name = 'syn-2', actual = ":syn", visibility = ['//visibility:public'])"#
+    ]];
+    expected.assert_eq(build.lines().nth(18).unwrap());
+}
+
+#[test]
+fn test_edit_errors() {
+    let error = edit::add_attr(BUILD, "syn", "srcs", "src/lib.rs").unwrap_err();
+    let expected = expect!["cannot add to srcs: its value is not a list literal"];
+    expected.assert_eq(&error.to_string());
+
+    let error = edit::set_attr(BUILD, "syn", "if", "x").unwrap_err();
+    let expected =
+        expect![[r#"invalid named argument: "if" is not a valid Starlark parameter name"#]];
+    expected.assert_eq(&error.to_string());
+
+    let error = edit::add_attr(BUILD, "syn", "foo bar", "x").unwrap_err();
+    let expected =
+        expect![[r#"invalid named argument: "foo bar" is not a valid Starlark parameter name"#]];
+    expected.assert_eq(&error.to_string());
+
+    let error = edit::remove_target(BUILD, "quote").unwrap_err();
+    let expected = expect![[r#"no target named "quote""#]];
+    expected.assert_eq(&error.to_string());
+
+    let error = edit::remove_target("rust_library(\n    name = \"syn,\n)\n", "syn").unwrap_err();
+    let expected = expect!["unterminated string literal at line 2 column 12"];
+    expected.assert_eq(&error.to_string());
+}
@@ -0,0 +1,164 @@
+use expect_test::expect;
+use serde_starlark::{Config, QuoteStyle};
+
+const BUILD: &str = r#"# Hand-maintained targets.
+# See README.md.
+
+load('@rules_rust//rust:defs.bzl', 'rust_library', 'rust_test')
+package(default_visibility = ["//visibility:public"])
+
+#The parser.
+rust_library(name = 'syn', srcs = glob(['src/**/*.rs']),
+    crate_features = ["full",
+        # Needed by the tests.
+        "extra-traits"
+
+        , "visit"],
+    deps = [':proc-macro2', ':quote'] + select({
+        "@platforms//os:linux": [":libc"],  # for linux only
+        "//conditions:default": []}),
+    rustc_flags = ["--cap-lints=allow"] if CAP else [],
+    version = 0x1F, edition = 2021,
+    **COMMON_ATTRS
+)
+
+def _helper(name):
+    # Not reformatted.
+    native.filegroup(name = name, srcs = [ "a" ])
+
+VERSION = '2.0.0'   # keep in sync
+
+# End of file.
+"#;
+
+#[test]
+fn test_fmt() {
+    let expected = expect![[r#"
+        # Hand-maintained targets.
+        # See README.md.
+
+        load("@rules_rust//rust:defs.bzl", "rust_library", "rust_test")
+
+        package(
+            default_visibility = ["//visibility:public"],
+        )
+
+        # The parser.
+        rust_library(
+            name = "syn",
+            srcs = glob(["src/**/*.rs"]),
+            crate_features = [
+                "full",
+                # Needed by the tests.
+                "extra-traits",
+                "visit",
+            ],
+            deps = [
+                ":proc-macro2",
+                ":quote",
+            ] + select({
+                "@platforms//os:linux": [":libc"],  # for linux only
+                "//conditions:default": [],
+            }),
+            rustc_flags = ["--cap-lints=allow"] if CAP else [],
+            version = 0x1F,
+            edition = 2021,
+            **COMMON_ATTRS,
+        )
+
+        def _helper(name):
+            # Not reformatted.
+            native.filegroup(name = name, srcs = [ "a" ])
+
+        VERSION = "2.0.0"  # keep in sync
+
+        # End of file.
+    "#]];
+    expected.assert_eq(&serde_starlark::fmt(BUILD).unwrap());
+}
+
+#[test]
+fn test_fmt_idempotent() {
+    let formatted = serde_starlark::fmt(BUILD).unwrap();
+    assert_eq!(serde_starlark::fmt(&formatted).unwrap(), formatted);
+}
+
+#[test]
+fn test_fmt_config() {
+    let mut config = Config::new();
+    config.quote_style(QuoteStyle::Single);
+    let build = "exports_files([\"LICENSE\", \"README.md\"])\n";
+    let expected = expect![[r"
+        exports_files([
+            'LICENSE',
+            'README.md',
+        ])
+    "]];
+    expected.assert_eq(&config.fmt(build).unwrap());
+}
+
+#[test]
+fn test_fmt_kept_as_written() {
+    // A comment after the last element, a comprehension spanning several
+    // lines, and assignments other than to a single name are not reformatted.
+    let build = r#"cc_library(name = "x", srcs = [
+    "x.cc",
+    # TODO: more
+])
+
+cc_binary(name = "y", srcs = [f
+    for f in SRCS])
+
+x, y = 1, 2
+y += [ "z" ]
+"#;
+    let expected = expect![[r#"
+        cc_library(name = "x", srcs = [
+            "x.cc",
+            # TODO: more
+        ])
+
+        cc_binary(name = "y", srcs = [f
+            for f in SRCS])
+
+        x, y = 1, 2
+
+        y += [ "z" ]
+    "#]];
+    expected.assert_eq(&serde_starlark::fmt(build).unwrap());
+}
+
+#[test]
+fn test_fmt_statement_comment() {
+    // The comment at the end of a statement stays after its closing bracket.
+    // Statements separated by `;` are written on lines of their own.
+    let build = "foo(name='a',deps=[ ':b' ])  # c\nX = [ 'x' ]  # short\nx = 1; y = 2  # both\n";
+    let expected = expect![[r#"
+        foo(
+            name = "a",
+            deps = [":b"],
+        )  # c
+
+        X = ["x"]  # short
+
+        x = 1
+
+        y = 2  # both
+    "#]];
+    expected.assert_eq(&serde_starlark::fmt(build).unwrap());
+}
+
+#[test]
+fn test_fmt_errors() {
+    let error = serde_starlark::fmt("foo(\n").unwrap_err();
+    let expected = expect!["unclosed bracket at line 2 column 1"];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::fmt("x = [1 2]\n").unwrap_err();
+    let expected = expect!["unexpected token at line 1 column 8"];
+    expected.assert_eq(&error.to_string());
+
+    let error = serde_starlark::fmt("x = 1\n  y = 2\n").unwrap_err();
+    let expected = expect!["unexpected indentation at line 2 column 1"];
+    expected.assert_eq(&error.to_string());
+}
@@ -0,0 +1,101 @@
+use expect_test::expect;
+use serde_starlark::lex::{Lexer, TokenKind};
+use std::fmt::Write as _;
+
+#[test]
+fn test_tokens() {
+    let source = r"def _impl(ctx):  # rule
+    srcs = [
+        f.path for f in ctx.files.srcs
+    ]
+
+    return 0x1F + 1.5
+VERSION = r'\d+'
+";
+    let mut tokens = String::new();
+    for token in Lexer::new(source) {
+        let token = token.unwrap();
+        let _ = writeln!(tokens, "{:?} {:?} {:?}", token.kind, token.text, token.span);
+    }
+    let expected = expect![[r##"
+        Identifier "def" 0..3
+        Identifier "_impl" 4..9
+        Punctuation "(" 9..10
+        Identifier "ctx" 10..13
+        Punctuation ")" 13..14
+        Punctuation ":" 14..15
+        Comment "# rule" 17..23
+        Newline "\n" 23..24
+        Indent "    " 24..28
+        Identifier "srcs" 28..32
+        Punctuation "=" 33..34
+        Punctuation "[" 35..36
+        Identifier "f" 45..46
+        Punctuation "." 46..47
+        Identifier "path" 47..51
+        Identifier "for" 52..55
+        Identifier "f" 56..57
+        Identifier "in" 58..60
+        Identifier "ctx" 61..64
+        Punctuation "." 64..65
+        Identifier "files" 65..70
+        Punctuation "." 70..71
+        Identifier "srcs" 71..75
+        Punctuation "]" 80..81
+        Newline "\n" 81..82
+        Identifier "return" 87..93
+        Int "0x1F" 94..98
+        Punctuation "+" 99..100
+        Float "1.5" 101..104
+        Newline "\n" 104..105
+        Dedent "" 105..105
+        Identifier "VERSION" 105..112
+        Punctuation "=" 113..114
+        String "r'\\d+'" 115..121
+        Newline "\n" 121..122
+    "##]];
+    expected.assert_eq(&tokens);
+}
+
+#[test]
+fn test_string_value() {
+    let source = r#"'a\'b' "\x41é" r"\n" """x
+y""" b"z" ident"#;
+    let values: Vec<Option<String>> = Lexer::new(source)
+        .map(|token| token.unwrap())
+        .filter(|token| token.kind != TokenKind::Newline)
+        .map(|token| token.string_value())
+        .collect();
+    let expected = expect![[r#"
+        [
+            Some(
+                "a'b",
+            ),
+            Some(
+                "Aé",
+            ),
+            Some(
+                "\\n",
+            ),
+            Some(
+                "x\ny",
+            ),
+            Some(
+                "z",
+            ),
+            None,
+        ]
+    "#]];
+    expected.assert_debug_eq(&values);
+}
+
+#[test]
+fn test_lex_errors() {
+    let error = Lexer::new("x = 'abc\n").find_map(Result::err).unwrap();
+    let expected = expect!["unterminated string literal at line 1 column 5"];
+    expected.assert_eq(&error.to_string());
+
+    let error = Lexer::new("x = $\n").find_map(Result::err).unwrap();
+    let expected = expect!["unexpected character at line 1 column 5"];
+    expected.assert_eq(&error.to_string());
+}
@@ -0,0 +1,404 @@
+#![cfg(feature = "bazel-rules")]
+
+use expect_test::expect;
+use serde_starlark::rules::{
+    Alias, Attr, AttrValue, Cmd, ConfigSetting, ConfigSettingGroup, ConstraintSetting,
+    ConstraintValue, Filegroup, Genrule, Platform, Provider, RuleDefinition, RustPlatforms,
+    ShBinary, ShLibrary, ShTest, TestSuite,
+};
+use serde_starlark::{Assignment, Document};
+use std::collections::BTreeMap;
+
+fn strings(strings: &[&str]) -> Vec<String> {
+    strings.iter().map(|&s| s.to_owned()).collect()
+}
+
+#[test]
+fn test_rules() {
+    let mut values = BTreeMap::new();
+    values.insert("compilation_mode".to_owned(), "opt".to_owned());
+    let mut env = BTreeMap::new();
+    env.insert("RUST_BACKTRACE".to_owned(), "1".to_owned());
+
+    let targets: Vec<Box<dyn erased_serde::Serialize>> = vec![
+        Box::new(Filegroup {
+            name: "srcs".to_owned(),
+            srcs: strings(&["src/lib.rs"]),
+            output_group: Some("compilation_outputs".to_owned()),
+            ..Default::default()
+        }),
+        Box::new(Alias {
+            name: "syn".to_owned(),
+            actual: ":syn-2.0.0".to_owned(),
+            visibility: strings(&["//visibility:public"]),
+            ..Default::default()
+        }),
+        Box::new(Genrule {
+            name: "version".to_owned(),
+            outs: strings(&["version.txt"]),
+            cmd: Cmd::new().shell("echo 2.0.0 > ").variable("@"),
+            ..Default::default()
+        }),
+        Box::new(ConfigSetting {
+            name: "opt".to_owned(),
+            values,
+            ..Default::default()
+        }),
+        Box::new(ShBinary {
+            name: "run".to_owned(),
+            srcs: strings(&["run.sh"]),
+            args: strings(&["--verbose"]),
+            ..Default::default()
+        }),
+        Box::new(ShLibrary {
+            name: "lib".to_owned(),
+            srcs: strings(&["lib.sh"]),
+            tags: strings(&["manual"]),
+            ..Default::default()
+        }),
+        Box::new(ShTest {
+            name: "test".to_owned(),
+            srcs: strings(&["test.sh"]),
+            deps: strings(&[":lib"]),
+            env,
+            size: Some("small".to_owned()),
+            ..Default::default()
+        }),
+    ];
+
+    let starlark = serde_starlark::to_string(&Document::new(targets)).unwrap();
+    let expected = expect![[r#"
+        filegroup(
+            name = "srcs",
+            srcs = ["src/lib.rs"],
+            output_group = "compilation_outputs",
+        )
+
+        alias(
+            name = "syn",
+            actual = ":syn-2.0.0",
+            visibility = ["//visibility:public"],
+        )
+
+        genrule(
+            name = "version",
+            outs = ["version.txt"],
+            cmd = "echo 2.0.0 > $@",
+        )
+
+        config_setting(
+            name = "opt",
+            values = {
+                "compilation_mode": "opt",
+            },
+        )
+
+        sh_binary(
+            name = "run",
+            srcs = ["run.sh"],
+            args = ["--verbose"],
+        )
+
+        sh_library(
+            name = "lib",
+            srcs = ["lib.sh"],
+            tags = ["manual"],
+        )
+
+        sh_test(
+            name = "test",
+            srcs = ["test.sh"],
+            deps = [":lib"],
+            env = {
+                "RUST_BACKTRACE": "1",
+            },
+            size = "small",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_alias() {
+    let alias = Alias {
+        tags: strings(&["manual"]),
+        ..Alias::new("serde", "@crates//:serde-1.0.193")
+    };
+    let starlark = serde_starlark::to_string(&alias).unwrap();
+    let expected = expect![[r#"
+        alias(
+            name = "serde",
+            actual = "@crates//:serde-1.0.193",
+            tags = ["manual"],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_genrule_cmd() {
+    let cmd = Cmd::new()
+        .shell("cp ")
+        .variable("SRCS")
+        .shell(" \"$TMPDIR\" && ")
+        .raw("$(execpath :tool) $<");
+    expect![[r#"cp $(SRCS) "$$TMPDIR" && $(execpath :tool) $<"#]].assert_eq(cmd.as_str());
+
+    let genrule = Genrule {
+        name: "script".to_owned(),
+        srcs: vec!["in.txt".to_owned()],
+        outs: vec!["out.txt".to_owned()],
+        cmd: Cmd::new()
+            .shell("for f in $SRCS; do\n  cat \"$f\"\ndone > ")
+            .location("out.txt"),
+        ..Default::default()
+    };
+    let starlark = serde_starlark::to_string(&genrule).unwrap();
+    let expected = expect![[r#"
+        genrule(
+            name = "script",
+            srcs = ["in.txt"],
+            outs = ["out.txt"],
+            cmd = """\
+        for f in $$SRCS; do
+          cat "$$f"
+        done > $(location out.txt)""",
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_suites_by_tag() {
+    let tests = [
+        ("a", vec!["slow", "manual"]),
+        ("b", vec![]),
+        ("c", vec!["slow", "slow"]),
+    ];
+    let suites = TestSuite::by_tag(tests.iter().map(|(name, tags)| (*name, tags)));
+    let starlark = serde_starlark::to_string(&Document::new(suites)).unwrap();
+    let expected = expect![[r#"
+        test_suite(
+            name = "manual",
+            tests = [":a"],
+        )
+
+        test_suite(
+            name = "slow",
+            tests = [
+                ":a",
+                ":c",
+            ],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_rule_definition() {
+    let mut definition = RuleDefinition::new("_rust_bindgen_impl");
+    definition.executable = true;
+    definition.provides = strings(&["CcInfo", "DefaultInfo"]);
+    definition.toolchains = strings(&["@rules_rust//bindgen:toolchain_type"]);
+    let attrs = &mut definition.attrs;
+    attrs.insert(
+        "header".to_owned(),
+        Attr::label()
+            .mandatory()
+            .allow_single_file(vec![".h", ".hpp"]),
+    );
+    attrs.insert("cc_lib".to_owned(), Attr::label().default(AttrValue::None));
+    attrs.insert(
+        "_process_wrapper".to_owned(),
+        Attr::label()
+            .default("@rules_rust//util/process_wrapper")
+            .executable()
+            .cfg("exec"),
+    );
+    attrs.insert("opts".to_owned(), Attr::string_list().allow_empty(false));
+    attrs.insert(
+        "depth".to_owned(),
+        Attr::int().default(1).values(vec![1, 2]),
+    );
+    attrs.insert("srcs".to_owned(), Attr::label_list().allow_files(true));
+    attrs.insert("env".to_owned(), Attr::string_dict());
+
+    let assignment = Assignment::new("rust_bindgen", definition);
+    let starlark = serde_starlark::to_string(&assignment).unwrap();
+    let expected = expect![[r#"
+        rust_bindgen = rule(
+            implementation = _rust_bindgen_impl,
+            attrs = {
+                "_process_wrapper": attr.label(
+                    default = "@rules_rust//util/process_wrapper",
+                    executable = True,
+                    cfg = "exec",
+                ),
+                "cc_lib": attr.label(
+                    default = None,
+                ),
+                "depth": attr.int(
+                    default = 1,
+                    values = [
+                        1,
+                        2,
+                    ],
+                ),
+                "env": attr.string_dict(),
+                "header": attr.label(
+                    mandatory = True,
+                    allow_single_file = [
+                        ".h",
+                        ".hpp",
+                    ],
+                ),
+                "opts": attr.string_list(
+                    allow_empty = False,
+                ),
+                "srcs": attr.label_list(
+                    allow_files = True,
+                ),
+            },
+            executable = True,
+            provides = [
+                CcInfo,
+                DefaultInfo,
+            ],
+            toolchains = ["@rules_rust//bindgen:toolchain_type"],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_provider() {
+    let providers = [
+        Provider::new("MarkerInfo"),
+        Provider {
+            doc: Some("Flags passed to rustc.".to_owned()),
+            ..Provider::new("RustcFlagsInfo")
+        },
+    ];
+    let starlark = serde_starlark::to_string(&Document::new(providers)).unwrap();
+    let expected = expect![[r#"
+        MarkerInfo = provider(
+            fields = {},
+        )
+
+        RustcFlagsInfo = provider(
+            doc = "Flags passed to rustc.",
+            fields = {},
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+
+    let error = serde_starlark::to_string(&Provider::new("Marker Info")).unwrap_err();
+    expect![[r#"invalid identifier: "Marker Info" is not a Starlark identifier"#]]
+        .assert_eq(&error.to_string());
+}
+
+#[test]
+fn test_platforms() {
+    let mut exec_properties = BTreeMap::new();
+    exec_properties.insert("container-image".to_owned(), "docker://rbe".to_owned());
+
+    let targets: Vec<Box<dyn erased_serde::Serialize>> = vec![
+        Box::new(ConstraintSetting {
+            name: "sanitizer".to_owned(),
+            visibility: strings(&["//visibility:public"]),
+            ..Default::default()
+        }),
+        Box::new(ConstraintValue {
+            visibility: strings(&["//visibility:public"]),
+            ..ConstraintValue::new("asan", ":sanitizer")
+        }),
+        Box::new(Platform {
+            name: "rbe_asan".to_owned(),
+            constraint_values: strings(&[":asan"]),
+            parents: strings(&["@local_config_platform//:host"]),
+            exec_properties,
+            ..Default::default()
+        }),
+    ];
+    let starlark = serde_starlark::to_string(&Document::new(targets)).unwrap();
+    let expected = expect![[r#"
+        constraint_setting(
+            name = "sanitizer",
+            visibility = ["//visibility:public"],
+        )
+
+        constraint_value(
+            name = "asan",
+            constraint_setting = ":sanitizer",
+            visibility = ["//visibility:public"],
+        )
+
+        platform(
+            name = "rbe_asan",
+            constraint_values = [":asan"],
+            parents = ["@local_config_platform//:host"],
+            exec_properties = {
+                "container-image": "docker://rbe",
+            },
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_config_setting_group() {
+    let group = ConfigSettingGroup {
+        name: "unix".to_owned(),
+        match_any: strings(&["@platforms//os:linux", "@platforms//os:macos"]),
+        ..Default::default()
+    };
+    let starlark = serde_starlark::to_string(&group).unwrap();
+    let expected = expect![[r#"
+        selects.config_setting_group(
+            name = "unix",
+            match_any = [
+                "@platforms//os:linux",
+                "@platforms//os:macos",
+            ],
+        )
+    "#]];
+    expected.assert_eq(&starlark);
+}
+
+#[test]
+fn test_rust_platforms() {
+    let mut platforms = RustPlatforms::new();
+    assert!(platforms
+        .triples()
+        .any(|triple| triple == "x86_64-pc-windows-msvc"));
+    assert_eq!(platforms.label("x86_64-unknown-linux-musl"), None);
+
+    platforms.insert("x86_64-unknown-linux-musl", "//platforms:musl");
+    platforms.insert("x86_64-pc-windows-msvc", "//platforms:windows");
+    let labels: Vec<_> = [
+        "x86_64-unknown-linux-musl",
+        "x86_64-pc-windows-msvc",
+        "wasm32-wasip1",
+    ]
+    .iter()
+    .map(|triple| platforms.label(triple))
+    .collect();
+    let expected = expect![[r#"
+        [
+            Some(
+                "//platforms:musl",
+            ),
+            Some(
+                "//platforms:windows",
+            ),
+            Some(
+                "@rules_rust//rust/platform:wasm32-wasip1",
+            ),
+        ]
+    "#]];
+    expected.assert_debug_eq(&labels);
+
+    let triples: Vec<&str> = platforms.triples().collect();
+    assert!(triples.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(triples.contains(&"x86_64-unknown-linux-musl"));
+}